@@ -1,11 +1,21 @@
-mod cmd;
-mod directories;
+use clap::{value_t, App, AppSettings, Arg, ArgMatches, SubCommand};
 
-use clap::{App, AppSettings, Arg, SubCommand};
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process,
+};
 
-use std::process;
+use ambit::{
+    cmd,
+    diagnostic::Format,
+    directories::AmbitPaths,
+    error::{self, AmbitResult},
+    report::ConsoleReporter,
+};
 
-use ambit::error::{self, AmbitResult};
+// Exit code of `ambit sync --check` when files would be synced.
+const PENDING_CHANGES_EXIT_CODE: i32 = 2;
 
 // Return instance of ambit application
 fn get_app() -> App<'static, 'static> {
@@ -13,11 +23,59 @@ fn get_app() -> App<'static, 'static> {
         .short("f")
         .long("force")
         .help("Overwrite currently initialized dotfile repository");
+    let sudo_arg = Arg::with_name("sudo").long("sudo").help(
+        "Retry steps that are not permitted through sudo, or the command in AMBIT_PRIVILEGE_HELPER",
+    );
 
     App::new("ambit")
         .about("Dotfile manager")
         .setting(AppSettings::ArgRequiredElseHelp)
         .setting(AppSettings::VersionlessSubcommands)
+        .arg(
+            Arg::with_name("profile")
+                .long("profile")
+                .takes_value(true)
+                .global(true)
+                .help("Select a profile declared in the configuration"),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .help("Use the configuration file at PATH, same as AMBIT_CONFIG_PATH"),
+        )
+        .arg(
+            Arg::with_name("repo")
+                .long("repo")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .help("Use the dotfile repository at PATH, same as AMBIT_REPO_PATH"),
+        )
+        .arg(
+            Arg::with_name("home")
+                .long("home")
+                .value_name("PATH")
+                .takes_value(true)
+                .global(true)
+                .help("Sync host files relative to PATH instead of the home directory, same as AMBIT_HOME_PATH"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .long("yes")
+                .short("y")
+                .global(true)
+                .help("Answer yes to every prompt"),
+        )
+        .arg(
+            Arg::with_name("non-interactive")
+                .long("non-interactive")
+                .global(true)
+                .conflicts_with("yes")
+                .help("Answer no to every prompt, e.g. when running from scripts"),
+        )
         .subcommand(
             SubCommand::with_name("init")
                 .about("Initialize an empty dotfile repository")
@@ -27,6 +85,32 @@ fn get_app() -> App<'static, 'static> {
             SubCommand::with_name("clone")
                 .arg(&force_arg)
                 .about("Clone an existing dotfile repository with given origin")
+                .arg(
+                    Arg::with_name("sparse")
+                        .long("sparse")
+                        .help("Only check out the files that the configuration uses"),
+                )
+                .arg(Arg::with_name("GIT_ARGUMENTS").required(true).min_values(1)),
+        )
+        .subcommand(
+            SubCommand::with_name("bootstrap")
+                .arg(&force_arg)
+                .about("Clone an existing dotfile repository and sync it without asking for confirmation")
+                .arg(
+                    Arg::with_name("allow-outside-home")
+                    .long("allow-outside-home")
+                    .help("Sync host files outside of the home directory without asking for confirmation")
+                )
+                .arg(
+                    Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only sync entries with the given tag")
+                )
+                .arg(&sudo_arg)
                 .arg(Arg::with_name("GIT_ARGUMENTS").required(true).min_values(1)),
         )
         .subcommand(
@@ -35,6 +119,65 @@ fn get_app() -> App<'static, 'static> {
                 .setting(AppSettings::AllowLeadingHyphen)
                 .arg(Arg::with_name("GIT_ARGUMENTS").required(true).min_values(1)),
         )
+        .subcommand(
+            SubCommand::with_name("sparse")
+                .about("Only check out the files of the dotfile repository that the configuration uses")
+                .arg(
+                    Arg::with_name("disable")
+                        .long("disable")
+                        .help("Check out every file again"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("update")
+                .about("Update the submodules of the dotfile repository and sync"),
+        )
+        .subcommand(
+            SubCommand::with_name("relocate")
+                .about("Move the dotfile repository and point its symlinks to the new location")
+                .arg(Arg::with_name("PATH").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("hook")
+                .about("Manage git hooks of the dotfile repository")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("install")
+                        .about("Install hooks that sync after merging or checking out with git")
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .short("f")
+                                .help("Overwrite existing hooks"),
+                        ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("commit")
+                .about("Stage and commit every change in the dotfile repository")
+                .arg(
+                    Arg::with_name("message")
+                        .long("message")
+                        .short("m")
+                        .takes_value(true)
+                        .help("Commit message, instead of one that lists the changed files"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("push")
+                .about("Push the dotfile repository to every remote if it has no uncommitted changes")
+                .setting(AppSettings::AllowLeadingHyphen)
+                .arg(Arg::with_name("GIT_ARGUMENTS").min_values(1)),
+        )
+        .subcommand(
+            SubCommand::with_name("pull")
+                .about("Fast-forward the dotfile repository and sync the changes")
+                .arg(
+                    Arg::with_name("autostash")
+                        .long("autostash")
+                        .help("Stash uncommitted changes while pulling"),
+                ),
+        )
         .subcommand(
             SubCommand::with_name("sync")
                 .about("Sync files in dotfile repository to system through symbolic links")
@@ -43,6 +186,11 @@ fn get_app() -> App<'static, 'static> {
                         .long("dry-run")
                         .help("If set, do not actually symlink the files"),
                 )
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("Dry run that exits with code 2 if any files would be synced"),
+                )
                 .arg(
                     Arg::with_name("quiet")
                         .long("quiet")
@@ -53,6 +201,7 @@ fn get_app() -> App<'static, 'static> {
                     Arg::with_name("move")
                         .long("move")
                         .short("m")
+                        .alias("adopt")
                         .help("Move host files into dotfile repository if needed")
                         .long_help("Will automatically move host files into repository if they don't already exist in the repository and then symlink them"),
                 )
@@ -71,47 +220,608 @@ fn get_app() -> App<'static, 'static> {
                     .long("use-any-repo-config-found")
                     .help("Use first repository configuration found after recursive search")
                 )
+                .arg(
+                    Arg::with_name("allow-outside-home")
+                    .long("allow-outside-home")
+                    .help("Sync host files outside of the home directory without asking for confirmation")
+                )
+                .arg(
+                    Arg::with_name("keep-partial")
+                    .long("keep-partial")
+                    .help("Keep the files synced before an error instead of rolling them back")
+                )
+                .arg(
+                    Arg::with_name("incremental")
+                    .long("incremental")
+                    .help("Skip files whose entry and repository file did not change since they were synced")
+                )
+                .arg(
+                    Arg::with_name("replace-identical")
+                    .long("replace-identical")
+                    .help("Replace host files that have the same content as their repository file with symlinks")
+                )
+                .arg(
+                    Arg::with_name("backup")
+                    .long("backup")
+                    .value_name("DIR")
+                    .takes_value(true)
+                    .min_values(0)
+                    .require_equals(true)
+                    .help("Move conflicting host files into a timestamped directory within DIR before syncing")
+                )
+                .arg(
+                    Arg::with_name("overwrite")
+                    .long("overwrite")
+                    .help("Delete conflicting host files so that the repository files are synced in their place")
+                    .long_help("Delete host files that already exist but are not synced from the repository, and sync the repository files in their place. This is unrelated to `--force` of `init` and `clone`, which overwrites the dotfile repository. Combine with `--backup` to keep the host files instead.")
+                )
+                .arg(
+                    Arg::with_name("interactive")
+                    .long("interactive")
+                    .short("i")
+                    .help("Ask before syncing each file")
+                )
+                .arg(
+                    Arg::with_name("copy-fallback")
+                    .long("copy-fallback")
+                    .help("Copy files instead if the filesystem does not support symlinks")
+                    .long_help("Copy repository files to the host instead of symlinking them if symlinks cannot be created, e.g. on FAT filesystems or on Windows without Developer Mode. The copies are recorded in the manifest, so they are synced, cleaned and reported as copies from then on.")
+                )
+                .arg(
+                    Arg::with_name("commit")
+                    .long("commit")
+                    .requires("move")
+                    .help("Commit the host files that were moved into the dotfile repository")
+                    .long_help("Stage and commit the host files that `--move` moved into the dotfile repository, leaving other changes to the repository uncommitted. This is the default if AMBIT_AUTO_COMMIT is `commit` or `push`.")
+                )
+                .arg(
+                    Arg::with_name("push")
+                    .long("push")
+                    .requires("move")
+                    .help("Commit the host files that were moved into the dotfile repository and push them")
+                )
+                .arg(
+                    Arg::with_name("message")
+                    .long("message")
+                    .takes_value(true)
+                    .help("Commit message for `--commit`, instead of one that lists the moved files")
+                )
+                .arg(
+                    Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only sync entries with the given tag")
+                )
+                .arg(
+                    Arg::with_name("only")
+                    .long("only")
+                    .value_name("GLOB")
+                    .takes_value(true)
+                    .help("Only sync host files that match GLOB, relative to the home directory")
+                )
+                .arg(
+                    Arg::with_name("entries")
+                    .long("entries")
+                    .alias("entry")
+                    .value_name("N")
+                    .takes_value(true)
+                    .help("Only sync the entries with the given numbers, e.g. `2,5-7`, as shown by `list --numbered`")
+                )
+                .arg(&sudo_arg)
         )
         .subcommand(
             SubCommand::with_name("clean")
             .about("Remove all symlinks and delete host files")
+                .arg(
+                    Arg::with_name("tag")
+                    .long("tag")
+                    .short("t")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .help("Only clean entries with the given tag")
+                )
+                .arg(
+                    Arg::with_name("only")
+                    .long("only")
+                    .value_name("GLOB")
+                    .takes_value(true)
+                    .help("Only clean host files that match GLOB, relative to the home directory")
+                )
+                .arg(
+                    Arg::with_name("entries")
+                    .long("entries")
+                    .alias("entry")
+                    .value_name("N")
+                    .takes_value(true)
+                    .help("Only clean the entries with the given numbers, e.g. `2,5-7`, as shown by `list --numbered`")
+                )
+                .arg(
+                    Arg::with_name("orphans")
+                    .long("orphans")
+                    .conflicts_with_all(&["tag", "only", "entries"])
+                    .help("Only remove symlinks into the repository that are no longer in the config")
+                )
+                .arg(
+                    Arg::with_name("scan-depth")
+                    .long("scan-depth")
+                    .value_name("DEPTH")
+                    .takes_value(true)
+                    .requires("orphans")
+                    .help("Also look for orphaned symlinks up to DEPTH directories deep in the home directory")
+                )
+                .arg(&sudo_arg)
         )
-        .subcommand(SubCommand::with_name("check").about("Check ambit configuration for errors"))
+        .subcommand(
+            SubCommand::with_name("plan")
+                .about("Save the actions that sync would take, to be reviewed and executed with apply")
+                .arg(
+                    Arg::with_name("move")
+                        .long("move")
+                        .short("m")
+                        .help("Move host files into dotfile repository if needed"),
+                )
+                .arg(
+                    Arg::with_name("out")
+                        .long("out")
+                        .short("o")
+                        .takes_value(true)
+                        .help("Path to save the plan to"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("apply")
+                .about("Execute the actions saved by plan, refusing if they no longer apply")
+                .arg(Arg::with_name("PLAN").help("Path of the plan to execute")),
+        )
+        .subcommand(
+            SubCommand::with_name("undo")
+                .about("Reverse the changes made by the most recent sync or clean")
+                .arg(&sudo_arg),
+        )
+        .subcommand(
+            SubCommand::with_name("status")
+                .about("Report the state of every path in the configuration")
+                .arg(
+                    Arg::with_name("tag")
+                        .long("tag")
+                        .short("t")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1)
+                        .help("Only report entries with the given tag"),
+                )
+                .arg(
+                    Arg::with_name("git")
+                        .long("git")
+                        .help("Also report the branch and uncommitted changes of the dotfile repository"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("list")
+                .about("Print the repository and host paths that the configuration resolves to")
+                .arg(Arg::with_name("PATTERN").help("Only print paths that match the given glob"))
+                .arg(
+                    Arg::with_name("numbered")
+                        .long("numbered")
+                        .short("n")
+                        .help("Prefix paths with the number of their entry"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("fmt")
+                .about("Format the configuration in the canonical style")
+                .arg(
+                    Arg::with_name("check")
+                        .long("check")
+                        .help("If set, do not format the configuration but fail if it is not formatted"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("edit")
+                .about("Open the configuration in VISUAL or EDITOR, checking it for errors afterwards"),
+        )
+        .subcommand(
+            SubCommand::with_name("expand")
+                .about("Print every string that a spec expands to on this system")
+                .arg(Arg::with_name("SPEC").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("tree")
+                .about("Print the host files that the configuration syncs as a tree")
+                .arg(
+                    Arg::with_name("dot")
+                        .long("dot")
+                        .help("If set, print the tree as a Graphviz graph instead"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("which")
+                .about("Print the repository file that a host file is synced from, along with its entry")
+                .arg(Arg::with_name("PATH").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("diff")
+                .about("Show how host files that are not synced differ from their repository files")
+                .arg(Arg::with_name("PATH").help("Only compare the host file at the given path")),
+        )
+        .subcommand(
+            SubCommand::with_name("doctor")
+                .about("Check the environment for problems, suggesting how to fix them"),
+        )
+        .subcommand(
+            SubCommand::with_name("repair")
+                .about("Recreate symlinks that are broken or link to the wrong repository file"),
+        )
+        .subcommand(
+            SubCommand::with_name("prune")
+                .about("List repository files that are not referenced by the config")
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .help("Delete the unreferenced files after confirmation"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("add")
+                .about("Move a host file into the dotfile repository, add it to the config and symlink it")
+                .arg(Arg::with_name("PATH").required(true))
+                .arg(
+                    Arg::with_name("commit")
+                        .long("commit")
+                        .help("Commit the file, along with the config if it is in the dotfile repository")
+                        .long_help("Commit the file, along with the config if it is in the dotfile repository. This is the default if AMBIT_AUTO_COMMIT is `commit` or `push`."),
+                )
+                .arg(
+                    Arg::with_name("push")
+                        .long("push")
+                        .help("Commit the file and push the dotfile repository"),
+                )
+                .arg(
+                    Arg::with_name("message")
+                        .long("message")
+                        .short("m")
+                        .takes_value(true)
+                        .help("Commit message, instead of one that lists the changed files"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("rm")
+                .about("Remove the entry that syncs a host file from the config, along with its links")
+                .arg(Arg::with_name("PATH").required(true))
+                .arg(
+                    Arg::with_name("delete")
+                        .long("delete")
+                        .help("If set, also delete the repository files of the entry"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("encrypt")
+                .about("Encrypt a host file back into the encrypted repository file it is synced from")
+                .arg(Arg::with_name("PATH").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("check")
+                .about("Check ambit configuration for errors")
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .takes_value(true)
+                        .possible_values(&["text", "json"])
+                        .default_value("text")
+                        .help("Format in which errors are reported"),
+                )
+                .arg(
+                    Arg::with_name("strict")
+                        .long("strict")
+                        .help("Report repository files referenced by the configuration that do not exist"),
+                ),
+        )
+}
+
+// Return the command that steps which are not permitted are retried with, if `--sudo` is given.
+fn get_privilege_helper(matches: &ArgMatches) -> Option<String> {
+    if !matches.is_present("sudo") {
+        return None;
+    }
+    Some(env::var("AMBIT_PRIVILEGE_HELPER").unwrap_or_else(|_| "sudo".to_owned()))
+}
+
+// Return whether moved files are committed and pushed, from AMBIT_AUTO_COMMIT unless `--commit` or `--push` asks for more.
+fn get_auto_commit(matches: &ArgMatches) -> AmbitResult<cmd::AutoCommit> {
+    let setting = match env::var("AMBIT_AUTO_COMMIT").as_deref() {
+        Ok("push") => cmd::AutoCommit::Push,
+        Ok("commit") => cmd::AutoCommit::Commit,
+        Ok("") | Err(_) => cmd::AutoCommit::Off,
+        Ok(value) => {
+            return Err(error::AmbitError::Other(format!(
+                "AMBIT_AUTO_COMMIT must be `commit` or `push`, not `{}`",
+                value
+            )))
+        }
+    };
+    Ok(if matches.is_present("push") {
+        cmd::AutoCommit::Push
+    } else if matches.is_present("commit") && setting == cmd::AutoCommit::Off {
+        cmd::AutoCommit::Commit
+    } else {
+        setting
+    })
 }
 
 // Fetch application matches and run commands accordingly
 fn run() -> AmbitResult<()> {
     let matches = get_app().get_matches();
 
+    // Options can be given before or after the subcommand.
+    let value_of_os = |name| {
+        matches
+            .subcommand()
+            .1
+            .and_then(|matches| matches.value_of_os(name))
+            .or_else(|| matches.value_of_os(name))
+    };
+    let is_present = |name| {
+        matches.is_present(name)
+            || matches
+                .subcommand()
+                .1
+                .is_some_and(|matches| matches.is_present(name))
+    };
+    // Overrides take precedence over the environment variables that the paths are otherwise built from.
+    let mut builder = AmbitPaths::builder();
+    if let Some(profile) = value_of_os("profile") {
+        builder = builder.profile(profile.to_string_lossy());
+    }
+    if let Some(path) = value_of_os("config") {
+        builder = builder.config(env::current_dir()?.join(path));
+    }
+    if let Some(path) = value_of_os("repo") {
+        builder = builder.repo(env::current_dir()?.join(path));
+    }
+    if let Some(path) = value_of_os("home") {
+        builder = builder.home(env::current_dir()?.join(path));
+    }
+    // Clap only catches the conflict if both flags are given at the same level.
+    if is_present("yes") && is_present("non-interactive") {
+        return Err(error::AmbitError::Other(
+            "`--yes` cannot be used with `--non-interactive`".to_owned(),
+        ));
+    } else if is_present("yes") {
+        builder = builder.assume(true);
+    } else if is_present("non-interactive") {
+        builder = builder.assume(false);
+    }
+    let ambit_paths = builder.build()?;
+    let mut reporter = ConsoleReporter::default();
     if let Some(matches) = matches.subcommand_matches("init") {
         let force = matches.is_present("force");
-        cmd::init(force)?;
+        cmd::init(&ambit_paths, force, &mut reporter)?;
     } else if let Some(matches) = matches.subcommand_matches("clone") {
         let force = matches.is_present("force");
         let git_arguments = matches.values_of("GIT_ARGUMENTS").unwrap().collect();
-        cmd::clone(force, git_arguments)?;
+        cmd::clone(
+            &ambit_paths,
+            force,
+            matches.is_present("sparse"),
+            git_arguments,
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("bootstrap") {
+        let tags: Vec<&str> = matches
+            .values_of("tag")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        cmd::bootstrap(
+            &ambit_paths,
+            matches.is_present("force"),
+            matches.is_present("allow-outside-home"),
+            &tags,
+            &mut reporter,
+            get_privilege_helper(matches).as_deref(),
+            matches.values_of("GIT_ARGUMENTS").unwrap().collect(),
+        )?;
     } else if let Some(matches) = matches.subcommand_matches("git") {
         let git_arguments = matches.values_of("GIT_ARGUMENTS").unwrap().collect();
-        cmd::git(git_arguments)?;
-    } else if matches.is_present("check") {
-        cmd::check()?;
+        cmd::git(&ambit_paths, git_arguments)?;
+    } else if let Some(matches) = matches.subcommand_matches("sparse") {
+        cmd::sparse(&ambit_paths, matches.is_present("disable"), &mut reporter)?;
+    } else if matches.subcommand_matches("update").is_some() {
+        cmd::update(&ambit_paths, &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("relocate") {
+        cmd::relocate(
+            &ambit_paths,
+            Path::new(matches.value_of("PATH").unwrap()),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("hook") {
+        if let Some(matches) = matches.subcommand_matches("install") {
+            cmd::install_hooks(&ambit_paths, matches.is_present("force"), &mut reporter)?;
+        }
+    } else if let Some(matches) = matches.subcommand_matches("commit") {
+        cmd::commit(&ambit_paths, matches.value_of("message"), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("push") {
+        let git_arguments = matches
+            .values_of("GIT_ARGUMENTS")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        cmd::push(&ambit_paths, git_arguments, &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("pull") {
+        cmd::pull(&ambit_paths, matches.is_present("autostash"), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("status") {
+        let tags: Vec<&str> = matches
+            .values_of("tag")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        cmd::status(
+            &ambit_paths,
+            &tags,
+            matches.is_present("git"),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("list") {
+        cmd::list(
+            &ambit_paths,
+            matches.value_of("PATTERN"),
+            matches.is_present("numbered"),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("fmt") {
+        cmd::fmt(&ambit_paths, matches.is_present("check"), &mut reporter)?;
+    } else if matches.subcommand_matches("edit").is_some() {
+        cmd::edit(&ambit_paths, &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("expand") {
+        cmd::expand(
+            &ambit_paths,
+            matches.value_of("SPEC").unwrap(),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("tree") {
+        cmd::tree(&ambit_paths, matches.is_present("dot"), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("which") {
+        let path = matches.value_of_os("PATH").unwrap();
+        cmd::which(&ambit_paths, Path::new(path), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("diff") {
+        cmd::diff(
+            &ambit_paths,
+            matches.value_of_os("PATH").map(Path::new),
+            &mut reporter,
+        )?;
+    } else if matches.subcommand_matches("doctor").is_some() {
+        cmd::doctor(&ambit_paths, &mut reporter)?;
+    } else if matches.subcommand_matches("repair").is_some() {
+        cmd::repair(&ambit_paths, &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("prune") {
+        cmd::prune(&ambit_paths, matches.is_present("delete"), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("add") {
+        let path = matches.value_of_os("PATH").unwrap();
+        cmd::add(
+            &ambit_paths,
+            Path::new(path),
+            get_auto_commit(matches)?,
+            matches.value_of("message"),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("rm") {
+        let path = matches.value_of_os("PATH").unwrap();
+        cmd::remove(
+            &ambit_paths,
+            Path::new(path),
+            matches.is_present("delete"),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("encrypt") {
+        let path = matches.value_of_os("PATH").unwrap();
+        cmd::encrypt(&ambit_paths, Path::new(path), &mut reporter)?;
+    } else if let Some(matches) = matches.subcommand_matches("check") {
+        let format = value_t!(matches, "format", Format).unwrap_or_else(|e| e.exit());
+        let strict = matches.is_present("strict");
+        cmd::check(&ambit_paths, format, strict, &mut reporter)?;
     } else if let Some(matches) = matches.subcommand_matches("sync") {
-        let dry_run = matches.is_present("dry-run");
-        let quiet = matches.is_present("quiet");
-        let move_files = matches.is_present("move");
-        let use_repo_config = matches.is_present("use-repo-config");
-        let use_repo_config_if_required = matches.is_present("use-repo-config-if-required");
-        let use_any_repo_config = matches.is_present("use-any-repo-config-found");
-        cmd::sync(
+        let check = matches.is_present("check");
+        let dry_run = matches.is_present("dry-run") || check;
+        reporter.quiet = matches.is_present("quiet");
+        // Backups are kept next to the manifest unless a directory is given.
+        let backup_dir = match matches.value_of("backup") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None if matches.is_present("backup") => Some(ambit_paths.backups.path.clone()),
+            None => None,
+        };
+        let tags: Vec<&str> = matches
+            .values_of("tag")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let selection = matches
+            .value_of("entries")
+            .map(cmd::EntrySelection::parse)
+            .transpose()?;
+        let only = matches
+            .value_of("only")
+            .map(cmd::HostFilter::new)
+            .transpose()?;
+        let privilege_helper = get_privilege_helper(matches);
+        let options = cmd::SyncOptions {
             dry_run,
-            quiet,
-            move_files,
-            use_repo_config,
-            use_repo_config_if_required,
-            use_any_repo_config,
+            move_files: matches.is_present("move"),
+            use_repo_config: matches.is_present("use-repo-config"),
+            use_repo_config_if_required: matches.is_present("use-repo-config-if-required"),
+            use_any_repo_config: matches.is_present("use-any-repo-config-found"),
+            allow_outside_home: matches.is_present("allow-outside-home"),
+            keep_partial: matches.is_present("keep-partial"),
+            incremental: matches.is_present("incremental"),
+            replace_identical: matches.is_present("replace-identical"),
+            backup_dir: backup_dir.as_deref(),
+            overwrite: matches.is_present("overwrite"),
+            interactive: matches.is_present("interactive"),
+            copy_fallback: matches.is_present("copy-fallback"),
+            auto_commit: get_auto_commit(matches)?,
+            commit_message: matches.value_of("message"),
+            tags: &tags,
+            selection: selection.as_ref(),
+            only: only.as_ref(),
+            privilege_helper: privilege_helper.as_deref(),
+        };
+        let pending_syncs = cmd::sync(&ambit_paths, &options, &mut reporter)?;
+        // Scripts can tell pending changes apart from errors, which exit with code 1.
+        if check && pending_syncs > 0 {
+            process::exit(PENDING_CHANGES_EXIT_CODE);
+        }
+    } else if let Some(matches) = matches.subcommand_matches("plan") {
+        cmd::plan(
+            &ambit_paths,
+            matches.is_present("move"),
+            matches.value_of_os("out").map(Path::new),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("apply") {
+        cmd::apply(
+            &ambit_paths,
+            matches.value_of_os("PLAN").map(Path::new),
+            &mut reporter,
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("undo") {
+        cmd::undo(
+            &ambit_paths,
+            &mut reporter,
+            get_privilege_helper(matches).as_deref(),
+        )?;
+    } else if let Some(matches) = matches.subcommand_matches("clean") {
+        let tags: Vec<&str> = matches
+            .values_of("tag")
+            .map(Iterator::collect)
+            .unwrap_or_default();
+        let selection = matches
+            .value_of("entries")
+            .map(cmd::EntrySelection::parse)
+            .transpose()?;
+        let only = matches
+            .value_of("only")
+            .map(cmd::HostFilter::new)
+            .transpose()?;
+        let orphans = matches.is_present("orphans");
+        let scan_depth = matches
+            .value_of("scan-depth")
+            .map(|depth| {
+                depth.parse().map_err(|_| {
+                    error::AmbitError::Other(format!("`{}` is not a valid scan depth", depth))
+                })
+            })
+            .transpose()?;
+        // Only orphans are listed, as they may come as a surprise unlike the files of the config.
+        reporter.quiet = !orphans;
+        cmd::clean(
+            &ambit_paths,
+            &tags,
+            selection.as_ref(),
+            only.as_ref(),
+            orphans,
+            scan_depth,
+            &mut reporter,
+            get_privilege_helper(matches).as_deref(),
         )?;
-    } else if matches.is_present("clean") {
-        cmd::clean()?;
     }
     Ok(())
 }
@@ -144,6 +854,18 @@ mod tests {
         }}
     }
 
+    #[test]
+    fn prompt_answer_flags() {
+        let matches = arguments_list!("sync", "--yes");
+        assert!(matches
+            .subcommand_matches("sync")
+            .unwrap()
+            .is_present("yes"));
+        let matches = arguments_list!("--non-interactive", "clean");
+        assert!(matches.is_present("non-interactive"));
+        fail_with_arguments_list!("--yes", "--non-interactive", "sync");
+    }
+
     #[test]
     fn force_flag() {
         let matches = arguments_list!("init", "-f");
@@ -211,6 +933,142 @@ mod tests {
         assert_eq!(git_arguments, vec!["https://github.com/plamorg/ambit"]);
     }
 
+    #[test]
+    fn clean_orphans() {
+        let matches = arguments_list!("clean", "--orphans");
+        assert!(matches
+            .subcommand_matches("clean")
+            .unwrap()
+            .is_present("orphans"));
+        fail_with_arguments_list!("clean", "--orphans", "-t", "work");
+        fail_with_arguments_list!("clean", "--orphans", "--only", ".config/*");
+        arguments_list!("clean", "--orphans", "--scan-depth", "3");
+        fail_with_arguments_list!("clean", "--scan-depth", "3");
+    }
+
+    #[test]
+    fn bootstrap_with_tags() {
+        let matches = arguments_list!(
+            "bootstrap",
+            "-t",
+            "work",
+            "https://github.com/plamorg/ambit",
+            "-f"
+        );
+        let bootstrap_matches = matches.subcommand_matches("bootstrap").unwrap();
+        let tags: Vec<_> = bootstrap_matches.values_of("tag").unwrap().collect();
+        let git_arguments: Vec<_> = bootstrap_matches
+            .values_of("GIT_ARGUMENTS")
+            .unwrap()
+            .collect();
+        assert!(bootstrap_matches.is_present("force"));
+        assert_eq!(tags, vec!["work"]);
+        assert_eq!(git_arguments, vec!["https://github.com/plamorg/ambit"]);
+    }
+
+    #[test]
+    fn push_with_git_arguments() {
+        let matches = arguments_list!("push", "--force-with-lease", "origin");
+        let push_matches = matches.subcommand_matches("push").unwrap();
+        let git_arguments: Vec<_> = push_matches.values_of("GIT_ARGUMENTS").unwrap().collect();
+        assert_eq!(git_arguments, vec!["--force-with-lease", "origin"]);
+    }
+
+    #[test]
+    fn status_tags() {
+        let matches = arguments_list!("status", "-t", "work");
+        let status_matches = matches.subcommand_matches("status").unwrap();
+        let tags: Vec<_> = status_matches.values_of("tag").unwrap().collect();
+        assert_eq!(tags, vec!["work"]);
+    }
+
+    #[test]
+    fn check_format() {
+        let matches = arguments_list!("check", "--format", "json");
+        let check_matches = matches.subcommand_matches("check").unwrap();
+        assert_eq!(
+            value_t!(check_matches, "format", Format).unwrap(),
+            Format::Json
+        );
+        // The format should default to text.
+        let matches = arguments_list!("check");
+        let check_matches = matches.subcommand_matches("check").unwrap();
+        assert_eq!(
+            value_t!(check_matches, "format", Format).unwrap(),
+            Format::Text
+        );
+        fail_with_arguments_list!("check", "--format", "xml");
+    }
+
+    #[test]
+    fn sync_tags() {
+        let matches = arguments_list!("sync", "--tag", "work", "-t", "gaming");
+        let tags: Vec<_> = matches
+            .subcommand_matches("sync")
+            .unwrap()
+            .values_of("tag")
+            .unwrap()
+            .collect();
+        assert_eq!(tags, vec!["work", "gaming"]);
+    }
+
+    #[test]
+    fn sync_and_clean_sudo() {
+        for subcommand in &["sync", "clean"] {
+            let matches = arguments_list!(*subcommand, "--sudo");
+            assert!(matches
+                .subcommand_matches(subcommand)
+                .unwrap()
+                .is_present("sudo"));
+        }
+        fail_with_arguments_list!("check", "--sudo");
+    }
+
+    #[test]
+    fn add_path() {
+        let matches = arguments_list!("add", "~/.zshrc");
+        let add_matches = matches.subcommand_matches("add").unwrap();
+        assert_eq!(add_matches.value_of("PATH"), Some("~/.zshrc"));
+        fail_with_arguments_list!("add");
+    }
+
+    #[test]
+    fn rm_path() {
+        let matches = arguments_list!("rm", "~/.zshrc", "--delete");
+        let rm_matches = matches.subcommand_matches("rm").unwrap();
+        assert_eq!(rm_matches.value_of("PATH"), Some("~/.zshrc"));
+        assert!(rm_matches.is_present("delete"));
+        fail_with_arguments_list!("rm");
+    }
+
+    #[test]
+    fn encrypt_path() {
+        let matches = arguments_list!("encrypt", ".ssh/id_ed25519");
+        let encrypt_matches = matches.subcommand_matches("encrypt").unwrap();
+        assert_eq!(encrypt_matches.value_of("PATH"), Some(".ssh/id_ed25519"));
+        fail_with_arguments_list!("encrypt");
+    }
+
+    #[test]
+    fn global_profile() {
+        for matches in &[
+            arguments_list!("--profile", "minimal", "sync"),
+            arguments_list!("sync", "--profile", "minimal"),
+        ] {
+            let sync_matches = matches.subcommand_matches("sync").unwrap();
+            assert_eq!(sync_matches.value_of("profile"), Some("minimal"));
+        }
+    }
+
+    #[test]
+    fn check_strict() {
+        let matches = arguments_list!("check", "--strict");
+        assert!(matches
+            .subcommand_matches("check")
+            .unwrap()
+            .is_present("strict"));
+    }
+
     #[test]
     fn clone_with_force_as_git_argument() {
         let matches = arguments_list!(
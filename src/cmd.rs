@@ -0,0 +1,5679 @@
+// Symlink function is dependent on OS
+#[cfg(unix)]
+use std::os::unix::fs::{symlink as symlink_file, symlink as symlink_dir};
+#[cfg(windows)]
+use std::os::windows::fs::{symlink_dir, symlink_file};
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap, HashSet},
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    hash::{Hash, Hasher},
+    io::{self, Write},
+    ops::{Range, RangeInclusive},
+    path::{Component, Path, PathBuf},
+    process::{Command, Stdio},
+    rc::Rc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use globset::{GlobBuilder, GlobMatcher};
+use similar::TextDiff;
+use walkdir::WalkDir;
+
+use crate::{
+    config::{
+        self,
+        ast::{Attributes, LinkMode, Spec, UnreachableCase},
+        Entry, Statement,
+    },
+    diagnostic::{self, Diagnostic, Format, Severity},
+    directories::{AmbitPath, AmbitPathKind, AmbitPaths, CONFIG_NAME, IGNORE_NAME},
+    encryption::Encryption,
+    error::{AmbitError, AmbitResult},
+    git,
+    ignore::Ignore,
+    journal::{Change, Journal, Operation},
+    manifest::{Manifest, ManifestEntry},
+    plan::{Action, Plan},
+    prompt,
+    report::{
+        display_entry, display_path, display_paths, display_secret_file, Command as ReportCommand,
+        Event, Link, Reporter,
+    },
+    template,
+};
+
+pub use crate::config::ast::AutoCommit;
+
+// Report output that spans lines, e.g. a diff, as a single message without its final newline.
+// Empty output is not reported, so that it does not show up as an empty line.
+fn report_output(reporter: &mut dyn Reporter, output: &str) {
+    if !output.is_empty() {
+        reporter.report(Event::Message(output.strip_suffix('\n').unwrap_or(output)));
+    }
+}
+
+// Initialize config and repository directory
+fn ensure_paths_exist(ambit_paths: &AmbitPaths, force: bool) -> AmbitResult<()> {
+    if !ambit_paths.config.exists() {
+        ambit_paths.config.ensure_parent_dirs_exist()?;
+        ambit_paths.config.create()?;
+    }
+    if ambit_paths.repo.exists() && !force {
+        // Dotfile repository should not be overwritten if force is false
+        return Err(AmbitError::Other(
+            "Dotfile repository already exists.\nUse '-f' flag to overwrite.".to_owned(),
+        ));
+    } else if ambit_paths.repo.exists() {
+        // Repository directory exists but force is enabled
+        ambit_paths.repo.remove()?;
+    }
+    Ok(())
+}
+
+// The contents of a configuration and the configurations it includes.
+#[derive(Default)]
+struct Config {
+    entries: Vec<Entry>,
+    // Where each entry is defined, in the same order as entries.
+    sources: Vec<EntrySource>,
+    // Profiles declared with `profile`.
+    profiles: Vec<String>,
+    // Variables defined in `vars` blocks, which are available to templates.
+    vars: HashMap<String, String>,
+    // Set with `autocommit`, where the last setting wins.
+    auto_commit: AutoCommit,
+}
+
+// The location of an entry within the configuration files.
+struct EntrySource {
+    path: PathBuf,
+    line: usize,
+    column: usize,
+    // The text of the entry as it is written in the configuration.
+    text: String,
+}
+
+// Fetch entries from config file and return as vector
+fn get_config_entries(
+    ambit_paths: &AmbitPaths,
+    config_path: &AmbitPath,
+) -> AmbitResult<Vec<Entry>> {
+    get_config(ambit_paths, config_path).map(|config| config.entries)
+}
+
+// Fetch the entries and variables of the config file, including the configs merged into it.
+fn get_config(ambit_paths: &AmbitPaths, config_path: &AmbitPath) -> AmbitResult<Config> {
+    let mut config = Config::default();
+    let mut including = Vec::new();
+    add_config_entries(config_path, &mut including, &mut config)?;
+    // Fragments are merged after the config itself.
+    for fragment_path in get_config_fragment_paths(&config_path.path)? {
+        add_included_config_entries(fragment_path, &mut including, &mut config)?;
+    }
+    // The machine-local config is merged last, regardless of which config is used.
+    if ambit_paths.local_config.exists() && ambit_paths.local_config.path != config_path.path {
+        add_included_config_entries(
+            ambit_paths.local_config.path.clone(),
+            &mut including,
+            &mut config,
+        )?;
+    }
+    // Catch typos in the selected profile, since they would silently match nothing.
+    if let Some(profile) = &ambit_paths.profile {
+        if !config.profiles.contains(profile) {
+            return Err(AmbitError::Other(format!(
+                "Profile `{}` is not declared in the configuration",
+                profile
+            )));
+        }
+    }
+    Ok(config)
+}
+
+// Return the paths of `*.ambit` files in the `.d` directory next to the config file, in lexical order.
+// For example, fragments of `config.ambit` are kept in `config.ambit.d/`.
+fn get_config_fragment_paths(config_path: &Path) -> AmbitResult<Vec<PathBuf>> {
+    let mut fragment_dir = config_path.as_os_str().to_owned();
+    fragment_dir.push(".d");
+    let fragment_dir = PathBuf::from(fragment_dir);
+    if !fragment_dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut fragment_paths = Vec::new();
+    for path in fs::read_dir(&fragment_dir)? {
+        let path = path?.path();
+        if path.is_file() && path.extension() == Some(OsStr::new("ambit")) {
+            fragment_paths.push(path);
+        }
+    }
+    fragment_paths.sort();
+    Ok(fragment_paths)
+}
+
+// Parse the config file and push its entries, splicing in the entries of included configs.
+// `including` holds the canonical paths of the configs currently being parsed, in order to detect cycles.
+fn add_config_entries(
+    config_path: &AmbitPath,
+    including: &mut Vec<PathBuf>,
+    config: &mut Config,
+) -> AmbitResult<()> {
+    let content = config_path.as_string()?;
+    let mut statements = Vec::new();
+    let mut errors = Vec::new();
+    // Collect every error so they can all be reported at once.
+    let mut parser = config::get_statements(content.chars().peekable());
+    while let Some(result) = parser.next() {
+        match result {
+            Ok(statement) => statements.push((statement, parser.statement_span())),
+            Err(error) => errors.push(error),
+        }
+    }
+    if !errors.is_empty() {
+        return Err(AmbitError::Parse { content, errors });
+    }
+    including.push(config_path.path.canonicalize()?);
+    for (statement, span) in statements {
+        match statement {
+            Statement::Entry(entry) => {
+                let line_start = content[..span.start].rfind('\n').map_or(0, |i| i + 1);
+                config.entries.push(entry);
+                config.sources.push(EntrySource {
+                    path: config_path.path.clone(),
+                    line: content[..span.start].matches('\n').count() + 1,
+                    column: content[line_start..span.start].chars().count() + 1,
+                    text: content[span].to_owned(),
+                });
+            }
+            Statement::Include(path) => {
+                let parent = config_path.path.parent().unwrap_or_else(|| Path::new(""));
+                add_included_config_entries(parent.join(path), including, config)?;
+            }
+            Statement::Profile(profile) => config.profiles.push(profile),
+            Statement::Var(name, value) => {
+                config.vars.insert(name, value);
+            }
+            Statement::AutoCommit(setting) => config.auto_commit = setting,
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+// Push the entries of a config that is included by another config.
+fn add_included_config_entries(
+    path: PathBuf,
+    including: &mut Vec<PathBuf>,
+    config: &mut Config,
+) -> AmbitResult<()> {
+    // If the path cannot be canonicalized, it does not exist and reading it will fail anyway.
+    let canonical_path = path.canonicalize().unwrap_or_else(|_| path.clone());
+    if including.contains(&canonical_path) {
+        return Err(AmbitError::Other(format!(
+            "Configuration `{}` is included recursively",
+            path.display()
+        )));
+    }
+    let config_path = AmbitPath::new(path, AmbitPathKind::File);
+    add_config_entries(&config_path, including, config).map_err(|e| match e {
+        // Parse errors do not mention the file they occurred in.
+        AmbitError::Parse { .. } => AmbitError::Include {
+            path: config_path.path.clone(),
+            error: Box::new(e),
+        },
+        e => e,
+    })
+}
+
+// Return if link_name is symlinked to target (link_name -> target).
+fn is_symlinked(link_name: &Path, target: &Path) -> bool {
+    fs::read_link(link_name)
+        .map(|link_path| link_path == *target)
+        .unwrap_or(false)
+}
+
+// Return the content of repo_file, decrypting it if it is encrypted and rendering it if it is a template.
+// The variables of the config are available to templates.
+fn read_repo_file(
+    ambit_paths: &AmbitPaths,
+    repo_file: &Path,
+    vars: &HashMap<String, String>,
+) -> AmbitResult<Vec<u8>> {
+    let content = match Encryption::from_path(repo_file) {
+        Some(encryption) => encryption.decrypt(repo_file, &ambit_paths.age_identity.path)?,
+        None => fs::read(repo_file)?,
+    };
+    if !template::is_template(repo_file) {
+        return Ok(content);
+    }
+    let content = String::from_utf8(content)
+        .map_err(|_| AmbitError::Other("Templates must be valid UTF-8".to_owned()))?;
+    Ok(template::render_with_pass(&content, vars)?.into_bytes())
+}
+
+// Return the mode that repo_file is synced with. Templates are always copied, as they are rendered first.
+fn get_link_mode(repo_file: &Path, mode: LinkMode) -> LinkMode {
+    match template::is_template(repo_file) {
+        true => LinkMode::Copy,
+        false => mode,
+    }
+}
+
+// Return if host_file is synced to repo_file with the given mode.
+fn is_synced(
+    ambit_paths: &AmbitPaths,
+    host_file: &Path,
+    repo_file: &Path,
+    mode: LinkMode,
+    vars: &HashMap<String, String>,
+) -> bool {
+    match mode {
+        LinkMode::Symlink => is_symlinked(host_file, repo_file),
+        LinkMode::Copy => {
+            // A symlink to the repo file would have the same content, but is not a copy.
+            let is_copy = fs::symlink_metadata(host_file)
+                .map(|metadata| metadata.is_file())
+                .unwrap_or(false);
+            is_copy
+                && match (
+                    fs::read(host_file),
+                    read_repo_file(ambit_paths, repo_file, vars),
+                ) {
+                    (Ok(host_content), Ok(repo_content)) => host_content == repo_content,
+                    _ => false,
+                }
+        }
+    }
+}
+
+// Run a command through the privilege helper, e.g. `sudo ln -s a b`.
+fn run_privileged(helper: &str, args: &[&OsStr]) -> AmbitResult<()> {
+    let status = Command::new(helper).args(args).status()?;
+    if status.success() {
+        return Ok(());
+    }
+    let command: Vec<_> = args.iter().map(|arg| arg.to_string_lossy()).collect();
+    Err(AmbitError::Other(format!(
+        "`{} {}` failed",
+        helper,
+        command.join(" ")
+    )))
+}
+
+// If result failed because it was not permitted, run the command in args through the privilege helper instead.
+fn escalate(result: AmbitResult<()>, helper: Option<&str>, args: &[&OsStr]) -> AmbitResult<()> {
+    match (result, helper) {
+        (Err(AmbitError::Io(e)), Some(helper)) if e.kind() == io::ErrorKind::PermissionDenied => {
+            run_privileged(helper, args)
+        }
+        (result, _) => result,
+    }
+}
+
+// Write content to path through the privilege helper, by piping it into `tee`.
+// This is used for content that has no file of its own for the helper to copy, such as rendered templates.
+fn write_privileged(helper: &str, path: &Path, content: &[u8]) -> AmbitResult<()> {
+    let mut child = Command::new(helper)
+        .arg("tee")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(content)?;
+    }
+    if child.wait()?.success() {
+        return Ok(());
+    }
+    Err(AmbitError::Other(format!(
+        "`{} tee {}` failed",
+        helper,
+        path.display()
+    )))
+}
+
+// Return if path (rather than the file it links to) is owned by owner and group,
+// which are either names or numeric ids.
+#[cfg(unix)]
+fn is_owned_by(path: &Path, owner: Option<&str>, group: Option<&str>) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return false,
+    };
+    let uid = |owner: &str| {
+        owner
+            .parse()
+            .ok()
+            .or_else(|| users::get_user_by_name(owner).map(|user| user.uid()))
+    };
+    let gid = |group: &str| {
+        group
+            .parse()
+            .ok()
+            .or_else(|| users::get_group_by_name(group).map(|group| group.gid()))
+    };
+    owner.is_none_or(|owner| uid(owner) == Some(metadata.uid()))
+        && group.is_none_or(|group| gid(group) == Some(metadata.gid()))
+}
+
+// Change the owner and group of path (rather than of the file it links to) with `chown`.
+// Nothing is run if path is owned by them already, as changing the owner usually requires root.
+#[cfg(unix)]
+fn change_owner(
+    path: &Path,
+    owner: Option<&str>,
+    group: Option<&str>,
+    helper: Option<&str>,
+) -> AmbitResult<()> {
+    if is_owned_by(path, owner, group) {
+        return Ok(());
+    }
+    let spec = match group {
+        Some(group) => format!("{}:{}", owner.unwrap_or(""), group),
+        None => owner.unwrap_or("").to_owned(),
+    };
+    let args: Vec<&OsStr> = vec![
+        "chown".as_ref(),
+        "-h".as_ref(),
+        spec.as_ref(),
+        path.as_os_str(),
+    ];
+    let output = Command::new(args[0]).args(&args[1..]).output()?;
+    if output.status.success() {
+        return Ok(());
+    }
+    match helper {
+        Some(helper) => run_privileged(helper, &args),
+        None => Err(AmbitError::Other(format!(
+            "Could not change the owner of `{}`: {}",
+            path.display(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
+// Owners can only be changed on unix.
+#[cfg(not(unix))]
+fn change_owner(
+    _path: &Path,
+    _owner: Option<&str>,
+    _group: Option<&str>,
+    _helper: Option<&str>,
+) -> AmbitResult<()> {
+    Ok(())
+}
+
+// Write content to a new file at path, creating it with perm (or the default permissions) on unix.
+// Decrypted and rendered files can hold secrets, so they are never readable by others before perm is set.
+fn write_new_file(path: &Path, content: &[u8], perm: Option<u32>) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(perm.unwrap_or(0o666));
+    }
+    #[cfg(not(unix))]
+    let _ = perm;
+    options.open(path)?.write_all(content)
+}
+
+// Copy repo_file to a new file at host_file, which is created with perm if it is given.
+fn copy_new_file(repo_file: &Path, host_file: &Path, perm: Option<u32>) -> io::Result<()> {
+    match perm {
+        Some(_) => write_new_file(host_file, &fs::read(repo_file)?, perm),
+        // Without perm, the copy keeps the permissions of the repository file.
+        None => fs::copy(repo_file, host_file).map(|_| ()),
+    }
+}
+
+// Set the permissions of path to the given unix mode.
+#[cfg(unix)]
+fn set_permissions(path: &Path, perm: u32) -> AmbitResult<()> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::set_permissions(path, fs::Permissions::from_mode(perm))?;
+    Ok(())
+}
+
+// Permissions can only be set on unix.
+#[cfg(not(unix))]
+fn set_permissions(_path: &Path, _perm: u32) -> AmbitResult<()> {
+    Ok(())
+}
+
+// Return the unix mode of path, or None if it does not exist.
+#[cfg(unix)]
+fn get_permissions(path: &Path) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    fs::metadata(path)
+        .ok()
+        .map(|metadata| metadata.permissions().mode() & 0o7777)
+}
+
+// Permissions can only be read on unix.
+#[cfg(not(unix))]
+fn get_permissions(_path: &Path) -> Option<u32> {
+    None
+}
+
+// Replace every `$VAR` in path with the value of the environment variable `VAR`.
+fn expand_env_vars(path: &str) -> AmbitResult<String> {
+    expand_vars(path, |name| env::var(name).ok())
+}
+
+// Replace every `$VAR` in path with the value that var returns for `VAR`.
+// The parser has already replaced the variables defined with `let`, so the rest are either environment variables
+// or misspelled `let` variables, which the error has to account for.
+fn expand_vars(path: &str, var: impl Fn(&str) -> Option<String>) -> AmbitResult<String> {
+    let mut unset = None;
+    let expanded = config::substitute_variables(path, |name| {
+        let value = var(name);
+        if value.is_none() && unset.is_none() {
+            unset = Some(name.to_owned());
+        }
+        value
+    });
+    match unset {
+        Some(name) => Err(AmbitError::Other(format!(
+            "Variable `{}` used in `{}` is not defined with `let`, and no environment variable of that name is set",
+            name, path
+        ))),
+        None => Ok(expanded),
+    }
+}
+
+// Expand a leading `~` or `~user` in path to the home directory of the current or given user.
+fn expand_tilde(ambit_paths: &AmbitPaths, path: &str) -> AmbitResult<String> {
+    if !path.starts_with('~') {
+        return Ok(path.to_owned());
+    }
+    let user_end = path.find(std::path::is_separator).unwrap_or(path.len());
+    let user = &path[1..user_end];
+    let home_path = if user.is_empty() {
+        ambit_paths.home.path.clone()
+    } else {
+        get_user_home_path(user)?
+    };
+    Ok(format!("{}{}", home_path.display(), &path[user_end..]))
+}
+
+// Return path as an absolute path, resolving `.` and `..` lexically against the current directory.
+// The current directory has its symlinks resolved, so a path whose parent resolves into a symlinked home
+// directory is given within the home directory, as the host files of the config are.
+fn get_absolute_path(ambit_paths: &AmbitPaths, path: &Path) -> AmbitResult<PathBuf> {
+    let mut absolute_path = PathBuf::new();
+    for component in env::current_dir()?.join(path).components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                absolute_path.pop();
+            }
+            component => absolute_path.push(component),
+        }
+    }
+    let home_path = &ambit_paths.home.path;
+    if absolute_path.starts_with(home_path) {
+        return Ok(absolute_path);
+    }
+    if let (Some(parent), Some(name)) = (absolute_path.parent(), absolute_path.file_name()) {
+        if let (Ok(parent), Ok(real_home_path)) = (parent.canonicalize(), home_path.canonicalize())
+        {
+            if let Ok(relative_path) = parent.strip_prefix(&real_home_path) {
+                return Ok(home_path.join(relative_path).join(name));
+            }
+        }
+    }
+    Ok(absolute_path)
+}
+
+// Return the home directory of the given user, as listed in /etc/passwd.
+#[cfg(unix)]
+fn get_user_home_path(user: &str) -> AmbitResult<PathBuf> {
+    let passwd = fs::read_to_string("/etc/passwd")?;
+    passwd
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.len() > 5 && fields[0] == user)
+        .map(|fields| PathBuf::from(fields[5]))
+        .ok_or_else(|| AmbitError::Other(format!("Could not find home directory of `{}`", user)))
+}
+
+#[cfg(not(unix))]
+fn get_user_home_path(user: &str) -> AmbitResult<PathBuf> {
+    Err(AmbitError::Other(format!(
+        "Could not find home directory of `{}`: `~user` is only supported on unix",
+        user
+    )))
+}
+
+// Expand environment variables in path, as well as a leading `~` if expand_home is true.
+fn expand_path(ambit_paths: &AmbitPaths, path: &str, expand_home: bool) -> AmbitResult<String> {
+    let path = expand_env_vars(path)?;
+    if expand_home {
+        expand_tilde(ambit_paths, &path)
+    } else {
+        Ok(path)
+    }
+}
+
+// Return the index of the `]` that closes the character class opened by the `[` at `open`, if there is one.
+// Escaped characters (e.g. from quoted strings) never open or close a class.
+fn find_class_end(chars: &[char], open: usize) -> Option<usize> {
+    let mut i = open + 1;
+    if chars.get(i) == Some(&'!') {
+        i += 1;
+    }
+    // A class must contain at least one character, so a `]` right away is part of the class.
+    i += 1;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            ']' => return Some(i),
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+// Return if the path component contains pattern matching characters or a character class.
+fn is_pattern(component: &str) -> bool {
+    if component.contains('*') || component.contains('?') {
+        return true;
+    }
+    let chars: Vec<char> = component.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '[' if find_class_end(&chars, i).is_some() => return true,
+            _ => {}
+        }
+        i += 1;
+    }
+    false
+}
+
+// Split path into the components before the first component that is a pattern, and the components after.
+fn split_at_pattern(path: &str) -> (PathBuf, Vec<String>) {
+    let components: Vec<String> = Path::new(path)
+        .components()
+        .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    let first_pattern = components
+        .iter()
+        .position(|comp| is_pattern(comp))
+        .unwrap_or(components.len());
+    (
+        components[..first_pattern]
+            .iter()
+            .map(|comp| unescape_brackets(comp))
+            .collect(),
+        components[first_pattern..].to_vec(),
+    )
+}
+
+// Remove the escapes of literal brackets, for paths that are not matched as patterns.
+fn unescape_brackets(path: &str) -> String {
+    path.replace("\\[", "[").replace("\\]", "]")
+}
+
+// Compile pattern components into a glob that matches paths relative to where the components start.
+// Characters that only have a special meaning to globs are escaped, as the lexer has already removed their escapes.
+fn compile_glob(components: &[String]) -> AmbitResult<GlobMatcher> {
+    let mut glob = String::new();
+    for (i, component) in components.iter().enumerate() {
+        if i > 0 {
+            glob.push('/');
+        }
+        let chars: Vec<char> = component.chars().collect();
+        // The index of the `]` that closes the current character class.
+        let mut class_end = None;
+        let mut j = 0;
+        while j < chars.len() {
+            let c = chars[j];
+            match c {
+                '\\' => {
+                    // Keep escaped characters escaped.
+                    glob.push(c);
+                    j += 1;
+                    if let Some(&escaped) = chars.get(j) {
+                        glob.push(escaped);
+                    }
+                }
+                '[' if class_end.is_none() && find_class_end(&chars, j).is_some() => {
+                    class_end = find_class_end(&chars, j);
+                    glob.push(c);
+                }
+                ']' if class_end == Some(j) => {
+                    class_end = None;
+                    glob.push(c);
+                }
+                '[' | ']' | '{' | '}' | '!' if class_end.is_none() => {
+                    glob.push('\\');
+                    glob.push(c);
+                }
+                _ => glob.push(c),
+            }
+            j += 1;
+        }
+    }
+    let glob = GlobBuilder::new(&glob)
+        // `*` and `?` should not match across directories, only `**` should.
+        .literal_separator(true)
+        .backslash_escape(true)
+        .build()
+        .map_err(|e| AmbitError::Other(format!("Invalid pattern `{}`: {}", glob, e.kind())))?;
+    Ok(glob.compile_matcher())
+}
+
+// A file within a listed directory. Symlinks are followed, as when matching patterns.
+struct DirListingEntry {
+    path: PathBuf,
+    is_dir: bool,
+    is_file: bool,
+    is_symlink: bool,
+}
+
+// Directory listings and compiled globs, which are shared by the patterns resolved during a command.
+// Entries often match within the same directories, which are then only read once.
+// Changes made to the file system after a directory was listed are not seen, so a cache should not outlive a command.
+#[derive(Default)]
+struct DirCache {
+    listings: RefCell<HashMap<PathBuf, Rc<Vec<DirListingEntry>>>>,
+    globs: RefCell<HashMap<Vec<String>, GlobMatcher>>,
+}
+
+impl DirCache {
+    // Return the files within dir, sorted by file name.
+    fn list(&self, dir: &Path) -> AmbitResult<Rc<Vec<DirListingEntry>>> {
+        if let Some(listing) = self.listings.borrow().get(dir) {
+            return Ok(Rc::clone(listing));
+        }
+        let mut listing = Vec::new();
+        for dir_entry in fs::read_dir(dir)? {
+            let path = dir_entry?.path();
+            let is_symlink = fs::symlink_metadata(&path)?.file_type().is_symlink();
+            let file_type = fs::metadata(&path)
+                .map_err(|e| AmbitError::File {
+                    path: path.clone(),
+                    error: e,
+                })?
+                .file_type();
+            listing.push(DirListingEntry {
+                is_dir: file_type.is_dir(),
+                is_file: file_type.is_file(),
+                is_symlink,
+                path,
+            });
+        }
+        listing.sort_by(|a, b| a.path.file_name().cmp(&b.path.file_name()));
+        let listing = Rc::new(listing);
+        self.listings
+            .borrow_mut()
+            .insert(dir.to_path_buf(), Rc::clone(&listing));
+        Ok(listing)
+    }
+
+    // Return the glob of pattern components, compiling it only once.
+    fn glob(&self, components: &[String]) -> AmbitResult<GlobMatcher> {
+        if let Some(glob) = self.globs.borrow().get(components) {
+            return Ok(glob.clone());
+        }
+        let glob = compile_glob(components)?;
+        self.globs
+            .borrow_mut()
+            .insert(components.to_vec(), glob.clone());
+        Ok(glob)
+    }
+
+    // Call visit with every path within dir up to max_depth levels deep, before the paths within it.
+    // ancestors holds the canonical paths of the symlinked directories that are being walked, to detect loops.
+    fn walk(
+        &self,
+        dir: &Path,
+        max_depth: usize,
+        ancestors: &mut Vec<PathBuf>,
+        visit: &mut dyn FnMut(&DirListingEntry) -> AmbitResult<()>,
+    ) -> AmbitResult<()> {
+        for dir_entry in self.list(dir)?.iter() {
+            visit(dir_entry)?;
+            if !dir_entry.is_dir || max_depth <= 1 {
+                continue;
+            }
+            if dir_entry.is_symlink {
+                let target = fs::canonicalize(&dir_entry.path)?;
+                if ancestors.contains(&target) || dir.starts_with(&target) {
+                    return Err(AmbitError::Other(format!(
+                        "File system loop found: {} points to an ancestor {}",
+                        dir_entry.path.display(),
+                        target.display()
+                    )));
+                }
+                ancestors.push(target);
+                self.walk(&dir_entry.path, max_depth - 1, ancestors, visit)?;
+                ancestors.pop();
+            } else {
+                self.walk(&dir_entry.path, max_depth - 1, ancestors, visit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Return a vector of PathBufs that match a pattern relative to the given start_path.
+// Absolute paths are returned as they are rather than relative to start_path.
+// A `**` component matches any number of nested directories.
+// Patterns ending with `/` match directories instead of files.
+fn get_paths_from_pattern(
+    pattern: &str,
+    start_path: &Path,
+    cache: &DirCache,
+) -> AmbitResult<Vec<PathBuf>> {
+    // Components before the first pattern can be matched exactly, so the search starts after them.
+    // (If the pattern is absolute, this also makes the search start from the root.)
+    let (prefix, components) = split_at_pattern(pattern);
+    if components.is_empty() {
+        // The pattern does not contain any pattern matching characters.
+        // This is a definitive path so we can simply return it, without the escapes of literal brackets.
+        // Collecting the components removes a trailing `/`, which symlinks cannot be created with.
+        return Ok(vec![Path::new(&unescape_brackets(pattern))
+            .components()
+            .collect()]);
+    }
+    let search_start = start_path.join(prefix);
+    if !search_start.is_dir() {
+        return Ok(Vec::new());
+    }
+    let glob = cache.glob(&components)?;
+    // Without `**`, matching files cannot be deeper than the number of components.
+    let max_depth = if components.iter().any(|comp| comp == "**") {
+        usize::MAX
+    } else {
+        components.len()
+    };
+    let match_dirs = pattern.ends_with('/');
+    let mut paths = Vec::new();
+    cache.walk(
+        &search_start,
+        max_depth,
+        &mut Vec::new(),
+        &mut |dir_entry| {
+            let is_match_kind = match match_dirs {
+                true => dir_entry.is_dir,
+                false => dir_entry.is_file,
+            };
+            if is_match_kind && glob.is_match(dir_entry.path.strip_prefix(&search_start)?) {
+                paths.push(dir_entry.path.clone());
+            }
+            Ok(())
+        },
+    )?;
+    // Strip prefix from all relative paths.
+    if Path::new(pattern).is_absolute() {
+        return Ok(paths);
+    }
+    paths
+        .iter()
+        .map(|path| Ok(path.strip_prefix(start_path)?.to_path_buf()))
+        .collect()
+}
+
+// A pattern of files to skip, e.g. `!*.swp`.
+struct Exclude {
+    glob: GlobMatcher,
+    // Patterns without a `/` match the file name, like in .gitignore.
+    matches_name: bool,
+}
+
+impl Exclude {
+    fn new(pattern: &str) -> AmbitResult<Self> {
+        let pattern = expand_env_vars(pattern)?;
+        let components: Vec<String> = Path::new(&pattern)
+            .components()
+            .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+            .collect();
+        Ok(Self {
+            glob: compile_glob(&components)?,
+            matches_name: components.len() == 1,
+        })
+    }
+
+    // Return if path, which was found by a pattern that starts matching at prefix, is excluded.
+    fn matches(&self, path: &Path, prefix: &Path) -> bool {
+        if self.matches_name {
+            path.file_name()
+                .map(|name| self.glob.is_match(name))
+                .unwrap_or(false)
+        } else {
+            self.glob
+                .is_match(path.strip_prefix(prefix).unwrap_or(path))
+        }
+    }
+}
+
+// Remove the paths found by pattern that match any of the exclusions.
+fn exclude_paths(paths: Vec<PathBuf>, pattern: &str, excludes: &[Exclude]) -> Vec<PathBuf> {
+    let (prefix, _) = split_at_pattern(pattern);
+    paths
+        .into_iter()
+        .filter(|path| {
+            !excludes
+                .iter()
+                .any(|exclude| exclude.matches(path, &prefix))
+        })
+        .collect()
+}
+
+// If the right side of an entry has the same pattern as the left side, e.g. `nvim/** => .config/nvim/**`,
+// return the right paths that correspond to left_paths, which match the left side.
+// The right paths do not have to exist, so they cannot be found by matching the pattern.
+fn mirror_paths(left: &str, right: &str, left_paths: &[PathBuf]) -> Option<Vec<PathBuf>> {
+    let (left_prefix, left_pattern) = split_at_pattern(left);
+    let (right_prefix, right_pattern) = split_at_pattern(right);
+    if right_pattern.is_empty() || left_pattern != right_pattern {
+        return None;
+    }
+    left_paths
+        .iter()
+        .map(|path| {
+            path.strip_prefix(&left_prefix)
+                .ok()
+                .map(|rest| right_prefix.join(rest))
+        })
+        .collect()
+}
+
+// A part of a pattern, used to find what its wildcards capture.
+enum PatternToken {
+    Literal(char),
+    // `*`, which matches any number of characters within a component.
+    Any,
+    // `?`, which matches a single character within a component.
+    One,
+    // A character class, e.g. `[!a-c]`, with whether it is negated and the ranges of characters it contains.
+    Class(bool, Vec<(char, char)>),
+    // A `**` component, which matches any number of components.
+    Recursive,
+}
+
+// Split pattern components into tokens, with `/` separating the components.
+fn tokenize_pattern(components: &[String]) -> Vec<PatternToken> {
+    let mut tokens = Vec::new();
+    for (i, component) in components.iter().enumerate() {
+        if component == "**" {
+            tokens.push(PatternToken::Recursive);
+            // The separator after `**` is part of what it matches, so that it can match no components.
+            continue;
+        }
+        let chars: Vec<char> = component.chars().collect();
+        let mut j = 0;
+        while j < chars.len() {
+            match chars[j] {
+                '\\' => {
+                    j += 1;
+                    if let Some(&c) = chars.get(j) {
+                        tokens.push(PatternToken::Literal(c));
+                    }
+                }
+                '*' => tokens.push(PatternToken::Any),
+                '?' => tokens.push(PatternToken::One),
+                '[' if find_class_end(&chars, j).is_some() => {
+                    let end = find_class_end(&chars, j).unwrap();
+                    let mut class: Vec<char> = Vec::new();
+                    let mut k = j + 1;
+                    let negated = chars[k] == '!';
+                    if negated {
+                        k += 1;
+                    }
+                    while k < end {
+                        if chars[k] == '\\' {
+                            k += 1;
+                        }
+                        class.push(chars[k]);
+                        k += 1;
+                    }
+                    let mut ranges = Vec::new();
+                    let mut k = 0;
+                    while k < class.len() {
+                        if k + 2 < class.len() && class[k + 1] == '-' {
+                            ranges.push((class[k], class[k + 2]));
+                            k += 3;
+                        } else {
+                            ranges.push((class[k], class[k]));
+                            k += 1;
+                        }
+                    }
+                    tokens.push(PatternToken::Class(negated, ranges));
+                    j = end;
+                }
+                c => tokens.push(PatternToken::Literal(c)),
+            }
+            j += 1;
+        }
+        if i + 1 < components.len() {
+            tokens.push(PatternToken::Literal('/'));
+        }
+    }
+    tokens
+}
+
+// Match path against tokens, pushing what each wildcard matched onto captures.
+fn match_tokens(tokens: &[PatternToken], path: &[char], captures: &mut Vec<String>) -> bool {
+    let (token, rest) = match tokens.split_first() {
+        Some(split) => split,
+        None => return path.is_empty(),
+    };
+    // Try to match rest after capturing the first len characters of path.
+    let try_capture = |len: usize, captures: &mut Vec<String>| {
+        let captured: String = path[..len].iter().collect();
+        captures.push(captured.trim_end_matches('/').to_owned());
+        if match_tokens(rest, &path[len..], captures) {
+            return true;
+        }
+        captures.pop();
+        false
+    };
+    match token {
+        PatternToken::Literal(c) => {
+            path.first() == Some(c) && match_tokens(rest, &path[1..], captures)
+        }
+        PatternToken::One => {
+            matches!(path.first(), Some(&c) if c != '/') && try_capture(1, captures)
+        }
+        PatternToken::Class(negated, ranges) => match path.first() {
+            Some(&c) if c != '/' => {
+                let in_class = ranges.iter().any(|&(start, end)| start <= c && c <= end);
+                in_class != *negated && try_capture(1, captures)
+            }
+            _ => false,
+        },
+        PatternToken::Any => {
+            let component_len = path.iter().position(|&c| c == '/').unwrap_or(path.len());
+            (0..=component_len).any(|len| try_capture(len, captures))
+        }
+        // A trailing `**` matches the rest of the path, while other `**` match whole components.
+        PatternToken::Recursive if rest.is_empty() => try_capture(path.len(), captures),
+        PatternToken::Recursive => (0..=path.len())
+            .filter(|&len| len == 0 || path[len - 1] == '/')
+            .any(|len| try_capture(len, captures)),
+    }
+}
+
+// Return what each wildcard of pattern matched in path, which was found by pattern.
+fn get_captures(pattern: &str, path: &Path) -> Option<Vec<String>> {
+    let (prefix, components) = split_at_pattern(pattern);
+    let rest = path.strip_prefix(&prefix).ok()?;
+    let rest: Vec<char> = rest
+        .components()
+        .map(|comp| comp.as_os_str().to_string_lossy().into_owned())
+        .collect::<Vec<String>>()
+        .join("/")
+        .chars()
+        .collect();
+    let mut captures = Vec::new();
+    match match_tokens(&tokenize_pattern(&components), &rest, &mut captures) {
+        true => Some(captures),
+        false => None,
+    }
+}
+
+// A reference on the right side of an entry to what the left side matched.
+enum CaptureReference {
+    // What the wildcard with the given number matched, e.g. `{1}`.
+    Wildcard(usize),
+    // The file name of the left path, `basename()`.
+    Basename,
+    // The directory of the left path, `dirname()`.
+    Dirname,
+}
+
+// Return the reference to a capture at the start of path along with its length.
+fn parse_capture_reference(path: &str) -> Option<(CaptureReference, usize)> {
+    if path.starts_with("basename()") {
+        return Some((CaptureReference::Basename, "basename()".len()));
+    }
+    if path.starts_with("dirname()") {
+        return Some((CaptureReference::Dirname, "dirname()".len()));
+    }
+    let end = path.strip_prefix('{')?.find('}')? + 1;
+    let digits = &path[1..end];
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((CaptureReference::Wildcard(digits.parse().ok()?), end + 1))
+}
+
+// Return if path references captures, e.g. `.local/bin/{1}`.
+fn has_capture_references(path: &str) -> bool {
+    path.char_indices()
+        .any(|(i, _)| parse_capture_reference(&path[i..]).is_some())
+}
+
+// Replace the references to captures in path with what the left path and its wildcards matched.
+fn substitute_captures(path: &str, left_path: &Path, captures: &[String]) -> AmbitResult<String> {
+    let mut result = String::new();
+    let mut i = 0;
+    while let Some(c) = path[i..].chars().next() {
+        let (reference, len) = match parse_capture_reference(&path[i..]) {
+            Some(reference) => reference,
+            None => {
+                result.push(c);
+                i += c.len_utf8();
+                continue;
+            }
+        };
+        let capture = match reference {
+            // Captures are numbered from 1.
+            CaptureReference::Wildcard(nr) => nr
+                .checked_sub(1)
+                .and_then(|nr| captures.get(nr))
+                .map(String::as_str),
+            CaptureReference::Basename => left_path.file_name().and_then(OsStr::to_str),
+            CaptureReference::Dirname => left_path.parent().and_then(Path::to_str),
+        };
+        match capture {
+            Some(capture) => result.push_str(capture),
+            None => {
+                return Err(AmbitError::Other(format!(
+                    "Capture `{}` does not refer to anything on the left side",
+                    &path[i..i + len]
+                )))
+            }
+        }
+        i += len;
+    }
+    Ok(result)
+}
+
+// Pair up the options of the left and right side of entry, which have the same number of options.
+// A left side with a single option is paired with every option of the right side instead.
+fn get_entry_options(ambit_paths: &AmbitPaths, entry: &Entry) -> Vec<(String, Option<String>)> {
+    let context = ambit_paths.context();
+    let left_options: Vec<String> = entry.left.iter_with(&context).collect();
+    match &entry.right {
+        Some(right) if left_options.len() == 1 => right
+            .iter_with(&context)
+            .map(|right| (left_options[0].clone(), Some(right)))
+            .collect(),
+        Some(right) => left_options
+            .into_iter()
+            .zip(right.iter_with(&context).map(Some))
+            .collect(),
+        None => left_options.into_iter().map(|left| (left, None)).collect(),
+    }
+}
+
+// Return vector over path pairs in the form of `(repo_file, host_file)` from given entry.
+// Files ignored by the dotfile repository are not matched by the left side.
+// Options ending with `/` on either side are paired up as directories rather than files.
+// Tree entries (`=>>`) pair up every file within the directories instead.
+fn get_ambit_paths_from_entry(
+    ambit_paths: &AmbitPaths,
+    entry: &Entry,
+    ignore: &Ignore,
+    cache: &DirCache,
+) -> AmbitResult<Vec<(AmbitPath, AmbitPath)>> {
+    let left_entry_start = if entry.right.is_some() {
+        PathBuf::from(ambit_paths.root.to_str()?)
+    } else {
+        PathBuf::from(ambit_paths.home.to_str()?)
+    };
+    let right_entry_start = PathBuf::from(ambit_paths.home.to_str()?);
+    let excludes = entry
+        .excludes
+        .iter()
+        .map(|exclude| Exclude::new(exclude))
+        .collect::<AmbitResult<Vec<_>>>()?;
+    let mut left_paths = Vec::new();
+    let mut kinds = Vec::new();
+    // The right entry may not exist. If so, treat the left entry as both the repo and host paths.
+    let mut right_paths = entry.right.as_ref().map(|_| Vec::new());
+    // A tree entry is the same as matching `**` within both sides, e.g. `nvim/** => .config/nvim/**`.
+    let to_tree = |path: String| match entry.tree {
+        true => format!("{}/**", path.trim_end_matches('/')),
+        false => path,
+    };
+    for (left, right) in get_entry_options(ambit_paths, entry) {
+        let mut left = to_tree(expand_path(ambit_paths, &left, false)?);
+        let right = right.map(to_tree);
+        let is_dir = left.ends_with('/') || matches!(&right, Some(right) if right.ends_with('/'));
+        if is_dir && !left.ends_with('/') {
+            left.push('/');
+        }
+        let left_option_paths = exclude_paths(
+            get_paths_from_pattern(&left, &left_entry_start, cache)?,
+            &left,
+            &excludes,
+        );
+        let left_option_paths = remove_ignored_paths(ambit_paths, left_option_paths, &left, ignore);
+        if let (Some(right), Some(right_paths)) = (right, right_paths.as_mut()) {
+            let right = expand_path(ambit_paths, &right, true)?;
+            if has_capture_references(&right) {
+                // Each host path is built from what the wildcards matched in the repository path.
+                for path in &left_option_paths {
+                    let captures = get_captures(&left, path).unwrap_or_default();
+                    right_paths.push(PathBuf::from(unescape_brackets(&substitute_captures(
+                        &right, path, &captures,
+                    )?)));
+                }
+            } else {
+                match mirror_paths(&left, &right, &left_option_paths) {
+                    Some(paths) => right_paths.extend(paths),
+                    None => right_paths.extend(exclude_paths(
+                        get_paths_from_pattern(&right, &right_entry_start, cache)?,
+                        &right,
+                        &excludes,
+                    )),
+                }
+            }
+        }
+        let kind = match is_dir {
+            true => AmbitPathKind::Directory,
+            false => AmbitPathKind::File,
+        };
+        kinds.extend(left_option_paths.iter().map(|_| kind));
+        left_paths.extend(left_option_paths);
+    }
+    // The number of left and right paths may be different due to pattern matching.
+    // An error is thrown if they have different sizes.
+    if let Some(right_paths) = &right_paths {
+        if left_paths.len() != right_paths.len() {
+            // Format the vector of PathBuf as a string delimited by a newline.
+            let format_paths = |paths: &Vec<PathBuf>| {
+                paths
+                    .iter()
+                    .map(|path| path.as_path().display().to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            };
+            return Err(AmbitError::Other(format!(
+                "Entry has imbalanced left and right side due to pattern matching\nAttempted to sync:\n{}\nwith:\n{}",
+                format_paths(&left_paths), format_paths(right_paths),
+            )));
+        }
+    }
+    let mut paths = Vec::new();
+    for (i, repo_path) in left_paths.iter().enumerate() {
+        let host_path = if let Some(ref right_paths) = right_paths {
+            &right_paths[i]
+        } else {
+            repo_path
+        };
+        paths.push((
+            AmbitPath::new(ambit_paths.root.path.join(repo_path), kinds[i]),
+            AmbitPath::new(ambit_paths.home.path.join(host_path), kinds[i]),
+        ))
+    }
+    Ok(paths)
+}
+
+// Return the repository and host directories of each option of a tree entry (`=>>`).
+fn get_tree_dirs(ambit_paths: &AmbitPaths, entry: &Entry) -> AmbitResult<Vec<(PathBuf, PathBuf)>> {
+    if !entry.tree {
+        return Ok(Vec::new());
+    }
+    get_entry_options(ambit_paths, entry)
+        .into_iter()
+        .filter_map(|(left, right)| Some((left, right?)))
+        .map(|(left, right)| {
+            let left = expand_path(ambit_paths, &left, false)?;
+            let right = expand_path(ambit_paths, &right, true)?;
+            Ok((
+                ambit_paths.root.path.join(left.trim_end_matches('/')),
+                ambit_paths.home.path.join(right.trim_end_matches('/')),
+            ))
+        })
+        .collect()
+}
+
+// Return the directories of entry that are folded, i.e. the host directory is a symlink to the repository directory.
+fn get_folded_dirs(
+    ambit_paths: &AmbitPaths,
+    entry: &Entry,
+) -> AmbitResult<Vec<(PathBuf, PathBuf)>> {
+    if !entry.attributes.fold {
+        return Ok(Vec::new());
+    }
+    Ok(get_tree_dirs(ambit_paths, entry)?
+        .into_iter()
+        .filter(|(repo_dir, host_dir)| is_symlinked(host_dir, repo_dir))
+        .collect())
+}
+
+// Return if host_dir can be replaced by a symlink to repo_dir, where paths are the files synced between them.
+// This requires every file in repo_dir to be synced and every file in host_dir to come from repo_dir.
+fn can_fold(
+    repo_dir: &Path,
+    host_dir: &Path,
+    paths: &[(AmbitPath, AmbitPath)],
+    other_host_paths: &[&Path],
+) -> bool {
+    // Files that are excluded or ignored would become visible in the host directory.
+    let repo_files = WalkDir::new(repo_dir)
+        .follow_links(true)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|dir_entry| dir_entry.file_type().is_file())
+        .count();
+    if repo_files != paths.len() {
+        return false;
+    }
+    // Files synced by other entries would end up in the repository directory.
+    if other_host_paths
+        .iter()
+        .any(|path| path.starts_with(host_dir))
+    {
+        return false;
+    }
+    if fs::symlink_metadata(host_dir).is_err() || is_symlinked(host_dir, repo_dir) {
+        return true;
+    }
+    // An unfolded host directory can only be folded if it does not contain foreign files.
+    WalkDir::new(host_dir)
+        .into_iter()
+        .filter_map(Result::ok)
+        .filter(|dir_entry| !dir_entry.file_type().is_dir())
+        .all(|dir_entry| match dir_entry.path().strip_prefix(host_dir) {
+            Ok(rest) => is_symlinked(dir_entry.path(), &repo_dir.join(rest)),
+            Err(_) => false,
+        })
+}
+
+// Fold or unfold the directories of a tree entry with the `fold` attribute, returning the paths to sync.
+// A directory is folded into a single symlink if all of its files come from the repository,
+// and unfolded into symlinks per file once a foreign file appears.
+// Nothing is changed on a dry run, so the paths are returned as they are currently synced.
+fn fold_tree_paths(
+    ambit_paths: &AmbitPaths,
+    entry: &Entry,
+    mut paths: Vec<(AmbitPath, AmbitPath)>,
+    other_host_paths: &[&Path],
+    dry_run: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<Vec<(AmbitPath, AmbitPath)>> {
+    if !entry.attributes.fold || entry.attributes.mode != LinkMode::Symlink {
+        return Ok(paths);
+    }
+    let mut tree_paths = Vec::new();
+    for (repo_dir, host_dir) in get_tree_dirs(ambit_paths, entry)? {
+        let (dir_paths, rest): (Vec<_>, Vec<_>) = paths
+            .into_iter()
+            .partition(|(repo_file, _)| repo_file.path.starts_with(&repo_dir));
+        paths = rest;
+        let is_folded = is_symlinked(&host_dir, &repo_dir);
+        let fold = if dry_run {
+            is_folded
+        } else {
+            can_fold(&repo_dir, &host_dir, &dir_paths, other_host_paths)
+        };
+        let host = AmbitPath::new(host_dir, AmbitPathKind::Directory);
+        if fold {
+            if !dry_run && !is_folded && fs::symlink_metadata(&host.path).is_ok() {
+                // The host directory only contains symlinks to the repository, which are replaced by one.
+                host.remove()?;
+            }
+            tree_paths.push((AmbitPath::new(repo_dir, AmbitPathKind::Directory), host));
+        } else {
+            if is_folded {
+                host.remove()?;
+                reporter.report(Event::Unfolded(&host.path));
+            }
+            tree_paths.extend(dir_paths);
+        }
+    }
+    tree_paths.extend(paths);
+    Ok(tree_paths)
+}
+
+// Read the ignore file of the dotfile repository.
+fn get_repo_ignore(ambit_paths: &AmbitPaths) -> AmbitResult<Ignore> {
+    Ignore::load(&ambit_paths.repo.path.join(IGNORE_NAME))
+}
+
+// Remove the paths found by pattern that are ignored by the dotfile repository.
+// Paths that are given explicitly rather than found through wildcards are never ignored.
+fn remove_ignored_paths(
+    ambit_paths: &AmbitPaths,
+    paths: Vec<PathBuf>,
+    pattern: &str,
+    ignore: &Ignore,
+) -> Vec<PathBuf> {
+    if split_at_pattern(pattern).1.is_empty() {
+        return paths;
+    }
+    paths
+        .into_iter()
+        .filter(|path| {
+            let repo_path = path.strip_prefix(&ambit_paths.repo.path).unwrap_or(path);
+            !ignore.is_ignored(repo_path, false)
+        })
+        .collect()
+}
+
+// Recursively search dotfile repository for config path, skipping ignored paths.
+fn get_repo_config_paths(
+    ambit_paths: &AmbitPaths,
+    stop_at_first_found: bool,
+    ignore: &Ignore,
+) -> Vec<PathBuf> {
+    let mut repo_config_paths = Vec::new();
+    let walker = WalkDir::new(&ambit_paths.repo.path)
+        .into_iter()
+        .filter_entry(|dir_entry| {
+            let path = dir_entry.path();
+            let repo_path = path.strip_prefix(&ambit_paths.repo.path).unwrap_or(path);
+            !ignore.is_ignored(repo_path, dir_entry.file_type().is_dir())
+        })
+        .flatten();
+    for dir_entry in walker {
+        let path = dir_entry.path();
+        if let Some(file_name) = path.file_name() {
+            if file_name == CONFIG_NAME {
+                repo_config_paths.push(path.to_path_buf());
+                if stop_at_first_found {
+                    break;
+                }
+            }
+        }
+    }
+    repo_config_paths
+}
+
+/// Initialize an empty dotfile repository
+pub fn init(ambit_paths: &AmbitPaths, force: bool, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    ensure_paths_exist(ambit_paths, force)?;
+    ambit_paths.repo.create()?;
+    // Initialize an empty git repository
+    if let Some(message) = git::init(ambit_paths)? {
+        reporter.report(Event::Message(&message));
+    }
+    Ok(())
+}
+
+/// Clone an existing dotfile repository with given origin
+/// If sparse is set, only the files used by the configuration are checked out.
+pub fn clone(
+    ambit_paths: &AmbitPaths,
+    force: bool,
+    sparse: bool,
+    arguments: Vec<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_paths_exist(ambit_paths, force)?;
+    git::clone(ambit_paths, &arguments)?;
+    // The patterns are set after cloning, as the configuration may be in the repository.
+    if sparse {
+        set_sparse_patterns(ambit_paths, reporter)?;
+    }
+    git::update_submodules(ambit_paths)?;
+    reporter.report(Event::Message(&format!(
+        "Successfully cloned repository to {}",
+        ambit_paths.repo.path.display()
+    )));
+    Ok(())
+}
+
+/// Clone an existing dotfile repository and sync it without asking for confirmation, for setting up new machines.
+/// The first configuration found in the repository is used, unless the configuration in the default location has content.
+pub fn bootstrap(
+    ambit_paths: &AmbitPaths,
+    force: bool,
+    allow_outside_home: bool,
+    tags: &[&str],
+    reporter: &mut dyn Reporter,
+    privilege_helper: Option<&str>,
+    arguments: Vec<&str>,
+) -> AmbitResult<()> {
+    clone(ambit_paths, force, false, arguments, reporter)?;
+    let use_repo_config = fs::read_to_string(&ambit_paths.config.path)?
+        .trim()
+        .is_empty();
+    let options = SyncOptions {
+        use_repo_config,
+        use_repo_config_if_required: true,
+        use_any_repo_config: true,
+        allow_outside_home,
+        tags,
+        privilege_helper,
+        ..SyncOptions::default()
+    };
+    sync(ambit_paths, &options, reporter)?;
+    Ok(())
+}
+
+// Return diagnostics for problems with entries that would otherwise only be found while syncing.
+// host_paths holds the expanded host files of each entry, and sources where each entry is defined.
+fn get_entry_diagnostics(
+    entries: &[&Entry],
+    sources: &[EntrySource],
+    host_paths: &[Vec<PathBuf>],
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (entry_nr, entry) in entries.iter().enumerate() {
+        let specs = std::iter::once(&entry.left).chain(entry.right.as_ref());
+        for match_expr in specs.flat_map(Spec::match_exprs) {
+            for (case, reason) in match_expr.unreachable_cases() {
+                let reason = match reason {
+                    UnreachableCase::AfterDefault => "it follows a `default` case",
+                    UnreachableCase::Duplicate => "an earlier case has the same condition",
+                };
+                diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    kind: "unreachable-case",
+                    message: format!(
+                        "Match case {} in entry {} can never be chosen because {}",
+                        case + 1,
+                        entry_nr + 1,
+                        reason
+                    ),
+                    file: Some(sources[entry_nr].path.clone()),
+                    line: Some(sources[entry_nr].line),
+                    column: Some(sources[entry_nr].column),
+                });
+            }
+        }
+    }
+    diagnostics.extend(get_duplicate_host_path_diagnostics(
+        entries, sources, host_paths,
+    ));
+    diagnostics
+}
+
+// Return an error for each host file that is produced by more than one entry, listing where those entries are defined.
+// The host files of secrets are not shown, and neither are the entries that produce them.
+fn get_duplicate_host_path_diagnostics(
+    entries: &[&Entry],
+    sources: &[EntrySource],
+    host_paths: &[Vec<PathBuf>],
+) -> Vec<Diagnostic> {
+    let mut host_entries: BTreeMap<&Path, Vec<usize>> = BTreeMap::new();
+    for (i, paths) in host_paths.iter().enumerate() {
+        for path in paths {
+            let entries = host_entries.entry(path).or_default();
+            // An entry may produce the same host file through several options, which only fails once it is synced.
+            if !entries.contains(&i) {
+                entries.push(i);
+            }
+        }
+    }
+    host_entries
+        .into_iter()
+        .filter(|(_, host_entries)| host_entries.len() > 1)
+        .map(|(host_path, host_entries)| {
+            let secret = host_entries.iter().any(|&i| entries[i].attributes.secret);
+            // The diagnostic points at the first entry that produces the file again.
+            let duplicate = &sources[host_entries[1]];
+            let mut message = format!(
+                "Host file `{}` is produced by more than one entry:",
+                display_path(host_path, secret)
+            );
+            for i in host_entries {
+                let source = &sources[i];
+                message.push_str(&format!("\n    {}:{}", source.path.display(), source.line));
+                // The entries of other files would give away the hidden host file.
+                if !secret {
+                    message.push_str(&format!(": {}", source.text));
+                }
+            }
+            Diagnostic {
+                severity: Severity::Error,
+                kind: "duplicate-host-path",
+                message,
+                file: Some(duplicate.path.clone()),
+                line: Some(duplicate.line),
+                column: Some(duplicate.column),
+            }
+        })
+        .collect()
+}
+
+// Return the error of expanding the environment variables of entry, as `list` and `sync` would fail with.
+fn get_variable_error(ambit_paths: &AmbitPaths, entry: &Entry) -> Option<AmbitError> {
+    get_entry_options(ambit_paths, entry)
+        .into_iter()
+        .find_map(|(left, right)| {
+            expand_path(ambit_paths, &left, false)
+                .and_then(|_| {
+                    right.map_or(Ok(String::new()), |right| {
+                        expand_path(ambit_paths, &right, true)
+                    })
+                })
+                .err()
+        })
+}
+
+// Return an error for each entry that references an environment variable that is not set.
+fn get_variable_diagnostics(
+    ambit_paths: &AmbitPaths,
+    entries: &[Entry],
+    sources: &[EntrySource],
+) -> Vec<Diagnostic> {
+    entries
+        .iter()
+        .zip(sources)
+        .filter_map(|(entry, source)| {
+            let error = get_variable_error(ambit_paths, entry)?;
+            Some(Diagnostic {
+                severity: Severity::Error,
+                kind: "unset-variable",
+                message: error.to_string(),
+                file: Some(source.path.clone()),
+                line: Some(source.line),
+                column: Some(source.column),
+            })
+        })
+        .collect()
+}
+
+// Return diagnostics for repository files referenced by entries that do not exist.
+// Entries with unset environment variables are skipped, as they are reported regardless.
+fn get_repo_file_diagnostics(
+    ambit_paths: &AmbitPaths,
+    entries: &[Entry],
+    sources: &[EntrySource],
+    ignore: &Ignore,
+    cache: &DirCache,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (entry_nr, (entry, source)) in entries.iter().zip(sources).enumerate() {
+        match get_ambit_paths_from_entry(ambit_paths, entry, ignore, cache) {
+            Ok(paths) => {
+                for (repo_file, _) in paths {
+                    if !repo_file.exists() {
+                        diagnostics.push(Diagnostic {
+                            severity: Severity::Error,
+                            kind: "missing-repo-file",
+                            message: format!(
+                                "Repository file `{}` does not exist",
+                                display_path(&repo_file.path, entry.attributes.secret)
+                            ),
+                            file: Some(source.path.clone()),
+                            line: Some(source.line),
+                            column: Some(source.column),
+                        });
+                    }
+                }
+            }
+            Err(_) if get_variable_error(ambit_paths, entry).is_some() => {}
+            Err(e) => diagnostics.push(Diagnostic {
+                severity: Severity::Error,
+                kind: "unresolved-entry",
+                message: format!("Could not resolve entry {}: {}", entry_nr + 1, e),
+                file: Some(source.path.clone()),
+                line: Some(source.line),
+                column: Some(source.column),
+            }),
+        }
+    }
+    diagnostics
+}
+
+// Return warnings for synced files whose permissions differ from the `perm` attribute of their entry.
+// Entries that cannot be resolved are skipped, as they are reported with `--strict`.
+fn get_permission_diagnostics(
+    ambit_paths: &AmbitPaths,
+    entries: &[Entry],
+    sources: &[EntrySource],
+    ignore: &Ignore,
+    cache: &DirCache,
+) -> Vec<Diagnostic> {
+    let mut diagnostics = Vec::new();
+    for (entry, source) in entries.iter().zip(sources) {
+        let perm = match entry.attributes.perm {
+            Some(perm) => perm,
+            None => continue,
+        };
+        let paths = match get_ambit_paths_from_entry(ambit_paths, entry, ignore, cache) {
+            Ok(paths) => paths,
+            Err(_) => continue,
+        };
+        for (repo_file, host_file) in paths {
+            // Permissions are set on the file linked to, or on the copy.
+            let path = match get_link_mode(&repo_file.path, entry.attributes.mode) {
+                LinkMode::Symlink => repo_file.path,
+                LinkMode::Copy => host_file.path,
+            };
+            match get_permissions(&path) {
+                Some(actual) if actual != perm => diagnostics.push(Diagnostic {
+                    severity: Severity::Warning,
+                    kind: "permission-drift",
+                    message: format!(
+                        "`{}` has permissions {:o} instead of {:o}",
+                        display_path(&path, entry.attributes.secret),
+                        actual,
+                        perm
+                    ),
+                    file: Some(source.path.clone()),
+                    line: Some(source.line),
+                    column: Some(source.column),
+                }),
+                _ => {}
+            }
+        }
+    }
+    diagnostics
+}
+
+// Return the diagnostics of the configuration, failing if it cannot be read.
+fn get_check_diagnostics(ambit_paths: &AmbitPaths, strict: bool) -> AmbitResult<Vec<Diagnostic>> {
+    let Config {
+        entries, sources, ..
+    } = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    // Entries whose paths cannot be expanded produce no host files, as they are reported separately:
+    // unset environment variables always, and other errors with `--strict`.
+    let host_paths: Vec<Vec<PathBuf>> = entries
+        .iter()
+        .map(|entry| {
+            get_ambit_paths_from_entry(ambit_paths, entry, &ignore, &cache)
+                .map(|paths| {
+                    paths
+                        .into_iter()
+                        .map(|(_, host_file)| host_file.path)
+                        .collect()
+                })
+                .unwrap_or_default()
+        })
+        .collect();
+    let mut diagnostics =
+        get_entry_diagnostics(&entries.iter().collect::<Vec<_>>(), &sources, &host_paths);
+    diagnostics.extend(get_variable_diagnostics(ambit_paths, &entries, &sources));
+    diagnostics.extend(get_permission_diagnostics(
+        ambit_paths,
+        &entries,
+        &sources,
+        &ignore,
+        &cache,
+    ));
+    if strict {
+        diagnostics.extend(get_repo_file_diagnostics(
+            ambit_paths,
+            &entries,
+            &sources,
+            &ignore,
+            &cache,
+        ));
+    }
+    Ok(diagnostics)
+}
+
+/// Check ambit configuration for errors
+/// If strict is true, the repository files referenced by the configuration must also exist.
+pub fn check(
+    ambit_paths: &AmbitPaths,
+    format: Format,
+    strict: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let diagnostics = match get_check_diagnostics(ambit_paths, strict) {
+        Ok(diagnostics) => diagnostics,
+        // Errors are rendered as usual unless they are reported as JSON.
+        Err(e) if format == Format::Json => Diagnostic::from_error(e, &ambit_paths.config.path),
+        Err(e) => return Err(e),
+    };
+    let has_errors = diagnostics
+        .iter()
+        .any(|diagnostic| diagnostic.severity == Severity::Error);
+    if format == Format::Json {
+        reporter.report(Event::Message(&diagnostic::to_json(&diagnostics)));
+        if has_errors {
+            return Err(AmbitError::Other(
+                "Configuration contains errors".to_owned(),
+            ));
+        }
+    } else {
+        // Warnings do not cause check to fail.
+        for warning in diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+        {
+            reporter.report(Event::Warning(&warning.to_string()));
+        }
+        if has_errors {
+            return Err(AmbitError::Other(
+                diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                    .map(Diagnostic::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Keep only the entries that have at least one of `tags`.
+// If no tags are given, all entries are kept.
+fn filter_entries_by_tags(entries: Vec<Entry>, tags: &[&str]) -> Vec<Entry> {
+    entries
+        .into_iter()
+        .filter(|entry| has_any_tag(entry, tags))
+        .collect()
+}
+
+/// Selects entries by their number in the configuration, counted from 1, as given to `--entries`.
+/// Numbers and ranges are separated by commas, e.g. `2,5-7`.
+#[derive(PartialEq, Eq, Debug)]
+pub struct EntrySelection {
+    ranges: Vec<RangeInclusive<usize>>,
+}
+
+impl EntrySelection {
+    /// Parse a selection such as `2,5-7`. Entries are numbered from 1.
+    pub fn parse(spec: &str) -> AmbitResult<Self> {
+        let invalid = || AmbitError::Other(format!("Invalid entry selection `{}`", spec));
+        let parse_number = |number: &str| match number.trim().parse::<usize>() {
+            Ok(number) if number > 0 => Ok(number),
+            _ => Err(invalid()),
+        };
+        let ranges = spec
+            .split(',')
+            .map(|part| {
+                let range = match part.split_once('-') {
+                    Some((start, end)) => parse_number(start)?..=parse_number(end)?,
+                    None => parse_number(part).map(|number| number..=number)?,
+                };
+                match range.is_empty() {
+                    true => Err(invalid()),
+                    false => Ok(range),
+                }
+            })
+            .collect::<AmbitResult<_>>()?;
+        Ok(Self { ranges })
+    }
+
+    fn contains(&self, number: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&number))
+    }
+
+    // Return the items of the selected entries, given the items of all entries in order.
+    fn select<T>(&self, items: Vec<T>) -> AmbitResult<Vec<T>> {
+        if let Some(number) = self
+            .ranges
+            .iter()
+            .map(|range| *range.end())
+            .find(|number| *number > items.len())
+        {
+            return Err(AmbitError::Other(format!(
+                "Entry {} does not exist, as the configuration has {} entries",
+                number,
+                items.len()
+            )));
+        }
+        Ok(items
+            .into_iter()
+            .enumerate()
+            .filter(|(i, _)| self.contains(i + 1))
+            .map(|(_, item)| item)
+            .collect())
+    }
+}
+
+// Return the items of the entries in selection, or all of them if there is none.
+fn select_entries<T>(items: Vec<T>, selection: Option<&EntrySelection>) -> AmbitResult<Vec<T>> {
+    match selection {
+        Some(selection) => selection.select(items),
+        None => Ok(items),
+    }
+}
+
+/// Selects host files by a glob relative to the home directory, as given to `--only`.
+pub struct HostFilter {
+    glob: GlobMatcher,
+}
+
+impl HostFilter {
+    /// Compile glob, failing if it is not a valid glob.
+    pub fn new(glob: &str) -> AmbitResult<Self> {
+        let glob = GlobBuilder::new(glob)
+            .literal_separator(true)
+            .backslash_escape(true)
+            .build()
+            .map_err(|e| AmbitError::Other(format!("Invalid glob `{}`: {}", glob, e.kind())))?;
+        Ok(Self {
+            glob: glob.compile_matcher(),
+        })
+    }
+
+    // Host files are matched relative to home.
+    fn is_match(&self, home: &Path, host_file: &Path) -> bool {
+        let path = host_file.strip_prefix(home).unwrap_or(host_file);
+        self.glob.is_match(path)
+    }
+
+    // A folded directory is selected if the glob matches the directory or any of the host files within it.
+    fn is_match_within(&self, home: &Path, host_file: &Path, host_files: &[PathBuf]) -> bool {
+        self.is_match(home, host_file)
+            || host_files
+                .iter()
+                .any(|path| path.starts_with(host_file) && self.is_match(home, path))
+    }
+}
+
+// Return if entry has one of tags, or if no tags are given.
+fn has_any_tag(entry: &Entry, tags: &[&str]) -> bool {
+    tags.is_empty() || entry.tags.iter().any(|tag| tags.contains(&tag.as_str()))
+}
+
+// Return if error means that symlinks cannot be created at all, rather than just the one that was attempted.
+// FAT filesystems refuse them with EPERM, other filesystems with EOPNOTSUPP,
+// and Windows with ERROR_PRIVILEGE_NOT_HELD unless Developer Mode is enabled.
+fn is_symlink_unsupported(error: &AmbitError) -> bool {
+    match error {
+        AmbitError::Io(e) if e.kind() == io::ErrorKind::Unsupported => true,
+        AmbitError::Io(e) if cfg!(unix) => e.raw_os_error() == Some(1),
+        AmbitError::Io(e) if cfg!(windows) => e.raw_os_error() == Some(1314),
+        _ => false,
+    }
+}
+
+// Symlink host_file to repo_file, or write a copy of repo_file to host_file,
+// then set its permissions and owner as attributes say.
+fn link_file(
+    ambit_paths: &AmbitPaths,
+    repo_file: &Path,
+    host_file: &Path,
+    mode: LinkMode,
+    attributes: &Attributes,
+    vars: &HashMap<String, String>,
+    privilege_helper: Option<&str>,
+) -> AmbitResult<()> {
+    match mode {
+        LinkMode::Symlink if repo_file.is_dir() => symlink_dir(repo_file, host_file)?,
+        LinkMode::Symlink => symlink_file(repo_file, host_file)?,
+        LinkMode::Copy => {
+            let perm = attributes.perm.or_else(|| {
+                (Encryption::from_path(repo_file).is_some() || attributes.secret).then_some(0o600)
+            });
+            write_new_file(
+                host_file,
+                &read_repo_file(ambit_paths, repo_file, vars)?,
+                perm,
+            )?
+        }
+    }
+    apply_attributes(host_file, repo_file, mode, attributes, privilege_helper)
+}
+
+// Set the permissions and owner of the synced host_file as attributes say.
+fn apply_attributes(
+    host_file: &Path,
+    repo_file: &Path,
+    mode: LinkMode,
+    attributes: &Attributes,
+    privilege_helper: Option<&str>,
+) -> AmbitResult<()> {
+    if let Some(perm) = attributes.perm {
+        // Symlinks do not have permissions of their own, so they are set on the file linked to.
+        let path = match mode {
+            LinkMode::Symlink => repo_file,
+            LinkMode::Copy => host_file,
+        };
+        let mode = format!("{:o}", perm);
+        escalate(
+            set_permissions(path, perm),
+            privilege_helper,
+            &["chmod".as_ref(), mode.as_ref(), path.as_os_str()],
+        )?;
+    }
+    if attributes.owner.is_some() || attributes.group.is_some() {
+        change_owner(
+            host_file,
+            attributes.owner.as_deref(),
+            attributes.group.as_deref(),
+            privilege_helper,
+        )?;
+    }
+    Ok(())
+}
+
+// Return the attributes of the entries of config by the host files they sync,
+// so that files synced outside of `ambit sync`, e.g. by undo, get the attributes of their entry.
+fn get_attributes_by_host_file(
+    ambit_paths: &AmbitPaths,
+    config: &Config,
+) -> AmbitResult<HashMap<PathBuf, Attributes>> {
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut attributes = HashMap::new();
+    for entry in &config.entries {
+        for (_, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            attributes
+                .entry(host_file.path)
+                .or_insert_with(|| entry.attributes.clone());
+        }
+    }
+    Ok(attributes)
+}
+
+// Return the ancestors of dir that do not exist, starting with the outermost one.
+fn get_missing_dirs(dir: &Path) -> Vec<PathBuf> {
+    let mut missing_dirs: Vec<PathBuf> = dir
+        .ancestors()
+        .take_while(|dir| !dir.exists())
+        .map(Path::to_path_buf)
+        .collect();
+    missing_dirs.reverse();
+    missing_dirs
+}
+
+// Undo changes in the reverse order they were made in, keeping the manifest up to date.
+// Changes that cannot be undone are reported, and the rest are still undone.
+// Returns the number of changes that could not be undone.
+fn undo_changes(
+    ambit_paths: &AmbitPaths,
+    changes: Vec<Change>,
+    manifest: &mut Manifest,
+    attributes: &HashMap<PathBuf, Attributes>,
+    vars: &HashMap<String, String>,
+    reporter: &mut dyn Reporter,
+    privilege_helper: Option<&str>,
+) -> usize {
+    let mut failures: usize = 0;
+    for change in changes.into_iter().rev() {
+        let (result, path) = match change {
+            Change::CreatedDir(dir) => {
+                let result = escalate(
+                    fs::remove_dir(&dir).map_err(AmbitError::Io),
+                    privilege_helper,
+                    &["rmdir".as_ref(), dir.as_os_str()],
+                );
+                (result, dir)
+            }
+            // Host files that were changed since they were synced are left alone.
+            Change::Linked {
+                host_file,
+                repo_file,
+                mode,
+            } => {
+                let result = match is_synced(ambit_paths, &host_file, &repo_file, mode, vars) {
+                    true => {
+                        let kind = match repo_file.is_dir() && mode == LinkMode::Symlink {
+                            true => AmbitPathKind::Directory,
+                            false => AmbitPathKind::File,
+                        };
+                        escalate(
+                            AmbitPath::new(host_file.clone(), kind).remove(),
+                            privilege_helper,
+                            &["rm".as_ref(), "-f".as_ref(), host_file.as_os_str()],
+                        )
+                    }
+                    false => Err(AmbitError::Other(
+                        "Host file was changed since it was synced".to_owned(),
+                    )),
+                };
+                if result.is_ok() {
+                    manifest.remove(&host_file);
+                }
+                (result, host_file)
+            }
+            Change::Unlinked {
+                host_file,
+                repo_file,
+                mode,
+            } => {
+                // Files of entries that are no longer in the config are restored without attributes.
+                let default_attributes = Attributes::default();
+                let attributes = attributes.get(&host_file).unwrap_or(&default_attributes);
+                let result = match fs::symlink_metadata(&host_file) {
+                    Ok(_) => Err(AmbitError::Other("Host file already exists".to_owned())),
+                    Err(_) => link_file(
+                        ambit_paths,
+                        &repo_file,
+                        &host_file,
+                        mode,
+                        attributes,
+                        vars,
+                        privilege_helper,
+                    ),
+                };
+                if result.is_ok() {
+                    manifest.insert(&host_file, &repo_file, mode);
+                }
+                (result, host_file)
+            }
+            // Encrypted files were encrypted on their way into the repository, so they are decrypted again.
+            Change::Moved(host_file, repo_file) => {
+                let result = match Encryption::from_path(&repo_file) {
+                    // The permissions of the host file are unknown, so the decrypted file is kept private.
+                    Some(encryption) => encryption
+                        .decrypt(&repo_file, &ambit_paths.age_identity.path)
+                        .and_then(|content| Ok(write_new_file(&host_file, &content, Some(0o600))?))
+                        .and_then(|_| Ok(fs::remove_file(&repo_file)?)),
+                    None => move_path(&repo_file, &host_file),
+                };
+                (result, host_file)
+            }
+        };
+        if let Err(e) = result {
+            reporter.report(Event::Warning(&format!(
+                "Could not undo change to {}: {}",
+                path.display(),
+                e
+            )));
+            failures += 1;
+        }
+    }
+    failures
+}
+
+// Record the changes made by the command name in the journal, so that they can be undone.
+fn record_operation(ambit_paths: &AmbitPaths, name: &str, changes: Vec<Change>) -> AmbitResult<()> {
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let mut journal = Journal::load(&ambit_paths.journal.path)?;
+    journal.push(Operation {
+        name: name.to_owned(),
+        changes,
+    });
+    journal.save(&ambit_paths.journal.path)
+}
+
+// Return a hash of entry and the variables of its config, which changes whenever the config changes how it is synced.
+// Hashes are only compared with those of earlier syncs, so they may differ between versions of ambit.
+fn hash_entry(entry: &Entry, vars: &HashMap<String, String>) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    format!("{:?}", entry).hash(&mut hasher);
+    vars.iter().collect::<BTreeMap<_, _>>().hash(&mut hasher);
+    hasher.finish()
+}
+
+// Return where host_file is backed up to within backup_dir, keeping its path relative to home.
+// Host files outside of home keep their absolute path instead.
+fn get_backup_path(backup_dir: &Path, home: &Path, host_file: &Path) -> PathBuf {
+    let relative_path = host_file.strip_prefix(home).unwrap_or(host_file);
+    let relative_path: PathBuf = relative_path
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect();
+    backup_dir.join(relative_path)
+}
+
+/// How `ambit sync` syncs the files of the config. The default syncs every entry of the config once,
+/// without moving, replacing or committing any files.
+#[derive(Default)]
+pub struct SyncOptions<'a> {
+    /// Only report what would be synced.
+    pub dry_run: bool,
+    /// Move host files into the repository if the repository file does not exist yet.
+    pub move_files: bool,
+    // Look for a config within the repository, asking which one to use.
+    pub use_repo_config: bool,
+    // Look for a config within the repository if there is none, without asking first.
+    pub use_repo_config_if_required: bool,
+    // Use the first config found within the repository without asking.
+    pub use_any_repo_config: bool,
+    pub allow_outside_home: bool,
+    /// Keep the files synced before an error, rather than rolling them back.
+    pub keep_partial: bool,
+    /// Skip the files of entries that did not change since the last sync.
+    pub incremental: bool,
+    /// Replace host files that have the same content as their repository file.
+    pub replace_identical: bool,
+    /// Move conflicting host files to this directory before syncing.
+    pub backup_dir: Option<&'a Path>,
+    /// Replace conflicting host files.
+    pub overwrite: bool,
+    /// Ask before replacing each conflicting host file.
+    pub interactive: bool,
+    /// Copy files that cannot be symlinked.
+    pub copy_fallback: bool,
+    pub auto_commit: AutoCommit,
+    pub commit_message: Option<&'a str>,
+    /// Only sync entries with any of these tags.
+    pub tags: &'a [&'a str],
+    pub selection: Option<&'a EntrySelection>,
+    pub only: Option<&'a HostFilter>,
+    /// Command that steps which are not permitted are retried with, e.g. `sudo`.
+    pub privilege_helper: Option<&'a str>,
+}
+
+// Return why repo_file cannot be synced with mode, if it cannot. moving_secret is set if a secret host file is
+// moved into the repository.
+fn get_sync_refusal(
+    repo_file: &AmbitPath,
+    mode: LinkMode,
+    moving_secret: bool,
+    auto_commit: AutoCommit,
+) -> Option<&'static str> {
+    let encryption = Encryption::from_path(&repo_file.path);
+    if repo_file.is_dir() && mode == LinkMode::Copy {
+        Some("Directories can only be symlinked")
+    } else if encryption.is_some() && mode != LinkMode::Copy {
+        Some("Encrypted files can only be copied")
+    } else if moving_secret && encryption.is_none() && auto_commit != AutoCommit::Off {
+        // Secrets that are moved into the repository in plain text would be published by committing them.
+        Some("Secret files are only committed if they are encrypted.\nEnd the repository file with `.age` or `.gpg` to encrypt it.")
+    } else {
+        None
+    }
+}
+
+// Return the error of a host file that could not be synced to its repository file.
+fn sync_error(
+    host_file: AmbitPath,
+    repo_file: AmbitPath,
+    secret: bool,
+    message: impl Into<String>,
+) -> AmbitError {
+    AmbitError::Sync {
+        host_file_path: host_file.path,
+        repo_file_path: repo_file.path,
+        secret,
+        error: Box::new(AmbitError::Other(message.into())),
+    }
+}
+
+// Create dir and its missing ancestors, which are recorded in changes so that undoing removes them again.
+fn create_dirs(
+    dir: &Path,
+    privilege_helper: Option<&str>,
+    changes: &mut Vec<Change>,
+) -> AmbitResult<()> {
+    let created_dirs = get_missing_dirs(dir);
+    escalate(
+        fs::create_dir_all(dir).map_err(AmbitError::Io),
+        privilege_helper,
+        &["mkdir".as_ref(), "-p".as_ref(), dir.as_os_str()],
+    )?;
+    changes.extend(created_dirs.into_iter().map(Change::CreatedDir));
+    Ok(())
+}
+
+// Move the conflicting host_file to backup, so that undoing moves it back.
+fn back_up_host_file(
+    host_file: &Path,
+    backup: &Path,
+    privilege_helper: Option<&str>,
+    changes: &mut Vec<Change>,
+) -> AmbitResult<()> {
+    if let Some(parent) = backup.parent() {
+        create_dirs(parent, privilege_helper, changes)?;
+    }
+    escalate(
+        move_path(host_file, backup),
+        privilege_helper,
+        &["mv".as_ref(), host_file.as_os_str(), backup.as_os_str()],
+    )?;
+    changes.push(Change::Moved(host_file.to_path_buf(), backup.to_path_buf()));
+    Ok(())
+}
+
+// Remove the conflicting host_file, whether it is a file or a directory.
+fn remove_host_file(host_file: &Path, privilege_helper: Option<&str>) -> AmbitResult<()> {
+    let result = match fs::symlink_metadata(host_file) {
+        Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(host_file),
+        _ => fs::remove_file(host_file),
+    };
+    escalate(
+        result.map_err(AmbitError::Io),
+        privilege_helper,
+        &["rm".as_ref(), "-rf".as_ref(), host_file.as_os_str()],
+    )
+}
+
+// Move host_file into the repository at repo_file. Files are encrypted on their way if repo_file says so.
+fn move_into_repo(
+    ambit_paths: &AmbitPaths,
+    host_file: &Path,
+    repo_file: &Path,
+    changes: &mut Vec<Change>,
+) -> AmbitResult<()> {
+    if let Some(parent) = repo_file.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    match Encryption::from_path(repo_file) {
+        Some(encryption) => {
+            encryption.encrypt(
+                &fs::read(host_file)?,
+                repo_file,
+                &ambit_paths.age_identity.path,
+            )?;
+            fs::remove_file(host_file)?;
+        }
+        None => move_path(host_file, repo_file)?,
+    }
+    changes.push(Change::Moved(
+        host_file.to_path_buf(),
+        repo_file.to_path_buf(),
+    ));
+    Ok(())
+}
+
+// Symlink host_file to repo_file, or write a copy of repo_file to host_file, retrying through the privilege helper
+// if it is not permitted. Files that cannot be symlinked are copied instead if the options allow it.
+// Return the mode that host_file was synced with.
+fn place_host_file(
+    ambit_paths: &AmbitPaths,
+    repo_file: &AmbitPath,
+    host_file: &Path,
+    mode: LinkMode,
+    attributes: &Attributes,
+    vars: &HashMap<String, String>,
+    options: &SyncOptions,
+) -> AmbitResult<LinkMode> {
+    let encryption = Encryption::from_path(&repo_file.path);
+    let is_template = template::is_template(&repo_file.path);
+    let mut content = None;
+    let result = match (mode, encryption) {
+        (LinkMode::Symlink, _) if repo_file.is_dir() => {
+            symlink_dir(&repo_file.path, host_file).map_err(AmbitError::Io)
+        }
+        (LinkMode::Symlink, _) => symlink_file(&repo_file.path, host_file).map_err(AmbitError::Io),
+        (LinkMode::Copy, None) if !is_template => {
+            copy_new_file(&repo_file.path, host_file, attributes.perm).map_err(AmbitError::Io)
+        }
+        // Encrypted files and templates are decrypted or rendered on the way.
+        // Decrypted secrets are only readable by the owner unless perm says otherwise.
+        (LinkMode::Copy, _) => {
+            let perm = attributes
+                .perm
+                .or_else(|| (encryption.is_some() || attributes.secret).then_some(0o600));
+            read_repo_file(ambit_paths, &repo_file.path, vars)
+                .and_then(|read| Ok(write_new_file(host_file, content.insert(read), perm)?))
+        }
+    };
+    // Directories cannot be copied, so only files fall back to copies.
+    let (mode, result) = match result {
+        Err(e)
+            if options.copy_fallback
+                && mode == LinkMode::Symlink
+                && !repo_file.is_dir()
+                && is_symlink_unsupported(&e) =>
+        {
+            let result =
+                copy_new_file(&repo_file.path, host_file, attributes.perm).map_err(AmbitError::Io);
+            (LinkMode::Copy, result)
+        }
+        result => (mode, result),
+    };
+    let command: Vec<OsString> = match (mode, encryption) {
+        (LinkMode::Symlink, _) => vec![
+            "ln".into(),
+            "-s".into(),
+            (&repo_file.path).into(),
+            host_file.into(),
+        ],
+        (LinkMode::Copy, Some(encryption)) => encryption.decrypt_command(
+            &repo_file.path,
+            Some(host_file),
+            &ambit_paths.age_identity.path,
+        ),
+        (LinkMode::Copy, None) => vec!["cp".into(), (&repo_file.path).into(), host_file.into()],
+    };
+    let command: Vec<&OsStr> = command.iter().map(OsString::as_os_str).collect();
+    match (&content, result, options.privilege_helper) {
+        // Rendered templates have no file for the helper to copy, so their content is written instead.
+        (Some(content), Err(AmbitError::Io(e)), Some(helper))
+            if is_template && e.kind() == io::ErrorKind::PermissionDenied =>
+        {
+            write_privileged(helper, host_file, content)?
+        }
+        (_, result, _) => escalate(result, options.privilege_helper, &command)?,
+    }
+    Ok(mode)
+}
+
+// Return the configuration that sync uses, which is searched for in the repository if asked to or if there is none.
+// None is returned if the user declines searching.
+fn get_sync_config(
+    ambit_paths: &AmbitPaths,
+    options: &SyncOptions,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<Option<Config>> {
+    if !options.use_repo_config && ambit_paths.config.exists() {
+        return get_config(ambit_paths, &ambit_paths.config).map(Some);
+    }
+    if !options.use_repo_config {
+        // Ask user if they want to search for repo config.
+        reporter.report(Event::Message(&format!(
+            "No configuration file found in {}",
+            ambit_paths.config.path.display()
+        )));
+        // No need to prompt if `use_repo_config_if_required` is true.
+        if !options.use_repo_config_if_required
+            && !prompt::confirm(
+                ambit_paths.assume,
+                "Search for configuration in repository?",
+                true,
+            )?
+        {
+            reporter.report(Event::Message("Ignoring sync..."));
+            return Ok(None);
+        }
+    }
+    reporter.report(Event::Message(&format!(
+        "Searching for {} in {}...",
+        CONFIG_NAME,
+        ambit_paths.repo.path.display()
+    )));
+    let repo_config_paths = get_repo_config_paths(
+        ambit_paths,
+        options.use_any_repo_config,
+        &get_repo_ignore(ambit_paths)?,
+    );
+    let mut repo_config = None;
+    // Iterate through repo configuration files that were found.
+    for path in repo_config_paths {
+        if options.use_any_repo_config
+            || prompt::confirm(
+                ambit_paths.assume,
+                &format!("Repo config found: {}. Use?", path.display()),
+                true,
+            )?
+        {
+            // config.ambit file has been found in repo and user has accepted it.
+            repo_config = Some(AmbitPath::new(path, AmbitPathKind::File));
+            break;
+        }
+    }
+    match repo_config {
+        Some(repo_config) => get_config(ambit_paths, &repo_config).map(Some),
+        None => Err(AmbitError::Other(
+            "Could not find configuration file in dotfile repository.".to_owned(),
+        )),
+    }
+}
+
+// A sync in progress, which keeps what it changed so that it can be rolled back or undone.
+struct SyncState<'a> {
+    ambit_paths: &'a AmbitPaths,
+    options: &'a SyncOptions<'a>,
+    auto_commit: AutoCommit,
+    // The directory that conflicting host files are backed up into during this sync.
+    backup_dir: Option<PathBuf>,
+    vars: HashMap<String, String>,
+    manifest: Manifest,
+    changes: Vec<Change>,
+    // The repository files of the host files moved into the repository, to be committed.
+    moved_files: Vec<PathBuf>,
+    successful_syncs: usize, // Number of syncs that actually occurred
+    total_syncs: usize,
+    pending_syncs: usize, // Number of files that were not synced yet
+}
+
+impl SyncState<'_> {
+    // Sync host_file to repo_file with the attributes of their entry, whose hash is entry_hash.
+    fn sync_file(
+        &mut self,
+        repo_file: AmbitPath,
+        host_file: AmbitPath,
+        attributes: &Attributes,
+        entry_hash: u64,
+        reporter: &mut dyn Reporter,
+    ) -> AmbitResult<()> {
+        let ambit_paths = self.ambit_paths;
+        let SyncOptions {
+            dry_run,
+            move_files,
+            allow_outside_home,
+            incremental,
+            replace_identical,
+            overwrite,
+            interactive,
+            privilege_helper,
+            ..
+        } = *self.options;
+        let entry_mode = get_link_mode(&repo_file.path, attributes.mode);
+        // Host files that were copied because they could not be symlinked are synced as copies from then on.
+        let mut mode = self
+            .manifest
+            .get_synced_mode(&host_file.path, &repo_file.path, entry_mode);
+        let mut fallback = mode != entry_mode;
+        // Files synced by the same entry that were not modified since do not have to be checked again.
+        if incremental
+            && self
+                .manifest
+                .is_unchanged(&host_file.path, &repo_file.path, mode, entry_hash)
+        {
+            self.total_syncs += 1;
+            return Ok(());
+        }
+        let event_link = Link {
+            host_file: &host_file.path,
+            repo_file: &repo_file.path,
+            secret: attributes.secret,
+        };
+        // already_synced holds whether host_file already links to (or is a copy of) repo_file
+        let already_synced = is_synced(
+            ambit_paths,
+            &host_file.path,
+            &repo_file.path,
+            mode,
+            &self.vars,
+        );
+        // cache for later
+        let host_file_exists = host_file.exists();
+        let repo_file_exists = repo_file.exists();
+        let move_to_repo = host_file_exists && !repo_file_exists && move_files;
+        let refusal = get_sync_refusal(
+            &repo_file,
+            mode,
+            move_to_repo && attributes.secret,
+            self.auto_commit,
+        );
+        if let Some(message) = refusal {
+            return Err(sync_error(host_file, repo_file, attributes.secret, message));
+        }
+
+        // Host files outside of the home directory are only synced if the user allows it.
+        if !already_synced
+            && !dry_run
+            && !allow_outside_home
+            && !host_file.path.starts_with(&ambit_paths.home.path)
+            && !prompt::confirm(
+                ambit_paths.assume,
+                &format!(
+                    "{} is outside of the home directory. Sync anyway?",
+                    display_path(&host_file.path, attributes.secret)
+                ),
+                false,
+            )?
+        {
+            self.total_syncs += 1;
+            return Ok(());
+        }
+
+        // A host file with the same content as the repository file can be replaced by the symlink without losing anything.
+        let replace_identical_file = replace_identical
+            && mode == LinkMode::Symlink
+            && !already_synced
+            && is_synced(
+                ambit_paths,
+                &host_file.path,
+                &repo_file.path,
+                LinkMode::Copy,
+                &self.vars,
+            );
+        // Copies that were not modified on the host since they were synced are updated with the repository file.
+        let outdated_copy = mode == LinkMode::Copy
+            && !already_synced
+            && host_file_exists
+            && self
+                .manifest
+                .is_unmodified_copy(&host_file.path, &repo_file.path);
+        let replace_host_file = replace_identical_file || outdated_copy;
+        let conflict = host_file_exists && !already_synced && !move_files && !replace_host_file;
+        if conflict && self.backup_dir.is_none() && !overwrite {
+            // Host file already exists but is not symlinked (or copied) correctly
+            reporter.report(Event::Conflict(event_link));
+            let message = match mode {
+                LinkMode::Symlink => "Host file already exists and is not correctly symlinked",
+                LinkMode::Copy => "Host file already exists and differs from repository file",
+            };
+            return Err(sync_error(host_file, repo_file, attributes.secret, message));
+        }
+        if !repo_file_exists && !move_files {
+            return Err(sync_error(
+                host_file,
+                repo_file,
+                attributes.secret,
+                "Repository file does not exist",
+            ));
+        }
+        if !already_synced {
+            self.pending_syncs += 1;
+            // Files are only synced once the user agrees to what happens to the host file.
+            if interactive && !dry_run {
+                let host = display_path(&host_file.path, attributes.secret);
+                let question = if conflict && self.backup_dir.is_some() {
+                    format!("Back up and replace {}?", host)
+                } else if conflict && overwrite {
+                    format!("Delete and replace {}?", host)
+                } else if outdated_copy {
+                    format!("Update {}?", host)
+                } else if replace_host_file {
+                    format!("Replace identical {}?", host)
+                } else if move_to_repo {
+                    format!("Move {} into the repository and link it?", host)
+                } else {
+                    format!("Sync {}?", host)
+                };
+                if !prompt::confirm(ambit_paths.assume, &question, true)? {
+                    reporter.report(Event::Skipped(event_link));
+                    self.total_syncs += 1;
+                    return Ok(());
+                }
+            }
+            let mut moved = false;
+            if !dry_run {
+                if let Some(backup_dir) = self.backup_dir.as_deref().filter(|_| conflict) {
+                    let backup =
+                        get_backup_path(backup_dir, &ambit_paths.home.path, &host_file.path);
+                    if fs::symlink_metadata(&backup).is_ok() {
+                        let message = format!(
+                            "Backup {} already exists",
+                            display_path(&backup, attributes.secret)
+                        );
+                        return Err(sync_error(host_file, repo_file, attributes.secret, message));
+                    }
+                    back_up_host_file(
+                        &host_file.path,
+                        &backup,
+                        privilege_helper,
+                        &mut self.changes,
+                    )?;
+                    reporter.report(Event::BackedUp {
+                        link: event_link,
+                        backup: &backup,
+                    });
+                } else if overwrite && conflict {
+                    // Overwritten host files are gone for good, so they cannot be restored by undo.
+                    remove_host_file(&host_file.path, privilege_helper)?;
+                    reporter.report(Event::Removed(event_link));
+                }
+                if replace_host_file {
+                    escalate(
+                        fs::remove_file(&host_file.path).map_err(AmbitError::Io),
+                        privilege_helper,
+                        &["rm".as_ref(), "-f".as_ref(), host_file.path.as_os_str()],
+                    )?;
+                    // Undoing this restores the host file as a copy of the repository file.
+                    self.changes.push(Change::Unlinked {
+                        host_file: host_file.path.clone(),
+                        repo_file: repo_file.path.clone(),
+                        mode: LinkMode::Copy,
+                    });
+                }
+                if move_to_repo {
+                    if attributes.secret && Encryption::from_path(&repo_file.path).is_none() {
+                        reporter.report(Event::Warning(
+                            "Moving a secret file into the repository without encrypting it",
+                        ));
+                    }
+                    move_into_repo(
+                        ambit_paths,
+                        &host_file.path,
+                        &repo_file.path,
+                        &mut self.changes,
+                    )?;
+                    self.moved_files.push(repo_file.path.clone());
+                    moved = true;
+                } else if let Some(parent) = host_file.path.parent() {
+                    create_dirs(parent, privilege_helper, &mut self.changes)?;
+                }
+                match place_host_file(
+                    ambit_paths,
+                    &repo_file,
+                    &host_file.path,
+                    mode,
+                    attributes,
+                    &self.vars,
+                    self.options,
+                ) {
+                    // Files that could not be symlinked were copied instead.
+                    Ok(synced_mode) if synced_mode != mode => {
+                        mode = synced_mode;
+                        fallback = true;
+                    }
+                    Ok(_) => {}
+                    Err(e) => {
+                        return Err(AmbitError::Sync {
+                            host_file_path: host_file.path,
+                            repo_file_path: repo_file.path,
+                            secret: attributes.secret,
+                            error: Box::new(e),
+                        });
+                    }
+                }
+                self.changes.push(Change::Linked {
+                    host_file: host_file.path.clone(),
+                    repo_file: repo_file.path.clone(),
+                    mode,
+                });
+                self.successful_syncs += 1;
+            }
+            reporter.report(match (moved, fallback) {
+                (true, _) => Event::Moved(event_link),
+                (false, true) if !dry_run => Event::Copied(event_link),
+                (false, _) => match !dry_run {
+                    true => Event::Linked(event_link),
+                    false => Event::Skipped(event_link),
+                },
+            });
+        }
+        if !dry_run {
+            apply_attributes(
+                &host_file.path,
+                &repo_file.path,
+                mode,
+                attributes,
+                privilege_helper,
+            )?;
+        }
+        // The host file is synced at this point, so it is recorded to be cleaned later.
+        if !dry_run {
+            self.manifest
+                .insert_synced(&host_file.path, &repo_file.path, mode, entry_hash);
+            if fallback {
+                self.manifest.set_fallback(&host_file.path);
+            }
+        }
+        self.total_syncs += 1;
+        Ok(())
+    }
+}
+
+/// Sync files in dotfile repository to system through symbolic links
+/// Return the number of files that were not synced yet, i.e. those that a dry run would sync.
+pub fn sync(
+    ambit_paths: &AmbitPaths,
+    options: &SyncOptions,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<usize> {
+    let SyncOptions {
+        dry_run,
+        keep_partial,
+        backup_dir,
+        auto_commit,
+        commit_message,
+        tags,
+        selection,
+        only,
+        privilege_helper,
+        ..
+    } = *options;
+    // Only symlink if repo and git directories exist
+    if !(ambit_paths.repo.exists() && ambit_paths.git.exists()) {
+        return Err(AmbitError::Other(
+            "Dotfile repository does not exist. Run `init` or `clone` before syncing.".to_owned(),
+        ));
+    }
+    let manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    let config = match get_sync_config(ambit_paths, options, reporter)? {
+        Some(config) => config,
+        None => return Ok(0),
+    };
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let entries = select_entries(config.entries, selection)?;
+    let sources: Vec<EntrySource> = entries
+        .iter()
+        .zip(select_entries(config.sources, selection)?)
+        .filter(|(entry, _)| has_any_tag(entry, tags))
+        .map(|(_, source)| source)
+        .collect();
+    let entry_paths = filter_entries_by_tags(entries, tags)
+        .into_iter()
+        .map(|entry| {
+            let paths = get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?;
+            Ok((entry, paths))
+        })
+        .collect::<AmbitResult<Vec<_>>>()?;
+    // The host paths of other entries decide whether directories can be folded.
+    let host_paths: Vec<Vec<PathBuf>> = entry_paths
+        .iter()
+        .map(|(_, paths)| {
+            paths
+                .iter()
+                .map(|(_, host_file)| host_file.path.clone())
+                .collect()
+        })
+        .collect();
+    // Entries that would fail partway through syncing, e.g. by producing the same host file, are refused up front.
+    let entries: Vec<&Entry> = entry_paths.iter().map(|(entry, _)| entry).collect();
+    let errors: Vec<String> = get_entry_diagnostics(&entries, &sources, &host_paths)
+        .iter()
+        .filter(|diagnostic| diagnostic.severity == Severity::Error)
+        .map(Diagnostic::to_string)
+        .collect();
+    if !errors.is_empty() {
+        return Err(AmbitError::Other(errors.join("\n")));
+    }
+    // Host files that are restored when rolling back get the attributes of their entry.
+    let attributes_by_host_file: HashMap<PathBuf, Attributes> = entry_paths
+        .iter()
+        .flat_map(|(entry, paths)| {
+            paths
+                .iter()
+                .map(move |(_, host_file)| (host_file.path.clone(), entry.attributes.clone()))
+        })
+        .collect();
+    let mut state = SyncState {
+        ambit_paths,
+        options,
+        // Auto-commit is enabled by the configuration as well as by the options.
+        auto_commit: auto_commit.max(config.auto_commit),
+        // Conflicting host files are backed up into a directory of their own for each sync.
+        backup_dir: backup_dir.map(|backup_dir| {
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+            backup_dir.join(timestamp.to_string())
+        }),
+        vars: config.vars,
+        manifest,
+        changes: Vec::new(),
+        moved_files: Vec::new(),
+        successful_syncs: 0,
+        total_syncs: 0,
+        pending_syncs: 0,
+    };
+    let sync_entries = || -> AmbitResult<()> {
+        for (i, (entry, paths)) in entry_paths.into_iter().enumerate() {
+            // Entries without selected files are left alone, so that their directories are not folded either.
+            if let Some(only) = only {
+                if !host_paths[i]
+                    .iter()
+                    .any(|path| only.is_match(&ambit_paths.home.path, path))
+                {
+                    continue;
+                }
+            }
+            let other_host_paths: Vec<&Path> = host_paths
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .flat_map(|(_, paths)| paths.iter().map(PathBuf::as_path))
+                .collect();
+            let paths = fold_tree_paths(
+                ambit_paths,
+                &entry,
+                paths,
+                &other_host_paths,
+                dry_run,
+                reporter,
+            )?;
+            let entry_hash = hash_entry(&entry, &state.vars);
+            for (repo_file, host_file) in paths {
+                if let Some(only) = only {
+                    if !only.is_match_within(
+                        &ambit_paths.home.path,
+                        &host_file.path,
+                        &host_paths[i],
+                    ) {
+                        continue;
+                    }
+                }
+                state.sync_file(
+                    repo_file,
+                    host_file,
+                    &entry.attributes,
+                    entry_hash,
+                    reporter,
+                )?;
+            }
+        }
+        Ok(())
+    };
+    let result = sync_entries();
+    if result.is_err() && !keep_partial && !state.changes.is_empty() {
+        reporter.report(Event::Warning(&format!(
+            "Sync failed, rolling back {} change(s)",
+            state.changes.len()
+        )));
+        undo_changes(
+            ambit_paths,
+            state.changes,
+            &mut state.manifest,
+            &attributes_by_host_file,
+            &state.vars,
+            reporter,
+            privilege_helper,
+        );
+    } else {
+        // With `--keep-partial`, the changes made before an error can be undone later as well.
+        record_operation(ambit_paths, "sync", state.changes)?;
+    }
+    // With `--keep-partial`, the files synced before an error are recorded as well.
+    if !dry_run {
+        state.manifest.save(&ambit_paths.manifest.path)?;
+    }
+    result?;
+    // Report the number of files symlinked
+    reporter.report(Event::Summary {
+        command: ReportCommand::Sync,
+        total: state.total_syncs,
+        done: state.successful_syncs,
+    });
+    let moved_files: Vec<&Path> = state.moved_files.iter().map(PathBuf::as_path).collect();
+    commit_moved_files(
+        ambit_paths,
+        &moved_files,
+        state.auto_commit,
+        commit_message,
+        reporter,
+    )?;
+    Ok(state.pending_syncs)
+}
+
+// Return the path pairs of entry that are removed when cleaning it.
+// Folded directories are removed as a whole instead of per file.
+fn get_clean_paths(
+    ambit_paths: &AmbitPaths,
+    entry: &Entry,
+    ignore: &Ignore,
+    cache: &DirCache,
+) -> AmbitResult<Vec<(AmbitPath, AmbitPath)>> {
+    let mut paths = get_ambit_paths_from_entry(ambit_paths, entry, ignore, cache)?;
+    for (repo_dir, host_dir) in get_folded_dirs(ambit_paths, entry)? {
+        paths.retain(|(repo_file, _)| !repo_file.path.starts_with(&repo_dir));
+        paths.push((
+            AmbitPath::new(repo_dir, AmbitPathKind::Directory),
+            AmbitPath::new(host_dir, AmbitPathKind::Directory),
+        ));
+    }
+    Ok(paths)
+}
+
+// Directories that are not scanned for orphaned symlinks, as they are large and never synced into.
+const ORPHAN_SCAN_SKIPPED_DIRS: [&str; 3] = [".cache", ".git", "node_modules"];
+
+// Return the host files that were synced by ambit but are not produced by any entry of config,
+// along with the repository files they are synced from.
+// These are left behind when entries are removed from the config.
+// Only the files recorded in the manifest are considered, unless scan_depth is given:
+// symlinks into the repository up to scan_depth directories deep in the home directory are found as well,
+// e.g. those synced before the manifest was kept.
+fn get_orphans(
+    ambit_paths: &AmbitPaths,
+    config: &Config,
+    manifest: &Manifest,
+    scan_depth: Option<usize>,
+) -> AmbitResult<Vec<ManifestEntry>> {
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut host_files = HashSet::new();
+    host_files.insert(ambit_paths.config.path.clone());
+    for entry in &config.entries {
+        for (_, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            host_files.insert(host_file.path);
+        }
+    }
+    let mut orphans = Vec::new();
+    for entry in manifest.entries() {
+        // Files that were changed since they were synced are no longer managed by ambit.
+        if !host_files.contains(&entry.host_file)
+            && is_synced(
+                ambit_paths,
+                &entry.host_file,
+                &entry.repo_file,
+                entry.mode,
+                &config.vars,
+            )
+        {
+            orphans.push(entry.clone());
+        }
+    }
+    let scan_depth = match scan_depth {
+        Some(scan_depth) => scan_depth,
+        None => return Ok(orphans),
+    };
+    // The repository itself is skipped, as its symlinks are not synced by ambit.
+    let walker = WalkDir::new(&ambit_paths.home.path)
+        .max_depth(scan_depth)
+        .into_iter()
+        .filter_entry(|dir_entry| {
+            dir_entry.path() != ambit_paths.repo.path
+                && !(dir_entry.file_type().is_dir()
+                    && ORPHAN_SCAN_SKIPPED_DIRS
+                        .iter()
+                        .any(|name| dir_entry.file_name() == *name))
+        });
+    // Directories that cannot be read are skipped.
+    for dir_entry in walker.filter_map(Result::ok) {
+        if !dir_entry.path_is_symlink()
+            || host_files.contains(dir_entry.path())
+            || orphans
+                .iter()
+                .any(|orphan| orphan.host_file == dir_entry.path())
+        {
+            continue;
+        }
+        let target = fs::read_link(dir_entry.path())?;
+        if target.starts_with(&ambit_paths.repo.path) {
+            orphans.push(ManifestEntry {
+                host_file: dir_entry.into_path(),
+                repo_file: target,
+                mode: LinkMode::Symlink,
+                state: None,
+                fallback: false,
+            });
+        }
+    }
+    Ok(orphans)
+}
+
+// Remove the host files that were synced by ambit but are not in the config.
+fn clean_orphans(
+    ambit_paths: &AmbitPaths,
+    config: &Config,
+    manifest: &mut Manifest,
+    scan_depth: Option<usize>,
+    reporter: &mut dyn Reporter,
+    privilege_helper: Option<&str>,
+) -> AmbitResult<()> {
+    let orphans = get_orphans(ambit_paths, config, manifest, scan_depth)?;
+    let mut changes = Vec::new();
+    for orphan in &orphans {
+        escalate(
+            fs::remove_file(&orphan.host_file).map_err(AmbitError::Io),
+            privilege_helper,
+            &["rm".as_ref(), "-f".as_ref(), orphan.host_file.as_os_str()],
+        )?;
+        manifest.remove(&orphan.host_file);
+        changes.push(Change::Unlinked {
+            host_file: orphan.host_file.clone(),
+            repo_file: orphan.repo_file.clone(),
+            mode: orphan.mode,
+        });
+        // Orphans are not secret, as their entry is no longer in the config.
+        reporter.report(Event::Removed(Link {
+            host_file: &orphan.host_file,
+            repo_file: &orphan.repo_file,
+            secret: false,
+        }));
+    }
+    manifest.save(&ambit_paths.manifest.path)?;
+    record_operation(ambit_paths, "clean", changes)?;
+    reporter.report(Event::Summary {
+        command: ReportCommand::Clean,
+        total: orphans.len(),
+        done: orphans.len(),
+    });
+    Ok(())
+}
+
+/// Remove all symlinks and delete host files.
+/// Without tags, the files recorded in the manifest are removed as well, even if their entry is no longer in the config.
+/// If orphans is set, only the host files that are not in the config are removed instead,
+/// which are searched for in the home directory up to scan_depth directories deep if it is given.
+#[allow(clippy::too_many_arguments)]
+pub fn clean(
+    ambit_paths: &AmbitPaths,
+    tags: &[&str],
+    selection: Option<&EntrySelection>,
+    only: Option<&HostFilter>,
+    orphans: bool,
+    scan_depth: Option<usize>,
+    reporter: &mut dyn Reporter,
+    privilege_helper: Option<&str>,
+) -> AmbitResult<()> {
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    if orphans {
+        return clean_orphans(
+            ambit_paths,
+            &config,
+            &mut manifest,
+            scan_depth,
+            reporter,
+            privilege_helper,
+        );
+    }
+    let mut total_syncs: usize = 0;
+    let mut deletions: usize = 0;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut paths = Vec::new();
+    for entry in filter_entries_by_tags(select_entries(config.entries, selection)?, tags) {
+        let host_files: Vec<PathBuf> =
+            get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?
+                .into_iter()
+                .map(|(_, host_file)| host_file.path)
+                .collect();
+        for (repo_file, host_file) in get_clean_paths(ambit_paths, &entry, &ignore, &cache)? {
+            if let Some(only) = only {
+                if !only.is_match_within(&ambit_paths.home.path, &host_file.path, &host_files) {
+                    continue;
+                }
+            }
+            let mode = manifest.get_synced_mode(
+                &host_file.path,
+                &repo_file.path,
+                get_link_mode(&repo_file.path, entry.attributes.mode),
+            );
+            paths.push((repo_file, host_file, mode, entry.attributes.secret));
+        }
+    }
+    if tags.is_empty() && selection.is_none() {
+        // Recorded files that were changed since they were synced are no longer managed by ambit.
+        let config_host_files: HashSet<PathBuf> = paths
+            .iter()
+            .map(|(_, host_file, _, _)| host_file.path.clone())
+            .collect();
+        for entry in manifest.entries() {
+            if config_host_files.contains(&entry.host_file)
+                || only.is_some_and(|only| !only.is_match(&ambit_paths.home.path, &entry.host_file))
+                || !is_synced(
+                    ambit_paths,
+                    &entry.host_file,
+                    &entry.repo_file,
+                    entry.mode,
+                    &config.vars,
+                )
+            {
+                continue;
+            }
+            let kind = match entry.repo_file.is_dir() {
+                true => AmbitPathKind::Directory,
+                false => AmbitPathKind::File,
+            };
+            paths.push((
+                AmbitPath::new(entry.repo_file.clone(), kind),
+                AmbitPath::new(entry.host_file.clone(), kind),
+                entry.mode,
+                false,
+            ));
+        }
+    }
+    let mut changes = Vec::new();
+    for (repo_file, host_file, mode, secret) in paths {
+        let link = Link {
+            host_file: &host_file.path,
+            repo_file: &repo_file.path,
+            secret,
+        };
+        if is_synced(
+            ambit_paths,
+            &host_file.path,
+            &repo_file.path,
+            mode,
+            &config.vars,
+        ) {
+            escalate(
+                host_file.remove(),
+                privilege_helper,
+                &["rm".as_ref(), "-f".as_ref(), host_file.path.as_os_str()],
+            )?;
+            reporter.report(Event::Removed(link));
+            changes.push(Change::Unlinked {
+                host_file: host_file.path.clone(),
+                repo_file: repo_file.path.clone(),
+                mode,
+            });
+            deletions += 1;
+        } else if fs::symlink_metadata(&host_file.path).is_ok() {
+            reporter.report(Event::Conflict(link));
+        }
+        manifest.remove(&host_file.path);
+        total_syncs += 1;
+    }
+    // Nothing that was recorded is synced anymore once every entry is cleaned.
+    if tags.is_empty() && selection.is_none() && only.is_none() {
+        manifest = Manifest::default();
+    }
+    manifest.save(&ambit_paths.manifest.path)?;
+    record_operation(ambit_paths, "clean", changes)?;
+    reporter.report(Event::Summary {
+        command: ReportCommand::Clean,
+        total: total_syncs,
+        done: deletions,
+    });
+    Ok(())
+}
+
+/// Reverse the changes made by the most recent sync or clean.
+pub fn undo(
+    ambit_paths: &AmbitPaths,
+    reporter: &mut dyn Reporter,
+    privilege_helper: Option<&str>,
+) -> AmbitResult<()> {
+    let mut journal = Journal::load(&ambit_paths.journal.path)?;
+    let operation = journal
+        .pop()
+        .ok_or_else(|| AmbitError::Other("Nothing to undo".to_owned()))?;
+    // Copies are restored with the variables and attributes of the current config, if it can be read.
+    let config = get_config(ambit_paths, &ambit_paths.config).unwrap_or_default();
+    let attributes = get_attributes_by_host_file(ambit_paths, &config).unwrap_or_default();
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    let total = operation.changes.len();
+    let failures = undo_changes(
+        ambit_paths,
+        operation.changes,
+        &mut manifest,
+        &attributes,
+        &config.vars,
+        reporter,
+        privilege_helper,
+    );
+    manifest.save(&ambit_paths.manifest.path)?;
+    journal.save(&ambit_paths.journal.path)?;
+    reporter.report(Event::Message(&format!(
+        "undo result (`ambit {}`, {} total): {} undone; {} failed",
+        operation.name,
+        total,
+        total - failures,
+        failures
+    )));
+    match failures {
+        0 => Ok(()),
+        _ => Err(AmbitError::Other(format!(
+            "Failed to undo {} change(s)",
+            failures
+        ))),
+    }
+}
+
+/// Compute the actions that sync the config and save them to path, or to the default plan path.
+/// Host files that exist but are not in the repository yet are only moved into it if move_files is set.
+pub fn plan(
+    ambit_paths: &AmbitPaths,
+    move_files: bool,
+    path: Option<&Path>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut plan = Plan::default();
+    for entry in &config.entries {
+        for (repo_file, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            let mode = get_link_mode(&repo_file.path, entry.attributes.mode);
+            let status = get_path_status(ambit_paths, &repo_file, &host_file, mode, &config.vars);
+            let (host_file, repo_file) = (host_file.path, repo_file.path);
+            match status {
+                PathStatus::Linked => {}
+                PathStatus::Unlinked => plan.actions.push(Action::Link {
+                    host_file,
+                    repo_file,
+                    mode,
+                }),
+                PathStatus::PendingMove if move_files => plan.actions.push(Action::Move {
+                    host_file,
+                    repo_file,
+                    mode,
+                }),
+                // Paths that cannot be synced are left for the user to resolve.
+                status => reporter.report(Event::Message(&format!(
+                    "skipped ({}): {}",
+                    status.as_str(),
+                    display_paths(&host_file, &repo_file, entry.attributes.secret)
+                ))),
+            }
+        }
+    }
+    for orphan in get_orphans(
+        ambit_paths,
+        &config,
+        &Manifest::load(&ambit_paths.manifest.path)?,
+        None,
+    )? {
+        plan.actions.push(Action::Delete {
+            host_file: orphan.host_file,
+            repo_file: orphan.repo_file,
+            mode: orphan.mode,
+        });
+    }
+    let count = |name: &str| {
+        plan.actions
+            .iter()
+            .filter(|action| action.name() == name)
+            .count()
+    };
+    let attributes = get_attributes_by_host_file(ambit_paths, &config)?;
+    for action in &plan.actions {
+        let (host_file, repo_file, _) = action.paths();
+        let secret = attributes
+            .get(host_file)
+            .is_some_and(|attributes| attributes.secret);
+        reporter.report(Event::Message(&format!(
+            "{}: {}",
+            action.name(),
+            display_paths(host_file, repo_file, secret)
+        )));
+    }
+    reporter.report(Event::Message(&format!(
+        "plan result ({} total): {} link; {} move; {} delete",
+        plan.actions.len(),
+        count("link"),
+        count("move"),
+        count("delete")
+    )));
+    let path = path.unwrap_or(&ambit_paths.plan.path);
+    plan.save(path)?;
+    reporter.report(Event::Message(&format!("Saved plan to {}", path.display())));
+    Ok(())
+}
+
+// Execute action, recording its changes so they can be undone.
+// Host files are synced with the attributes of their entry, as with `ambit sync`.
+fn apply_action(
+    ambit_paths: &AmbitPaths,
+    action: &Action,
+    manifest: &mut Manifest,
+    changes: &mut Vec<Change>,
+    attributes: &HashMap<PathBuf, Attributes>,
+    vars: &HashMap<String, String>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let (host_file, repo_file, mode) = action.paths();
+    let default_attributes = Attributes::default();
+    let attributes = attributes.get(host_file).unwrap_or(&default_attributes);
+    if let Action::Delete { .. } = action {
+        let kind = match repo_file.is_dir() && mode == LinkMode::Symlink {
+            true => AmbitPathKind::Directory,
+            false => AmbitPathKind::File,
+        };
+        AmbitPath::new(host_file.to_path_buf(), kind).remove()?;
+        manifest.remove(host_file);
+        changes.push(Change::Unlinked {
+            host_file: host_file.to_path_buf(),
+            repo_file: repo_file.to_path_buf(),
+            mode,
+        });
+        reporter.report(Event::Message(&format!(
+            "Deleted {}",
+            display_path(host_file, attributes.secret)
+        )));
+        return Ok(());
+    }
+    if let Action::Move { .. } = action {
+        move_into_repo(ambit_paths, host_file, repo_file, changes)?;
+    } else if let Some(parent) = host_file.parent() {
+        create_dirs(parent, None, changes)?;
+    }
+    link_file(
+        ambit_paths,
+        repo_file,
+        host_file,
+        mode,
+        attributes,
+        vars,
+        None,
+    )?;
+    manifest.insert(host_file, repo_file, mode);
+    changes.push(Change::Linked {
+        host_file: host_file.to_path_buf(),
+        repo_file: repo_file.to_path_buf(),
+        mode,
+    });
+    reporter.report(Event::Message(&format!(
+        "Synced {}",
+        display_paths(host_file, repo_file, attributes.secret)
+    )));
+    Ok(())
+}
+
+/// Execute the plan saved at path, or at the default plan path.
+/// The plan is refused if any of its actions no longer apply, e.g. because a host file was created since.
+pub fn apply(
+    ambit_paths: &AmbitPaths,
+    path: Option<&Path>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let path = path.unwrap_or(&ambit_paths.plan.path);
+    let plan = Plan::load(path)?;
+    // Copies are written with the variables and attributes of the current config, as with undo.
+    let config = get_config(ambit_paths, &ambit_paths.config).unwrap_or_default();
+    let attributes = get_attributes_by_host_file(ambit_paths, &config).unwrap_or_default();
+    let vars = config.vars;
+    // Every action is checked before any is executed, so that an outdated plan changes nothing.
+    for action in &plan.actions {
+        let (host_file, repo_file, mode) = action.paths();
+        let host_file_exists = fs::symlink_metadata(host_file).is_ok();
+        let repo_file_exists = fs::symlink_metadata(repo_file).is_ok();
+        let problem = match action {
+            Action::Link { .. } if host_file_exists => Some("host file already exists"),
+            Action::Link { .. } if !repo_file_exists => Some("repository file does not exist"),
+            Action::Move { .. } if !host_file_exists => Some("host file does not exist"),
+            Action::Move { .. } if repo_file_exists => Some("repository file already exists"),
+            Action::Delete { .. } if !is_synced(ambit_paths, host_file, repo_file, mode, &vars) => {
+                Some("host file is no longer synced")
+            }
+            _ => None,
+        };
+        if let Some(problem) = problem {
+            let secret = attributes
+                .get(host_file)
+                .is_some_and(|attributes| attributes.secret);
+            return Err(AmbitError::Other(format!(
+                "Plan is out of date, as the {}: {}\nRun `ambit plan` again.",
+                problem,
+                display_path(host_file, secret)
+            )));
+        }
+    }
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    let mut changes = Vec::new();
+    let result = plan.actions.iter().try_for_each(|action| {
+        apply_action(
+            ambit_paths,
+            action,
+            &mut manifest,
+            &mut changes,
+            &attributes,
+            &vars,
+            reporter,
+        )
+    });
+    if result.is_err() {
+        reporter.report(Event::Warning(&format!(
+            "Apply failed, rolling back {} change(s)",
+            changes.len()
+        )));
+        undo_changes(
+            ambit_paths,
+            changes,
+            &mut manifest,
+            &attributes,
+            &vars,
+            reporter,
+            None,
+        );
+    } else {
+        record_operation(ambit_paths, "apply", changes)?;
+    }
+    manifest.save(&ambit_paths.manifest.path)?;
+    result?;
+    // A plan describes the state it was computed from, so it can only be applied once.
+    fs::remove_file(path)?;
+    reporter.report(Event::Message(&format!(
+        "apply result ({} total): {} applied",
+        plan.actions.len(),
+        plan.actions.len()
+    )));
+    Ok(())
+}
+
+// The state of a host file with regard to the repository file it is synced from.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+enum PathStatus {
+    // The host file is symlinked to (or a copy of) the repository file.
+    Linked,
+    // The host file does not exist yet, so it is created by `ambit sync`.
+    Unlinked,
+    // Neither the host file nor the repository file exist.
+    MissingRepoFile,
+    // The host file exists, but is not linked to the repository file.
+    Conflict,
+    // The host file is a symlink to a path that does not exist.
+    BrokenSymlink,
+    // The host file exists but the repository file does not, so `ambit sync --move` moves it into the repository.
+    PendingMove,
+}
+
+impl PathStatus {
+    fn as_str(&self) -> &'static str {
+        match self {
+            PathStatus::Linked => "linked",
+            PathStatus::Unlinked => "unlinked",
+            PathStatus::MissingRepoFile => "missing repo file",
+            PathStatus::Conflict => "conflict",
+            PathStatus::BrokenSymlink => "broken symlink",
+            PathStatus::PendingMove => "pending move",
+        }
+    }
+}
+
+fn get_path_status(
+    ambit_paths: &AmbitPaths,
+    repo_file: &AmbitPath,
+    host_file: &AmbitPath,
+    mode: LinkMode,
+    vars: &HashMap<String, String>,
+) -> PathStatus {
+    let is_symlink = fs::symlink_metadata(&host_file.path)
+        .map(|metadata| metadata.file_type().is_symlink())
+        .unwrap_or(false);
+    // A symlink is broken if the path it links to cannot be followed, even if it links to the repository file.
+    if is_symlink && fs::metadata(&host_file.path).is_err() {
+        return PathStatus::BrokenSymlink;
+    }
+    if is_synced(ambit_paths, &host_file.path, &repo_file.path, mode, vars) {
+        return PathStatus::Linked;
+    }
+    let host_file_exists = is_symlink || host_file.path.exists();
+    match (repo_file.exists(), host_file_exists) {
+        (true, true) => PathStatus::Conflict,
+        (true, false) => PathStatus::Unlinked,
+        (false, true) => PathStatus::PendingMove,
+        (false, false) => PathStatus::MissingRepoFile,
+    }
+}
+
+/// Report the state of every path of the entries with one of tags, or of all entries if no tags are given.
+pub fn status(
+    ambit_paths: &AmbitPaths,
+    tags: &[&str],
+    git: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    let mut linked: usize = 0;
+    let mut total: usize = 0;
+    for entry in filter_entries_by_tags(config.entries, tags) {
+        for (repo_file, host_file) in get_clean_paths(ambit_paths, &entry, &ignore, &cache)? {
+            let mode = manifest.get_synced_mode(
+                &host_file.path,
+                &repo_file.path,
+                get_link_mode(&repo_file.path, entry.attributes.mode),
+            );
+            let status = get_path_status(ambit_paths, &repo_file, &host_file, mode, &config.vars);
+            if status == PathStatus::Linked {
+                linked += 1;
+            }
+            total += 1;
+            reporter.report(Event::Message(&format!(
+                "{}: {}",
+                status.as_str(),
+                display_paths(&host_file.path, &repo_file.path, entry.attributes.secret)
+            )));
+        }
+    }
+    reporter.report(Event::Message(&format!(
+        "status result ({} total): {} linked; {} not linked",
+        total,
+        linked,
+        total - linked
+    )));
+    if git {
+        ensure_repo_exists(ambit_paths)?;
+        report_output(reporter, &get_git_summary(&git::status(ambit_paths)?));
+    }
+    Ok(())
+}
+
+// Return a summary of the branch and uncommitted changes of the dotfile repository,
+// from the output of `git status --porcelain --branch`.
+fn get_git_summary(status: &str) -> String {
+    let mut lines = status.lines();
+    let header = lines
+        .next()
+        .and_then(|line| line.strip_prefix("## "))
+        .unwrap_or("");
+    // The header looks like `main...origin/main [ahead 1, behind 2]`.
+    let (branches, tracking) = match header.find(" [") {
+        Some(i) => (&header[..i], Some(header[i + 2..].trim_end_matches(']'))),
+        None => (header, None),
+    };
+    let mut summary = format!("git branch: {}", branches.replacen("...", " -> ", 1));
+    if let Some(tracking) = tracking {
+        summary.push_str(&format!(" ({})", tracking));
+    }
+    let changes: Vec<&str> = lines.collect();
+    summary.push_str(&format!("\ngit changes ({} total)\n", changes.len()));
+    for change in changes {
+        summary.push_str(&format!("    {}\n", change.trim_start()));
+    }
+    summary
+}
+
+// Return the host files that are symlinks into the repository, but whose repository file no longer exists.
+fn get_broken_symlinks(ambit_paths: &AmbitPaths, config: &Config) -> AmbitResult<Vec<PathBuf>> {
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut broken_symlinks = Vec::new();
+    for entry in &config.entries {
+        for (repo_file, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            let mode = get_link_mode(&repo_file.path, entry.attributes.mode);
+            let links_into_repo = fs::read_link(&host_file.path)
+                .map(|target| target.starts_with(&ambit_paths.repo.path))
+                .unwrap_or(false);
+            if links_into_repo
+                && get_path_status(ambit_paths, &repo_file, &host_file, mode, &config.vars)
+                    == PathStatus::BrokenSymlink
+            {
+                broken_symlinks.push(host_file.path);
+            }
+        }
+    }
+    Ok(broken_symlinks)
+}
+
+// Return if a symlink can be created in dir, which requires Developer Mode (or admin rights) on Windows.
+fn can_symlink(ambit_paths: &AmbitPaths, dir: &Path) -> AmbitResult<()> {
+    let link = dir.join(format!(".ambit-doctor-{}", std::process::id()));
+    symlink_file(&ambit_paths.config.path, &link)?;
+    fs::remove_file(&link)?;
+    Ok(())
+}
+
+// Return if files can be created in dir.
+fn can_write(dir: &Path) -> AmbitResult<()> {
+    let file = dir.join(format!(".ambit-doctor-{}", std::process::id()));
+    fs::write(&file, "")?;
+    fs::remove_file(&file)?;
+    Ok(())
+}
+
+/// Check the environment that ambit runs in, suggesting how to fix each problem that is found.
+pub fn doctor(ambit_paths: &AmbitPaths, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let mut problems: usize = 0;
+    // Each check is reported with whether it passed, along with a suggestion if it did not.
+    let mut report = |description: &str, result: Result<(), String>| match result {
+        Ok(()) => reporter.report(Event::Message(&format!("ok: {}", description))),
+        Err(suggestion) => {
+            problems += 1;
+            reporter.report(Event::Message(&format!(
+                "fail: {}\n    {}",
+                description, suggestion
+            )));
+        }
+    };
+    let git_result = Command::new("git").arg("--version").output();
+    report(
+        "git is installed",
+        match git_result {
+            Ok(output) if output.status.success() => Ok(()),
+            _ => {
+                Err("Could not run `git`. Install git and make sure that it is in PATH.".to_owned())
+            }
+        },
+    );
+    report(
+        "dotfile repository is initialized",
+        match ambit_paths.repo.exists() && ambit_paths.git.exists() {
+            true => Ok(()),
+            false => Err(format!(
+                "{} is not a git repository. Run `ambit init` or `ambit clone <ORIGIN>`.",
+                ambit_paths.repo.path.display()
+            )),
+        },
+    );
+    let config = match ambit_paths.config.exists() {
+        true => get_config(ambit_paths, &ambit_paths.config).map_err(|e| {
+            format!(
+                "{}\n    Run `ambit check` to see every problem with the configuration.",
+                e
+            )
+        }),
+        false => Err(format!(
+            "{} does not exist. Run `ambit init` to create it.",
+            ambit_paths.config.path.display()
+        )),
+    };
+    report(
+        "configuration parses",
+        config.as_ref().map(|_| ()).map_err(Clone::clone),
+    );
+    report(
+        "home directory is writable",
+        can_write(&ambit_paths.home.path).map_err(|e| {
+            format!(
+                "Could not create a file in {}: {}. Check the permissions of the directory or set AMBIT_HOME_PATH.",
+                ambit_paths.home.path.display(),
+                e
+            )
+        }),
+    );
+    report(
+        "symlinks can be created",
+        can_symlink(ambit_paths, &ambit_paths.home.path).map_err(|e| {
+            let suggestion = match cfg!(windows) {
+                true => "Enable Developer Mode or run ambit as an administrator.",
+                false => "Check the permissions of the home directory.",
+            };
+            format!("Could not create a symlink: {}. {}", e, suggestion)
+        }),
+    );
+    // Broken symlinks can only be found through a valid configuration.
+    if let Ok(config) = &config {
+        report(
+            "no broken symlinks into the repository",
+            match get_broken_symlinks(ambit_paths, config)? {
+                broken_symlinks if broken_symlinks.is_empty() => Ok(()),
+                broken_symlinks => Err(format!(
+                    "{} link to repository files that do not exist. Restore the files and run `ambit repair`, or remove the links with `ambit clean`.",
+                    broken_symlinks
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+        );
+        report(
+            "no orphaned files from removed entries",
+            match get_orphans(ambit_paths, config, &Manifest::load(&ambit_paths.manifest.path)?, None)? {
+                orphans if orphans.is_empty() => Ok(()),
+                orphans => Err(format!(
+                    "{} were synced by entries that are no longer in the configuration. Remove them with `ambit clean --orphans`.",
+                    orphans
+                        .iter()
+                        .map(|orphan| orphan.host_file.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                )),
+            },
+        );
+    }
+    match problems {
+        0 => Ok(()),
+        1 => Err(AmbitError::Other("Found 1 problem".to_owned())),
+        _ => Err(AmbitError::Other(format!("Found {} problems", problems))),
+    }
+}
+
+// Return if host_file is a symlink that should be recreated to link to repo_file,
+// because the path it links to does not exist or is another file of the repository.
+fn needs_repair(ambit_paths: &AmbitPaths, repo_file: &Path, host_file: &Path) -> bool {
+    let target = match fs::read_link(host_file) {
+        Ok(target) => target,
+        Err(_) => return false,
+    };
+    let is_broken = fs::metadata(host_file).is_err();
+    is_broken || (target != repo_file && target.starts_with(&ambit_paths.repo.path))
+}
+
+/// Recreate the symlinks of the configuration that are broken or link to the wrong repository file.
+pub fn repair(ambit_paths: &AmbitPaths, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    let mut total: usize = 0;
+    let mut repaired: usize = 0;
+    for entry in &config.entries {
+        for (repo_file, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            if get_link_mode(&repo_file.path, entry.attributes.mode) != LinkMode::Symlink
+                || !needs_repair(ambit_paths, &repo_file.path, &host_file.path)
+            {
+                continue;
+            }
+            total += 1;
+            if !repo_file.exists() {
+                reporter.report(Event::Warning(&format!(
+                    "Could not repair {}: repository file {} does not exist",
+                    display_path(&host_file.path, entry.attributes.secret),
+                    display_path(&repo_file.path, entry.attributes.secret)
+                )));
+                continue;
+            }
+            fs::remove_file(&host_file.path)?;
+            match repo_file.is_dir() {
+                true => symlink_dir(&repo_file.path, &host_file.path)?,
+                false => symlink_file(&repo_file.path, &host_file.path)?,
+            }
+            manifest.insert(&host_file.path, &repo_file.path, LinkMode::Symlink);
+            repaired += 1;
+            reporter.report(Event::Message(&format!(
+                "Repaired {}",
+                display_paths(&host_file.path, &repo_file.path, entry.attributes.secret)
+            )));
+        }
+    }
+    manifest.save(&ambit_paths.manifest.path)?;
+    reporter.report(Event::Message(&format!(
+        "repair result ({} total): {} repaired; {} not repaired",
+        total,
+        repaired,
+        total - repaired
+    )));
+    Ok(())
+}
+
+/// Format the config in the canonical style.
+/// If check is set, the config is left untouched and how it would change is shown instead.
+pub fn fmt(ambit_paths: &AmbitPaths, check: bool, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let content = ambit_paths.config.as_string()?;
+    let formatted = config::formatter::format(&content).map_err(|errors| AmbitError::Parse {
+        content: content.clone(),
+        errors,
+    })?;
+    if formatted == content {
+        return Ok(());
+    }
+    if check {
+        report_output(
+            reporter,
+            &get_unified_diff(
+                &ambit_paths.config.path,
+                content.as_bytes(),
+                &ambit_paths.config.path,
+                formatted.as_bytes(),
+            ),
+        );
+        return Err(AmbitError::Other(
+            "Configuration is not formatted. Run `ambit fmt` to format it.".to_owned(),
+        ));
+    }
+    fs::write(&ambit_paths.config.path, formatted)?;
+    reporter.report(Event::Message(&format!(
+        "Formatted {}",
+        ambit_paths.config.path.display()
+    )));
+    Ok(())
+}
+
+// Return the command that edits files, which is taken from VISUAL or EDITOR.
+// The command may contain arguments, e.g. `code --wait`.
+fn get_editor() -> Vec<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| {
+            match cfg!(windows) {
+                true => "notepad",
+                false => "vi",
+            }
+            .to_owned()
+        });
+    editor.split_whitespace().map(str::to_owned).collect()
+}
+
+/// Open the config in an editor, only accepting the changes once they parse.
+/// The config is edited through a copy, so that it is left untouched if the changes are not accepted.
+pub fn edit(ambit_paths: &AmbitPaths, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let editor = get_editor();
+    if editor.is_empty() {
+        return Err(AmbitError::Other("VISUAL or EDITOR is empty".to_owned()));
+    }
+    let content = match ambit_paths.config.exists() {
+        true => ambit_paths.config.as_string()?,
+        false => String::new(),
+    };
+    let edit_path = env::temp_dir().join(format!("ambit-{}-{}", std::process::id(), CONFIG_NAME));
+    // The file is created anew and only readable by the user, as the config may name secrets.
+    write_new_file(&edit_path, content.as_bytes(), Some(0o600))?;
+    let result = edit_config_file(ambit_paths, &editor, &edit_path, reporter);
+    // The copy is removed whether or not the changes were saved.
+    fs::remove_file(&edit_path)?;
+    result
+}
+
+// Open edit_path in editor until it holds a valid config, which is then saved as the config.
+fn edit_config_file(
+    ambit_paths: &AmbitPaths,
+    editor: &[String],
+    edit_path: &Path,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    loop {
+        let status = Command::new(&editor[0])
+            .args(&editor[1..])
+            .arg(edit_path)
+            .status()
+            .map_err(|e| AmbitError::Other(format!("Could not run `{}`: {}", editor[0], e)))?;
+        if !status.success() {
+            return Err(AmbitError::Other(format!("`{}` failed", editor[0])));
+        }
+        let content = fs::read_to_string(edit_path)?;
+        let errors: Vec<_> = config::get_statements(content.chars().peekable())
+            .filter_map(Result::err)
+            .collect();
+        if errors.is_empty() {
+            ambit_paths.config.ensure_parent_dirs_exist()?;
+            fs::write(&ambit_paths.config.path, content)?;
+            reporter.report(Event::Message(&format!(
+                "Saved {}",
+                ambit_paths.config.path.display()
+            )));
+            return Ok(());
+        }
+        reporter.report(Event::Warning(
+            &AmbitError::Parse { content, errors }.to_string(),
+        ));
+        if !prompt::confirm(
+            ambit_paths.assume,
+            "Configuration contains errors. Edit again?",
+            true,
+        )? {
+            return Err(AmbitError::Other(
+                "Configuration contains errors, changes were discarded".to_owned(),
+            ));
+        }
+    }
+}
+
+/// Print every string that spec expands to on this system, along with the case that each match expression takes.
+pub fn expand(
+    ambit_paths: &AmbitPaths,
+    spec: &str,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let content = format!("{};", spec);
+    let statements = config::get_statements(content.chars().peekable())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|error| AmbitError::Parse {
+            content: content.clone(),
+            errors: vec![error],
+        })?;
+    let spec = match statements.as_slice() {
+        [Statement::Entry(entry)] if entry.right.is_none() => &entry.left,
+        _ => {
+            return Err(AmbitError::Other(format!(
+                "`{}` is not a single spec",
+                spec
+            )))
+        }
+    };
+    let context = ambit_paths.context();
+    // Environment variables and `~` are expanded as they are in host paths when syncing.
+    for string in spec.iter_with(&context) {
+        reporter.report(Event::Message(&expand_path(ambit_paths, &string, true)?));
+    }
+    for (i, expr) in spec.match_exprs().iter().enumerate() {
+        match expr.cases.iter().find(|(case, _)| case.is_true(&context)) {
+            Some((case, _)) => {
+                reporter.report(Event::Message(&format!("match {}: took `{}`", i + 1, case)))
+            }
+            None => reporter.report(Event::Message(&format!("match {}: took no case", i + 1))),
+        }
+    }
+    Ok(())
+}
+
+/// Print the repository file that the host file at path is synced from, along with the entry that maps them.
+pub fn which(
+    ambit_paths: &AmbitPaths,
+    path: &Path,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let host_path = get_absolute_path(ambit_paths, path)?;
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut found = false;
+    for (entry, source) in config.entries.iter().zip(&config.sources) {
+        for (repo_file, host_file) in
+            get_ambit_paths_from_entry(ambit_paths, entry, &ignore, &cache)?
+        {
+            if host_file.path == host_path {
+                found = true;
+                reporter.report(Event::Message(&display_path(
+                    &repo_file.path,
+                    entry.attributes.secret,
+                )));
+                reporter.report(Event::Message(&format!(
+                    "    from {}:{}: {}",
+                    source.path.display(),
+                    source.line,
+                    display_entry(&source.text, entry.attributes.secret)
+                )));
+            }
+        }
+    }
+    match found {
+        true => Ok(()),
+        false => Err(AmbitError::Other(format!(
+            "`{}` is not synced by any entry",
+            path.display()
+        ))),
+    }
+}
+
+/// Print the repository and host paths that every entry resolves to.
+/// If pattern is given, only the pairs where either path matches it are printed.
+/// As with excludes, patterns without a `/` match the file name.
+pub fn list(
+    ambit_paths: &AmbitPaths,
+    pattern: Option<&str>,
+    numbered: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let filter = pattern.map(Exclude::new).transpose()?;
+    let entries = get_config_entries(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    for (i, entry) in entries.into_iter().enumerate() {
+        for (j, (repo_file, host_file)) in
+            get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?
+                .into_iter()
+                .enumerate()
+        {
+            let is_match = match &filter {
+                Some(filter) => {
+                    filter.matches(&repo_file.path, &ambit_paths.root.path)
+                        || filter.matches(&host_file.path, &ambit_paths.home.path)
+                }
+                None => true,
+            };
+            let paths = if entry.attributes.secret {
+                display_secret_file(i + 1, j + 1)
+            } else {
+                display_paths(&repo_file.path, &host_file.path, false)
+            };
+            // Paths are numbered by their entry, as selected with `--entries`.
+            if is_match && numbered {
+                reporter.report(Event::Message(&format!("{}: {}", i + 1, paths)));
+            } else if is_match {
+                reporter.report(Event::Message(&paths));
+            }
+        }
+    }
+    Ok(())
+}
+
+// Host files grouped by directory, along with the repository files they are synced from.
+#[derive(Default)]
+struct HostTree {
+    children: BTreeMap<String, HostTree>,
+    // The repository file that this host path is synced from, relative to the repository.
+    repo_file: Option<PathBuf>,
+}
+
+impl HostTree {
+    fn insert(&mut self, components: &[String], repo_file: PathBuf) {
+        match components.split_first() {
+            Some((first, rest)) => self
+                .children
+                .entry(first.clone())
+                .or_default()
+                .insert(rest, repo_file),
+            None => self.repo_file = Some(repo_file),
+        }
+    }
+
+    // Write the children of the tree, with every line starting with prefix.
+    fn write_tree(&self, prefix: &str, out: &mut String) {
+        for (i, (name, child)) in self.children.iter().enumerate() {
+            let is_last = i + 1 == self.children.len();
+            out.push_str(prefix);
+            out.push_str(if is_last { "└── " } else { "├── " });
+            out.push_str(name);
+            if let Some(repo_file) = &child.repo_file {
+                out.push_str(&format!(" -> {}", repo_file.display()));
+            }
+            out.push('\n');
+            let prefix = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+            child.write_tree(&prefix, out);
+        }
+    }
+
+    // Write the edges of the tree in the Graphviz dot language, where id is the path of this node.
+    fn write_dot(&self, id: &str, out: &mut String) {
+        let quote = |s: &str| format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""));
+        if let Some(repo_file) = &self.repo_file {
+            let repo_file = repo_file.display().to_string();
+            out.push_str(&format!(
+                "    {} -> {} [style=dashed];\n    {} [shape=box, label={}];\n",
+                quote(id),
+                quote(&format!("repo:{}", repo_file)),
+                quote(&format!("repo:{}", repo_file)),
+                quote(&repo_file)
+            ));
+        }
+        for (name, child) in &self.children {
+            let child_id = format!("{}/{}", id.trim_end_matches('/'), name);
+            out.push_str(&format!(
+                "    {} -> {};\n    {} [label={}];\n",
+                quote(id),
+                quote(&child_id),
+                quote(&child_id),
+                quote(name)
+            ));
+            child.write_dot(&child_id, out);
+        }
+    }
+}
+
+/// Print the host files that the config syncs as a tree grouped by directory, or as a Graphviz graph if dot is set.
+/// Host files within the home directory are shown under `~`.
+pub fn tree(ambit_paths: &AmbitPaths, dot: bool, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let entries = get_config_entries(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut roots: BTreeMap<String, HostTree> = BTreeMap::new();
+    for entry in entries {
+        for (repo_file, host_file) in
+            get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?
+        {
+            let (root, path) = match host_file.path.strip_prefix(&ambit_paths.home.path) {
+                Ok(path) => ("~".to_owned(), path),
+                Err(_) => {
+                    let root = host_file.path.ancestors().last().unwrap_or(Path::new(""));
+                    let path = host_file.path.strip_prefix(root).unwrap_or(&host_file.path);
+                    (root.display().to_string(), path)
+                }
+            };
+            let components: Vec<String> = path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let repo_file = repo_file
+                .path
+                .strip_prefix(&ambit_paths.root.path)
+                .unwrap_or(&repo_file.path)
+                .to_path_buf();
+            roots
+                .entry(root)
+                .or_default()
+                .insert(&components, repo_file);
+        }
+    }
+    let mut out = String::new();
+    if dot {
+        out.push_str("digraph ambit {\n    rankdir=LR;\n");
+        for (root, tree) in &roots {
+            tree.write_dot(root, &mut out);
+        }
+        out.push_str("}\n");
+    } else {
+        for (root, tree) in &roots {
+            out.push_str(root);
+            out.push('\n');
+            tree.write_tree("", &mut out);
+        }
+    }
+    report_output(reporter, &out);
+    Ok(())
+}
+
+// Return a unified diff from the content of repo_file to the content of host_file.
+fn get_unified_diff(
+    repo_file: &Path,
+    repo_content: &[u8],
+    host_file: &Path,
+    host_content: &[u8],
+) -> String {
+    let repo_content = String::from_utf8_lossy(repo_content);
+    let host_content = String::from_utf8_lossy(host_content);
+    TextDiff::from_lines(repo_content.as_ref(), host_content.as_ref())
+        .unified_diff()
+        .header(
+            &repo_file.display().to_string(),
+            &host_file.display().to_string(),
+        )
+        .to_string()
+}
+
+/// Show how host files that exist but are not synced differ from their repository files.
+/// If path is given, only the host file at path is compared.
+pub fn diff(
+    ambit_paths: &AmbitPaths,
+    path: Option<&Path>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let host_path = path
+        .map(|path| get_absolute_path(ambit_paths, path))
+        .transpose()?;
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut found = false;
+    for entry in &config.entries {
+        for (repo_file, host_file) in
+            get_ambit_paths_from_entry(ambit_paths, entry, &ignore, &cache)?
+        {
+            if matches!(&host_path, Some(host_path) if *host_path != host_file.path) {
+                continue;
+            }
+            found = true;
+            let mode = get_link_mode(&repo_file.path, entry.attributes.mode);
+            // Symlinks would be compared with the file they link to, so only regular files are compared.
+            let is_file = fs::symlink_metadata(&host_file.path)
+                .map(|metadata| metadata.is_file())
+                .unwrap_or(false);
+            if !is_file
+                || !repo_file.path.is_file()
+                || is_synced(
+                    ambit_paths,
+                    &host_file.path,
+                    &repo_file.path,
+                    mode,
+                    &config.vars,
+                )
+            {
+                continue;
+            }
+            // Encrypted files and templates are compared with what they would be synced as.
+            let repo_content = read_repo_file(ambit_paths, &repo_file.path, &config.vars)?;
+            let host_content = fs::read(&host_file.path)?;
+            if repo_content == host_content {
+                continue;
+            }
+            // The content of secrets is not printed.
+            if entry.attributes.secret {
+                reporter.report(Event::Message("Secret file differs from repository file"));
+                continue;
+            }
+            report_output(
+                reporter,
+                &get_unified_diff(
+                    &repo_file.path,
+                    &repo_content,
+                    &host_file.path,
+                    &host_content,
+                ),
+            );
+        }
+    }
+    match (path, found) {
+        (Some(path), false) => Err(AmbitError::Other(format!(
+            "`{}` is not synced by any entry",
+            path.display()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+// Return path as it is written in a configuration file, quoting it if it contains special characters.
+fn format_config_path(path: &Path) -> String {
+    let path = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/");
+    let is_special = |c: char| c.is_whitespace() || "(){}[],;:=*?\"\\$".contains(c);
+    // Tags and exclusions start with `#` and `!`.
+    if !path.contains(is_special) && !path.starts_with(&['#', '!'][..]) {
+        return path;
+    }
+    format!("\"{}\"", path.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Move a host file into the repository, add an entry for it to the config and symlink it.
+/// The file keeps its path relative to the home directory within the repository.
+pub fn add(
+    ambit_paths: &AmbitPaths,
+    path: &Path,
+    mut auto_commit: AutoCommit,
+    commit_message: Option<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    if !(ambit_paths.repo.exists() && ambit_paths.git.exists()) {
+        return Err(AmbitError::Other(
+            "Dotfile repository does not exist. Run `init` or `clone` before adding files."
+                .to_owned(),
+        ));
+    }
+    // The configuration is read before anything is moved, so that errors in it leave the file where it is.
+    if ambit_paths.config.exists() {
+        auto_commit = auto_commit.max(get_config(ambit_paths, &ambit_paths.config)?.auto_commit);
+    }
+    let host_path = get_absolute_path(ambit_paths, path)?;
+    let metadata = fs::symlink_metadata(&host_path).map_err(|e| AmbitError::File {
+        path: host_path.clone(),
+        error: e,
+    })?;
+    if metadata.file_type().is_symlink() {
+        return Err(AmbitError::Other(format!(
+            "`{}` is a symlink, it may already be synced",
+            host_path.display()
+        )));
+    }
+    let relative_path = host_path
+        .strip_prefix(&ambit_paths.home.path)
+        .map_err(|_| {
+            AmbitError::Other(format!(
+                "`{}` is not within the home directory",
+                host_path.display()
+            ))
+        })?
+        .to_path_buf();
+    let kind = match metadata.is_dir() {
+        true => AmbitPathKind::Directory,
+        false => AmbitPathKind::File,
+    };
+    let repo_file = AmbitPath::new(ambit_paths.root.path.join(&relative_path), kind);
+    if fs::symlink_metadata(&repo_file.path).is_ok() {
+        return Err(AmbitError::Other(format!(
+            "Repository file `{}` already exists",
+            repo_file.path.display()
+        )));
+    }
+    repo_file.ensure_parent_dirs_exist()?;
+    move_path(&host_path, &repo_file.path)?;
+    let result = match kind {
+        AmbitPathKind::Directory => symlink_dir(&repo_file.path, &host_path),
+        AmbitPathKind::File => symlink_file(&repo_file.path, &host_path),
+    };
+    if let Err(e) = result {
+        // Put the file back, so that it is not lost in the repository.
+        move_path(&repo_file.path, &host_path)?;
+        return Err(e.into());
+    }
+    // Directories are marked with a trailing `/`, so that they are symlinked as a whole.
+    let mut entry = format_config_path(&relative_path);
+    if kind == AmbitPathKind::Directory {
+        entry.push('/');
+    }
+    let mut config = match ambit_paths.config.exists() {
+        true => ambit_paths.config.as_string()?,
+        false => String::new(),
+    };
+    if !config.is_empty() && !config.ends_with('\n') {
+        config.push('\n');
+    }
+    config.push_str(&format!("{};\n", entry));
+    ambit_paths.config.ensure_parent_dirs_exist()?;
+    fs::write(&ambit_paths.config.path, config)?;
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    manifest.insert(&host_path, &repo_file.path, LinkMode::Symlink);
+    manifest.save(&ambit_paths.manifest.path)?;
+    reporter.report(Event::Message(&format!(
+        "Added {} -> {}",
+        host_path.display(),
+        repo_file.path.display()
+    )));
+    // The entry is committed along with the file if the config is kept in the repository.
+    let mut moved_files = vec![repo_file.path.as_path()];
+    if ambit_paths.config.path.starts_with(&ambit_paths.repo.path) {
+        moved_files.push(&ambit_paths.config.path);
+    }
+    commit_moved_files(
+        ambit_paths,
+        &moved_files,
+        auto_commit,
+        commit_message,
+        reporter,
+    )
+}
+
+// Return the path within to that corresponds to path within from.
+fn get_copied_path(from: &Path, to: &Path, path: &Path) -> AmbitResult<PathBuf> {
+    let relative_path = path.strip_prefix(from)?;
+    // Joining an empty path would add a trailing `/`, which files cannot be opened with.
+    Ok(match relative_path.as_os_str().is_empty() {
+        true => to.to_path_buf(),
+        false => to.join(relative_path),
+    })
+}
+
+// Copy the file or directory at from to to, recreating the symlinks within it rather than following them.
+// Directories keep their permissions, as files do when copied.
+fn copy_path(from: &Path, to: &Path) -> AmbitResult<()> {
+    let mut dirs = Vec::new();
+    for dir_entry in WalkDir::new(from) {
+        let dir_entry = dir_entry?;
+        let path = get_copied_path(from, to, dir_entry.path())?;
+        if dir_entry.path_is_symlink() {
+            let target = fs::read_link(dir_entry.path())?;
+            match dir_entry.path().is_dir() {
+                true => symlink_dir(target, &path)?,
+                false => symlink_file(target, &path)?,
+            }
+            // A symlink to a directory at from itself would be walked into otherwise.
+            if dir_entry.depth() == 0 {
+                break;
+            }
+        } else if dir_entry.file_type().is_dir() {
+            fs::create_dir_all(&path)?;
+            dirs.push((path, dir_entry.metadata()?.permissions()));
+        } else {
+            fs::copy(dir_entry.path(), &path)?;
+        }
+    }
+    // Read-only directories could not be filled, so their permissions are set last.
+    for (dir, permissions) in dirs.into_iter().rev() {
+        fs::set_permissions(dir, permissions)?;
+    }
+    Ok(())
+}
+
+// Return if to holds the same files and symlinks as from, with the same content.
+fn is_copy(from: &Path, to: &Path) -> AmbitResult<bool> {
+    for dir_entry in WalkDir::new(from) {
+        let dir_entry = dir_entry?;
+        let path = get_copied_path(from, to, dir_entry.path())?;
+        let is_same = if dir_entry.path_is_symlink() {
+            fs::read_link(&path).ok() == Some(fs::read_link(dir_entry.path())?)
+        } else if dir_entry.file_type().is_dir() {
+            fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_dir())
+        } else {
+            fs::read(&path).ok() == Some(fs::read(dir_entry.path())?)
+        };
+        if !is_same {
+            return Ok(false);
+        }
+        if dir_entry.path_is_symlink() && dir_entry.depth() == 0 {
+            break;
+        }
+    }
+    Ok(true)
+}
+
+// Remove the file, symlink or directory at path.
+fn remove_path(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path)?.is_dir() {
+        true => fs::remove_dir_all(path),
+        false => fs::remove_file(path),
+    }
+}
+
+// Move from to to by copying it, e.g. if they are on different filesystems.
+// from is only removed once the copy is found to be identical, and the copy is removed if it is not.
+fn move_by_copy(from: &Path, to: &Path) -> AmbitResult<()> {
+    let result = copy_path(from, to).and_then(|_| match is_copy(from, to)? {
+        true => Ok(()),
+        false => Err(AmbitError::Other(format!(
+            "Could not move {} to {}, as the copy differs",
+            from.display(),
+            to.display()
+        ))),
+    });
+    if let Err(e) = result {
+        // The copy is incomplete, so it is not worth keeping.
+        let _ = remove_path(to);
+        return Err(e);
+    }
+    Ok(remove_path(from)?)
+}
+
+// Move the file or directory at from to to.
+// Renaming fails if they are on different filesystems, e.g. if the repository is on another disk than the home directory,
+// in which case from is copied instead.
+fn move_path(from: &Path, to: &Path) -> AmbitResult<()> {
+    match fs::rename(from, to) {
+        Err(e) if e.kind() == io::ErrorKind::CrossesDevices => move_by_copy(from, to),
+        result => Ok(result?),
+    }
+}
+
+/// Move the dotfile repository to new_path and point the symlinks of the configuration into it.
+pub fn relocate(
+    ambit_paths: &AmbitPaths,
+    new_path: &Path,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let new_path = get_absolute_path(ambit_paths, new_path)?;
+    if new_path.starts_with(&ambit_paths.repo.path) {
+        return Err(AmbitError::Other(
+            "Cannot relocate the repository into itself".to_owned(),
+        ));
+    }
+    // The location is stored as text, so it is read back the same on every platform.
+    let location = new_path
+        .to_str()
+        .ok_or_else(|| AmbitError::Other(format!("`{}` is not valid UTF-8", new_path.display())))?;
+    if fs::symlink_metadata(&new_path).is_ok() {
+        return Err(AmbitError::Other(format!(
+            "`{}` already exists",
+            new_path.display()
+        )));
+    }
+    // The symlinks are found before moving, as the configuration may be within the repository.
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let mut host_files = vec![AmbitPath::new(
+        ambit_paths.config.path.clone(),
+        AmbitPathKind::File,
+    )];
+    for entry in &config.entries {
+        for (_, host_file) in get_clean_paths(ambit_paths, entry, &ignore, &cache)? {
+            host_files.push(host_file);
+        }
+    }
+    // Files synced by entries that are no longer in the configuration are kept pointing into the repository too.
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    for entry in manifest.entries() {
+        let kind = match fs::metadata(&entry.host_file).map(|metadata| metadata.is_dir()) {
+            Ok(true) => AmbitPathKind::Directory,
+            _ => AmbitPathKind::File,
+        };
+        host_files.push(AmbitPath::new(entry.host_file.clone(), kind));
+    }
+    // Each link is kept with its current and new target, so that it can be pointed back if relocating fails.
+    let mut links: Vec<(AmbitPath, PathBuf, PathBuf)> = Vec::new();
+    for host_file in host_files {
+        let target = match fs::read_link(&host_file.path) {
+            Ok(target) => target,
+            Err(_) => continue,
+        };
+        if let Ok(relative_target) = target.strip_prefix(&ambit_paths.repo.path) {
+            if !links.iter().any(|(link, _, _)| link.path == host_file.path) {
+                let new_target = new_path.join(relative_target);
+                links.push((host_file, target, new_target));
+            }
+        }
+    }
+    if let Some(parent) = new_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let previous_location = fs::read(&ambit_paths.repo_location.path).ok();
+    move_path(&ambit_paths.repo.path, &new_path)?;
+    // Later runs find the repository at the new location through the stored path. It is stored before any link is
+    // rewritten, so that the repository is still found if rewriting is interrupted.
+    let mut finish = || -> AmbitResult<()> {
+        ambit_paths.repo_location.ensure_parent_dirs_exist()?;
+        fs::write(&ambit_paths.repo_location.path, format!("{}\n", location))?;
+        for (link, _, new_target) in &links {
+            replace_symlink(link, new_target)?;
+        }
+        manifest.relocate(&ambit_paths.repo.path, &new_path);
+        manifest.save(&ambit_paths.manifest.path)
+    };
+    if let Err(e) = finish() {
+        // The move is rolled back, so that the repository, its location and the links agree again.
+        for (link, target, _) in &links {
+            let _ = replace_symlink(link, target);
+        }
+        let _ = match previous_location {
+            Some(previous_location) => {
+                fs::write(&ambit_paths.repo_location.path, previous_location)
+            }
+            None => fs::remove_file(&ambit_paths.repo_location.path),
+        };
+        move_path(&new_path, &ambit_paths.repo.path)?;
+        return Err(e);
+    }
+    reporter.report(Event::Message(&format!(
+        "Relocated repository to {}; rewrote {} symlink(s)",
+        new_path.display(),
+        links.len()
+    )));
+    if env::var_os("AMBIT_REPO_PATH").is_some() {
+        reporter.report(Event::Message(&format!(
+            "AMBIT_REPO_PATH is set, update it to {} to keep using the repository",
+            new_path.display()
+        )));
+    }
+    Ok(())
+}
+
+// Point link at target, replacing the link if it exists.
+fn replace_symlink(link: &AmbitPath, target: &Path) -> AmbitResult<()> {
+    if fs::symlink_metadata(&link.path).is_ok() {
+        link.remove()?;
+    }
+    match link.is_dir() {
+        true => symlink_dir(target, &link.path)?,
+        false => symlink_file(target, &link.path)?,
+    }
+    Ok(())
+}
+
+// Return the files within the repository root that are not synced by any entry of config,
+// skipping the files of git, ignored paths and the configuration files themselves.
+fn get_unreferenced_repo_files(
+    ambit_paths: &AmbitPaths,
+    config: &Config,
+    ignore: &Ignore,
+    cache: &DirCache,
+) -> AmbitResult<Vec<PathBuf>> {
+    let mut referenced = vec![
+        ambit_paths.config.path.clone(),
+        ambit_paths.repo.path.join(IGNORE_NAME),
+        ambit_paths.repo.path.join(".gitmodules"),
+    ];
+    referenced.extend(config.sources.iter().map(|source| source.path.clone()));
+    for entry in &config.entries {
+        for (repo_file, _) in get_ambit_paths_from_entry(ambit_paths, entry, ignore, cache)? {
+            referenced.push(repo_file.path);
+        }
+    }
+    let walker = WalkDir::new(&ambit_paths.root.path)
+        .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+        .into_iter()
+        .filter_entry(|dir_entry| {
+            let path = dir_entry.path();
+            let repo_path = path.strip_prefix(&ambit_paths.repo.path).unwrap_or(path);
+            dir_entry.file_name() != ".git"
+                && !ignore.is_ignored(repo_path, dir_entry.file_type().is_dir())
+        });
+    let mut unreferenced = Vec::new();
+    for dir_entry in walker {
+        let dir_entry = dir_entry?;
+        // Directories are referenced through their files.
+        if dir_entry.file_type().is_dir() {
+            continue;
+        }
+        // Files within a directory that is synced as a whole are referenced by it.
+        if !referenced
+            .iter()
+            .any(|path| dir_entry.path().starts_with(path))
+        {
+            unreferenced.push(dir_entry.into_path());
+        }
+    }
+    Ok(unreferenced)
+}
+
+/// List the repository files that are not referenced by the config.
+/// If delete is set, they are deleted after confirmation, along with the directories left empty.
+pub fn prune(
+    ambit_paths: &AmbitPaths,
+    delete: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let files = get_unreferenced_repo_files(
+        ambit_paths,
+        &config,
+        &get_repo_ignore(ambit_paths)?,
+        &DirCache::default(),
+    )?;
+    for file in &files {
+        reporter.report(Event::Message(&format!("unreferenced: {}", file.display())));
+    }
+    let mut deletions: usize = 0;
+    if delete
+        && !files.is_empty()
+        && prompt::confirm(
+            ambit_paths.assume,
+            &format!("Delete {} unreferenced repository file(s)?", files.len()),
+            false,
+        )?
+    {
+        for file in &files {
+            fs::remove_file(file)?;
+            deletions += 1;
+            // Removing a directory fails once a directory that is not empty is reached.
+            let mut parent = file.parent();
+            while let Some(dir) = parent.filter(|dir| *dir != ambit_paths.root.path) {
+                if fs::remove_dir(dir).is_err() {
+                    break;
+                }
+                parent = dir.parent();
+            }
+        }
+    }
+    reporter.report(Event::Message(&format!(
+        "prune result ({} total): {} deleted; {} kept",
+        files.len(),
+        deletions,
+        files.len() - deletions
+    )));
+    Ok(())
+}
+
+// Return content without the given span, along with the rest of its line if nothing else is on it.
+fn remove_span(content: &str, span: Range<usize>) -> String {
+    let line_start = content[..span.start]
+        .rfind('\n')
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let line_end = content[span.end..]
+        .find('\n')
+        .map(|i| span.end + i + 1)
+        .unwrap_or_else(|| content.len());
+    let (start, end) = match content[line_start..span.start].trim().is_empty()
+        && content[span.end..line_end].trim().is_empty()
+    {
+        true => (line_start, line_end),
+        false => (span.start, span.end),
+    };
+    format!("{}{}", &content[..start], &content[end..])
+}
+
+/// Drop the entry that syncs the given host file from the config and delete the host files of that entry.
+/// If delete_repo_files is set, the repository files of the entry are deleted as well.
+pub fn remove(
+    ambit_paths: &AmbitPaths,
+    path: &Path,
+    delete_repo_files: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let host_path = get_absolute_path(ambit_paths, path)?;
+    let config = get_config(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    let content = ambit_paths.config.as_string()?;
+    // Only the entries of the config itself are considered, as they are the ones that can be edited here.
+    let mut parser = config::get_statements(content.chars().peekable());
+    let mut found = None;
+    while let Some(statement) = parser.next() {
+        if let Statement::Entry(entry) = statement.map_err(|e| AmbitError::Parse {
+            content: content.clone(),
+            errors: vec![e],
+        })? {
+            let paths = get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?;
+            if paths
+                .iter()
+                .any(|(_, host_file)| host_file.path == host_path)
+            {
+                found = Some((entry, parser.statement_span()));
+                break;
+            }
+        }
+    }
+    let (entry, span) = found.ok_or_else(|| {
+        AmbitError::Other(format!(
+            "`{}` is not synced by an entry of `{}`",
+            path.display(),
+            ambit_paths.config.path.display()
+        ))
+    })?;
+    let mut manifest = Manifest::load(&ambit_paths.manifest.path)?;
+    for (repo_file, host_file) in get_clean_paths(ambit_paths, &entry, &ignore, &cache)? {
+        let mode = get_link_mode(&repo_file.path, entry.attributes.mode);
+        if is_synced(
+            ambit_paths,
+            &host_file.path,
+            &repo_file.path,
+            mode,
+            &config.vars,
+        ) {
+            host_file.remove()?;
+            reporter.report(Event::Message(&format!(
+                "Deleted {}",
+                display_path(&host_file.path, entry.attributes.secret)
+            )));
+        }
+        manifest.remove(&host_file.path);
+        if delete_repo_files && repo_file.exists() {
+            repo_file.remove()?;
+            reporter.report(Event::Message(&format!(
+                "Deleted {}",
+                display_path(&repo_file.path, entry.attributes.secret)
+            )));
+        }
+    }
+    manifest.save(&ambit_paths.manifest.path)?;
+    fs::write(
+        &ambit_paths.config.path,
+        remove_span(&content, span.clone()),
+    )?;
+    reporter.report(Event::Message(&format!(
+        "Removed `{}` from {}",
+        display_entry(&content[span], entry.attributes.secret),
+        ambit_paths.config.path.display()
+    )));
+    Ok(())
+}
+
+/// Encrypt a host file back into the encrypted repository file that it is synced from.
+pub fn encrypt(
+    ambit_paths: &AmbitPaths,
+    path: &Path,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    let host_path = get_absolute_path(ambit_paths, path)?;
+    let entries = get_config_entries(ambit_paths, &ambit_paths.config)?;
+    let ignore = get_repo_ignore(ambit_paths)?;
+    let cache = DirCache::default();
+    for entry in entries {
+        for (repo_file, host_file) in
+            get_ambit_paths_from_entry(ambit_paths, &entry, &ignore, &cache)?
+        {
+            if host_file.path != host_path {
+                continue;
+            }
+            if let Some(encryption) = Encryption::from_path(&repo_file.path) {
+                encryption.encrypt(
+                    &fs::read(&host_file.path)?,
+                    &repo_file.path,
+                    &ambit_paths.age_identity.path,
+                )?;
+                reporter.report(Event::Message(&format!(
+                    "Encrypted {}",
+                    repo_file.path.display()
+                )));
+                return Ok(());
+            }
+        }
+    }
+    Err(AmbitError::Other(format!(
+        "`{}` is not synced from an encrypted repository file",
+        path.display()
+    )))
+}
+
+// Return an error if the dotfile repository has not been initialized.
+fn ensure_repo_exists(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+    if !(ambit_paths.repo.exists() && ambit_paths.git.exists()) {
+        return Err(AmbitError::Other(
+            "Dotfile repository does not exist. Run `init` or `clone` first.".to_owned(),
+        ));
+    }
+    Ok(())
+}
+
+// Return a commit message for the changes listed by `git diff --name-status`.
+// The subject names the changed files if there are few of them, and the body lists every change.
+fn get_default_commit_message(name_status: &str) -> String {
+    let mut paths = Vec::new();
+    let mut changes = Vec::new();
+    for line in name_status.lines() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let change = match (fields[0].chars().next(), &fields[1..]) {
+            (Some('A'), [path]) => format!("Add {}", path),
+            (Some('D'), [path]) => format!("Delete {}", path),
+            (Some('R'), [from, to]) => format!("Rename {} to {}", from, to),
+            (Some('C'), [from, to]) => format!("Copy {} to {}", from, to),
+            (_, [.., path]) => format!("Update {}", path),
+            _ => continue,
+        };
+        paths.push(fields[fields.len() - 1]);
+        changes.push(change);
+    }
+    let subject = match paths.len() {
+        1 => return changes.remove(0),
+        2 | 3 => format!("Update {}", paths.join(", ")),
+        n => format!("Update {} files", n),
+    };
+    let body: Vec<String> = changes
+        .iter()
+        .map(|change| format!("- {}", change))
+        .collect();
+    format!("{}\n\n{}", subject, body.join("\n"))
+}
+
+// Commit the files that were moved into the dotfile repository, and push them if auto_commit is Push.
+// Other changes to the repository are left uncommitted, and are not pushed either.
+fn commit_moved_files(
+    ambit_paths: &AmbitPaths,
+    paths: &[&Path],
+    auto_commit: AutoCommit,
+    message: Option<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    if auto_commit == AutoCommit::Off || paths.is_empty() {
+        return Ok(());
+    }
+    commit_paths(ambit_paths, paths, message, reporter)?;
+    if auto_commit == AutoCommit::Push {
+        push_to_remotes(ambit_paths, Vec::new(), reporter)?;
+    }
+    Ok(())
+}
+
+/// Stage every change in the dotfile repository and commit it.
+/// If message is None, a message listing the changed files is used.
+pub fn commit(
+    ambit_paths: &AmbitPaths,
+    message: Option<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    commit_paths(ambit_paths, &[], message, reporter)
+}
+
+// Stage the changes to paths within the dotfile repository and commit only those, or every change if paths is empty.
+fn commit_paths(
+    ambit_paths: &AmbitPaths,
+    paths: &[&Path],
+    message: Option<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    // Paths are passed relative to the repository, so that they show up in the default message as they do in git.
+    let pathspec: Vec<String> = paths
+        .iter()
+        .map(|path| {
+            let path = path.strip_prefix(&ambit_paths.repo.path).unwrap_or(path);
+            path.to_string_lossy().into_owned()
+        })
+        .collect();
+    let pathspec: Vec<&str> = pathspec.iter().map(String::as_str).collect();
+    if !git::run(
+        ambit_paths,
+        &[&["add", "--all", "--"][..], &pathspec].concat(),
+    )? {
+        return Err(AmbitError::Other("Failed to stage changes".to_owned()));
+    }
+    let name_status = git::output(
+        ambit_paths,
+        &[&["diff", "--cached", "--name-status", "--"][..], &pathspec].concat(),
+    )?;
+    if name_status.is_empty() {
+        reporter.report(Event::Message("Nothing to commit"));
+        return Ok(());
+    }
+    let message = match message {
+        Some(message) => message.to_owned(),
+        None => get_default_commit_message(&name_status),
+    };
+    let commit = [
+        &["commit", "--quiet", "--message", &message, "--"][..],
+        &pathspec,
+    ]
+    .concat();
+    if !git::run(ambit_paths, &commit)? {
+        return Err(AmbitError::Other("Failed to commit changes".to_owned()));
+    }
+    reporter.report(Event::Message(message.lines().next().unwrap_or("")));
+    Ok(())
+}
+
+/// Push the dotfile repository with the given git arguments, or to every remote if there are none.
+/// Pushing is refused if there are uncommitted changes.
+pub fn push(
+    ambit_paths: &AmbitPaths,
+    arguments: Vec<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    if git::has_uncommitted_changes(ambit_paths)? {
+        return Err(AmbitError::Other(
+            "Dotfile repository has uncommitted changes.\nCommit them with `ambit git commit` before pushing.".to_owned(),
+        ));
+    }
+    push_to_remotes(ambit_paths, arguments, reporter)
+}
+
+// Push the dotfile repository with the given git arguments, or to every remote if there are none.
+fn push_to_remotes(
+    ambit_paths: &AmbitPaths,
+    arguments: Vec<&str>,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    if !arguments.is_empty() {
+        if !git::run(ambit_paths, &[&["push"], arguments.as_slice()].concat())? {
+            return Err(AmbitError::Other(
+                "Failed to push dotfile repository".to_owned(),
+            ));
+        }
+        return Ok(());
+    }
+    // Without arguments, the current branch is pushed to every remote, e.g. to keep mirrors up to date.
+    let remotes = git::output(ambit_paths, &["remote"])?;
+    if remotes.trim().is_empty() {
+        return Err(AmbitError::Other(
+            "Dotfile repository has no remotes.\nAdd one with `ambit git remote add <NAME> <URL>`."
+                .to_owned(),
+        ));
+    }
+    let mut pushed: usize = 0;
+    let mut total: usize = 0;
+    for remote in remotes.lines() {
+        if git::run(ambit_paths, &["push", remote, "HEAD"])? {
+            reporter.report(Event::Message(&format!("pushed: {}", remote)));
+            pushed += 1;
+        } else {
+            reporter.report(Event::Message(&format!("failed: {}", remote)));
+        }
+        total += 1;
+    }
+    reporter.report(Event::Message(&format!(
+        "push result ({} total): {} pushed; {} failed",
+        total,
+        pushed,
+        total - pushed
+    )));
+    if pushed < total {
+        return Err(AmbitError::Other(format!(
+            "Failed to push to {} remote(s)",
+            total - pushed
+        )));
+    }
+    Ok(())
+}
+
+/// Fast-forward the dotfile repository and sync the changes.
+/// Uncommitted changes are stashed while pulling if autostash is set.
+pub fn pull(
+    ambit_paths: &AmbitPaths,
+    autostash: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let stashed = git::has_uncommitted_changes(ambit_paths)?;
+    if stashed {
+        if !autostash {
+            return Err(AmbitError::Other(
+                "Dotfile repository has uncommitted changes.\nCommit them, or use '--autostash' to stash them while pulling.".to_owned(),
+            ));
+        }
+        git::stash(ambit_paths)?;
+    }
+    let pulled = git::pull(ambit_paths);
+    // The stash is restored even if pulling failed.
+    if stashed {
+        git::unstash(ambit_paths)?;
+    }
+    match pulled? {
+        git::Pull::Done(Some(summary)) => reporter.report(Event::Message(&summary)),
+        git::Pull::Done(None) => {}
+        git::Pull::Diverged => return Err(AmbitError::Other(
+            "Could not fast-forward dotfile repository.\nMerge or rebase the changes with `ambit git pull`, then run `ambit sync`.".to_owned(),
+        )),
+    }
+    // Pulled commits may point submodules to other commits.
+    git::update_submodules(ambit_paths)?;
+    sync(ambit_paths, &SyncOptions::default(), reporter)?;
+    Ok(())
+}
+
+// Hooks that re-sync after the repository is updated with git directly.
+const SYNC_HOOKS: [&str; 2] = ["post-merge", "post-checkout"];
+// Marks hooks that ambit wrote, so that they can be overwritten.
+const SYNC_HOOK_MARKER: &str = "# Installed by `ambit hook install`.";
+
+// Quote an argument for a POSIX shell.
+fn shell_quote(argument: &str) -> String {
+    format!("'{}'", argument.replace('\'', "'\\''"))
+}
+
+// Return the content of a hook that runs `ambit sync --quiet` with the ambit executable at exe.
+// The paths and profile are resolved at install time, so that the hook syncs the same repository, even when they
+// were given with flags or environment variables that git does not run it with.
+fn get_sync_hook(exe: &Path, ambit_paths: &AmbitPaths) -> String {
+    let mut arguments = vec![
+        shell_quote(&exe.to_string_lossy()),
+        "--home".to_owned(),
+        shell_quote(&ambit_paths.home.path.to_string_lossy()),
+        "--config".to_owned(),
+        shell_quote(&ambit_paths.config.path.to_string_lossy()),
+        "--repo".to_owned(),
+        shell_quote(&ambit_paths.repo.path.to_string_lossy()),
+    ];
+    if let Some(profile) = &ambit_paths.profile {
+        arguments.push("--profile".to_owned());
+        arguments.push(shell_quote(profile));
+    }
+    format!(
+        "#!/bin/sh\n{}\nexec {} sync --quiet\n",
+        SYNC_HOOK_MARKER,
+        arguments.join(" ")
+    )
+}
+
+/// Install git hooks into the dotfile repository that sync after merging (e.g. pulling) or checking out.
+/// Existing hooks that ambit did not write are only overwritten if force is set.
+pub fn install_hooks(
+    ambit_paths: &AmbitPaths,
+    force: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    let hooks_dir = ambit_paths.git.path.join("hooks");
+    fs::create_dir_all(&hooks_dir)?;
+    let hook = get_sync_hook(&env::current_exe()?, ambit_paths);
+    // Every hook is checked before any is written, so that refusing leaves all of them as they were.
+    for name in SYNC_HOOKS.iter().filter(|_| !force) {
+        let path = hooks_dir.join(name);
+        // Hooks are compared as bytes, as ones that are not valid UTF-8 still exist.
+        let is_foreign = match fs::read(&path) {
+            Ok(content) => !content
+                .windows(SYNC_HOOK_MARKER.len())
+                .any(|window| window == SYNC_HOOK_MARKER.as_bytes()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => false,
+            Err(e) => return Err(AmbitError::File { path, error: e }),
+        };
+        if is_foreign {
+            return Err(AmbitError::Other(format!(
+                "Hook {} already exists.\nUse '-f' flag to overwrite.",
+                path.display()
+            )));
+        }
+    }
+    for name in SYNC_HOOKS.iter() {
+        let path = hooks_dir.join(name);
+        fs::write(&path, &hook)?;
+        set_permissions(&path, 0o755)?;
+        reporter.report(Event::Message(&format!("Installed {}", path.display())));
+    }
+    Ok(())
+}
+
+/// Check out the submodules of the dotfile repository at the commits it records, then sync.
+pub fn update(ambit_paths: &AmbitPaths, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    // The configuration may use other files than when the patterns were set.
+    if git::is_sparse(ambit_paths)? {
+        set_sparse_patterns(ambit_paths, reporter)?;
+    }
+    git::update_submodules(ambit_paths)?;
+    sync(ambit_paths, &SyncOptions::default(), reporter)?;
+    Ok(())
+}
+
+// Return the sparse checkout patterns that match the repository files of config,
+// along with the configuration files within the repository.
+fn get_sparse_patterns(
+    ambit_paths: &AmbitPaths,
+    config: &Config,
+    config_path: &Path,
+) -> Vec<String> {
+    let root = ambit_paths
+        .root
+        .path
+        .strip_prefix(&ambit_paths.repo.path)
+        .unwrap_or_else(|_| Path::new(""));
+    let context = ambit_paths.context();
+    let repo_paths = config
+        .entries
+        .iter()
+        .flat_map(|entry| entry.left.iter_with(&context))
+        .map(|path| root.join(path));
+    let config_paths = std::iter::once(config_path)
+        .chain(config.sources.iter().map(|source| source.path.as_path()))
+        .filter_map(|path| path.strip_prefix(&ambit_paths.repo.path).ok())
+        .map(Path::to_path_buf);
+    // Patterns are anchored to the root of the repository.
+    let to_pattern = |path: PathBuf| {
+        let path = path.to_string_lossy().replace('\\', "/");
+        format!("/{}", path.trim_end_matches('/'))
+    };
+    // Directories only match their content through a separate pattern.
+    let entry_patterns = repo_paths
+        .flat_map(|path| vec![format!("{}/**", to_pattern(path.clone())), to_pattern(path)]);
+    let file_patterns = config_paths
+        .chain(vec![
+            PathBuf::from(IGNORE_NAME),
+            PathBuf::from(".gitmodules"),
+        ])
+        .map(to_pattern);
+    let mut patterns: Vec<String> = Vec::new();
+    for pattern in entry_patterns.chain(file_patterns) {
+        if !patterns.contains(&pattern) {
+            patterns.push(pattern);
+        }
+    }
+    patterns
+}
+
+// Set the sparse checkout patterns of the dotfile repository from the configuration in the default location,
+// or, if it has no content, from the first configuration found in the repository.
+fn set_sparse_patterns(ambit_paths: &AmbitPaths, reporter: &mut dyn Reporter) -> AmbitResult<()> {
+    let config_path = match fs::read_to_string(&ambit_paths.config.path) {
+        Ok(content) if !content.trim().is_empty() => ambit_paths.config.path.clone(),
+        _ => get_repo_config_paths(ambit_paths, true, &get_repo_ignore(ambit_paths)?)
+            .into_iter()
+            .next()
+            .ok_or_else(|| {
+                AmbitError::Other(
+                    "Could not find configuration file to derive sparse checkout patterns from."
+                        .to_owned(),
+                )
+            })?,
+    };
+    let config = get_config(
+        ambit_paths,
+        &AmbitPath::new(config_path.clone(), AmbitPathKind::File),
+    )?;
+    let patterns = get_sparse_patterns(ambit_paths, &config, &config_path);
+    git::set_sparse_patterns(ambit_paths, &patterns)?;
+    reporter.report(Event::Message(&format!(
+        "Checked out {} sparse checkout pattern(s) from {}",
+        patterns.len(),
+        config_path.display()
+    )));
+    Ok(())
+}
+
+/// Only check out the files of the dotfile repository that the configuration uses, or check out every file if disable is set.
+pub fn sparse(
+    ambit_paths: &AmbitPaths,
+    disable: bool,
+    reporter: &mut dyn Reporter,
+) -> AmbitResult<()> {
+    ensure_repo_exists(ambit_paths)?;
+    match disable {
+        true => git::disable_sparse(ambit_paths),
+        false => set_sparse_patterns(ambit_paths, reporter),
+    }
+}
+
+/// Run git commands from the dotfile repository
+pub fn git(ambit_paths: &AmbitPaths, arguments: Vec<&str>) -> AmbitResult<()> {
+    let mut command = git::command(ambit_paths)?;
+    command.args(arguments);
+    // Conditional compilation so that this still compiles on Windows.
+    #[cfg(unix)]
+    fn exec_git_cmd(mut command: Command) -> AmbitResult<()> {
+        use std::os::unix::process::CommandExt;
+        // Try to replace this process with the `git` process.
+        // This is to allow stuff like terminal colors.
+        // We just want `ambit git` to act like `cd ~/.config/ambit/repo; git`.
+        // If the `.exec()` method returns, it failed to execute, so it's automatically an error.
+        Err(AmbitError::Io(command.exec()))
+    }
+    #[cfg(not(unix))]
+    fn exec_git_cmd(mut command: Command) -> AmbitResult<()> {
+        use std::io::Write;
+        // Not easy to do this on other systems, just use defaults
+        let output = command.output()?;
+        io::stdout().write_all(&output.stdout)?;
+        io::stdout().write_all(&output.stderr)?;
+        Ok(())
+    }
+    exec_git_cmd(command)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        copy_path, escalate, exclude_paths, expand_tilde, expand_vars, format_config_path,
+        get_backup_path, get_captures, get_default_commit_message, get_git_summary,
+        get_paths_from_pattern, get_sync_hook, get_unified_diff, has_capture_references,
+        is_symlink_unsupported, mirror_paths, move_by_copy, remove_span, split_at_pattern,
+        substitute_captures, DirCache, EntrySelection, Exclude,
+    };
+    use crate::{directories::AmbitPaths, error::AmbitError};
+    use std::{
+        collections::HashSet,
+        ffi::OsStr,
+        fs::{self, File},
+        io,
+        path::{Path, PathBuf},
+    };
+
+    fn test_pattern(pattern: &str, existing_paths: &[&str], expected_paths: &[PathBuf]) {
+        let dir_path = tempfile::tempdir().unwrap().into_path();
+        // Create paths.
+        for path in existing_paths {
+            let path = dir_path.join(path);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            File::create(path).unwrap();
+        }
+        let paths = get_paths_from_pattern(pattern, &dir_path, &DirCache::default()).unwrap();
+        // Assert that there are no duplicates as they would be removed when collected into a HashSet.
+        assert_eq!(paths.len(), expected_paths.len());
+        let paths: HashSet<&PathBuf> = paths.iter().collect();
+        // Use a HashSet as order of paths should not matter.
+        assert_eq!(paths, expected_paths.iter().collect::<HashSet<&PathBuf>>());
+    }
+
+    #[test]
+    fn expand_env_vars_in_path() {
+        let var = |name: &str| {
+            Some(name)
+                .filter(|name| *name == "DIR")
+                .map(str::to_lowercase)
+        };
+        assert_eq!(expand_vars("$DIR/a/$/b", var).unwrap(), "dir/a/$/b");
+        assert!(expand_vars("$UNSET_DIR/a", var).is_err());
+    }
+
+    #[test]
+    fn expand_tilde_in_path() {
+        let ambit_paths = AmbitPaths::builder().home("/home/a").build().unwrap();
+        assert_eq!(
+            expand_tilde(&ambit_paths, "~/.config/a").unwrap(),
+            "/home/a/.config/a"
+        );
+        assert_eq!(expand_tilde(&ambit_paths, "~").unwrap(), "/home/a");
+        assert_eq!(expand_tilde(&ambit_paths, "a/~").unwrap(), "a/~");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escalate_permission_denied() {
+        let denied = || Err(io::Error::from(io::ErrorKind::PermissionDenied).into());
+        let args: [&OsStr; 0] = [];
+        // Only failures caused by a lack of permission are retried through the helper.
+        assert!(escalate(denied(), Some("true"), &args).is_ok());
+        assert!(escalate(denied(), Some("false"), &args).is_err());
+        assert!(escalate(denied(), None, &args).is_err());
+        let other = Err(AmbitError::Other("Other".to_owned()));
+        assert!(escalate(other, Some("true"), &args).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_privileged_through_helper() {
+        use super::write_privileged;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rendered");
+        // `env` runs `tee` as is, standing in for a helper such as `sudo`.
+        write_privileged("env", &path, b"rendered").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"rendered");
+        assert!(write_privileged("false", &path, b"other").is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn write_new_file_with_perm() {
+        use super::write_new_file;
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secret");
+        write_new_file(&path, b"secret", Some(0o600)).unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+        // Existing files are never written through.
+        assert!(write_new_file(&path, b"other", Some(0o600)).is_err());
+        assert_eq!(fs::read(&path).unwrap(), b"secret");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn is_owned_by_ids_and_names() {
+        use super::is_owned_by;
+        use std::os::unix::fs::MetadataExt;
+        let dir = tempfile::tempdir().unwrap();
+        let metadata = fs::metadata(dir.path()).unwrap();
+        let (uid, gid) = (metadata.uid().to_string(), metadata.gid().to_string());
+        assert!(is_owned_by(dir.path(), Some(&uid), Some(&gid)));
+        assert!(is_owned_by(dir.path(), None, Some(&gid)));
+        let user = users::get_user_by_uid(metadata.uid()).unwrap();
+        assert!(is_owned_by(dir.path(), user.name().to_str(), None));
+        let other_uid = (metadata.uid() + 1).to_string();
+        assert!(!is_owned_by(dir.path(), Some(&other_uid), Some(&gid)));
+        assert!(!is_owned_by(
+            dir.path(),
+            Some("ambit-nonexistent-user"),
+            None
+        ));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn expand_tilde_with_user() {
+        let ambit_paths = AmbitPaths::builder().home("/home/a").build().unwrap();
+        assert_eq!(expand_tilde(&ambit_paths, "~root/a").unwrap(), "/root/a");
+        assert!(expand_tilde(&ambit_paths, "~ambit-nonexistent-user/a").is_err());
+    }
+
+    #[test]
+    fn get_paths_from_pattern_absolute_pattern() {
+        let dir_path = tempfile::tempdir().unwrap().into_path();
+        File::create(dir_path.join("a.txt")).unwrap();
+        let pattern = format!("{}/*.txt", dir_path.display());
+        // Absolute paths are not relative to the start path.
+        let paths =
+            get_paths_from_pattern(&pattern, Path::new("start"), &DirCache::default()).unwrap();
+        assert_eq!(paths, vec![dir_path.join("a.txt")]);
+    }
+
+    #[test]
+    fn get_paths_from_pattern_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir_path = dir.path();
+        fs::create_dir(dir_path.join("a")).unwrap();
+        File::create(dir_path.join("a").join("b.txt")).unwrap();
+        let cache = DirCache::default();
+        let get_paths = |pattern: &str, cache: &DirCache| {
+            get_paths_from_pattern(pattern, dir_path, cache).unwrap()
+        };
+        assert_eq!(get_paths("a/*.txt", &cache), vec![PathBuf::from("a/b.txt")]);
+        // Directories are only listed once, so files created since are not seen with the same cache.
+        File::create(dir_path.join("a").join("c.txt")).unwrap();
+        assert_eq!(get_paths("*/*.txt", &cache), vec![PathBuf::from("a/b.txt")]);
+        assert_eq!(cache.listings.borrow().len(), 2);
+        assert_eq!(cache.globs.borrow().len(), 2);
+        assert_eq!(get_paths("*/*.txt", &DirCache::default()).len(), 2);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn get_paths_from_pattern_symlink_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join("a")).unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("a").join("loop")).unwrap();
+        assert!(get_paths_from_pattern("**/*.txt", dir.path(), &DirCache::default()).is_err());
+    }
+
+    #[test]
+    fn get_paths_from_pattern_without_pattern() {
+        test_pattern(
+            "a/b/c",
+            &["c/b/a", "a/b/c"],
+            &[PathBuf::from("a").join("b").join("c")],
+        );
+    }
+
+    #[test]
+    fn get_paths_from_pattern_ignore_parent() {
+        // This will resolve to a/b/c because if the user explicitly specifies a file (without pattern matching characters)
+        // its existence has to be verified at the symlinking stage which would error if it doesn't exist.
+        // This is to inform the user that the file does not exist.
+        // This differs from a pattern matching spec that will not resolve if the file does not exist.
+        test_pattern("a/b/c", &["a/b"], &[PathBuf::from("a").join("b").join("c")]);
+    }
+
+    #[test]
+    fn get_paths_from_pattern_adjacent_wildcard() {
+        test_pattern(
+            ".config/*/*",
+            &[
+                ".config/foo",
+                ".config/bar",
+                ".config/hello",
+                ".config/nvim/init.vim",
+                ".config/ambit/config.ambit",
+                ".config/ambit/repo/.vimrc",
+            ],
+            &[
+                PathBuf::from(".config").join("nvim").join("init.vim"),
+                PathBuf::from(".config").join("ambit").join("config.ambit"),
+            ],
+        );
+    }
+
+    #[test]
+    fn get_paths_from_pattern_with_unknown_char() {
+        test_pattern(
+            "Pictures/*.???",
+            &[
+                "Pictures/foo.jpg",
+                "Pictures/bar.png",
+                "Pictures/hello.svg",
+                // The following 2 should be ignored.
+                "Pictures/world.webp",
+                "Pictures/image.jpeg",
+            ],
+            &[
+                PathBuf::from("Pictures").join("foo.jpg"),
+                PathBuf::from("Pictures").join("bar.png"),
+                PathBuf::from("Pictures").join("hello.svg"),
+            ],
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn get_paths_from_pattern_with_escaped_char() {
+        test_pattern("x\\*y", &["x*y", "xay", "xaay"], &[PathBuf::from("x*y")]);
+    }
+
+    #[test]
+    fn get_paths_from_pattern_with_glob_chars() {
+        // Braces and unmatched brackets are not special in patterns, as they have already been handled by the parser.
+        test_pattern(
+            "notes {a}] [*",
+            &["notes {a}] [.txt", "notes a.txt"],
+            &[PathBuf::from("notes {a}] [.txt")],
+        );
+    }
+
+    #[test]
+    fn get_paths_from_pattern_with_char_class() {
+        test_pattern(
+            ".bash_[ap]*",
+            &[".bash_aliases", ".bash_profile", ".bash_logout", ".bashrc"],
+            &[
+                PathBuf::from(".bash_aliases"),
+                PathBuf::from(".bash_profile"),
+            ],
+        );
+        test_pattern(
+            "[!a]*.txt",
+            &["a.txt", "b.txt", "c.log"],
+            &[PathBuf::from("b.txt")],
+        );
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn get_paths_from_pattern_with_escaped_brackets() {
+        // Escaped brackets (e.g. from quoted strings) are matched literally.
+        test_pattern(
+            "\\[a\\]*",
+            &["[a].txt", "a.txt"],
+            &[PathBuf::from("[a].txt")],
+        );
+        test_pattern("\\[a\\].txt", &[], &[PathBuf::from("[a].txt")]);
+        // Directories before the first pattern are not matched, so their brackets are unescaped.
+        assert_eq!(
+            split_at_pattern("x\\[1\\]/*.txt"),
+            (PathBuf::from("x[1]"), vec!["*.txt".to_owned()])
+        );
+    }
+
+    #[test]
+    fn get_paths_from_pattern_directories() {
+        // Patterns ending with `/` only match directories.
+        test_pattern(
+            "a/*/",
+            &["a/b/c.txt", "a/d/e.txt", "a/f.txt"],
+            &[PathBuf::from("a").join("b"), PathBuf::from("a").join("d")],
+        );
+        test_pattern(".config/nvim/", &[], &[PathBuf::from(".config/nvim")]);
+    }
+
+    #[test]
+    fn get_paths_from_pattern_recursive() {
+        test_pattern(
+            "nvim/**",
+            &[
+                "nvim/init.vim",
+                "nvim/lua/plugins.lua",
+                "nvim/lua/a/b.lua",
+                "other.txt",
+            ],
+            &[
+                PathBuf::from("nvim").join("init.vim"),
+                PathBuf::from("nvim").join("lua").join("plugins.lua"),
+                PathBuf::from("nvim").join("lua").join("a").join("b.lua"),
+            ],
+        );
+    }
+
+    #[test]
+    fn get_paths_from_pattern_recursive_in_middle() {
+        test_pattern(
+            "a/**/*.conf",
+            &["a/x.conf", "a/b/y.conf", "a/b/c/z.conf", "a/b/c/z.txt"],
+            &[
+                PathBuf::from("a").join("x.conf"),
+                PathBuf::from("a").join("b").join("y.conf"),
+                PathBuf::from("a").join("b").join("c").join("z.conf"),
+            ],
+        );
+    }
+
+    #[test]
+    fn exclude_matching_paths() {
+        let excludes = vec![
+            Exclude::new("*.swp").unwrap(),
+            Exclude::new("lua/*").unwrap(),
+        ];
+        let paths = vec![
+            PathBuf::from("nvim").join("init.vim"),
+            PathBuf::from("nvim").join(".init.vim.swp"),
+            PathBuf::from("nvim").join("lua").join("a.lua"),
+            PathBuf::from("nvim")
+                .join("after")
+                .join("lua")
+                .join("b.lua"),
+        ];
+        // Patterns without a `/` match the file name anywhere,
+        // while other patterns are relative to where the pattern starts matching.
+        assert_eq!(
+            exclude_paths(paths, "nvim/**", &excludes),
+            vec![
+                PathBuf::from("nvim").join("init.vim"),
+                PathBuf::from("nvim")
+                    .join("after")
+                    .join("lua")
+                    .join("b.lua"),
+            ]
+        );
+    }
+
+    #[test]
+    fn mirror_recursive_paths() {
+        let left_paths = vec![
+            PathBuf::from("nvim").join("init.vim"),
+            PathBuf::from("nvim").join("lua").join("a.lua"),
+        ];
+        assert_eq!(
+            mirror_paths("nvim/**", ".config/nvim/**", &left_paths),
+            Some(vec![
+                PathBuf::from(".config").join("nvim").join("init.vim"),
+                PathBuf::from(".config")
+                    .join("nvim")
+                    .join("lua")
+                    .join("a.lua"),
+            ])
+        );
+        // The patterns differ, so the right side has to be matched on its own.
+        assert_eq!(mirror_paths("nvim/**", ".config/*", &left_paths), None);
+        assert_eq!(mirror_paths("nvim/**", ".config/nvim", &left_paths), None);
+    }
+
+    #[test]
+    fn capture_wildcards() {
+        let captures = |pattern: &str, path: &str| get_captures(pattern, Path::new(path)).unwrap();
+        assert_eq!(captures("scripts/*.sh", "scripts/a.b.sh"), vec!["a.b"]);
+        assert_eq!(captures("a/**/*.conf", "a/b/c/z.conf"), vec!["b/c", "z"]);
+        assert_eq!(captures("a/**/*.conf", "a/x.conf"), vec!["", "x"]);
+        assert_eq!(captures("bash_[ap]?", "bash_pl"), vec!["p", "l"]);
+        assert_eq!(get_captures("a/*.conf", Path::new("a/x.txt")), None);
+    }
+
+    #[test]
+    fn substitute_capture_references() {
+        let captures = vec!["a".to_owned(), "b".to_owned()];
+        assert!(has_capture_references(".local/bin/{2}"));
+        assert!(!has_capture_references("{host}/{}/{1a}"));
+        let left_path = Path::new("a/b.txt");
+        assert_eq!(
+            substitute_captures("{2}/{1}.{host}", left_path, &captures).unwrap(),
+            "b/a.{host}"
+        );
+        assert!(substitute_captures("{3}", left_path, &captures).is_err());
+        assert!(substitute_captures("{0}", left_path, &captures).is_err());
+        assert!(has_capture_references(".config/basename()"));
+        assert_eq!(
+            substitute_captures("x/dirname()/basename()", left_path, &[]).unwrap(),
+            "x/a/b.txt"
+        );
+    }
+
+    #[test]
+    fn config_path_formatting() {
+        assert_eq!(format_config_path(Path::new(".zshrc")), ".zshrc");
+        assert_eq!(
+            format_config_path(&Path::new(".config").join("nvim")),
+            ".config/nvim"
+        );
+        assert_eq!(
+            format_config_path(Path::new("My Documents/a\"b\".txt")),
+            "\"My Documents/a\\\"b\\\".txt\""
+        );
+        assert_eq!(format_config_path(Path::new("#notes")), "\"#notes\"");
+        assert_eq!(format_config_path(Path::new("a,b")), "\"a,b\"");
+    }
+
+    #[test]
+    fn remove_config_span() {
+        let content = "a;\n  b => c; d;\n  e;\n";
+        assert_eq!(remove_span(content, 5..12), "a;\n   d;\n  e;\n");
+        assert_eq!(remove_span(content, 0..2), "  b => c; d;\n  e;\n");
+        assert_eq!(remove_span(content, 18..20), "a;\n  b => c; d;\n");
+        assert_eq!(remove_span("a;\nb;", 3..5), "a;\n");
+    }
+
+    #[test]
+    fn default_commit_messages() {
+        assert_eq!(get_default_commit_message("M\t.bashrc\n"), "Update .bashrc");
+        assert_eq!(
+            get_default_commit_message("A\tnvim/init.vim\nD\t.vimrc\n"),
+            "Update nvim/init.vim, .vimrc\n\n- Add nvim/init.vim\n- Delete .vimrc"
+        );
+        assert_eq!(
+            get_default_commit_message("R100\ta\tb\nM\tc\nT\td\nA\te\n"),
+            "Update 4 files\n\n- Rename a to b\n- Update c\n- Update d\n- Add e"
+        );
+    }
+
+    #[test]
+    fn git_summaries() {
+        assert_eq!(
+            get_git_summary("## main...origin/main [ahead 1, behind 2]\n M .bashrc\n?? .vimrc\n"),
+            "git branch: main -> origin/main (ahead 1, behind 2)\ngit changes (2 total)\n    M .bashrc\n    ?? .vimrc\n"
+        );
+        assert_eq!(
+            get_git_summary("## main\n"),
+            "git branch: main\ngit changes (0 total)\n"
+        );
+    }
+
+    #[test]
+    fn entry_selection() {
+        let selection = EntrySelection::parse("2, 5-7").unwrap();
+        let selected: Vec<usize> = (1..=8).filter(|i| selection.contains(*i)).collect();
+        assert_eq!(selected, [2, 5, 6, 7]);
+        assert_eq!(
+            selection.select((1..=8).collect()).unwrap(),
+            vec![2, 5, 6, 7]
+        );
+        assert!(selection.select(vec![1, 2, 3]).is_err());
+        for spec in ["", "0", "3-1", "a", "1,,2", "-2"] {
+            assert!(EntrySelection::parse(spec).is_err(), "{}", spec);
+        }
+    }
+
+    #[test]
+    fn backup_path() {
+        let backup_dir = Path::new("/backups/1");
+        let home = Path::new("/home/a");
+        assert_eq!(
+            get_backup_path(backup_dir, home, Path::new("/home/a/.config/nvim/init.vim")),
+            PathBuf::from("/backups/1/.config/nvim/init.vim")
+        );
+        assert_eq!(
+            get_backup_path(backup_dir, home, Path::new("/etc/hosts")),
+            PathBuf::from("/backups/1/etc/hosts")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn symlink_unsupported_errors() {
+        let error = |e: io::Error| AmbitError::Io(e);
+        assert!(is_symlink_unsupported(&error(
+            io::Error::from_raw_os_error(1)
+        )));
+        assert!(is_symlink_unsupported(&error(
+            io::ErrorKind::Unsupported.into()
+        )));
+        // Missing write permission is a problem of the directory, not of the filesystem.
+        assert!(!is_symlink_unsupported(&error(
+            io::Error::from_raw_os_error(13)
+        )));
+        assert!(!is_symlink_unsupported(&AmbitError::Other(
+            "EPERM".to_owned()
+        )));
+    }
+
+    #[test]
+    fn sync_hook_quotes_exe() {
+        let paths = AmbitPaths::builder()
+            .home("/home")
+            .config("/config.ambit")
+            .repo("/it's/repo")
+            .profile("work")
+            .build()
+            .unwrap();
+        assert_eq!(
+            get_sync_hook(Path::new("/it's/ambit"), &paths),
+            "#!/bin/sh\n# Installed by `ambit hook install`.\nexec '/it'\\''s/ambit' --home '/home' --config '/config.ambit' --repo '/it'\\''s/repo' --profile 'work' sync --quiet\n"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn copy_path_keeps_symlinks() {
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        fs::create_dir_all(from.join("nvim")).unwrap();
+        fs::write(from.join("nvim").join("init.vim"), "set number").unwrap();
+        std::os::unix::fs::symlink("nvim/init.vim", from.join("vimrc")).unwrap();
+        copy_path(&from, &to).unwrap();
+        assert_eq!(
+            fs::read_to_string(to.join("nvim").join("init.vim")).unwrap(),
+            "set number"
+        );
+        assert_eq!(
+            fs::read_link(to.join("vimrc")).unwrap(),
+            PathBuf::from("nvim/init.vim")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn move_by_copy_keeps_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        let to = dir.path().join("to");
+        fs::create_dir_all(from.join("ssh")).unwrap();
+        fs::write(from.join("ssh").join("config"), "Host *").unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        let set_mode = |path: &Path, mode| {
+            fs::set_permissions(path, fs::Permissions::from_mode(mode)).unwrap()
+        };
+        set_mode(&from.join("ssh").join("config"), 0o600);
+        set_mode(&from.join("ssh"), 0o700);
+        move_by_copy(&from, &to).unwrap();
+        assert!(fs::symlink_metadata(&from).is_err());
+        assert_eq!(
+            fs::read_to_string(to.join("ssh").join("config")).unwrap(),
+            "Host *"
+        );
+        assert_eq!(mode(&to.join("ssh").join("config")), 0o600);
+        assert_eq!(mode(&to.join("ssh")), 0o700);
+        // Single files are moved as well.
+        move_by_copy(&to.join("ssh").join("config"), &dir.path().join("config")).unwrap();
+        assert_eq!(mode(&dir.path().join("config")), 0o600);
+        assert!(!to.join("ssh").join("config").exists());
+    }
+
+    #[test]
+    fn unified_diff() {
+        let diff = get_unified_diff(
+            Path::new("repo/a.txt"),
+            b"a\nb\nc\n",
+            Path::new("a.txt"),
+            b"a\nd\nc\n",
+        );
+        assert_eq!(
+            diff,
+            "--- repo/a.txt\n+++ a.txt\n@@ -1,3 +1,3 @@\n a\n-b\n+d\n c\n"
+        );
+    }
+}
@@ -1,31 +1,101 @@
 use crate::config::parser::SimpleParse;
 
+use globset::GlobBuilder;
 use lazy_static::lazy_static;
 
+use std::{
+    ffi::{OsStr, OsString},
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+};
+
+/// A top-level item of a configuration.
+/// Most statements are entries, so they are not boxed.
+#[allow(clippy::large_enum_variant)]
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Statement {
+    Entry(Entry),
+    /// The path of another configuration whose entries are spliced in,
+    /// relative to the directory of the including configuration.
+    Include(String),
+    /// The name of a profile that can be selected with `--profile`.
+    Profile(String),
+    /// The name and value of a variable defined in a `vars` block, which is available to templates.
+    Var(String, String),
+    /// Whether files moved into the repository are committed, e.g. `autocommit push;`.
+    AutoCommit(AutoCommit),
+}
+
+/// Whether the files moved into the dotfile repository by `sync --move` and `add` are committed, and pushed.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Debug, Clone, Copy, Default)]
+pub enum AutoCommit {
+    #[default]
+    Off,
+    Commit,
+    Push,
+}
+
+/// An entry of the configuration, which syncs the host files of its right side (or left side) from the repository files of its left side.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Entry {
     pub left: Spec,
+    /// Patterns of files matched by the left side to skip, without the leading `!`.
+    pub excludes: Vec<String>,
     pub right: Option<Spec>,
+    /// If the sides are mapped with `=>>`, every file within the left directory is synced to the right directory.
+    pub tree: bool,
+    /// Tags without the leading `#`, e.g. `work` for `#work`.
+    pub tags: Vec<String>,
+    pub attributes: Attributes,
+}
+
+/// Options that change how the files of an entry are synced, e.g. `[mode=copy, perm=600]`.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct Attributes {
+    pub mode: LinkMode,
+    /// Unix permissions to set on the synced files.
+    pub perm: Option<u32>,
+    /// If a tree entry (`=>>`) should symlink its host directories as a whole where possible.
+    pub fold: bool,
+    /// The user and group that should own the host files, e.g. for system files in `/etc`.
+    pub owner: Option<String>,
+    pub group: Option<String>,
+    /// If the files are secrets, e.g. private keys, which are copied with 600 permissions and not printed.
+    pub secret: bool,
+}
+
+/// How a host file is synced from its repository file.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+pub enum LinkMode {
+    /// Symlink host files to repository files.
+    #[default]
+    Symlink,
+    /// Copy repository files to host files.
+    Copy,
 }
 
-// A `Spec` specifies a fragment of a path, e.g. "~/.config/[nvim/init.vim, spectrwm.conf]".
+/// A `Spec` specifies a fragment of a path, e.g. "~/.config/[nvim/init.vim, spectrwm.conf]".
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Spec {
     pub string: Option<String>,
     pub spectype: SpecType,
 }
+/// What follows the string at the start of a spec.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum SpecType {
     None,
     Variant(Box<VariantExpr>, Option<Box<Spec>>),
     Match(Box<MatchExpr>, Option<Box<Spec>>),
+    Placeholder(Placeholder, Option<Box<Spec>>),
 }
 impl Spec {
-    // Returns None if the nr. of options is larger than usize::MAX.
+    /// Returns None if the nr. of options is larger than usize::MAX.
     pub fn nr_of_options(&self) -> Option<usize> {
         match &self.spectype {
             SpecType::None => Some(1),
-            SpecType::Match(_, spec) => spec.as_ref().map(|s| s.nr_of_options()).unwrap_or(Some(1)),
+            SpecType::Match(_, spec) | SpecType::Placeholder(_, spec) => {
+                spec.as_ref().map(|s| s.nr_of_options()).unwrap_or(Some(1))
+            }
             SpecType::Variant(expr, spec) => {
                 let exprnr = expr.nr_of_options()?;
                 let specnr = spec
@@ -36,6 +106,58 @@ impl Spec {
             }
         }
     }
+
+    /// Calls `f` on every string within the spec, including those of nested specs.
+    pub fn for_each_string_mut(&mut self, f: &mut dyn FnMut(&mut String)) {
+        if let Some(string) = self.string.as_mut() {
+            f(string);
+        }
+        let rest = match &mut self.spectype {
+            SpecType::None => None,
+            SpecType::Variant(expr, rest) => {
+                for spec in &mut expr.specs {
+                    spec.for_each_string_mut(f);
+                }
+                rest.as_mut()
+            }
+            SpecType::Match(expr, rest) => {
+                for (_, spec) in &mut expr.cases {
+                    spec.for_each_string_mut(f);
+                }
+                rest.as_mut()
+            }
+            SpecType::Placeholder(_, rest) => rest.as_mut(),
+        };
+        if let Some(rest) = rest {
+            rest.for_each_string_mut(f);
+        }
+    }
+
+    /// Returns every match expression within the spec, including nested ones.
+    pub fn match_exprs(&self) -> Vec<&MatchExpr> {
+        let mut exprs = Vec::new();
+        let rest = match &self.spectype {
+            SpecType::None => None,
+            SpecType::Variant(expr, rest) => {
+                for spec in &expr.specs {
+                    exprs.extend(spec.match_exprs());
+                }
+                rest.as_ref()
+            }
+            SpecType::Match(expr, rest) => {
+                exprs.push(expr.as_ref());
+                for (_, spec) in &expr.cases {
+                    exprs.extend(spec.match_exprs());
+                }
+                rest.as_ref()
+            }
+            SpecType::Placeholder(_, rest) => rest.as_ref(),
+        };
+        if let Some(rest) = rest {
+            exprs.extend(rest.match_exprs());
+        }
+        exprs
+    }
 }
 impl From<&str> for Spec {
     fn from(s: &str) -> Self {
@@ -54,20 +176,118 @@ impl From<SpecType> for Spec {
     }
 }
 impl SpecType {
+    /// A `{a, b}` expression followed by rest.
     pub fn variant_expr(specs: Vec<Spec>, rest: Option<Spec>) -> Self {
         SpecType::Variant(Box::new(VariantExpr { specs }), rest.map(Box::new))
     }
+    /// A `{expr: a, default: b}` expression followed by rest.
     pub fn match_expr(cases: Vec<(Expr, Spec)>, rest: Option<Spec>) -> Self {
         SpecType::Match(Box::new(MatchExpr { cases }), rest.map(Box::new))
     }
 }
 
+/// A value of the system that is substituted into a spec, e.g. `{host}`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Placeholder {
+    Host,
+    Os,
+    /// What the left side of an entry matched, e.g. `{1}` for its first wildcard or `basename()`.
+    /// This is only known when syncing, so the reference is kept as it is written until then.
+    Capture(String),
+    /// The name of a variable defined in a `vars` block, e.g. `{email}`.
+    /// The parser replaces it with the value of the variable.
+    Var(String),
+    /// Where the current platform keeps files of a kind, e.g. `configdir()`.
+    PlatformDir(PlatformDir),
+}
+impl Placeholder {
+    /// Returns the placeholder with the given name, if there is one.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "host" => Some(Placeholder::Host),
+            "os" => Some(Placeholder::Os),
+            _ if !name.is_empty() && name.chars().all(|c| c.is_ascii_digit()) => {
+                Some(Placeholder::Capture(format!("{{{}}}", name)))
+            }
+            _ => None,
+        }
+    }
+    /// Returns the transform of the left side or the builtin with the given name, e.g. `basename` for `basename()`.
+    pub fn from_transform_name(name: &str) -> Option<Self> {
+        match name {
+            "basename" | "dirname" => Some(Placeholder::Capture(format!("{}()", name))),
+            "configdir" => Some(Placeholder::PlatformDir(PlatformDir::Config)),
+            "datadir" => Some(Placeholder::PlatformDir(PlatformDir::Data)),
+            "cachedir" => Some(Placeholder::PlatformDir(PlatformDir::Cache)),
+            _ => None,
+        }
+    }
+    /// Return what the placeholder stands for on this system, within the given context.
+    pub fn value<'a>(&'a self, context: &'a Context) -> &'a str {
+        match self {
+            Placeholder::Host => HOSTNAME.as_str(),
+            Placeholder::Os => std::env::consts::OS,
+            Placeholder::Capture(reference) => reference,
+            Placeholder::Var(name) => name,
+            Placeholder::PlatformDir(PlatformDir::Config) => &context.config_dir,
+            Placeholder::PlatformDir(PlatformDir::Data) => &context.data_dir,
+            Placeholder::PlatformDir(PlatformDir::Cache) => &context.cache_dir,
+        }
+    }
+}
+
+/// A base directory of the platform, e.g. `{config}`.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum PlatformDir {
+    Config,
+    Data,
+    Cache,
+}
+impl PlatformDir {
+    // Returns dir relative to home, or to the home directory of the user that it is found in, if it is within either.
+    // Relative directories are then placed within home, like other host files.
+    // Without a platform directory, the directory of other unix systems is used.
+    fn resolve(self, dir: Option<PathBuf>, user_home: Option<&Path>, home: &Path) -> String {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => {
+                return match self {
+                    PlatformDir::Config => ".config",
+                    PlatformDir::Data => ".local/share",
+                    PlatformDir::Cache => ".cache",
+                }
+                .to_owned()
+            }
+        };
+        let dir = match dir
+            .strip_prefix(home)
+            .ok()
+            .or_else(|| user_home.and_then(|user_home| dir.strip_prefix(user_home).ok()))
+        {
+            Some(relative) => relative,
+            None => &dir,
+        };
+        dir.to_string_lossy().into_owned()
+    }
+
+    // Returns the directory of the current platform for home, e.g. `%APPDATA%` on Windows.
+    fn get(self, home: &Path) -> String {
+        let dir = match self {
+            PlatformDir::Config => dirs::config_dir(),
+            PlatformDir::Data => dirs::data_dir(),
+            PlatformDir::Cache => dirs::cache_dir(),
+        };
+        self.resolve(dir, dirs::home_dir().as_deref(), home)
+    }
+}
+
+/// A `{a, b}` expression, which expands to each of its specs.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct VariantExpr {
     pub specs: Vec<Spec>,
 }
 impl VariantExpr {
-    // Returns None if the nr. of options is larger than usize::MAX.
+    /// Returns None if the nr. of options is larger than usize::MAX.
     pub fn nr_of_options(&self) -> Option<usize> {
         self.specs.iter().try_fold(0usize, |nr, spec| {
             spec.nr_of_options()
@@ -76,53 +296,253 @@ impl VariantExpr {
     }
 }
 
-// Matches, based on the expr, which spec to produce.
+/// Matches, based on the expr, which spec to produce.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct MatchExpr {
     pub cases: Vec<(Expr, Spec)>,
 }
 impl MatchExpr {
-    pub fn resolve(&self) -> Option<&Spec> {
+    /// Return the spec of the first case that is true, or None if none is.
+    pub fn resolve(&self, context: &Context) -> Option<&Spec> {
         for (expr, spec) in &self.cases {
-            if expr.is_true() {
+            if expr.is_true(context) {
                 // it matches
                 return Some(&spec);
             }
         }
         None
     }
+
+    /// Returns the indices of cases that can never be chosen, along with the reason why.
+    pub fn unreachable_cases(&self) -> Vec<(usize, UnreachableCase)> {
+        let mut unreachable = Vec::new();
+        let mut after_default = false;
+        for (i, (expr, _)) in self.cases.iter().enumerate() {
+            if after_default {
+                unreachable.push((i, UnreachableCase::AfterDefault));
+            } else if self.cases[..i].iter().any(|(prev, _)| prev == expr) {
+                unreachable.push((i, UnreachableCase::Duplicate));
+            }
+            after_default = after_default || *expr == Expr::Any;
+        }
+        unreachable
+    }
 }
 
-// A comma seperated list of `T`s, with optional trailing comma.
-// (The delimiters are passed to the parse() function.)
+/// Why a case of a match expression can never be chosen.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum UnreachableCase {
+    /// The case follows a `default` case, which always matches.
+    AfterDefault,
+    /// The case has the same expr as an earlier case.
+    Duplicate,
+}
+
+/// A comma seperated list of `T`s, with optional trailing comma.
+/// (The delimiters are passed to the parse() function.)
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct CommaList<T: SimpleParse> {
     pub list: Vec<T>,
 }
 
-// Something that is either true or false, depending on the system.
+/// Something that is either true or false, depending on the system.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum Expr {
     Os(Vec<String>),
+    /// Operating system family, either "unix" or "windows".
+    Family(Vec<String>),
     Host(Vec<String>),
     NotOs(Vec<String>),
     NotHost(Vec<String>),
-    // The "Default" exprtype,
-    // so-named due to conflicts with the Default iterator.
+    /// An environment variable name, followed by the values it may hold.
+    /// If no values are given, the variable only has to be set.
+    Env(String, Vec<String>),
+    Distro(Vec<String>),
+    /// Paths relative to the home directory.
+    Exists(Vec<String>),
+    /// Commands that are looked up in PATH.
+    Has(Vec<String>),
+    /// Names of profiles, one of which must be active.
+    Profile(Vec<String>),
+    /// True if all of the nested exprs are true.
+    All(Vec<Expr>),
+    /// True if any of the nested exprs are true.
+    /// (Not to be confused with `Expr::Any`, which is always true.)
+    AnyOf(Vec<Expr>),
+    Not(Box<Expr>),
+    /// The "Default" exprtype,
+    /// so-named due to conflicts with the Default iterator.
     Any,
 }
 impl Expr {
-    pub fn is_true(&self) -> bool {
+    /// Evaluate the expr on this system, within the given context.
+    pub fn is_true(&self, context: &Context) -> bool {
+        self.is_true_with(context, &|name| std::env::var_os(name))
+    }
+
+    /// Evaluate the expr within the given context, reading environment variables through var.
+    pub fn is_true_with(&self, context: &Context, var: &dyn Fn(&str) -> Option<OsString>) -> bool {
+        let is_true = |expr: &Expr| expr.is_true_with(context, var);
         match self {
             Expr::Os(oss) => oss.iter().any(|os| std::env::consts::OS == os),
-            Expr::Host(hosts) => hosts.iter().any(|host| &*HOSTNAME == host),
+            Expr::Family(families) => families
+                .iter()
+                .any(|family| std::env::consts::FAMILY == family),
+            Expr::Host(hosts) => hosts.iter().any(|host| host_matches(host, &HOSTNAME)),
             Expr::NotOs(oss) => oss.iter().all(|os| std::env::consts::OS != os),
-            Expr::NotHost(hosts) => hosts.iter().all(|host| &*HOSTNAME != host),
+            Expr::NotHost(hosts) => hosts.iter().all(|host| !host_matches(host, &HOSTNAME)),
+            Expr::Env(name, values) => match var(name) {
+                Some(var) => values.is_empty() || values.iter().any(|value| var == **value),
+                None => false,
+            },
+            Expr::Distro(distros) => distros.iter().any(|distro| DISTROS.contains(distro)),
+            Expr::Exists(paths) => paths
+                .iter()
+                .any(|path| resolve_home_path(&context.home, path).exists()),
+            Expr::Has(commands) => commands.iter().any(|command| is_in_path(command)),
+            Expr::Profile(profiles) => match &context.profile {
+                Some(active) => profiles.iter().any(|profile| profile == active),
+                None => false,
+            },
+            Expr::All(exprs) => exprs.iter().all(is_true),
+            Expr::AnyOf(exprs) => exprs.iter().any(is_true),
+            Expr::Not(expr) => !is_true(expr),
             Expr::Any => true,
         }
     }
 }
 
+// Displays the expr as it is written in a configuration, e.g. `os(linux, macos)`.
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let (name, strings) = match self {
+            Expr::Os(oss) => ("os", oss),
+            Expr::Family(families) => ("family", families),
+            Expr::Host(hosts) => ("host", hosts),
+            Expr::NotOs(oss) => ("!os", oss),
+            Expr::NotHost(hosts) => ("!host", hosts),
+            Expr::Env(name, values) => {
+                let mut strings = vec![name.as_str()];
+                strings.extend(values.iter().map(String::as_str));
+                return write!(f, "env({})", strings.join(", "));
+            }
+            Expr::Distro(distros) => ("distro", distros),
+            Expr::Exists(paths) => ("exists", paths),
+            Expr::Has(commands) => ("has", commands),
+            Expr::Profile(profiles) => ("profile", profiles),
+            Expr::All(exprs) | Expr::AnyOf(exprs) => {
+                let name = match self {
+                    Expr::All(_) => "all",
+                    _ => "any",
+                };
+                let exprs: Vec<String> = exprs.iter().map(Expr::to_string).collect();
+                return write!(f, "{}({})", name, exprs.join(", "));
+            }
+            Expr::Not(expr) => return write!(f, "not({})", expr),
+            Expr::Any => return f.write_str("default"),
+        };
+        write!(f, "{}({})", name, strings.join(", "))
+    }
+}
+
+/// What exprs and placeholders are evaluated against, besides the system itself.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Context {
+    /// The selected profile, which `profile(...)` matches.
+    pub profile: Option<String>,
+    /// The home directory that `exists(...)` paths and platform directories are relative to.
+    pub home: PathBuf,
+    config_dir: String,
+    data_dir: String,
+    cache_dir: String,
+}
+impl Context {
+    pub fn new(profile: Option<String>, home: PathBuf) -> Self {
+        Self {
+            profile,
+            config_dir: PlatformDir::Config.get(&home),
+            data_dir: PlatformDir::Data.get(&home),
+            cache_dir: PlatformDir::Cache.get(&home),
+            home,
+        }
+    }
+}
+// Without a profile, within the home directory of the user.
+impl Default for Context {
+    fn default() -> Self {
+        Context::new(None, dirs::home_dir().unwrap_or_default())
+    }
+}
+
+// Return if the hostname matches the given host, which may contain wildcards.
+fn host_matches(host: &str, hostname: &str) -> bool {
+    GlobBuilder::new(host)
+        .backslash_escape(true)
+        .build()
+        .map(|glob| glob.compile_matcher().is_match(hostname))
+        .unwrap_or(false)
+}
+
+// Resolve a path relative to home, expanding a leading `~`.
+fn resolve_home_path(home: &Path, path: &str) -> PathBuf {
+    let path = if path == "~" {
+        ""
+    } else {
+        path.strip_prefix("~/").unwrap_or(path)
+    };
+    // Joining an absolute path will replace the home path entirely.
+    home.join(path)
+}
+
+// Return if the given command can be found in a directory listed in PATH.
+fn is_in_path(command: &str) -> bool {
+    match std::env::var_os("PATH") {
+        Some(paths) => is_in_paths(command, &paths),
+        None => false,
+    }
+}
+
+// Return if the given command can be found in a directory listed in `paths`.
+fn is_in_paths(command: &str, paths: &OsStr) -> bool {
+    // On Windows, executables are found with any of the extensions listed in PATHEXT.
+    let extensions: Vec<String> = if cfg!(windows) {
+        std::env::var("PATHEXT")
+            .unwrap_or_else(|_| ".COM;.EXE;.BAT;.CMD".to_owned())
+            .split(';')
+            .map(str::to_owned)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    std::env::split_paths(paths).any(|dir| {
+        let path = dir.join(command);
+        path.is_file()
+            || extensions
+                .iter()
+                .any(|ext| dir.join(format!("{}{}", command, ext)).is_file())
+    })
+}
+
+// Return the distribution identifiers (`ID` followed by `ID_LIKE`) from the content of an os-release file.
+fn parse_os_release(content: &str) -> Vec<String> {
+    let mut id = Vec::new();
+    let mut id_like = Vec::new();
+    for line in content.lines() {
+        if let Some((key, value)) = line.trim().split_once('=') {
+            // Values may optionally be quoted.
+            let value = value.trim_matches(|c| c == '"' || c == '\'');
+            match key {
+                "ID" => id = vec![value.to_owned()],
+                // ID_LIKE is a space separated list of related distributions.
+                "ID_LIKE" => id_like = value.split_whitespace().map(str::to_owned).collect(),
+                _ => {}
+            }
+        }
+    }
+    id.append(&mut id_like);
+    id
+}
+
 // Cache hostname to avoid having to call hostname::get() multiple times.
 lazy_static! {
     static ref HOSTNAME: String = hostname::get()
@@ -130,3 +550,184 @@ lazy_static! {
         .into_string()
         .expect("hostname must be a valid encoding");
 }
+
+// Cache distribution identifiers as os-release only has to be read once.
+// If os-release does not exist (e.g. the OS is not linux), no distribution will match.
+lazy_static! {
+    static ref DISTROS: Vec<String> = std::fs::read_to_string("/etc/os-release")
+        .or_else(|_| std::fs::read_to_string("/usr/lib/os-release"))
+        .map(|content| parse_os_release(&content))
+        .unwrap_or_default();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_spec(content: &str) -> Spec {
+        let mut statements = crate::config::get_statements(content.chars().peekable());
+        match statements.next().unwrap().unwrap() {
+            Statement::Entry(entry) => entry.left,
+            statement => panic!("Expected entry, found {:?}", statement),
+        }
+    }
+
+    #[test]
+    fn env_and_profile_exprs() {
+        let var = |name: &str| match name {
+            "XDG_SESSION_TYPE" => Some(OsString::from("wayland")),
+            "EMPTY" => Some(OsString::new()),
+            _ => None,
+        };
+        let context = Context::new(Some("full".to_owned()), PathBuf::new());
+        let is_true = |expr: Expr| expr.is_true_with(&context, &var);
+        let strings = |strings: &[&str]| strings.iter().map(|s| s.to_string()).collect();
+        assert!(!is_true(Expr::Env(
+            "XDG_SESSION_TYPE".to_owned(),
+            strings(&["x11"])
+        )));
+        assert!(is_true(Expr::Env(
+            "XDG_SESSION_TYPE".to_owned(),
+            strings(&["x11", "wayland"])
+        )));
+        // The variable only has to be set, even if it is empty.
+        assert!(is_true(Expr::Env("EMPTY".to_owned(), Vec::new())));
+        assert!(!is_true(Expr::Env("UNSET".to_owned(), Vec::new())));
+        assert!(!is_true(Expr::Profile(strings(&["minimal"]))));
+        assert!(is_true(Expr::Profile(strings(&["minimal", "full"]))));
+        assert!(is_true(Expr::Not(Box::new(Expr::Profile(strings(&[
+            "minimal"
+        ]))))));
+        assert!(!Expr::Profile(strings(&["full"])).is_true_with(&Context::default(), &var));
+    }
+
+    #[test]
+    fn nested_match_exprs() {
+        let spec = parse_spec("{os(linux): a, default: [b{host(x): c}, d]}{os(macos): e};");
+        assert_eq!(spec.match_exprs().len(), 3);
+    }
+
+    #[test]
+    fn display_exprs() {
+        let spec = parse_spec(
+            "{os(linux, macos): a, !host(x): b, env(EDITOR, vim): c, all(has(git), not(exists(d))): e, default: f};",
+        );
+        let exprs: Vec<String> = spec.match_exprs()[0]
+            .cases
+            .iter()
+            .map(|(expr, _)| expr.to_string())
+            .collect();
+        assert_eq!(
+            exprs,
+            vec![
+                "os(linux, macos)",
+                "!host(x)",
+                "env(EDITOR, vim)",
+                "all(has(git), not(exists(d)))",
+                "default"
+            ]
+        );
+    }
+
+    #[test]
+    fn unreachable_match_cases() {
+        let spec =
+            parse_spec("{os(linux): a, os(macos): b, os(linux): c, default: d, host(x): e};");
+        assert_eq!(
+            spec.match_exprs()[0].unreachable_cases(),
+            vec![
+                (2, UnreachableCase::Duplicate),
+                (4, UnreachableCase::AfterDefault)
+            ]
+        );
+    }
+
+    #[test]
+    fn host_wildcards() {
+        assert!(host_matches("work-01", "work-01"));
+        assert!(host_matches("work-*", "work-01"));
+        assert!(host_matches("work-??", "work-02"));
+        assert!(!host_matches("work-?", "work-02"));
+        assert!(!host_matches("work-*", "home"));
+        // Escaped wildcards should be matched literally.
+        assert!(host_matches("work-\\*", "work-*"));
+        assert!(!host_matches("work-\\*", "work-01"));
+    }
+
+    #[test]
+    fn exists_expr_within_home() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::File::create(dir.path().join("marker")).unwrap();
+        let expr = Expr::Exists(vec!["~/marker".to_owned()]);
+        assert!(expr.is_true(&Context::new(None, dir.path().to_path_buf())));
+        assert!(!expr.is_true(&Context::new(None, dir.path().join("other"))));
+    }
+
+    #[test]
+    fn resolve_home_path_with_tilde() {
+        let home = Path::new("/home/a");
+        assert_eq!(resolve_home_path(home, "~"), home);
+        assert_eq!(
+            resolve_home_path(home, "~/.config/sway"),
+            home.join(".config/sway")
+        );
+        assert_eq!(
+            resolve_home_path(home, ".config/sway"),
+            home.join(".config/sway")
+        );
+        assert_eq!(resolve_home_path(home, "/etc/sway"), Path::new("/etc/sway"));
+    }
+
+    #[test]
+    fn command_in_path() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::File::create(dir.path().join("ambit-test-command")).unwrap();
+        let paths = std::env::join_paths([dir.path()]).unwrap();
+        assert!(is_in_paths("ambit-test-command", &paths));
+        assert!(!is_in_paths("ambit-missing-command", &paths));
+    }
+
+    #[test]
+    fn os_release_id_and_id_like() {
+        let content = r#"NAME="Ubuntu"
+VERSION="20.04.2 LTS (Focal Fossa)"
+ID=ubuntu
+ID_LIKE=debian
+PRETTY_NAME="Ubuntu 20.04.2 LTS"
+"#;
+        assert_eq!(parse_os_release(content), vec!["ubuntu", "debian"]);
+    }
+
+    #[test]
+    fn os_release_quoted_id_like() {
+        let content = "ID=\"manjaro\"\nID_LIKE=\"arch archlinux\"\n";
+        assert_eq!(
+            parse_os_release(content),
+            vec!["manjaro", "arch", "archlinux"]
+        );
+    }
+
+    #[test]
+    fn os_release_without_id() {
+        assert!(parse_os_release("NAME=Unknown\n").is_empty());
+    }
+
+    #[test]
+    fn platform_dirs() {
+        let user_home = Path::new("/home/a");
+        let home = Path::new("/tmp/setup");
+        let resolve = |dir: PlatformDir, path: &str| {
+            dir.resolve(Some(PathBuf::from(path)), Some(user_home), home)
+        };
+        assert_eq!(resolve(PlatformDir::Config, "/home/a/.config"), ".config");
+        assert_eq!(
+            resolve(PlatformDir::Cache, "/home/a/Library/Caches"),
+            "Library/Caches"
+        );
+        // A base directory within the configured home directory is relative to it as well.
+        assert_eq!(resolve(PlatformDir::Data, "/tmp/setup/data"), "data");
+        // Directories outside of both home directories, e.g. an absolute XDG base directory, are kept absolute.
+        assert_eq!(resolve(PlatformDir::Config, "/xdg/config"), "/xdg/config");
+        assert_eq!(PlatformDir::Data.resolve(None, None, home), ".local/share");
+    }
+}
@@ -0,0 +1,356 @@
+use crate::config::{
+    get_statements,
+    lexer::{Lexer, TokType, Token},
+    parser::{is_exclude, is_group_header, is_tag, is_vars_header},
+    ParseError,
+};
+
+const INDENT: &str = "    ";
+
+// A formatted statement, or the `}` that closes a block.
+struct Line {
+    // The number of blocks (groups or `vars` blocks) that the line is within.
+    depth: usize,
+    // Lines after the first are already indented.
+    text: String,
+    // The offset of the top-level `=>` (or `=>>`) within text, if it is on the first line of the statement.
+    arrow: Option<usize>,
+    // If the line was preceded by an empty line, which is kept to separate sections of the config.
+    blank_before: bool,
+}
+
+/// Format content in the canonical style of configurations:
+/// - every statement is on its own line, indented by its depth within groups and `vars` blocks,
+/// - lists that were written across multiple lines have one item per line, each followed by a comma,
+///   while the other lists are kept on one line without a trailing comma,
+/// - the `=>` of consecutive entries are aligned.
+///
+/// The text of every token is kept as it is written, so only whitespace and trailing commas change.
+pub fn format(content: &str) -> Result<String, Vec<ParseError>> {
+    let errors: Vec<ParseError> = get_statements(content.chars().peekable())
+        .filter_map(Result::err)
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let formatter = Formatter { content };
+    let tokens: Vec<Token> = Lexer::new(content.chars().peekable()).collect();
+    let mut lines: Vec<Line> = Vec::new();
+    let mut depth = 0;
+    let mut last_line = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        // Empty lines are not kept at the start of the config or of a block.
+        let is_first = match lines.last() {
+            Some(line) => line.text.ends_with('{'),
+            None => true,
+        };
+        let blank_before =
+            !is_first && matches!(last_line, Some(line) if tokens[i].line > line + 1);
+        // A `}` at the start of a statement closes the innermost block.
+        if tokens[i].toktype == TokType::RBrace && depth > 0 {
+            depth -= 1;
+            lines.push(Line {
+                depth,
+                text: "}".to_owned(),
+                arrow: None,
+                blank_before: false,
+            });
+            last_line = Some(tokens[i].line);
+            i += 1;
+            continue;
+        }
+        // Take the tokens up to the end of the statement, as the parser does.
+        let start = i;
+        let mut opens_block = false;
+        while i < tokens.len() {
+            i += 1;
+            match tokens[i - 1].toktype {
+                TokType::Semicolon => break,
+                TokType::LBrace
+                    if is_group_header(&tokens[start..i - 1])
+                        || is_vars_header(&tokens[start..i - 1]) =>
+                {
+                    opens_block = true;
+                    break;
+                }
+                _ => {}
+            }
+        }
+        let statement = match opens_block {
+            true => &tokens[start..i - 1],
+            false => &tokens[start..i],
+        };
+        let (text, arrow) = formatter.format_statement(statement, depth);
+        lines.push(Line {
+            depth,
+            text: match opens_block {
+                true => format!("{} {{", text),
+                false => text,
+            },
+            arrow,
+            blank_before,
+        });
+        if opens_block {
+            depth += 1;
+        }
+        last_line = Some(tokens[i - 1].line);
+    }
+    align_arrows(&mut lines);
+    let mut formatted = String::with_capacity(content.len());
+    for line in &lines {
+        if line.blank_before {
+            formatted.push('\n');
+        }
+        formatted.push_str(&INDENT.repeat(line.depth));
+        formatted.push_str(&line.text);
+        formatted.push('\n');
+    }
+    Ok(formatted)
+}
+
+// Pad the left sides of consecutive single line entries, so that their `=>` line up.
+fn align_arrows(lines: &mut [Line]) {
+    let mut start = 0;
+    while start < lines.len() {
+        let mut end = start;
+        while end < lines.len()
+            && lines[end].arrow.is_some()
+            && lines[end].depth == lines[start].depth
+            && (end == start || !lines[end].blank_before)
+        {
+            end += 1;
+        }
+        let run = &mut lines[start..end.max(start + 1)];
+        let width = run.iter().filter_map(|line| line.arrow).max();
+        for line in run.iter_mut() {
+            if let (Some(arrow), Some(width)) = (line.arrow, width) {
+                line.text.insert_str(arrow, &" ".repeat(width - arrow));
+            }
+        }
+        start = end.max(start + 1);
+    }
+}
+
+// Return the index of the bracket that closes the one at open.
+fn find_close(tokens: &[Token], open: usize) -> usize {
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        match token.toktype {
+            TokType::LParen | TokType::LBracket | TokType::LBrace => depth += 1,
+            TokType::RParen | TokType::RBracket | TokType::RBrace => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+    }
+    tokens.len() - 1
+}
+
+// Return the whitespace that separates prev from next.
+fn get_separator(prev: &Token, next: &Token, in_attributes: bool) -> &'static str {
+    use TokType::*;
+    match (&prev.toktype, &next.toktype) {
+        (_, Semicolon) | (_, Comma) | (_, Colon) | (_, RParen) | (_, RBracket) | (_, RBrace) => "",
+        (Comma, _) | (Colon, _) => " ",
+        (MapsTo, _) | (TreeMapsTo, _) | (_, MapsTo) | (_, TreeMapsTo) => " ",
+        (LParen, _) | (LBracket, _) | (LBrace, _) => "",
+        // Attributes are written as `name=value`, while variables are defined with `name = value`.
+        (Str(name), Str(value)) if in_attributes && (name == "=" || value.starts_with('=')) => "",
+        (Str(s), _) if s == "=" => " ",
+        // Adjacent strings would otherwise be read as a single string.
+        (Str(_), Str(_)) => " ",
+        // Tags and exclusions end the spec before them.
+        (_, Str(s)) if is_tag(s) || is_exclude(s) => " ",
+        // Parts of a spec are written without whitespace, e.g. `.config/[a, b]`.
+        _ => "",
+    }
+}
+
+struct Formatter<'a> {
+    content: &'a str,
+}
+
+impl<'a> Formatter<'a> {
+    // The text of token as it is written in the content.
+    fn text(&self, token: &Token) -> &'a str {
+        &self.content[token.span.clone()]
+    }
+
+    // Return the formatted statement, along with the offset of its top-level `=>` if it is on the first line.
+    fn format_statement(&self, tokens: &[Token], depth: usize) -> (String, Option<usize>) {
+        let mut text = String::new();
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i].toktype {
+                TokType::LParen | TokType::LBracket | TokType::LBrace => i = find_close(tokens, i),
+                TokType::MapsTo | TokType::TreeMapsTo => break,
+                _ => {}
+            }
+            i += 1;
+        }
+        if i >= tokens.len() {
+            self.format_tokens(tokens, depth, false, &mut text);
+            return (text, None);
+        }
+        self.format_tokens(&tokens[..i], depth, false, &mut text);
+        let arrow = Some(text.len()).filter(|_| !text.contains('\n'));
+        text.push(' ');
+        self.format_tokens(&tokens[i..], depth, false, &mut text);
+        (text, arrow)
+    }
+
+    // Format a list from its opening to its closing bracket, e.g. `[a, b]`.
+    // Lists that were written across multiple lines are kept that way, with an item per line.
+    fn format_list(&self, tokens: &[Token], depth: usize, in_attributes: bool, text: &mut String) {
+        let open = &tokens[0];
+        let close = &tokens[tokens.len() - 1];
+        let inner = &tokens[1..tokens.len() - 1];
+        // Split the items at the commas that are not nested in other lists.
+        let mut items: Vec<&[Token]> = Vec::new();
+        let mut start = 0;
+        let mut i = 0;
+        while i < inner.len() {
+            match inner[i].toktype {
+                TokType::LParen | TokType::LBracket | TokType::LBrace => i = find_close(inner, i),
+                TokType::Comma => {
+                    items.push(&inner[start..i]);
+                    start = i + 1;
+                }
+                _ => {}
+            }
+            i += 1;
+        }
+        if start < inner.len() {
+            items.push(&inner[start..]);
+        }
+        let is_multiline = open.toktype != TokType::LParen
+            && self.content[open.span.end..close.span.start].contains('\n');
+        text.push_str(self.text(open));
+        for (i, item) in items.iter().enumerate() {
+            if is_multiline {
+                text.push('\n');
+                text.push_str(&INDENT.repeat(depth + 1));
+            } else if i > 0 {
+                text.push_str(", ");
+            }
+            self.format_tokens(item, depth + 1, in_attributes, text);
+            if is_multiline {
+                text.push(',');
+            }
+        }
+        if is_multiline {
+            text.push('\n');
+            text.push_str(&INDENT.repeat(depth));
+        }
+        text.push_str(self.text(close));
+    }
+
+    // Format the tokens of a list item, which may contain lists of its own.
+    fn format_tokens(
+        &self,
+        tokens: &[Token],
+        depth: usize,
+        in_attributes: bool,
+        text: &mut String,
+    ) {
+        let mut i = 0;
+        while i < tokens.len() {
+            let token = &tokens[i];
+            if i > 0 {
+                text.push_str(get_separator(&tokens[i - 1], token, in_attributes));
+            }
+            match token.toktype {
+                TokType::LParen | TokType::LBracket | TokType::LBrace => {
+                    let close = find_close(tokens, i);
+                    // Attributes are the last list of an entry, e.g. `[mode=copy]`.
+                    let is_attributes = token.toktype == TokType::LBracket
+                        && matches!(
+                            tokens.get(close + 1),
+                            Some(Token {
+                                toktype: TokType::Semicolon,
+                                ..
+                            })
+                        )
+                        && matches!(tokens.get(i + 2), Some(Token { toktype: TokType::Str(s), .. }) if s.starts_with('='));
+                    // A space separates attributes from the entry.
+                    if is_attributes && i > 0 {
+                        text.push(' ');
+                    }
+                    self.format_list(&tokens[i..=close], depth, is_attributes, text);
+                    i = close + 1;
+                }
+                _ => {
+                    text.push_str(self.text(token));
+                    i += 1;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check_format(content: &str, expected: &str) {
+        let formatted = format(content).unwrap();
+        assert_eq!(formatted, expected);
+        // Formatting should be idempotent and keep the meaning of the configuration.
+        assert_eq!(format(&formatted).unwrap(), formatted);
+        let statements = |content: &str| {
+            get_statements(content.chars().peekable())
+                .collect::<Result<Vec<_>, _>>()
+                .unwrap()
+        };
+        assert_eq!(statements(&formatted), statements(content));
+    }
+
+    #[test]
+    fn format_spacing() {
+        check_format(
+            "a;b  =>c ;\n\n.config/[ x,y, ] !*.swp=>>  \"my dir\"/[p,q]{ os(linux):z , default : w}#work;",
+            "a;\nb => c;\n\n.config/[x, y] !*.swp =>> \"my dir\"/[p, q]{os(linux): z, default: w} #work;\n",
+        );
+    }
+
+    #[test]
+    fn format_attributes() {
+        check_format(
+            "a[mode = copy,perm=600];\n[b, c][fold=true];",
+            "a [mode=copy, perm=600];\n[b, c] [fold=true];\n",
+        );
+    }
+
+    #[test]
+    fn format_multiline_lists() {
+        check_format(
+            "nvim/[init.vim,\nlua/[a, b]] => .config/nvim/[\n  init.vim,\n  lua/[\n a,\n b\n ]\n];",
+            "nvim/[\n    init.vim,\n    lua/[a, b],\n] => .config/nvim/[\n    init.vim,\n    lua/[\n        a,\n        b,\n    ],\n];\n",
+        );
+    }
+
+    #[test]
+    fn format_blocks() {
+        check_format(
+            "let x = y;\n\n\n\ngroup work if os(linux){\n\na;   b #t;\n}\nvars{\nemail = me;\nwork = {os(linux): a, default: b};\n}\n",
+            "let x = y;\n\ngroup work if os(linux) {\n    a;\n    b #t;\n}\nvars {\n    email = me;\n    work = {os(linux): a, default: b};\n}\n",
+        );
+    }
+
+    #[test]
+    fn align_consecutive_arrows() {
+        check_format(
+            "a => b;\nlonger => c;\n\nd => e;\ngroup g {\nf => g;\n}\n",
+            "a      => b;\nlonger => c;\n\nd => e;\ngroup g {\n    f => g;\n}\n",
+        );
+    }
+
+    #[test]
+    fn format_invalid() {
+        assert_eq!(format("a b;").unwrap_err().len(), 1);
+    }
+}
@@ -1,25 +1,38 @@
-use std::iter::Peekable;
+use std::{
+    cell::Cell,
+    fmt::{self, Display, Formatter},
+    iter::Peekable,
+    ops::Range,
+    rc::Rc,
+};
 
+/// The kind of a token, along with its string if it has one.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub enum TokType {
-    // An unquoted string, e.g. `.config/`.
+    /// An unquoted string, e.g. `.config/`.
     Str(String),
-    // "Paren" refers to parentheses: `(` and `)`.
+    /// "Paren" refers to parentheses: `(` and `)`.
     LParen,
     RParen,
-    // "Brace" refers to curly braces: `{` and `}`.
+    /// "Brace" refers to curly braces: `{` and `}`.
     LBrace,
     RBrace,
-    // "Bracket" refers to square brackets: `[` and `]`.
+    /// "Bracket" refers to square brackets: `[` and `]`.
     LBracket,
     RBracket,
-    // The mapping operator, `=>`.
+    /// The mapping operator, `=>`.
     MapsTo,
+    /// The tree mapping operator, `=>>`.
+    TreeMapsTo,
     Comma,
     Colon,
     Semicolon,
+    /// Input that could not be lexed, along with the reason why.
+    /// This is reported by the parser as a `ParseErrorType::Lex`.
+    Invalid(&'static str),
 }
 impl TokType {
+    /// Return the string of a `Str` token. Panics for any other token.
     pub fn unwrap_str(self) -> String {
         match self {
             TokType::Str(s) => s,
@@ -28,6 +41,28 @@ impl TokType {
     }
 }
 
+impl Display for TokType {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let symbol = match self {
+            TokType::Str(s) => s.as_str(),
+            TokType::LParen => "(",
+            TokType::RParen => ")",
+            TokType::LBrace => "{",
+            TokType::RBrace => "}",
+            TokType::LBracket => "[",
+            TokType::RBracket => "]",
+            TokType::MapsTo => "=>",
+            TokType::TreeMapsTo => "=>>",
+            TokType::Comma => ",",
+            TokType::Colon => ":",
+            TokType::Semicolon => ";",
+            TokType::Invalid(_) => return f.write_str("invalid input"),
+        };
+        write!(f, "`{}`", symbol)
+    }
+}
+
+/// The tokens expected where any string is expected, for parse errors.
 pub const EXPECTED_STR: &[TokType; 1] = &[TokType::Str(String::new())];
 
 impl<'a> From<&'a str> for TokType {
@@ -36,51 +71,246 @@ impl<'a> From<&'a str> for TokType {
     }
 }
 
+/// A token of the configuration and where it was found.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct Token {
     pub toktype: TokType,
     pub line: usize,
+    /// The column is the (1-indexed) number of characters from the start of the line.
+    /// A column of 0 means that the position of the token is unknown.
+    pub column: usize,
+    /// Byte offsets of the token in the input.
+    pub span: Range<usize>,
 }
 
 impl Token {
+    /// A token on line at an unknown column.
     pub fn new(toktype: TokType, line: usize) -> Self {
-        Self { toktype, line }
+        Self {
+            toktype,
+            line,
+            column: 0,
+            span: 0..0,
+        }
     }
+    /// A `Str` token on line at an unknown column.
     pub fn string(s: String, line: usize) -> Self {
+        Self::new(TokType::Str(s), line)
+    }
+    /// Set the column and span of the token.
+    pub fn at(self, column: usize, span: Range<usize>) -> Self {
         Self {
-            toktype: TokType::Str(s),
-            line,
+            column,
+            span,
+            ..self
         }
     }
 }
 
+// How much of the input has been consumed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Default)]
+struct Offset {
+    bytes: usize,
+    chars: usize,
+}
+
+// Wraps an iterator, keeping count of the consumed characters.
+// (The count is shared as the iterator itself is owned by a `Peekable`.)
+struct Counted<I: Iterator<Item = char>> {
+    iter: I,
+    count: Rc<Cell<Offset>>,
+}
+
+impl<I: Iterator<Item = char>> Iterator for Counted<I> {
+    type Item = char;
+    fn next(&mut self) -> Option<Self::Item> {
+        let c = self.iter.next()?;
+        let count = self.count.get();
+        self.count.set(Offset {
+            bytes: count.bytes + c.len_utf8(),
+            chars: count.chars + 1,
+        });
+        Some(c)
+    }
+}
+
+/// Yields the tokens of the characters of the configuration.
 pub struct Lexer<I: Iterator<Item = char>> {
-    iter: Peekable<I>,
+    iter: Peekable<Counted<Peekable<I>>>,
+    count: Rc<Cell<Offset>>,
     line: usize,
+    // The character offset at which the current line starts.
+    line_start: usize,
+    // The offset of a `[` that was consumed while lexing a string, which is the next token.
+    bracket: Option<Offset>,
 }
 
 impl<I: Iterator<Item = char>> Lexer<I> {
     pub fn new(iter: Peekable<I>) -> Lexer<I> {
-        Lexer { iter, line: 1 }
+        let count = Rc::new(Cell::new(Offset::default()));
+        Lexer {
+            iter: Counted {
+                iter,
+                count: Rc::clone(&count),
+            }
+            .peekable(),
+            count,
+            line: 1,
+            line_start: 0,
+            bracket: None,
+        }
+    }
+
+    // Return the offset of the next character to be lexed.
+    fn offset(&mut self) -> Offset {
+        // Peeking consumes a character from the inner iterator, so it has to be accounted for.
+        let peeked_len = self.iter.peek().map(|c| c.len_utf8());
+        let count = self.count.get();
+        match peeked_len {
+            Some(len) => Offset {
+                bytes: count.bytes - len,
+                chars: count.chars - 1,
+            },
+            None => count,
+        }
+    }
+
+    // Lex a string starting with `start`, where a start of `[` is a character class whose `[:` was consumed.
+    fn string_toktype(&mut self, start: char) -> TokType {
+        let mut ret = String::new();
+        let result = match start {
+            '"' => process_quoted(&mut self.iter, &mut ret),
+            '[' => process_class(&mut self.iter, &mut ret),
+            _ => {
+                ret.push(start);
+                Ok(())
+            }
+        };
+        match result.and_then(|_| process_string(&mut self.iter, ret)) {
+            Ok((s, bracket)) => {
+                if bracket {
+                    // The `[` is the character before the next one.
+                    let offset = self.offset();
+                    self.bracket = Some(Offset {
+                        bytes: offset.bytes - 1,
+                        chars: offset.chars - 1,
+                    });
+                }
+                TokType::Str(s)
+            }
+            Err(e) => TokType::Invalid(e),
+        }
     }
 }
 
-fn process_string<I: Iterator<Item = char>>(iter: &mut Peekable<I>, start: char) -> String {
+// Characters that have a special meaning when matching paths.
+// Brackets form character classes, e.g. `bash_[:ap]*` is passed on as `bash_[ap]*`.
+fn is_pattern_char(c: char) -> bool {
+    matches!(c, '*' | '?' | '[' | ']')
+}
+
+// Process the rest of a quoted string (after the opening `"`), pushing its characters to `ret`.
+// Everything inside quotes is taken literally, including pattern characters.
+fn process_quoted<I: Iterator<Item = char>>(
+    iter: &mut Peekable<I>,
+    ret: &mut String,
+) -> Result<(), &'static str> {
+    const UNTERMINATED: &str = "Unterminated string";
+    loop {
+        match iter.peek() {
+            // Strings may not span multiple lines, '\n' should be used instead.
+            None | Some('\n') => return Err(UNTERMINATED),
+            Some('"') => {
+                iter.next();
+                return Ok(());
+            }
+            Some('\\') => {
+                iter.next();
+                match iter.next() {
+                    None => return Err(UNTERMINATED),
+                    Some('n') => ret.push('\n'),
+                    Some('t') => ret.push('\t'),
+                    Some(c) => {
+                        if is_pattern_char(c) {
+                            ret.push('\\');
+                        }
+                        ret.push(c);
+                    }
+                }
+            }
+            Some(&c) => {
+                iter.next();
+                if is_pattern_char(c) {
+                    // Escape pattern characters so that they are matched literally.
+                    ret.push('\\');
+                }
+                ret.push(c);
+            }
+        }
+    }
+}
+
+// Process the rest of a character class (after the opening `[:`), pushing it to `ret` as `[...]`.
+// Other brackets are escaped, so only classes written this way reach the matcher as classes.
+fn process_class<I: Iterator<Item = char>>(
+    iter: &mut Peekable<I>,
+    ret: &mut String,
+) -> Result<(), &'static str> {
+    let mut class = String::new();
+    loop {
+        match iter.peek() {
+            None | Some('\n') => return Err("Unterminated character class"),
+            Some(']') => {
+                iter.next();
+                break;
+            }
+            Some(&c) => {
+                iter.next();
+                class.push(c);
+            }
+        }
+    }
+    if class.is_empty() || class == "!" {
+        return Err("Empty character class");
+    }
+    ret.push('[');
+    ret.push_str(&class);
+    ret.push(']');
+    Ok(())
+}
+
+// Process the rest of a string, which starts with `ret`.
+// A string ends before a `[`, unless it starts a character class. If the `[` had to be consumed to tell,
+// this is returned along with the string.
+fn process_string<I: Iterator<Item = char>>(
+    iter: &mut Peekable<I>,
+    mut ret: String,
+) -> Result<(String, bool), &'static str> {
     let is_ending_char = |c: char| -> bool {
         c.is_ascii_whitespace()
-            || ['(', ')', '{', '}', '[', ']', ',', ';', ':', '=']
+            || ['(', ')', '{', '}', ']', ',', ';', ':', '=']
                 .iter()
                 .any(|e| *e == c)
     };
-    let mut ret = start.to_string();
     while let Some(peek_char) = iter.peek() {
-        if peek_char == &'\\' {
+        if peek_char == &'"' {
+            // Quoted sections can be directly adjacent to unquoted sections, e.g. `"my dir"/file`.
+            iter.next();
+            process_quoted(iter, &mut ret)?;
+        } else if peek_char == &'[' {
+            iter.next();
+            if iter.peek() != Some(&':') {
+                return Ok((ret, true));
+            }
+            iter.next();
+            process_class(iter, &mut ret)?;
+        } else if peek_char == &'\\' {
             iter.next();
             let next_char = iter.peek().cloned();
             match next_char {
-                Some('*') | Some('?') | None => {
-                    ret.push('\\');
-                }
+                // Escaped pattern characters are matched literally.
+                Some(c) if is_pattern_char(c) => ret.push('\\'),
+                None => ret.push('\\'),
                 _ => {}
             }
             if let Some(c) = next_char {
@@ -94,52 +324,62 @@ fn process_string<I: Iterator<Item = char>>(iter: &mut Peekable<I>, start: char)
             break;
         }
     }
-    ret
+    Ok((ret, false))
 }
 
 impl<I: Iterator<Item = char>> Iterator for Lexer<I> {
     type Item = Token;
     fn next(&mut self) -> Option<Self::Item> {
-        macro_rules! new_tok {
-            ($t:ident) => {
-                Token::new(TokType::$t, self.line)
-            };
+        if let Some(start) = self.bracket.take() {
+            let column = start.chars - self.line_start + 1;
+            return Some(
+                Token::new(TokType::LBracket, self.line).at(column, start.bytes..start.bytes + 1),
+            );
         }
-
         loop {
-            match self.iter.next() {
-                None => return None,
-                Some(chr) => match chr {
-                    '\n' => self.line += 1,
-                    '(' => return Some(new_tok!(LParen)),
-                    ')' => return Some(new_tok!(RParen)),
-                    '{' => return Some(new_tok!(LBrace)),
-                    '}' => return Some(new_tok!(RBrace)),
-                    '[' => return Some(new_tok!(LBracket)),
-                    ']' => return Some(new_tok!(RBracket)),
-                    ',' => return Some(new_tok!(Comma)),
-                    ';' => return Some(new_tok!(Semicolon)),
-                    ':' => return Some(new_tok!(Colon)),
-                    '=' => {
+            let start = self.offset();
+            let toktype = match self.iter.next()? {
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = start.chars + 1;
+                    continue;
+                }
+                ' ' | '\t' | '\r' => continue,
+                '(' => TokType::LParen,
+                ')' => TokType::RParen,
+                '{' => TokType::LBrace,
+                '}' => TokType::RBrace,
+                '[' => {
+                    if self.iter.peek() == Some(&':') {
+                        self.iter.next();
+                        self.string_toktype('[')
+                    } else {
+                        TokType::LBracket
+                    }
+                }
+                ']' => TokType::RBracket,
+                ',' => TokType::Comma,
+                ';' => TokType::Semicolon,
+                ':' => TokType::Colon,
+                '=' => {
+                    if self.iter.peek() == Some(&'>') {
+                        self.iter.next();
                         if self.iter.peek() == Some(&'>') {
                             self.iter.next();
-                            return Some(new_tok!(MapsTo));
+                            TokType::TreeMapsTo
                         } else {
-                            return Some(Token::string(
-                                process_string(&mut self.iter, '='),
-                                self.line,
-                            ));
+                            TokType::MapsTo
                         }
+                    } else {
+                        self.string_toktype('=')
                     }
-                    ' ' | '\t' | '\r' => {}
-                    _ => {
-                        return Some(Token::string(
-                            process_string(&mut self.iter, chr),
-                            self.line,
-                        ))
-                    }
-                },
-            }
+                }
+                chr => self.string_toktype(chr),
+            };
+            // A string ends before a `[` that had to be consumed.
+            let end = self.bracket.unwrap_or_else(|| self.offset());
+            let column = start.chars - self.line_start + 1;
+            return Some(Token::new(toktype, self.line).at(column, start.bytes..end.bytes));
         }
     }
 }
@@ -148,10 +388,12 @@ impl<I: Iterator<Item = char>> Iterator for Lexer<I> {
 mod tests {
     use super::*;
 
+    // Check the type and line of each token. (Positions are checked separately.)
     fn check_lexer_output(input: &str, expected: Vec<Token>) {
         let chars = input.chars().peekable();
         let lex = Lexer::new(chars);
-        lex.zip(expected.iter())
+        lex.map(|tok| Token::new(tok.toktype, tok.line))
+            .zip(expected.iter())
             .enumerate()
             .for_each(|(idx, (out, ex_out))| {
                 assert!(
@@ -175,10 +417,14 @@ mod tests {
 
     #[test]
     fn ignore_pattern_chars_in_processed_string() {
-        // '*' and '?' are pattern chars. They should be ignored if the user tries to escape them.
-        // These characters should be handled later with patmatch.
-        let proc_str = process_string(&mut "\\[\\]\\*\\?".to_owned().chars().peekable(), '[');
-        assert_eq!(proc_str, "[[]\\*\\?");
+        // '*', '?' and brackets are pattern chars. They should be ignored if the user tries to escape them.
+        // These characters should be handled later when paths are matched.
+        let (proc_str, _) = process_string(
+            &mut "\\[\\]\\*\\?".to_owned().chars().peekable(),
+            "[".to_owned(),
+        )
+        .unwrap();
+        assert_eq!(proc_str, "[\\[\\]\\*\\?");
     }
 
     #[test]
@@ -222,6 +468,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn tree_maps_to() {
+        check_lexer_output(
+            "nvim =>> .config/nvim; a=>>>b;",
+            vec![
+                tok!("nvim", 1),
+                tok!(TreeMapsTo, 1),
+                tok!(".config/nvim", 1),
+                tok!(Semicolon, 1),
+                tok!("a", 1),
+                tok!(TreeMapsTo, 1),
+                tok!(">b", 1),
+                tok!(Semicolon, 1),
+            ],
+        );
+    }
+
     #[test]
     fn single_statement() {
         check_lexer_output(
@@ -263,6 +526,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn quoted_string() {
+        check_lexer_output(
+            "\"my dir\"/file => \"a=b;c\";",
+            vec![
+                tok!("my dir/file", 1),
+                tok!(MapsTo, 1),
+                tok!("a=b;c", 1),
+                tok!(Semicolon, 1),
+            ],
+        );
+    }
+
+    #[test]
+    fn quoted_string_escapes() {
+        check_lexer_output(
+            r#""tab\there" "\"quote\"" "\\""#,
+            vec![tok!("tab\there", 1), tok!("\"quote\"", 1), tok!("\\", 1)],
+        );
+    }
+
+    #[test]
+    fn quoted_pattern_chars_are_escaped() {
+        // Pattern characters inside quotes should be matched literally.
+        check_lexer_output(
+            r#""*.?[a]" * x\[a\]"#,
+            vec![tok!("\\*.\\?\\[a\\]", 1), tok!("*", 1), tok!("x\\[a\\]", 1)],
+        );
+    }
+
+    #[test]
+    fn char_class() {
+        check_lexer_output(
+            "bash_[:ap]* [:!a]x [a] b[c]\n[:] x[:a",
+            vec![
+                tok!("bash_[ap]*", 1),
+                tok!("[!a]x", 1),
+                tok!(LBracket, 1),
+                tok!("a", 1),
+                tok!(RBracket, 1),
+                tok!("b", 1),
+                tok!(LBracket, 1),
+                tok!("c", 1),
+                tok!(RBracket, 1),
+                Token::new(TokType::Invalid("Empty character class"), 2),
+                Token::new(TokType::Invalid("Unterminated character class"), 2),
+            ],
+        );
+    }
+
+    #[test]
+    fn bracket_after_string_position() {
+        let lex = Lexer::new("ab[c]".chars().peekable());
+        let positions: Vec<_> = lex.map(|tok| (tok.column, tok.span)).collect();
+        assert_eq!(positions, vec![(1, 0..2), (3, 2..3), (4, 3..4), (5, 4..5)]);
+    }
+
+    #[test]
+    fn unterminated_string() {
+        check_lexer_output(
+            "\"abc\ndef",
+            vec![
+                Token::new(TokType::Invalid("Unterminated string"), 1),
+                tok!("def", 2),
+            ],
+        );
+    }
+
+    #[test]
+    fn token_positions() {
+        let lex = Lexer::new("a => b;\n  {ö: \"c d\"}".chars().peekable());
+        let positions: Vec<_> = lex.map(|tok| (tok.line, tok.column, tok.span)).collect();
+        assert_eq!(
+            positions,
+            vec![
+                (1, 1, 0..1),
+                (1, 3, 2..4),
+                (1, 6, 5..6),
+                (1, 7, 6..7),
+                (2, 3, 10..11),
+                // Spans are in bytes, columns are in characters.
+                (2, 4, 11..13),
+                (2, 5, 13..14),
+                (2, 7, 15..20),
+                (2, 12, 20..21),
+            ]
+        );
+    }
+
     #[test]
     fn backslash_escape() {
         check_lexer_output("test\\{\\}\\:\\ \\\n", vec![tok!("test{}: \n", 1)])
@@ -1,16 +1,24 @@
+/// The syntax tree of the configuration.
 pub mod ast;
+/// Formatting of the configuration for `ambit fmt`.
+pub mod formatter;
+/// Splits the configuration into tokens.
 pub mod lexer;
+/// Parses tokens into the statements of the configuration.
 pub mod parser;
+/// Generates the strings that a spec expands to.
 pub mod strgen;
 
-pub use ast::Entry;
+pub use ast::{Entry, Statement};
 use lexer::Lexer;
-pub use parser::Parser;
+pub use parser::{substitute_variables, Parser};
 
 use std::error::Error;
 use std::fmt::{self, Display, Formatter};
 use std::iter::Peekable;
+use std::ops::Range;
 
+/// What went wrong while parsing.
 #[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum ParseErrorType {
     Expected(&'static [lexer::TokType]),
@@ -18,19 +26,113 @@ pub enum ParseErrorType {
     Lex(&'static str),
 }
 
+/// An error found while parsing the configuration, at the token where it occurred.
 #[derive(PartialEq, Eq, Debug, Clone)]
 pub struct ParseError {
     pub ty: ParseErrorType,
-    // Some(_) if it failed at a token, or None if it failed at EOF.
+    /// Some(_) if it failed at a token, or None if it failed at EOF.
     pub tok: Option<lexer::Token>,
 }
 
+impl ParseError {
+    /// The line and column of the token the error occurred at, or None if it occurred at EOF.
+    pub fn position(&self) -> Option<(usize, usize)> {
+        self.tok.as_ref().map(|tok| (tok.line, tok.column))
+    }
+
+    /// The byte offsets of the token the error occurred at, or None if it occurred at EOF.
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.tok.as_ref().map(|tok| tok.span.clone())
+    }
+
+    // The byte offsets of the error within `content`.
+    // Errors at EOF are reported after the last (non-whitespace) character.
+    fn span_in(&self, content: &str) -> Range<usize> {
+        let span = self.span().unwrap_or_else(|| {
+            let end = content.trim_end().len();
+            end..end
+        });
+        span.start.min(content.len())..span.end.min(content.len())
+    }
+
+    /// The line and column of the error within `content`, including errors at EOF.
+    /// `content` must be the input that was parsed.
+    pub fn location(&self, content: &str) -> (usize, usize) {
+        let start = self.span_in(content).start;
+        let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line = content[..start].matches('\n').count() + 1;
+        let column = content[line_start..start].chars().count() + 1;
+        (line, column)
+    }
+
+    /// Render the error along with the offending line of `content`, with a caret under the bad token.
+    /// `content` must be the input that was parsed.
+    pub fn render(&self, content: &str) -> String {
+        let span = self.span_in(content);
+        let start = span.start;
+        let (line, column) = self.location(content);
+        let line_start = content[..start].rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = content[start..]
+            .find('\n')
+            .map(|i| start + i)
+            .unwrap_or_else(|| content.len());
+        let caret_len = content[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+        let line_nr = line.to_string();
+        let gutter = " ".repeat(line_nr.len());
+        format!(
+            "{message}\n{gutter}--> line {line}, column {column}\n{gutter} |\n{line_nr} | {source}\n{gutter} | {padding}{carets}",
+            message = self.message(),
+            gutter = gutter,
+            line = line,
+            column = column,
+            line_nr = line_nr,
+            source = content[line_start..line_end].trim_end_matches('\r'),
+            padding = " ".repeat(column - 1),
+            carets = "^".repeat(caret_len),
+        )
+    }
+
+    /// A description of the error without its position.
+    pub fn message(&self) -> String {
+        let found = match &self.tok {
+            Some(tok) => tok.toktype.to_string(),
+            None => "end of file".to_owned(),
+        };
+        match self.ty {
+            ParseErrorType::Expected(choices) => {
+                let choices: Vec<String> = choices
+                    .iter()
+                    .map(|choice| match choice {
+                        // The contents of an expected string do not matter.
+                        lexer::TokType::Str(_) => "string".to_owned(),
+                        _ => choice.to_string(),
+                    })
+                    .collect();
+                let expected = match choices.len() {
+                    1 => choices[0].clone(),
+                    _ => format!("one of {}", choices.join(", ")),
+                };
+                format!("Expected {}, found {}", expected, found)
+            }
+            ParseErrorType::Custom(message) | ParseErrorType::Lex(message) => message.to_owned(),
+        }
+    }
+}
+
 impl Error for ParseError {}
 
 impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        // TODO: Output parse error nicely
-        write!(f, "{:?}", self)
+        f.write_str(&self.message())?;
+        match self.position() {
+            Some((line, column)) => write!(f, " at line {}, column {}", line, column),
+            // Expected errors already report that the end of file was found.
+            None if matches!(self.ty, ParseErrorType::Expected(_)) => Ok(()),
+            None => f.write_str(" at end of file"),
+        }
     }
 }
 
@@ -40,9 +142,101 @@ impl From<ParseErrorType> for ParseError {
     }
 }
 
+/// The result of parsing part of the configuration.
 pub type ParseResult<T> = std::result::Result<T, ParseError>;
 
-pub fn get_entries<I: Iterator<Item = char>>(char_iter: Peekable<I>) -> Parser<Lexer<I>> {
+/// Return a parser that yields the statements of the configuration in char_iter.
+pub fn get_statements<I: Iterator<Item = char>>(char_iter: Peekable<I>) -> Parser<Lexer<I>> {
     let lex = Lexer::new(char_iter);
     Parser::new(lex.peekable())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn first_error(content: &str) -> ParseError {
+        get_statements(content.chars().peekable())
+            .collect::<ParseResult<Vec<_>>>()
+            .unwrap_err()
+    }
+
+    #[test]
+    fn statement_spans() {
+        let content = "a => b;\nvars {\n  c = d;\n}\n[e, f] [mode=copy];";
+        let mut parser = get_statements(content.chars().peekable());
+        let mut spans = Vec::new();
+        while let Some(statement) = parser.next() {
+            statement.unwrap();
+            spans.push(&content[parser.statement_span()]);
+        }
+        assert_eq!(spans, vec!["a => b;", "c = d;", "[e, f] [mode=copy];"]);
+    }
+
+    #[test]
+    fn display_expected() {
+        assert_eq!(
+            first_error("a;\n[b c];").to_string(),
+            "Expected `,`, found `c` at line 2, column 4"
+        );
+    }
+
+    #[test]
+    fn display_at_eof() {
+        assert_eq!(
+            first_error("a").to_string(),
+            "Expected `;`, found end of file"
+        );
+    }
+
+    #[test]
+    fn display_expected_choices() {
+        let err = ParseError {
+            ty: ParseErrorType::Expected(&[lexer::TokType::Comma, lexer::TokType::RBrace]),
+            tok: Some(lexer::Token::string("b".to_owned(), 1).at(14, 13..14)),
+        };
+        assert_eq!(
+            err.to_string(),
+            "Expected one of `,`, `}`, found `b` at line 1, column 14"
+        );
+    }
+
+    #[test]
+    fn render_snippet() {
+        let content = "a;\n[b long];\n";
+        assert_eq!(
+            first_error(content).render(content),
+            r#"Expected `,`, found `long`
+ --> line 2, column 4
+  |
+2 | [b long];
+  |    ^^^^"#
+        );
+    }
+
+    #[test]
+    fn render_snippet_at_eof() {
+        let content = "a => b\n\n";
+        assert_eq!(
+            first_error(content).render(content),
+            r#"Expected `;`, found end of file
+ --> line 1, column 7
+  |
+1 | a => b
+  |       ^"#
+        );
+    }
+
+    #[test]
+    fn render_custom_error() {
+        let content = "[a, b] => c;";
+        assert_eq!(
+            first_error(content).render(content),
+            r#"Left and right sides of mapping must match up
+ --> line 1, column 12
+  |
+1 | [a, b] => c;
+  |            ^"#
+        );
+    }
+}
@@ -1,8 +1,8 @@
 use crate::config::{ast::*, lexer::*, ParseError, ParseErrorType, ParseResult};
 
-use std::iter::Peekable;
+use std::{collections::HashMap, iter::Peekable, ops::Range};
 
-// Can be simply parsed.
+/// Can be simply parsed.
 pub trait SimpleParse
 where
     Self: Sized,
@@ -59,40 +59,528 @@ impl SimpleParse for String {
     }
 }
 
+/// Yields the statements of the tokens of the configuration.
 pub struct Parser<I: Iterator<Item = Token>> {
     iter: Peekable<I>,
+    // Variables defined with `let` so far.
+    variables: HashMap<String, String>,
+    // Variables defined in `vars` blocks so far, with their values on this system.
+    vars: HashMap<String, String>,
+    // If the current statement is within a `vars` block.
+    in_vars: bool,
+    // The groups that enclose the current statement, from outermost to innermost.
+    groups: Vec<Group>,
+    // Byte offsets of the tokens of the last statement, including its `;`.
+    span: Range<usize>,
 }
 impl<I: Iterator<Item = Token>> Parser<I> {
     pub fn new(iter: Peekable<I>) -> Self {
-        Self { iter }
+        Self {
+            iter,
+            variables: HashMap::new(),
+            vars: HashMap::new(),
+            in_vars: false,
+            groups: Vec::new(),
+            span: 0..0,
+        }
+    }
+
+    /// The byte offsets of the statement last returned by `next`, e.g. to remove it from the input.
+    pub fn statement_span(&self) -> Range<usize> {
+        self.span.clone()
+    }
+
+    // Apply the tags and guards of the enclosing groups to `entry`.
+    fn apply_groups(&self, entry: &mut Entry) {
+        for group in &self.groups {
+            if !entry.tags.contains(&group.name) {
+                entry.tags.push(group.name.clone());
+            }
+        }
+        let mut guards: Vec<Expr> = self
+            .groups
+            .iter()
+            .filter_map(|group| group.guard.clone())
+            .collect();
+        let guard = match guards.len() {
+            0 => return,
+            1 => guards.remove(0),
+            _ => Expr::All(guards),
+        };
+        // A guard behaves like a match expression with a single case around each side.
+        let guarded =
+            |spec: Spec, guard: Expr| Spec::from(SpecType::match_expr(vec![(guard, spec)], None));
+        let left = std::mem::replace(&mut entry.left, Spec::from(SpecType::None));
+        entry.left = guarded(left, guard.clone());
+        entry.right = entry.right.take().map(|right| guarded(right, guard));
+    }
+
+    // Replace every `$name` in `s` with the value of the variable `name`.
+    // Unknown variables are left untouched.
+    fn substitute_variables(&self, s: &str) -> String {
+        substitute_variables(s, |name| self.variables.get(name).cloned())
+    }
+
+    // Replace the `{name}` placeholders in `spec` with the values of their variables.
+    // Errors are reported at the name of the unknown variable within `tokens`.
+    fn resolve_vars(&self, spec: &mut Spec, tokens: &[Token]) -> ParseResult<()> {
+        resolve_vars(spec, &self.vars).map_err(|name| ParseError {
+            ty: ParseErrorType::Custom("Unknown variable"),
+            tok: tokens
+                .windows(3)
+                .find(|window| {
+                    window[0].toktype == TokType::LBrace
+                        && window[1].toktype == TokType::Str(name.clone())
+                        && window[2].toktype == TokType::RBrace
+                })
+                .map(|window| window[1].clone()),
+        })
+    }
+
+    // Define the variable of a `vars` block, returning its statement if it has a value.
+    // A variable without a value, e.g. because no case of its match expression applies, is left undefined.
+    fn define_var(
+        &mut self,
+        name: String,
+        mut value: Spec,
+        tokens: &[Token],
+    ) -> Option<ParseResult<Statement>> {
+        value.for_each_string_mut(&mut |s| *s = self.substitute_variables(s));
+        if let Err(e) = self.resolve_vars(&mut value, tokens) {
+            return Some(Err(e));
+        }
+        let mut values: Vec<String> = value.into_iter().collect();
+        match values.len() {
+            0 => None,
+            1 => {
+                let value = values.remove(0);
+                self.vars.insert(name.clone(), value.clone());
+                Some(Ok(Statement::Var(name, value)))
+            }
+            _ => Some(Err(ParseError::from(ParseErrorType::Custom(
+                "Variables must have a single value",
+            )))),
+        }
     }
 }
 impl<I: Iterator<Item = Token>> Iterator for Parser<I> {
-    type Item = ParseResult<Entry>;
+    type Item = ParseResult<Statement>;
     fn next(&mut self) -> Option<Self::Item> {
-        // If there's nothing left, we've consumed all the input - yay!
-        match self.iter.peek() {
-            None => None,
-            Some(_) => Some({
-                let new = Entry::parse(&mut self.iter);
-                match new {
-                    Err(mut e) => {
-                        e.tok = self.iter.peek().cloned();
-                        Err(e)
+        loop {
+            // If there's nothing left, we've consumed all the input - yay!
+            if self.iter.peek().is_none() {
+                if self.groups.is_empty() && !self.in_vars {
+                    return None;
+                }
+                // Report the unclosed group (or `vars` block) only once.
+                self.groups.clear();
+                self.in_vars = false;
+                return Some(Err(ParseError::from(ParseErrorType::Expected(&[
+                    TokType::RBrace,
+                ]))));
+            }
+            // A `}` at the start of a statement closes the `vars` block or the innermost group.
+            if self.in_vars && next_is(&mut self.iter, &TokType::RBrace) {
+                self.iter.next();
+                self.in_vars = false;
+                continue;
+            }
+            if !self.groups.is_empty() && next_is(&mut self.iter, &TokType::RBrace) {
+                self.iter.next();
+                self.groups.pop();
+                continue;
+            }
+            // Take the tokens up to (and including) the end of the statement.
+            // Consuming all of them also recovers from errors, allowing errors in later statements to be reported as well.
+            let mut tokens = Vec::new();
+            let mut opens_vars = false;
+            for tok in &mut self.iter {
+                let is_end = match tok.toktype {
+                    TokType::Semicolon => true,
+                    TokType::LBrace => {
+                        opens_vars = !self.in_vars && is_vars_header(&tokens);
+                        opens_vars || is_group_header(&tokens)
+                    }
+                    _ => false,
+                };
+                tokens.push(tok);
+                if is_end {
+                    break;
+                }
+            }
+            if let (Some(first), Some(last)) = (tokens.first(), tokens.last()) {
+                self.span = first.span.start..last.span.end;
+            }
+            if self.in_vars {
+                let var = parse_tokens(tokens.clone(), parse_var);
+                match var {
+                    Ok((name, value)) => match self.define_var(name, value, &tokens) {
+                        Some(statement) => return Some(statement),
+                        None => continue,
+                    },
+                    Err(e) => return Some(Err(e)),
+                }
+            }
+            if opens_vars {
+                self.in_vars = true;
+                continue;
+            }
+            let attribute_tokens = split_attributes(&mut tokens);
+            let statement_tokens = tokens.clone();
+            let new = parse_tokens(tokens, RawStatement::parse).and_then(|statement| {
+                let attributes = match attribute_tokens {
+                    Some(attribute_tokens) => parse_tokens(attribute_tokens, Attributes::parse)?,
+                    None => return Ok((statement, Attributes::default())),
+                };
+                match statement {
+                    RawStatement::Statement(Statement::Entry(_)) => Ok((statement, attributes)),
+                    _ => Err(ParseError::from(ParseErrorType::Custom(
+                        "Only entries can be given attributes",
+                    ))),
+                }
+            });
+            return Some(match new {
+                Err(e) => Err(e),
+                Ok((RawStatement::Let(name, value), _)) => {
+                    let value = self.substitute_variables(&value);
+                    self.variables.insert(name, value);
+                    continue;
+                }
+                Ok((RawStatement::Group(group), _)) => {
+                    self.groups.push(group);
+                    continue;
+                }
+                Ok((RawStatement::Statement(Statement::Entry(mut entry)), attributes)) => {
+                    let mut substitute = |s: &mut String| *s = self.substitute_variables(s);
+                    entry.left.for_each_string_mut(&mut substitute);
+                    entry.excludes.iter_mut().for_each(&mut substitute);
+                    if let Some(right) = entry.right.as_mut() {
+                        right.for_each_string_mut(&mut substitute);
+                    }
+                    if let Err(e) = self.resolve_vars(&mut entry.left, &statement_tokens) {
+                        return Some(Err(e));
                     }
-                    Ok(p) => Ok(p),
+                    if let Some(right) = entry.right.as_mut() {
+                        if let Err(e) = self.resolve_vars(right, &statement_tokens) {
+                            return Some(Err(e));
+                        }
+                    }
+                    entry.attributes = attributes;
+                    self.apply_groups(&mut entry);
+                    Ok(Statement::Entry(entry))
+                }
+                Ok((RawStatement::Statement(Statement::Include(_)), _))
+                    if !self.groups.is_empty() =>
+                {
+                    Err(ParseError::from(ParseErrorType::Custom(
+                        "Groups cannot contain include statements",
+                    )))
+                }
+                Ok((RawStatement::Statement(Statement::Include(path)), _)) => {
+                    Ok(Statement::Include(self.substitute_variables(&path)))
+                }
+                Ok((
+                    RawStatement::Statement(
+                        statement @ (Statement::Profile(_)
+                        | Statement::Var(..)
+                        | Statement::AutoCommit(_)),
+                    ),
+                    _,
+                )) => Ok(statement),
+            });
+        }
+    }
+}
+
+// Parse `tokens` with `parse`, reporting errors at the token they occurred at.
+fn parse_tokens<T>(
+    tokens: Vec<Token>,
+    parse: fn(&mut Peekable<std::vec::IntoIter<Token>>) -> ParseResult<T>,
+) -> ParseResult<T> {
+    let mut iter = tokens.into_iter().peekable();
+    parse(&mut iter).map_err(|mut e| {
+        // Errors that concern an earlier token already point at it.
+        if e.tok.is_none() {
+            e.tok = iter.peek().cloned();
+        }
+        // The parser fails on invalid tokens, but the lexer knows why they are invalid.
+        if let Some(Token {
+            toktype: TokType::Invalid(reason),
+            ..
+        }) = e.tok
+        {
+            e.ty = ParseErrorType::Lex(reason);
+        }
+        e
+    })
+}
+
+/* Remove the attribute list from the end of a statement's tokens, returning its tokens.
+ * An attribute list looks like a variant expression at the end of a spec,
+ * so it is told apart by the `=` that follows the name of its first attribute,
+ * or by starting with a flag that is written without a value, e.g. `[secret]`.
+ */
+fn split_attributes(tokens: &mut Vec<Token>) -> Option<Vec<Token>> {
+    let is_last = |tokens: &[Token], i: usize, ty: &TokType| {
+        i < tokens.len() && tokens[tokens.len() - 1 - i].toktype == *ty
+    };
+    if !(is_last(tokens, 0, &TokType::Semicolon) && is_last(tokens, 1, &TokType::RBracket)) {
+        return None;
+    }
+    // Find the matching `[`.
+    let close = tokens.len() - 2;
+    let mut depth = 0;
+    let mut open = None;
+    for i in (0..=close).rev() {
+        match tokens[i].toktype {
+            TokType::RBracket => depth += 1,
+            TokType::LBracket => {
+                depth -= 1;
+                if depth == 0 {
+                    open = Some(i);
+                    break;
                 }
+            }
+            _ => {}
+        }
+    }
+    let open = open?;
+    let is_attribute_list = match (tokens.get(open + 1), tokens.get(open + 2)) {
+        (
+            _,
+            Some(Token {
+                toktype: TokType::Str(s),
+                ..
             }),
+        ) if s.starts_with('=') => true,
+        (
+            Some(Token {
+                toktype: TokType::Str(name),
+                ..
+            }),
+            Some(next),
+        ) => {
+            FLAG_ATTRIBUTES.contains(&name.as_str())
+                && matches!(next.toktype, TokType::Comma | TokType::RBracket)
+        }
+        _ => false,
+    };
+    if !is_attribute_list {
+        return None;
+    }
+    let semicolon = tokens.pop();
+    let attribute_tokens = tokens.split_off(open);
+    tokens.extend(semicolon);
+    Some(attribute_tokens)
+}
+
+/* Returns whether `tokens` are the start of a group, i.e. `group name` optionally followed by `if expr`.
+ * No entry can start like this, so the `{` that follows ends the statement instead of starting a match expression.
+ */
+pub(super) fn is_group_header(tokens: &[Token]) -> bool {
+    let is_str = |i: usize, f: &dyn Fn(&str) -> bool| matches!(tokens.get(i), Some(Token { toktype: TokType::Str(s), .. }) if f(s));
+    is_str(0, &|s| s == "group")
+        && is_str(1, &|s| !is_tag(s))
+        && (tokens.len() == 2 || is_str(2, &|s| s == "if"))
+}
+
+// The start of a `vars` block, i.e. `vars` before its `{`.
+pub(super) fn is_vars_header(tokens: &[Token]) -> bool {
+    matches!(tokens, [Token { toktype: TokType::Str(s), .. }] if s == "vars")
+}
+
+// Replace the `{name}` placeholders in `spec` with the values of their variables.
+// A variant with a single option stands in for each value.
+// Returns the name of the first variable that is not defined.
+fn resolve_vars(spec: &mut Spec, vars: &HashMap<String, String>) -> Result<(), String> {
+    if let SpecType::Placeholder(Placeholder::Var(name), rest) = &mut spec.spectype {
+        let value = vars.get(name.as_str()).ok_or_else(|| name.clone())?;
+        let rest = rest.take().map(|rest| *rest);
+        spec.spectype = SpecType::variant_expr(vec![Spec::from(value.as_str())], rest);
+    }
+    let rest = match &mut spec.spectype {
+        SpecType::None => None,
+        SpecType::Variant(expr, rest) => {
+            for spec in &mut expr.specs {
+                resolve_vars(spec, vars)?;
+            }
+            rest.as_mut()
+        }
+        SpecType::Match(expr, rest) => {
+            for (_, spec) in &mut expr.cases {
+                resolve_vars(spec, vars)?;
+            }
+            rest.as_mut()
+        }
+        SpecType::Placeholder(_, rest) => rest.as_mut(),
+    };
+    match rest {
+        Some(rest) => resolve_vars(rest, vars),
+        None => Ok(()),
+    }
+}
+
+// var -> str "=" spec ";"
+fn parse_var<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<(String, Spec)> {
+    let name = String::parse(iter)?;
+    if !name.chars().all(is_variable_char) {
+        return Err(ParseError::from(ParseErrorType::Custom(
+            "Variable names may only contain letters, digits and underscores",
+        )));
+    }
+    if !eat(iter, &TokType::Str("=".to_owned())) {
+        return Err(ParseError::from(ParseErrorType::Custom(
+            "Expected `=` after variable name",
+        )));
+    }
+    let value = Spec::parse(iter)?;
+    expect(iter, &[TokType::Semicolon])?;
+    Ok((name, value))
+}
+
+fn is_variable_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+/// Replace every `$name` in `s` with `lookup(name)`.
+/// If `lookup` returns None, the reference is left untouched.
+pub fn substitute_variables(s: &str, mut lookup: impl FnMut(&str) -> Option<String>) -> String {
+    let mut ret = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(i) = rest.find('$') {
+        ret.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        let len = after
+            .find(|c: char| !is_variable_char(c))
+            .unwrap_or(after.len());
+        // A lone `$` is not a reference.
+        let value = if len > 0 { lookup(&after[..len]) } else { None };
+        match value {
+            Some(value) => ret.push_str(&value),
+            None => {
+                ret.push('$');
+                ret.push_str(&after[..len]);
+            }
+        }
+        rest = &after[len..];
+    }
+    ret.push_str(rest);
+    ret
+}
+
+// A statement as it is written, including variable definitions which are handled by the parser itself.
+enum RawStatement {
+    Statement(Statement),
+    // The name and value of a variable.
+    Let(String, String),
+    // The start of a group, which lasts until the matching `}`.
+    Group(Group),
+}
+
+// A group of entries that share a tag and an optional guard, e.g. `group work if os(linux) { ... }`.
+struct Group {
+    name: String,
+    guard: Option<Expr>,
+}
+
+// statement -> "include" str ";"
+//            -> "profile" str ";"
+//            -> "autocommit" ( "off" | "commit" | "push" ) ";"
+//            -> "let" str "=" str ";"
+//            -> "group" str ("if" expr)? "{" statement* "}"
+//            -> entry
+impl SimpleParse for RawStatement {
+    fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
+        let left = Spec::parse(iter)?;
+        // A lone `include`, `profile`, `autocommit` or `let` is still a valid entry, so it is only a directive if a string follows.
+        let is_directive = matches!(
+            iter.peek(),
+            Some(Token {
+                toktype: TokType::Str(_),
+                ..
+            })
+        );
+        if is_directive && left == Spec::from("include") {
+            let path = String::parse(iter)?;
+            expect(iter, &[TokType::Semicolon])?;
+            Ok(RawStatement::Statement(Statement::Include(path)))
+        } else if is_directive && left == Spec::from("profile") {
+            let name = String::parse(iter)?;
+            expect(iter, &[TokType::Semicolon])?;
+            Ok(RawStatement::Statement(Statement::Profile(name)))
+        } else if is_directive && left == Spec::from("autocommit") {
+            let setting = match String::parse(iter)?.as_str() {
+                "off" => AutoCommit::Off,
+                "commit" => AutoCommit::Commit,
+                "push" => AutoCommit::Push,
+                _ => {
+                    return Err(ParseError::from(ParseErrorType::Custom(
+                        "Expected `off`, `commit` or `push` after `autocommit`",
+                    )))
+                }
+            };
+            expect(iter, &[TokType::Semicolon])?;
+            Ok(RawStatement::Statement(Statement::AutoCommit(setting)))
+        } else if is_directive && left == Spec::from("let") {
+            let name = String::parse(iter)?;
+            if !name.chars().all(is_variable_char) {
+                return Err(ParseError::from(ParseErrorType::Custom(
+                    "Variable names may only contain letters, digits and underscores",
+                )));
+            }
+            if !eat(iter, &TokType::Str("=".to_owned())) {
+                return Err(ParseError::from(ParseErrorType::Custom(
+                    "Expected `=` after variable name",
+                )));
+            }
+            let value = String::parse(iter)?;
+            expect(iter, &[TokType::Semicolon])?;
+            Ok(RawStatement::Let(name, value))
+        } else if is_directive && left == Spec::from("group") {
+            let name = String::parse(iter)?;
+            let guard = if eat(iter, &TokType::Str("if".to_owned())) {
+                Some(Expr::parse(iter)?)
+            } else {
+                None
+            };
+            expect(iter, &[TokType::LBrace])?;
+            Ok(RawStatement::Group(Group { name, guard }))
+        } else {
+            Entry::parse_with_left(left, iter)
+                .map(|entry| RawStatement::Statement(Statement::Entry(entry)))
         }
     }
 }
 
-// entry -> spec ("=>" spec)? ";"
+// entry -> spec exclude* (("=>" | "=>>") spec)? tag* ";"
+// exclude -> "!" str
+// tag -> "#" str
 impl SimpleParse for Entry {
     fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
         let left = Spec::parse(iter)?;
+        Entry::parse_with_left(left, iter)
+    }
+}
+impl Entry {
+    // Parse the rest of an entry whose left spec has already been parsed.
+    fn parse_with_left<I: Iterator<Item = Token>>(
+        left: Spec,
+        iter: &mut Peekable<I>,
+    ) -> ParseResult<Self> {
+        let mut excludes = Vec::new();
+        while let Some(Token {
+            toktype: TokType::Str(s),
+            ..
+        }) = iter.peek()
+        {
+            if !is_exclude(s) {
+                break;
+            }
+            excludes.push(iter.next().unwrap().toktype.unwrap_str()[1..].to_owned());
+        }
         let mut right = None;
-        if eat(iter, &TokType::MapsTo) {
+        let tree = eat(iter, &TokType::TreeMapsTo);
+        if tree || eat(iter, &TokType::MapsTo) {
             let right_val = Spec::parse(iter)?;
             let left_nr = left.nr_of_options().ok_or_else(|| {
                 ParseError::from(ParseErrorType::Custom("Too many options on left hand side"))
@@ -102,21 +590,49 @@ impl SimpleParse for Entry {
                     "Too many options on right hand side",
                 ))
             })?;
-            if left_nr != right_nr {
+            // A left side with a single option is synced to every option of the right side.
+            if left_nr != right_nr && left_nr != 1 {
                 return Err(ParseError::from(ParseErrorType::Custom(
                     "Left and right sides of mapping must match up",
                 )));
             }
             right = Some(right_val);
         }
+        let mut tags = Vec::new();
+        while let Some(Token {
+            toktype: TokType::Str(s),
+            ..
+        }) = iter.peek()
+        {
+            if !is_tag(s) {
+                break;
+            }
+            let tok = iter.next().unwrap();
+            let tag = tok.toktype.clone().unwrap_str()[1..].to_owned();
+            if tags.contains(&tag) {
+                return Err(error_at("Duplicate tag", Some(tok)));
+            }
+            tags.push(tag);
+        }
         expect(iter, &[TokType::Semicolon])?;
-        Ok(Self { left, right })
+        Ok(Self {
+            left,
+            excludes,
+            right,
+            tree,
+            tags,
+            attributes: Attributes::default(),
+        })
     }
 }
 
 /* spec -> str
  *      -> str? variant-expr spec?
  *      -> str? match-expr spec?
+ *      -> str? placeholder spec?
+ *      -> str transform spec?
+ * transform -> ( "basename" | "dirname" | "configdir" | "datadir" | "cachedir" ) "(" ")"
+ * The name of a transform is part of the string before it, e.g. `.config/basename()`.
  */
 impl SimpleParse for Spec {
     fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
@@ -128,23 +644,6 @@ impl SimpleParse for Spec {
         {
             string = Some(iter.next().unwrap().toktype.unwrap_str());
         }
-        fn try_parse_spec<I: Iterator<Item = Token>>(
-            iter: &mut Peekable<I>,
-        ) -> ParseResult<Option<Box<Spec>>> {
-            // Check if a new spec could start here.
-            // Note that this should be updated if the spec specification changes.
-            fn is_starting_token(next: &Token) -> bool {
-                matches!(
-                    next.toktype,
-                    TokType::Str(_) | TokType::LBrace | TokType::LBracket
-                )
-            }
-            if iter.peek().map(is_starting_token).unwrap_or(false) {
-                Ok(Some(Box::new(Spec::parse(iter)?)))
-            } else {
-                Ok(None)
-            }
-        }
         // optimization
         match iter.peek() {
             None => {}
@@ -152,10 +651,7 @@ impl SimpleParse for Spec {
                 TokType::LBrace => {
                     return Ok(Spec {
                         string,
-                        spectype: SpecType::Match(
-                            Box::new(MatchExpr::parse(iter)?),
-                            try_parse_spec(iter)?,
-                        ),
+                        spectype: parse_match_or_placeholder(iter)?,
                     });
                 }
                 TokType::LBracket => {
@@ -167,6 +663,16 @@ impl SimpleParse for Spec {
                         ),
                     });
                 }
+                TokType::LParen => {
+                    if let Some((prefix, transform)) = string.as_deref().and_then(split_transform) {
+                        iter.next();
+                        expect(iter, &[TokType::RParen])?;
+                        return Ok(Spec {
+                            string: Some(prefix.to_owned()).filter(|prefix| !prefix.is_empty()),
+                            spectype: SpecType::Placeholder(transform, try_parse_spec(iter)?),
+                        });
+                    }
+                }
                 _ => {}
             },
         }
@@ -181,6 +687,204 @@ impl SimpleParse for Spec {
     }
 }
 
+// Split the name of a transform from the end of s, e.g. `basename` from `.config/basename`.
+fn split_transform(s: &str) -> Option<(&str, Placeholder)> {
+    let name_start = s.rfind('/').map(|i| i + 1).unwrap_or(0);
+    let transform = Placeholder::from_transform_name(&s[name_start..])?;
+    Some((&s[..name_start], transform))
+}
+
+// Tags are strings that start with `#`, e.g. `#work`.
+pub(super) fn is_tag(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('#')
+}
+
+// Exclusions are strings that start with `!`, e.g. `!*.swp`.
+pub(super) fn is_exclude(s: &str) -> bool {
+    s.len() > 1 && s.starts_with('!')
+}
+
+fn try_parse_spec<I: Iterator<Item = Token>>(
+    iter: &mut Peekable<I>,
+) -> ParseResult<Option<Box<Spec>>> {
+    // Check if a new spec could start here.
+    // Note that this should be updated if the spec specification changes.
+    fn is_starting_token(next: &Token) -> bool {
+        match &next.toktype {
+            // Exclusions and tags end the spec.
+            TokType::Str(s) => !is_tag(s) && !is_exclude(s),
+            TokType::LBrace | TokType::LBracket => true,
+            _ => false,
+        }
+    }
+    if iter.peek().map(is_starting_token).unwrap_or(false) {
+        Ok(Some(Box::new(Spec::parse(iter)?)))
+    } else {
+        Ok(None)
+    }
+}
+
+// placeholder -> "{" ( "host" | "os" | digit+ | str ) "}"
+// Any other string within a placeholder is the name of a variable from a `vars` block.
+// Placeholders and match expressions both start with "{",
+// so they can only be told apart after the string that follows.
+fn parse_match_or_placeholder<I: Iterator<Item = Token>>(
+    iter: &mut Peekable<I>,
+) -> ParseResult<SpecType> {
+    expect(iter, &[TokType::LBrace])?;
+    let is_placeholder = match iter.peek() {
+        Some(Token {
+            toktype: TokType::Str(s),
+            ..
+        }) => Placeholder::from_name(s).is_some() || !EXPR_NAMES.contains(&s.as_str()),
+        _ => false,
+    };
+    let expr = match is_placeholder {
+        true => {
+            let name = iter.next().unwrap().toktype.unwrap_str();
+            let placeholder = Placeholder::from_name(&name);
+            if placeholder.is_none() {
+                expect(iter, &[TokType::RBrace])?;
+                return Ok(SpecType::Placeholder(
+                    Placeholder::Var(name),
+                    try_parse_spec(iter)?,
+                ));
+            }
+            if eat(iter, &TokType::RBrace) {
+                return Ok(SpecType::Placeholder(
+                    placeholder.unwrap(),
+                    try_parse_spec(iter)?,
+                ));
+            }
+            // This is the expr of the first case of a match expression, e.g. `{host(a): b}`.
+            Some(Expr::parse_with_name(&name, iter)?)
+        }
+        false => None,
+    };
+    Ok(SpecType::Match(
+        Box::new(MatchExpr::parse_cases(expr, iter)?),
+        try_parse_spec(iter)?,
+    ))
+}
+
+const ATTRIBUTES: &[&str] = &["mode", "perm", "owner", "group", "fold", "secret"];
+// Attributes that are true when they are given without a value, e.g. `[secret]`.
+const FLAG_ATTRIBUTES: &[&str] = &["fold", "secret"];
+
+// attributes -> [ comma-list<(str "=" str) | str> ]
+// The `=` may also be attached to the value, e.g. `perm=600` is lexed as `perm` followed by `=600`.
+// Errors are reported at the name or value of the attribute they concern.
+impl SimpleParse for Attributes {
+    fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
+        expect(iter, &[TokType::LBracket])?;
+        let mut attributes = Attributes::default();
+        // Whether `mode` was given, as secrets cannot be symlinked.
+        let mut has_mode = false;
+        let mut names = Vec::new();
+        while !eat(iter, &TokType::RBracket) {
+            let name_tok = iter.peek().cloned();
+            let name = String::parse(iter)?;
+            if !ATTRIBUTES.contains(&name.as_str()) {
+                return Err(error_at("Unknown attribute", name_tok));
+            }
+            if names.contains(&name) {
+                return Err(error_at("Duplicate attribute", name_tok));
+            }
+            let is_flag = matches!(
+                iter.peek(),
+                Some(Token {
+                    toktype: TokType::Comma | TokType::RBracket,
+                    ..
+                })
+            );
+            let (mut value, mut value_tok) = if is_flag && FLAG_ATTRIBUTES.contains(&name.as_str())
+            {
+                ("=true".to_owned(), name_tok.clone())
+            } else {
+                let value_tok = iter.peek().cloned();
+                (String::parse(iter)?, value_tok)
+            };
+            if value == "=" {
+                value_tok = iter.peek().cloned();
+                value = String::parse(iter)?;
+            } else if value.starts_with('=') {
+                value.remove(0);
+                value_tok = value_tok.map(skip_equals);
+            } else {
+                return Err(error_at("Expected `=` after attribute name", value_tok));
+            }
+            let value_error = |message| error_at(message, value_tok.clone());
+            match name.as_str() {
+                "mode" => {
+                    attributes.mode = match value.as_str() {
+                        "symlink" => LinkMode::Symlink,
+                        "copy" => LinkMode::Copy,
+                        _ => return Err(value_error("mode must be either `symlink` or `copy`")),
+                    };
+                    has_mode = true;
+                }
+                "perm" => {
+                    attributes.perm = Some(
+                        u32::from_str_radix(&value, 8)
+                            .map_err(|_| value_error("perm must be an octal number, e.g. `600`"))?,
+                    )
+                }
+                "owner" => attributes.owner = Some(value),
+                "group" => attributes.group = Some(value),
+                "fold" => {
+                    attributes.fold = match value.as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(value_error("fold must be either `true` or `false`")),
+                    }
+                }
+                "secret" => {
+                    attributes.secret = match value.as_str() {
+                        "true" => true,
+                        "false" => false,
+                        _ => return Err(value_error("secret must be either `true` or `false`")),
+                    }
+                }
+                _ => unreachable!("Attribute names are checked before their values"),
+            }
+            if attributes.secret && has_mode && attributes.mode == LinkMode::Symlink {
+                return Err(error_at("Secret files cannot be symlinked", name_tok));
+            }
+            names.push(name);
+            // Allow list without trailing comma
+            if eat(iter, &TokType::RBracket) {
+                break;
+            }
+            expect(iter, &[TokType::Comma])?;
+        }
+        // Secrets are always copied, and only readable by the user unless a permission is given.
+        if attributes.secret {
+            attributes.mode = LinkMode::Copy;
+            attributes.perm.get_or_insert(0o600);
+        }
+        Ok(attributes)
+    }
+}
+
+// An error with a custom message at tok, rather than at the token the parser stopped at.
+fn error_at(message: &'static str, tok: Option<Token>) -> ParseError {
+    ParseError {
+        ty: ParseErrorType::Custom(message),
+        tok,
+    }
+}
+
+// Narrow a value with an attached `=`, e.g. `=600`, down to the value itself.
+fn skip_equals(tok: Token) -> Token {
+    if tok.span.len() < 2 {
+        // The position of the token is unknown.
+        return tok;
+    }
+    let span = tok.span.start + 1..tok.span.end;
+    let column = tok.column + 1;
+    tok.at(column, span)
+}
+
 // variant-expr -> [ spec (, spec)* ]
 impl SimpleParse for VariantExpr {
     fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
@@ -191,16 +895,72 @@ impl SimpleParse for VariantExpr {
                 "Variant expression must have at least one option",
             )));
         }
-        Ok(VariantExpr {
-            specs: CommaList::parse(iter, &TokType::RBracket)?.list,
-        })
+        let mut specs = Vec::new();
+        for spec in CommaList::<Spec>::parse(iter, &TokType::RBracket)?.list {
+            match parse_range(&spec)? {
+                Some(range) => specs.extend(range.iter().map(|s| Spec::from(s.as_str()))),
+                None => specs.push(spec),
+            }
+        }
+        Ok(VariantExpr { specs })
+    }
+}
+
+// The largest number of options that a range can expand to.
+const MAX_RANGE_LEN: u64 = 10_000;
+
+// range -> str of the form `start..end`, e.g. `1..9`
+// Returns the numbers from start to end (inclusive) if spec is a range.
+// Numbers are padded with zeros to the width of a bound that starts with a zero, e.g. `01..10`.
+fn parse_range(spec: &Spec) -> ParseResult<Option<Vec<String>>> {
+    let (start, end) = match (&spec.string, &spec.spectype) {
+        (Some(s), SpecType::None) => match s.split_once("..") {
+            Some(bounds) => bounds,
+            None => return Ok(None),
+        },
+        _ => return Ok(None),
+    };
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if !is_number(start) || !is_number(end) {
+        return Ok(None);
+    }
+    let too_large = || ParseError::from(ParseErrorType::Custom("Range has too many options"));
+    let start_nr: u64 = start.parse().map_err(|_| too_large())?;
+    let end_nr: u64 = end.parse().map_err(|_| too_large())?;
+    if start_nr.max(end_nr) - start_nr.min(end_nr) >= MAX_RANGE_LEN {
+        return Err(too_large());
     }
+    let is_padded = |s: &str| s.len() > 1 && s.starts_with('0');
+    let width = match is_padded(start) || is_padded(end) {
+        true => start.len().max(end.len()),
+        false => 0,
+    };
+    let numbers: Vec<u64> = match start_nr <= end_nr {
+        true => (start_nr..=end_nr).collect(),
+        false => (end_nr..=start_nr).rev().collect(),
+    };
+    Ok(Some(
+        numbers
+            .iter()
+            .map(|nr| format!("{:0width$}", nr, width = width))
+            .collect(),
+    ))
 }
 
 // match-expr -> { comma-list<(expr ":" spec)> }
 impl SimpleParse for MatchExpr {
     fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
         expect(iter, &[TokType::LBrace])?;
+        MatchExpr::parse_cases(None, iter)
+    }
+}
+impl MatchExpr {
+    // Parse the cases after the opening "{".
+    // `first_expr` is the expr of the first case if it has already been parsed.
+    fn parse_cases<I: Iterator<Item = Token>>(
+        first_expr: Option<Expr>,
+        iter: &mut Peekable<I>,
+    ) -> ParseResult<Self> {
         // Allow `expr ":" spec` to be parsed into a tuple `(expr, spec)`.
         // (This would be confusing if placed in outer scope,
         // since it's unnecessary, so it's placed here.)
@@ -212,15 +972,24 @@ impl SimpleParse for MatchExpr {
                 Ok((expr, spec))
             }
         }
-        Ok(Self {
-            cases: CommaList::parse(iter, &TokType::RBrace)?.list,
-        })
+        let mut cases = Vec::new();
+        if let Some(expr) = first_expr {
+            expect(iter, &[TokType::Colon])?;
+            cases.push((expr, Spec::parse(iter)?));
+            if eat(iter, &TokType::RBrace) {
+                return Ok(Self { cases });
+            }
+            expect(iter, &[TokType::Comma])?;
+        }
+        cases.extend(CommaList::parse(iter, &TokType::RBrace)?.list);
+        Ok(Self { cases })
     }
 }
 
 // comma-list<T> -> (T ",")* T?
 // Note that CommaList does not implement SimpleParse.
 impl<T: SimpleParse> CommaList<T> {
+    /// Parse the items of the list up to end, which is not consumed.
     pub fn parse<I: Iterator<Item = Token>>(
         iter: &mut Peekable<I>,
         // What token the comma-list should end at, such as RBrace or RBracket.
@@ -240,33 +1009,82 @@ impl<T: SimpleParse> CommaList<T> {
     }
 }
 
-// expr -> ( "os" | "host" ) "(" comma-list<str> ")"
+// The names that an expr can start with.
+const EXPR_NAMES: &[&str] = &[
+    "os", "family", "host", "!os", "!host", "distro", "exists", "has", "profile", "env", "all",
+    "any", "not", "default",
+];
+
+// expr -> ( "os" | "family" | "host" | "!os" | "!host" | "distro" | "exists" | "has" | "profile" )
+//         "(" comma-list<str> ")"
+//       | "env" "(" str ("," comma-list<str>)? ")"
+//       | ( "all" | "any" ) "(" comma-list<expr> ")"
+//       | "not" "(" expr ")"
 //       | "default"
 impl SimpleParse for Expr {
     fn parse<I: Iterator<Item = Token>>(iter: &mut Peekable<I>) -> ParseResult<Self> {
-        let err = ParseError::from(ParseErrorType::Expected(EXPECTED_STR));
-        let expr_type: fn(Vec<String>) -> Expr;
         match iter.peek() {
             Some(Token {
                 toktype: TokType::Str(s),
                 ..
-            }) => match s.as_str() {
-                "os" => expr_type = Expr::Os,
-                "host" => expr_type = Expr::Host,
-                "!os" => expr_type = Expr::NotOs,
-                "!host" => expr_type = Expr::NotHost,
-                "default" => {
-                    // "default" takes no strings to check (since it's always true).
-                    iter.next();
-                    return Ok(Expr::Any);
-                }
-                _ => return Err(err),
-            },
-            _ => return Err(err),
+            }) if EXPR_NAMES.contains(&s.as_str()) => {
+                let name = iter.next().unwrap().toktype.unwrap_str();
+                Expr::parse_with_name(&name, iter)
+            }
+            _ => Err(ParseError::from(ParseErrorType::Expected(EXPECTED_STR))),
         }
-        iter.next();
+    }
+}
+impl Expr {
+    // Parse the rest of an expr whose name (one of `EXPR_NAMES`) has already been consumed.
+    fn parse_with_name<I: Iterator<Item = Token>>(
+        name: &str,
+        iter: &mut Peekable<I>,
+    ) -> ParseResult<Self> {
+        let expr_type: fn(Vec<String>) -> ParseResult<Expr> = match name {
+            "os" => |list| Ok(Expr::Os(list)),
+            "family" => |list| Ok(Expr::Family(list)),
+            "host" => |list| Ok(Expr::Host(list)),
+            "!os" => |list| Ok(Expr::NotOs(list)),
+            "!host" => |list| Ok(Expr::NotHost(list)),
+            "distro" => |list| Ok(Expr::Distro(list)),
+            "exists" => |list| Ok(Expr::Exists(list)),
+            "has" => |list| Ok(Expr::Has(list)),
+            "profile" => |list| Ok(Expr::Profile(list)),
+            "env" => {
+                // The first string is the variable name, the rest are possible values.
+                |mut list| {
+                    if list.is_empty() {
+                        return Err(ParseError::from(ParseErrorType::Custom(
+                            "env expression must be given a variable name",
+                        )));
+                    }
+                    let name = list.remove(0);
+                    Ok(Expr::Env(name, list))
+                }
+            }
+            "all" | "any" => {
+                // Combinators take a list of nested expressions instead of strings.
+                expect(iter, &[TokType::LParen])?;
+                let exprs = CommaList::parse(iter, &TokType::RParen)?.list;
+                return Ok(if name == "all" {
+                    Expr::All(exprs)
+                } else {
+                    Expr::AnyOf(exprs)
+                });
+            }
+            "not" => {
+                expect(iter, &[TokType::LParen])?;
+                let expr = Expr::parse(iter)?;
+                expect(iter, &[TokType::RParen])?;
+                return Ok(Expr::Not(Box::new(expr)));
+            }
+            // "default" takes no strings to check (since it's always true).
+            "default" => return Ok(Expr::Any),
+            _ => unreachable!("`{}` is not an expr name", name),
+        };
         expect(iter, &[TokType::LParen])?;
-        Ok(expr_type(CommaList::parse(iter, &TokType::RParen)?.list))
+        expr_type(CommaList::parse(iter, &TokType::RParen)?.list)
     }
 }
 
@@ -281,14 +1099,14 @@ mod tests {
             {
                 trait StrToToken where Self: ToString {
                     fn repr_as_token(&self) -> Token {
-                        Token { line: 0, toktype: TokType::Str(self.to_string()) }
+                        Token::string(self.to_string(), 0)
                     }
                 }
                 // If the type is a `&str`, make the outputted Token be a TokType::Str.
                 impl StrToToken for &str {}
                 trait OtherToToken where Self: Into<TokType> + Clone {
                     fn repr_as_token(&self) -> Token {
-                        Token { line: 0, toktype: self.clone().into() }
+                        Token::new(self.clone().into(), 0)
                     }
                 }
                 // If the type is a `TokType`, make the outputted Token be that toktype.
@@ -298,13 +1116,17 @@ mod tests {
         }
     }
 
-    fn success(toks: &[Token], ast: &[Entry]) {
+    fn success_statements(toks: &[Token], ast: &[Statement]) {
         let iter = toks.iter().cloned().peekable();
         match Parser::new(iter).collect::<ParseResult<Vec<_>>>() {
             Err(e) => panic!("{:?} at token {:?}", e.ty, e.tok),
             Ok(parsed) => assert_eq!(parsed, ast),
         }
     }
+    fn success(toks: &[Token], ast: &[Entry]) {
+        let ast: Vec<Statement> = ast.iter().cloned().map(Statement::Entry).collect();
+        success_statements(toks, &ast);
+    }
     fn fail(toks: &[Token], err: ParseError) {
         let iter = toks.iter().cloned().peekable();
         let res = Parser::new(iter)
@@ -319,148 +1141,350 @@ mod tests {
             &toklist!["yes", TokType::Semicolon],
             &[Entry {
                 left: Spec::from("yes"),
+                excludes: Vec::new(),
                 right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
         );
     }
 
     #[test]
-    fn choice_expr_basic() {
+    fn include_statement() {
+        success_statements(
+            &toklist!["include", "laptop.ambit", TokType::Semicolon],
+            &[Statement::Include("laptop.ambit".to_owned())],
+        );
+    }
+
+    #[test]
+    fn profile_statement() {
+        success_statements(
+            &toklist!["profile", "minimal", TokType::Semicolon],
+            &[Statement::Profile("minimal".to_owned())],
+        );
+    }
+
+    #[test]
+    fn autocommit_statement() {
+        success_statements(
+            &toklist!["autocommit", "push", TokType::Semicolon],
+            &[Statement::AutoCommit(AutoCommit::Push)],
+        );
+        let toks = toklist!["autocommit", "always", TokType::Semicolon];
+        assert!(Parser::new(toks.iter().cloned().peekable())
+            .collect::<ParseResult<Vec<_>>>()
+            .is_err());
+    }
+
+    #[test]
+    fn include_as_entry() {
+        // Without a path, `include` is an ordinary entry.
         success(
             &toklist![
-                TokType::LBracket,
+                "include",
+                TokType::Semicolon,
+                "include",
+                TokType::MapsTo,
                 "a",
-                TokType::Comma,
-                "b",
+                TokType::Semicolon
+            ],
+            &[
+                Entry {
+                    left: Spec::from("include"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes::default(),
+                },
+                Entry {
+                    left: Spec::from("include"),
+                    excludes: Vec::new(),
+                    right: Some(Spec::from("a")),
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes::default(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn let_statement() {
+        success(
+            &toklist![
+                "let",
+                "confdir",
+                "=",
+                ".config",
+                TokType::Semicolon,
+                "let",
+                "nvim",
+                "=",
+                "$confdir/nvim",
+                TokType::Semicolon,
+                "$nvim/init.vim",
+                TokType::MapsTo,
+                TokType::LBracket,
+                "$confdir/a",
                 TokType::RBracket,
                 TokType::Semicolon
             ],
             &[Entry {
-                left: Spec::from(SpecType::variant_expr(
-                    vec![Spec::from("a"), Spec::from("b")],
+                left: Spec::from(".config/nvim/init.vim"),
+                excludes: Vec::new(),
+                right: Some(Spec::from(SpecType::variant_expr(
+                    vec![Spec::from(".config/a")],
                     None,
-                )),
-                right: None,
+                ))),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
         );
     }
 
     #[test]
-    fn match_expr_basic() {
+    fn unknown_variable() {
+        // Unknown variables are left as they are.
         success(
+            &toklist!["$HOME/$/a$b", TokType::Semicolon],
+            &[Entry {
+                left: Spec::from("$HOME/$/a$b"),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn let_without_equals() {
+        fail(
+            &toklist!["let", "a", "b", TokType::Semicolon],
+            ParseError {
+                ty: ParseErrorType::Custom("Expected `=` after variable name"),
+                tok: Some(Token::string("b".to_owned(), 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn vars_block() {
+        success_statements(
             &toklist![
+                "vars",
                 TokType::LBrace,
-                "default",
+                "email",
+                "=",
+                "me@home.com",
+                TokType::Semicolon,
+                "font",
+                "=",
+                TokType::LBrace,
+                "os",
+                TokType::LParen,
+                "plan9",
+                TokType::RParen,
                 TokType::Colon,
-                "b",
+                "10",
                 TokType::Comma,
+                "default",
+                TokType::Colon,
+                "12",
+                TokType::RBrace,
+                TokType::Semicolon,
+                TokType::RBrace,
+                "mail/",
+                TokType::LBrace,
+                "email",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[
+                Statement::Var("email".to_owned(), "me@home.com".to_owned()),
+                Statement::Var("font".to_owned(), "12".to_owned()),
+                Statement::Entry(Entry {
+                    left: Spec {
+                        string: Some("mail/".to_owned()),
+                        spectype: SpecType::variant_expr(vec![Spec::from("me@home.com")], None),
+                    },
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes::default(),
+                }),
+            ],
+        );
+    }
+
+    #[test]
+    fn vars_without_value() {
+        // A variable whose match expression has no applicable case is not defined.
+        success_statements(
+            &toklist![
+                "vars",
+                TokType::LBrace,
+                "font",
+                "=",
+                TokType::LBrace,
                 "os",
                 TokType::LParen,
-                "windows",
+                "plan9",
                 TokType::RParen,
                 TokType::Colon,
-                "a",
+                "10",
+                TokType::RBrace,
+                TokType::Semicolon,
+                TokType::RBrace
+            ],
+            &[],
+        );
+    }
+
+    #[test]
+    fn unknown_var_placeholder() {
+        fail(
+            &toklist![
+                "a/",
+                TokType::LBrace,
+                "email",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("Unknown variable"),
+                tok: Some(Token::string("email".to_owned(), 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn capture_placeholder() {
+        success(
+            &toklist![
+                "scripts/*",
+                TokType::MapsTo,
+                ".local/bin/",
+                TokType::LBrace,
+                "1",
                 TokType::RBrace,
-                "c",
                 TokType::Semicolon
             ],
             &[Entry {
-                left: Spec::from(SpecType::match_expr(
-                    vec![
-                        (Expr::Any, Spec::from("b")),
-                        (Expr::Os(vec!["windows".to_owned()]), Spec::from("a")),
-                    ],
-                    Some(Spec::from("c")),
-                )),
-                right: None,
+                left: Spec::from("scripts/*"),
+                excludes: Vec::new(),
+                right: Some(Spec {
+                    string: Some(".local/bin/".to_owned()),
+                    spectype: SpecType::Placeholder(Placeholder::Capture("{1}".to_owned()), None),
+                }),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
         );
     }
 
     #[test]
-    fn multiple_exprs() {
+    fn transform() {
         success(
             &toklist![
-                "examples of ",
-                TokType::LBracket,
-                "gui",
-                TokType::Comma,
-                "cli",
-                TokType::RBracket,
+                "configs/**",
                 TokType::MapsTo,
-                TokType::LBracket,
-                "gvim",
-                TokType::Comma,
-                "ed",
-                TokType::RBracket,
+                ".config/basename",
+                TokType::LParen,
+                TokType::RParen,
                 TokType::Semicolon
             ],
             &[Entry {
-                left: Spec {
-                    string: Some("examples of ".to_owned()),
-                    spectype: SpecType::variant_expr(
-                        vec![Spec::from("gui"), Spec::from("cli")],
+                left: Spec::from("configs/**"),
+                excludes: Vec::new(),
+                right: Some(Spec {
+                    string: Some(".config/".to_owned()),
+                    spectype: SpecType::Placeholder(
+                        Placeholder::Capture("basename()".to_owned()),
                         None,
                     ),
-                },
-                right: Some(Spec::from(SpecType::variant_expr(
-                    vec![Spec::from("gvim"), Spec::from("ed")],
-                    None,
+                }),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn platform_dir() {
+        success(
+            &toklist![
+                "nvim",
+                TokType::MapsTo,
+                "configdir",
+                TokType::LParen,
+                TokType::RParen,
+                "/nvim",
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from("nvim"),
+                excludes: Vec::new(),
+                right: Some(Spec::from(SpecType::Placeholder(
+                    Placeholder::PlatformDir(PlatformDir::Config),
+                    Some(Box::new(Spec::from("/nvim"))),
                 ))),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
         );
     }
 
     #[test]
-    fn nested_variant_with_only_one_option() {
+    fn placeholder() {
         success(
             &toklist![
-                ".config/",
-                TokType::LBracket,
-                "kitty/",
-                TokType::LBracket,
-                "kitty.conf",
-                TokType::Comma,
-                "theme.conf",
-                TokType::RBracket,
-                TokType::RBracket,
+                "hosts/",
+                TokType::LBrace,
+                "host",
+                TokType::RBrace,
+                "/sshconfig",
                 TokType::Semicolon
             ],
             &[Entry {
                 left: Spec {
-                    string: Some(".config/".to_owned()),
-                    spectype: SpecType::variant_expr(
-                        vec![Spec {
-                            string: Some("kitty/".to_owned()),
-                            spectype: SpecType::variant_expr(
-                                vec![Spec::from("kitty.conf"), Spec::from("theme.conf")],
-                                None,
-                            ),
-                        }],
-                        None,
+                    string: Some("hosts/".to_owned()),
+                    spectype: SpecType::Placeholder(
+                        Placeholder::Host,
+                        Some(Box::new(Spec::from("/sshconfig"))),
                     ),
                 },
+                excludes: Vec::new(),
                 right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
         );
     }
 
     #[test]
-    fn match_expr_without_default() {
+    fn match_expr_starting_with_placeholder_name() {
         success(
             &toklist![
                 TokType::LBrace,
-                "host",
+                "os",
                 TokType::LParen,
-                "hexagon",
+                "linux",
                 TokType::RParen,
                 TokType::Colon,
                 "a",
                 TokType::Comma,
-                "os",
-                TokType::LParen,
-                "macos",
-                TokType::RParen,
+                "default",
                 TokType::Colon,
                 "b",
                 TokType::RBrace,
@@ -469,64 +1493,1120 @@ mod tests {
             &[Entry {
                 left: Spec::from(SpecType::match_expr(
                     vec![
-                        (Expr::Host(vec!["hexagon".to_owned()]), Spec::from("a")),
-                        (Expr::Os(vec!["macos".to_owned()]), Spec::from("b")),
+                        (Expr::Os(vec!["linux".to_owned()]), Spec::from("a")),
+                        (Expr::Any, Spec::from("b")),
                     ],
                     None,
                 )),
+                excludes: Vec::new(),
                 right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
-        )
+        );
     }
 
-    // Trailing commas are valid syntax and must be supported.
-
     #[test]
-    fn variant_trailing_comma() {
+    fn entry_attributes() {
         success(
             &toklist![
-                TokType::LBracket,
                 "a",
+                TokType::MapsTo,
+                "b",
+                TokType::LBracket,
+                "mode",
+                "=copy",
                 TokType::Comma,
+                "perm",
+                "=",
+                "600",
                 TokType::RBracket,
                 TokType::Semicolon
             ],
             &[Entry {
-                left: Spec::from(SpecType::variant_expr(vec![Spec::from("a")], None)),
-                right: None,
+                left: Spec::from("a"),
+                excludes: Vec::new(),
+                right: Some(Spec::from("b")),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes {
+                    mode: LinkMode::Copy,
+                    perm: Some(0o600),
+                    fold: false,
+                    owner: None,
+                    group: None,
+                    secret: false,
+                },
             }],
-        )
+        );
     }
 
     #[test]
-    fn match_trailing_comma() {
+    fn entry_tags() {
         success(
             &toklist![
-                TokType::LBrace,
-                "os",
-                TokType::LParen,
-                "linux",
-                TokType::Comma,
-                "windows",
-                TokType::Comma, // also checks trailing comma on `os(linux, windows, )`
-                TokType::RParen,
-                TokType::Colon,
                 "a",
+                TokType::LBracket,
+                "b",
+                TokType::RBracket,
+                "#work",
+                "#gaming",
+                TokType::Semicolon,
+                "c",
+                TokType::MapsTo,
+                "d",
+                "#work",
+                TokType::LBracket,
+                "mode",
+                "=copy",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[
+                Entry {
+                    left: Spec {
+                        string: Some("a".to_owned()),
+                        spectype: SpecType::variant_expr(vec![Spec::from("b")], None),
+                    },
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: vec!["work".to_owned(), "gaming".to_owned()],
+                    attributes: Attributes::default(),
+                },
+                Entry {
+                    left: Spec::from("c"),
+                    excludes: Vec::new(),
+                    right: Some(Spec::from("d")),
+                    tree: false,
+                    tags: vec!["work".to_owned()],
+                    attributes: Attributes {
+                        mode: LinkMode::Copy,
+                        perm: None,
+                        fold: false,
+                        owner: None,
+                        group: None,
+                        secret: false,
+                    },
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn entry_excludes() {
+        success(
+            &toklist![
+                "a/",
+                TokType::LBracket,
+                "b",
+                TokType::RBracket,
+                "*",
+                "!*.swp",
+                "!c/*",
+                TokType::MapsTo,
+                "d/*",
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec {
+                    string: Some("a/".to_owned()),
+                    spectype: SpecType::variant_expr(vec![Spec::from("b")], Some(Spec::from("*"))),
+                },
+                excludes: vec!["*.swp".to_owned(), "c/*".to_owned()],
+                right: Some(Spec::from("d/*")),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn one_to_many_entry() {
+        success(
+            &toklist![
+                "gitconfig",
+                TokType::MapsTo,
+                TokType::LBracket,
+                ".gitconfig",
                 TokType::Comma,
-                TokType::RBrace,
+                ".config/git/config",
+                TokType::RBracket,
                 TokType::Semicolon
             ],
             &[Entry {
-                left: Spec::from(SpecType::match_expr(
-                    vec![(
-                        Expr::Os(vec!["linux".to_owned(), "windows".to_owned()]),
-                        Spec::from("a"),
-                    )],
+                left: Spec::from("gitconfig"),
+                excludes: Vec::new(),
+                right: Some(Spec::from(SpecType::variant_expr(
+                    vec![Spec::from(".gitconfig"), Spec::from(".config/git/config")],
                     None,
-                )),
-                right: None,
+                ))),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
             }],
-        )
+        );
+    }
+
+    #[test]
+    fn tree_entry() {
+        success(
+            &toklist![
+                "nvim",
+                TokType::TreeMapsTo,
+                ".config/nvim",
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from("nvim"),
+                excludes: Vec::new(),
+                right: Some(Spec::from(".config/nvim")),
+                tree: true,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn entry_owner() {
+        success(
+            &toklist![
+                "hosts",
+                TokType::MapsTo,
+                "/etc/hosts",
+                TokType::LBracket,
+                "mode",
+                "=copy",
+                TokType::Comma,
+                "owner",
+                "=root",
+                TokType::Comma,
+                "group",
+                "=wheel",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from("hosts"),
+                excludes: Vec::new(),
+                right: Some(Spec::from("/etc/hosts")),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes {
+                    mode: LinkMode::Copy,
+                    owner: Some("root".to_owned()),
+                    group: Some("wheel".to_owned()),
+                    ..Attributes::default()
+                },
+            }],
+        );
+    }
+
+    #[test]
+    fn tree_entry_fold() {
+        success(
+            &toklist![
+                "nvim",
+                TokType::TreeMapsTo,
+                ".config/nvim",
+                TokType::LBracket,
+                "fold",
+                "=true",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from("nvim"),
+                excludes: Vec::new(),
+                right: Some(Spec::from(".config/nvim")),
+                tree: true,
+                tags: Vec::new(),
+                attributes: Attributes {
+                    fold: true,
+                    ..Attributes::default()
+                },
+            }],
+        );
+    }
+
+    #[test]
+    fn entry_secret() {
+        success(
+            &toklist![
+                ".ssh/id_ed25519",
+                TokType::LBracket,
+                "secret",
+                "=true",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(".ssh/id_ed25519"),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes {
+                    mode: LinkMode::Copy,
+                    perm: Some(0o600),
+                    secret: true,
+                    ..Attributes::default()
+                },
+            }],
+        );
+        fail(
+            &toklist![
+                "a",
+                TokType::LBracket,
+                "secret",
+                "=true",
+                TokType::Comma,
+                "mode",
+                "=symlink",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("Secret files cannot be symlinked"),
+                tok: Some(Token::string("mode".to_owned(), 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn variant_is_not_attributes() {
+        success(
+            &toklist![
+                "a",
+                TokType::LBracket,
+                "b",
+                TokType::Comma,
+                "c",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec {
+                    string: Some("a".to_owned()),
+                    spectype: SpecType::variant_expr(vec![Spec::from("b"), Spec::from("c")], None),
+                },
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn flag_attributes() {
+        success(
+            &toklist![
+                "a",
+                TokType::LBracket,
+                "secret",
+                TokType::RBracket,
+                TokType::Semicolon,
+                "b",
+                TokType::LBracket,
+                "fold",
+                TokType::Comma,
+                "perm",
+                "=644",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[
+                Entry {
+                    left: Spec::from("a"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes {
+                        mode: LinkMode::Copy,
+                        perm: Some(0o600),
+                        secret: true,
+                        ..Attributes::default()
+                    },
+                },
+                Entry {
+                    left: Spec::from("b"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes {
+                        perm: Some(0o644),
+                        fold: true,
+                        ..Attributes::default()
+                    },
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn invalid_attribute() {
+        fail(
+            &toklist![
+                "a",
+                TokType::LBracket,
+                "perm",
+                "=rw",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("perm must be an octal number, e.g. `600`"),
+                tok: Some(Token::string("=rw".to_owned(), 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn choice_expr_basic() {
+        success(
+            &toklist![
+                TokType::LBracket,
+                "a",
+                TokType::Comma,
+                "b",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::variant_expr(
+                    vec![Spec::from("a"), Spec::from("b")],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn match_expr_basic() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "default",
+                TokType::Colon,
+                "b",
+                TokType::Comma,
+                "os",
+                TokType::LParen,
+                "windows",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                "c",
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![
+                        (Expr::Any, Spec::from("b")),
+                        (Expr::Os(vec!["windows".to_owned()]), Spec::from("a")),
+                    ],
+                    Some(Spec::from("c")),
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn multiple_exprs() {
+        success(
+            &toklist![
+                "examples of ",
+                TokType::LBracket,
+                "gui",
+                TokType::Comma,
+                "cli",
+                TokType::RBracket,
+                TokType::MapsTo,
+                TokType::LBracket,
+                "gvim",
+                TokType::Comma,
+                "ed",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec {
+                    string: Some("examples of ".to_owned()),
+                    spectype: SpecType::variant_expr(
+                        vec![Spec::from("gui"), Spec::from("cli")],
+                        None,
+                    ),
+                },
+                excludes: Vec::new(),
+                right: Some(Spec::from(SpecType::variant_expr(
+                    vec![Spec::from("gvim"), Spec::from("ed")],
+                    None,
+                ))),
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn nested_variant_with_only_one_option() {
+        success(
+            &toklist![
+                ".config/",
+                TokType::LBracket,
+                "kitty/",
+                TokType::LBracket,
+                "kitty.conf",
+                TokType::Comma,
+                "theme.conf",
+                TokType::RBracket,
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec {
+                    string: Some(".config/".to_owned()),
+                    spectype: SpecType::variant_expr(
+                        vec![Spec {
+                            string: Some("kitty/".to_owned()),
+                            spectype: SpecType::variant_expr(
+                                vec![Spec::from("kitty.conf"), Spec::from("theme.conf")],
+                                None,
+                            ),
+                        }],
+                        None,
+                    ),
+                },
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        );
+    }
+
+    #[test]
+    fn match_expr_without_default() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "host",
+                TokType::LParen,
+                "hexagon",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::Comma,
+                "os",
+                TokType::LParen,
+                "macos",
+                TokType::RParen,
+                TokType::Colon,
+                "b",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![
+                        (Expr::Host(vec!["hexagon".to_owned()]), Spec::from("a")),
+                        (Expr::Os(vec!["macos".to_owned()]), Spec::from("b")),
+                    ],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    // Trailing commas are valid syntax and must be supported.
+
+    #[test]
+    fn variant_trailing_comma() {
+        success(
+            &toklist![
+                TokType::LBracket,
+                "a",
+                TokType::Comma,
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::variant_expr(vec![Spec::from("a")], None)),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn variant_range() {
+        success(
+            &toklist![
+                "workspace-",
+                TokType::LBracket,
+                "3..1",
+                TokType::Comma,
+                "08..10",
+                TokType::Comma,
+                "a..b",
+                TokType::RBracket,
+                ".conf",
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec {
+                    string: Some("workspace-".to_owned()),
+                    spectype: SpecType::variant_expr(
+                        ["3", "2", "1", "08", "09", "10", "a..b"]
+                            .iter()
+                            .map(|&s| Spec::from(s))
+                            .collect(),
+                        Some(Spec::from(".conf")),
+                    ),
+                },
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn variant_range_too_large() {
+        fail(
+            &toklist![
+                TokType::LBracket,
+                "1..100000",
+                TokType::RBracket,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("Range has too many options"),
+                tok: Some(Token::new(TokType::Semicolon, 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn match_trailing_comma() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "os",
+                TokType::LParen,
+                "linux",
+                TokType::Comma,
+                "windows",
+                TokType::Comma, // also checks trailing comma on `os(linux, windows, )`
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::Comma,
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![(
+                        Expr::Os(vec!["linux".to_owned(), "windows".to_owned()]),
+                        Spec::from("a"),
+                    )],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn env_expr() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "env",
+                TokType::LParen,
+                "XDG_SESSION_TYPE",
+                TokType::Comma,
+                "wayland",
+                TokType::RParen,
+                TokType::Colon,
+                "sway/config",
+                TokType::Comma,
+                "env",
+                TokType::LParen,
+                "DISPLAY",
+                TokType::RParen,
+                TokType::Colon,
+                "i3/config",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![
+                        (
+                            Expr::Env("XDG_SESSION_TYPE".to_owned(), vec!["wayland".to_owned()]),
+                            Spec::from("sway/config"),
+                        ),
+                        (
+                            Expr::Env("DISPLAY".to_owned(), vec![]),
+                            Spec::from("i3/config"),
+                        ),
+                    ],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn negated_exprs() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "!os",
+                TokType::LParen,
+                "windows",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::Comma,
+                "!host",
+                TokType::LParen,
+                "hexagon",
+                TokType::Comma,
+                "rpi",
+                TokType::RParen,
+                TokType::Colon,
+                "b",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![
+                        (Expr::NotOs(vec!["windows".to_owned()]), Spec::from("a")),
+                        (
+                            Expr::NotHost(vec!["hexagon".to_owned(), "rpi".to_owned()]),
+                            Spec::from("b"),
+                        ),
+                    ],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn combinator_exprs() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "all",
+                TokType::LParen,
+                "os",
+                TokType::LParen,
+                "linux",
+                TokType::RParen,
+                TokType::Comma,
+                "not",
+                TokType::LParen,
+                "any",
+                TokType::LParen,
+                "host",
+                TokType::LParen,
+                "laptop",
+                TokType::RParen,
+                TokType::Comma,
+                "default",
+                TokType::RParen,
+                TokType::RParen,
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![(
+                        Expr::All(vec![
+                            Expr::Os(vec!["linux".to_owned()]),
+                            Expr::Not(Box::new(Expr::AnyOf(vec![
+                                Expr::Host(vec!["laptop".to_owned()]),
+                                Expr::Any,
+                            ]))),
+                        ]),
+                        Spec::from("a"),
+                    )],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn not_expr_with_multiple_exprs() {
+        fail(
+            &toklist![
+                TokType::LBrace,
+                "not",
+                TokType::LParen,
+                "default",
+                TokType::Comma,
+                "default",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Expected(&[TokType::RParen]),
+                tok: Some(Token::new(TokType::Comma, 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn distro_expr() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "distro",
+                TokType::LParen,
+                "arch",
+                TokType::Comma,
+                "debian",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![(
+                        Expr::Distro(vec!["arch".to_owned(), "debian".to_owned()]),
+                        Spec::from("a"),
+                    )],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn profile_expr() {
+        success(
+            &toklist![
+                TokType::LBrace,
+                "profile",
+                TokType::LParen,
+                "minimal",
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            &[Entry {
+                left: Spec::from(SpecType::match_expr(
+                    vec![(Expr::Profile(vec!["minimal".to_owned()]), Spec::from("a"))],
+                    None,
+                )),
+                excludes: Vec::new(),
+                right: None,
+                tree: false,
+                tags: Vec::new(),
+                attributes: Attributes::default(),
+            }],
+        )
+    }
+
+    #[test]
+    fn env_expr_without_name() {
+        fail(
+            &toklist![
+                TokType::LBrace,
+                "env",
+                TokType::LParen,
+                TokType::RParen,
+                TokType::Colon,
+                "a",
+                TokType::RBrace,
+                TokType::Semicolon
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("env expression must be given a variable name"),
+                tok: Some(Token::new(TokType::Colon, 0)),
+            },
+        );
+    }
+
+    #[test]
+    fn invalid_token_error() {
+        fail(
+            &toklist![
+                "a",
+                TokType::MapsTo,
+                TokType::Invalid("Unterminated string")
+            ],
+            ParseError {
+                ty: ParseErrorType::Lex("Unterminated string"),
+                tok: Some(Token::new(TokType::Invalid("Unterminated string"), 0)),
+            },
+        );
+    }
+
+    // Return the line and column of the error of parsing s.
+    fn error_position_of(s: &str) -> Option<(usize, usize)> {
+        let lex = Lexer::new(s.chars().peekable());
+        Parser::new(lex.peekable())
+            .collect::<ParseResult<Vec<_>>>()
+            .unwrap_err()
+            .position()
+    }
+
+    #[test]
+    fn attribute_error_positions() {
+        // Errors point at the offending value or name rather than the end of the list.
+        assert_eq!(error_position_of("a [mode=foo];"), Some((1, 9)));
+        assert_eq!(error_position_of("a [perm = x];"), Some((1, 11)));
+        assert_eq!(error_position_of("a [bogus=1];"), Some((1, 4)));
+        assert_eq!(error_position_of("a [fold, bogus];"), Some((1, 10)));
+        assert_eq!(error_position_of("a [perm=600, perm=644];"), Some((1, 14)));
+        assert_eq!(
+            error_position_of("a [secret, secret=false];"),
+            Some((1, 12))
+        );
+    }
+
+    #[test]
+    fn duplicate_tags() {
+        fail(
+            &toklist!["a", "#t", "#u", "#t", TokType::Semicolon],
+            ParseError {
+                ty: ParseErrorType::Custom("Duplicate tag"),
+                tok: Some(Token::string("#t".to_owned(), 0)),
+            },
+        );
+        assert_eq!(error_position_of("a #t #t;"), Some((1, 6)));
+    }
+
+    #[test]
+    fn error_position() {
+        let lex = Lexer::new("a;\n[b c];".chars().peekable());
+        let err = Parser::new(lex.peekable())
+            .collect::<ParseResult<Vec<_>>>()
+            .unwrap_err();
+        // The error occurs at `c`, which is on the second line.
+        assert_eq!(err.position(), Some((2, 4)));
+        assert_eq!(err.span(), Some(6..7));
+    }
+
+    #[test]
+    fn group_entries() {
+        let guarded = |guard: Expr, spec: &str| {
+            Spec::from(SpecType::match_expr(vec![(guard, Spec::from(spec))], None))
+        };
+        let linux = || Expr::Os(vec!["linux".to_owned()]);
+        let laptop = || Expr::Host(vec!["laptop".to_owned()]);
+        success(
+            &toklist![
+                "group",
+                "work",
+                "if",
+                "os",
+                TokType::LParen,
+                "linux",
+                TokType::RParen,
+                TokType::LBrace,
+                "a",
+                TokType::MapsTo,
+                "b",
+                "#gaming",
+                TokType::Semicolon,
+                "group",
+                "laptop",
+                "if",
+                "host",
+                TokType::LParen,
+                "laptop",
+                TokType::RParen,
+                TokType::LBrace,
+                "c",
+                TokType::Semicolon,
+                TokType::RBrace,
+                TokType::RBrace,
+                "group",
+                "misc",
+                TokType::LBrace,
+                "d",
+                TokType::Semicolon,
+                TokType::RBrace,
+                "e",
+                TokType::Semicolon
+            ],
+            &[
+                Entry {
+                    left: guarded(linux(), "a"),
+                    excludes: Vec::new(),
+                    right: Some(guarded(linux(), "b")),
+                    tree: false,
+                    tags: vec!["gaming".to_owned(), "work".to_owned()],
+                    attributes: Attributes::default(),
+                },
+                Entry {
+                    left: guarded(Expr::All(vec![linux(), laptop()]), "c"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: vec!["work".to_owned(), "laptop".to_owned()],
+                    attributes: Attributes::default(),
+                },
+                Entry {
+                    left: Spec::from("d"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: vec!["misc".to_owned()],
+                    attributes: Attributes::default(),
+                },
+                Entry {
+                    left: Spec::from("e"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes::default(),
+                },
+            ],
+        );
+    }
+
+    #[test]
+    fn unclosed_group() {
+        fail(
+            &toklist!["group", "a", TokType::LBrace, "b", TokType::Semicolon],
+            ParseError {
+                ty: ParseErrorType::Expected(&[TokType::RBrace]),
+                tok: None,
+            },
+        );
+    }
+
+    #[test]
+    fn include_in_group() {
+        fail(
+            &toklist![
+                "group",
+                "a",
+                TokType::LBrace,
+                "include",
+                "b",
+                TokType::Semicolon,
+                TokType::RBrace
+            ],
+            ParseError {
+                ty: ParseErrorType::Custom("Groups cannot contain include statements"),
+                tok: None,
+            },
+        );
+    }
+
+    #[test]
+    fn recover_after_error() {
+        let iter = toklist![
+            "a",
+            TokType::Comma,
+            "b",
+            TokType::Semicolon,
+            "c",
+            TokType::Semicolon,
+            TokType::LBracket,
+            TokType::RBracket,
+            TokType::Semicolon
+        ]
+        .to_vec()
+        .into_iter()
+        .peekable();
+        let results: Vec<_> = Parser::new(iter).collect();
+        assert_eq!(
+            results,
+            vec![
+                Err(ParseError {
+                    ty: ParseErrorType::Expected(&[TokType::Semicolon]),
+                    tok: Some(Token::new(TokType::Comma, 0)),
+                }),
+                Ok(Statement::Entry(Entry {
+                    left: Spec::from("c"),
+                    excludes: Vec::new(),
+                    right: None,
+                    tree: false,
+                    tags: Vec::new(),
+                    attributes: Attributes::default(),
+                })),
+                Err(ParseError {
+                    ty: ParseErrorType::Custom("Variant expression must have at least one option"),
+                    tok: Some(Token::new(TokType::RBracket, 0)),
+                }),
+            ]
+        );
     }
 
     #[test]
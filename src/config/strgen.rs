@@ -1,7 +1,13 @@
 use crate::config::ast::*;
 
+use lazy_static::lazy_static;
+
 use std::rc::Rc;
 
+lazy_static! {
+    static ref DEFAULT_CONTEXT: Context = Context::default();
+}
+
 // Restarts an iterator.
 trait Restartable
 where
@@ -38,6 +44,7 @@ impl<T> PairTree<T> {
     }
 }
 impl<'a> PairTree<&'a str> {
+    /// Concatenate the values of the tree from left to right.
     pub fn flatten_to_string(&self) -> String {
         fn get_total_length(tree: &PairTree<&str>) -> usize {
             match tree {
@@ -72,6 +79,7 @@ impl<T> From<(Box<PairTree<T>>, Box<PairTree<T>>)> for PairTree<T> {
     }
 }
 
+/// Yields every string that a spec expands to.
 pub struct SpecStrIter<'a> {
     iter: SpecIter<'a>,
 }
@@ -83,34 +91,42 @@ impl<'a> Iterator for SpecStrIter<'a> {
     }
 }
 
+// Without a context, `profile(...)` cases are never chosen and paths are relative to the home directory of the user.
 impl<'a> IntoIterator for &'a Spec {
     type Item = String;
     type IntoIter = SpecStrIter<'a>;
     fn into_iter(self) -> Self::IntoIter {
-        Self::IntoIter {
-            iter: SpecIter::new(self),
-        }
+        self.iter_with(&DEFAULT_CONTEXT)
     }
 }
 
 impl Spec {
-    fn raw_iter(&self) -> SpecIter {
-        SpecIter::new(self)
+    /// Iterate over every string that the spec expands to, choosing match cases within the given context.
+    pub fn iter_with<'a>(&'a self, context: &'a Context) -> SpecStrIter<'a> {
+        SpecStrIter {
+            iter: SpecIter::new(self, context),
+        }
+    }
+
+    fn raw_iter<'a>(&'a self, context: &'a Context) -> SpecIter<'a> {
+        SpecIter::new(self, context)
     }
 }
 
 #[derive(Debug)]
 struct SpecIter<'a> {
     spec: &'a Spec,
+    context: &'a Context,
     expr_iter: Option<Box<dyn Restartable<Item = PairTree<&'a str>> + 'a>>,
     curr_expr: Option<Rc<PairTree<&'a str>>>,
     spec_iter: Option<Box<SpecIter<'a>>>,
     should_emit_string: bool,
 }
 impl<'a> SpecIter<'a> {
-    pub fn new(spec: &'a Spec) -> Self {
+    pub fn new(spec: &'a Spec, context: &'a Context) -> Self {
         let mut ret = Self {
             spec,
+            context,
             curr_expr: None,
             expr_iter: None,
             spec_iter: None,
@@ -123,16 +139,21 @@ impl<'a> SpecIter<'a> {
     fn init_expr_iter(&mut self) {
         self.expr_iter = match &self.spec.spectype {
             SpecType::None => None,
-            SpecType::Match(expr, _) => Some(Box::new(expr.raw_iter())),
-            SpecType::Variant(expr, _) => Some(Box::new(expr.raw_iter())),
+            SpecType::Match(expr, _) => Some(Box::new(expr.raw_iter(self.context))),
+            SpecType::Variant(expr, _) => Some(Box::new(expr.raw_iter(self.context))),
+            SpecType::Placeholder(placeholder, _) => {
+                Some(Box::new(placeholder.raw_iter(self.context)))
+            }
         }
     }
     fn init_spec_iter(&mut self) {
         self.spec_iter = match &self.spec.spectype {
             SpecType::None => None,
-            SpecType::Match(_, next_spec) | SpecType::Variant(_, next_spec) => {
-                next_spec.as_ref().map(|spec| Box::new(spec.raw_iter()))
-            }
+            SpecType::Match(_, next_spec)
+            | SpecType::Variant(_, next_spec)
+            | SpecType::Placeholder(_, next_spec) => next_spec
+                .as_ref()
+                .map(|spec| Box::new(spec.raw_iter(self.context))),
         }
     }
     // Returns the next item, not considering `self.spec.string`.
@@ -200,6 +221,7 @@ impl<'a> Iterator for SpecIter<'a> {
 #[derive(Debug)]
 struct VariantIter<'a> {
     expr: &'a VariantExpr,
+    context: &'a Context,
     // The current variant's iterator.
     curr_iter: Option<Box<SpecIter<'a>>>,
     // The index after the current variant.
@@ -225,7 +247,7 @@ impl<'a> Iterator for VariantIter<'a> {
                     return None;
                 }
                 // Advance to the next variant's iterator.
-                self.curr_iter = Some(Box::new(self.expr.specs[self.index].raw_iter()));
+                self.curr_iter = Some(Box::new(self.expr.specs[self.index].raw_iter(self.context)));
                 self.index += 1;
             }
         }
@@ -233,9 +255,10 @@ impl<'a> Iterator for VariantIter<'a> {
 }
 
 impl VariantExpr {
-    fn raw_iter(&self) -> VariantIter {
+    fn raw_iter<'a>(&'a self, context: &'a Context) -> VariantIter<'a> {
         VariantIter {
             expr: self,
+            context,
             curr_iter: None,
             index: 0,
         }
@@ -264,13 +287,40 @@ impl Restartable for MatchIter<'_> {
     }
 }
 impl MatchExpr {
-    fn raw_iter(&self) -> MatchIter {
+    fn raw_iter<'a>(&'a self, context: &'a Context) -> MatchIter<'a> {
         MatchIter {
-            expr: &self,
-            spec_iter: match self.resolve() {
-                Some(spec) => Some(spec.raw_iter()),
-                None => None,
-            },
+            expr: self,
+            spec_iter: self.resolve(context).map(|spec| spec.raw_iter(context)),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct PlaceholderIter<'a> {
+    value: &'a str,
+    emitted: bool,
+}
+impl<'a> Iterator for PlaceholderIter<'a> {
+    type Item = PairTree<&'a str>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.emitted {
+            None
+        } else {
+            self.emitted = true;
+            Some(PairTree::value(self.value))
+        }
+    }
+}
+impl Restartable for PlaceholderIter<'_> {
+    fn restart(&mut self) {
+        self.emitted = false;
+    }
+}
+impl Placeholder {
+    fn raw_iter<'a>(&'a self, context: &'a Context) -> PlaceholderIter<'a> {
+        PlaceholderIter {
+            value: self.value(context),
+            emitted: false,
         }
     }
 }
@@ -347,6 +397,24 @@ mod tests {
         )
     }
 
+    #[test]
+    fn family_match() {
+        let not_family = if cfg!(windows) { "unix" } else { "windows" };
+        results_in(
+            Spec::from(SpecType::match_expr(
+                vec![
+                    (Expr::Family(vec![not_family.to_owned()]), Spec::from("a")),
+                    (
+                        Expr::Family(vec![std::env::consts::FAMILY.to_owned()]),
+                        Spec::from("b"),
+                    ),
+                ],
+                None,
+            )),
+            vec!["b"],
+        )
+    }
+
     #[test]
     fn hostname_match() {
         results_in(
@@ -387,6 +455,30 @@ mod tests {
         )
     }
 
+    #[test]
+    fn placeholders() {
+        results_in(
+            // Equivalent to `a/{os}/{host}[b, c]`.
+            Spec {
+                string: Some("a/".to_owned()),
+                spectype: SpecType::Placeholder(
+                    Placeholder::Os,
+                    Some(Box::new(Spec::from(SpecType::Placeholder(
+                        Placeholder::Host,
+                        Some(Box::new(Spec::from(SpecType::variant_expr(
+                            vec![Spec::from("/b"), Spec::from("/c")],
+                            None,
+                        )))),
+                    )))),
+                ),
+            },
+            vec![
+                format!("a/{}{}/b", *OS, *HOSTNAME).as_str(),
+                format!("a/{}{}/c", *OS, *HOSTNAME).as_str(),
+            ],
+        )
+    }
+
     #[test]
     fn not_os_match() {
         results_in(
@@ -415,6 +507,31 @@ mod tests {
         )
     }
 
+    #[test]
+    fn combinator_match() {
+        results_in(
+            Spec::from(SpecType::match_expr(
+                vec![
+                    (
+                        // Equivalent to `all(os(incorrect-os), default)`.
+                        Expr::All(vec![Expr::Os(vec![NOT_OS.clone()]), Expr::Any]),
+                        Spec::from("a"),
+                    ),
+                    (
+                        // Equivalent to `any(os(incorrect-os), not(host(incorrect-host)))`.
+                        Expr::AnyOf(vec![
+                            Expr::Os(vec![NOT_OS.clone()]),
+                            Expr::Not(Box::new(Expr::Host(vec![NOT_HOSTNAME.clone()]))),
+                        ]),
+                        Spec::from("b"),
+                    ),
+                ],
+                None,
+            )),
+            vec!["b"],
+        )
+    }
+
     #[test]
     fn nested_variant() {
         results_in(
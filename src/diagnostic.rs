@@ -0,0 +1,241 @@
+use std::{
+    fmt::{self, Display, Formatter},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::{
+    config::{ParseError, ParseErrorType},
+    error::AmbitError,
+};
+
+/// Format in which diagnostics are reported.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Format {
+    Text,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            _ => Err(format!("Unknown format `{}`", s)),
+        }
+    }
+}
+
+/// How serious a diagnostic is. Only errors make `ambit check` fail.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+/// A problem found within a configuration file.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// Identifies the type of problem, e.g. "expected".
+    pub kind: &'static str,
+    pub message: String,
+    /// The configuration file the problem occurred in.
+    pub file: Option<PathBuf>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+}
+
+impl Diagnostic {
+    /// `content` must be the content of `file`, the configuration the error occurred in.
+    pub fn from_parse_error(error: &ParseError, content: &str, file: &Path) -> Self {
+        let (line, column) = error.location(content);
+        Self {
+            severity: Severity::Error,
+            kind: match error.ty {
+                ParseErrorType::Expected(_) => "expected",
+                ParseErrorType::Custom(_) => "custom",
+                ParseErrorType::Lex(_) => "lex",
+            },
+            message: error.message(),
+            file: Some(file.to_owned()),
+            line: Some(line),
+            column: Some(column),
+        }
+    }
+
+    /// Turn an error that occurred while reading the configuration `file` into diagnostics.
+    /// Parse errors of included configurations are attributed to the included file.
+    pub fn from_error(error: AmbitError, file: &Path) -> Vec<Self> {
+        match error {
+            AmbitError::Parse { content, errors } => errors
+                .iter()
+                .map(|error| Diagnostic::from_parse_error(error, &content, file))
+                .collect(),
+            AmbitError::Include { path, error } => Diagnostic::from_error(*error, &path),
+            error => vec![Self {
+                severity: Severity::Error,
+                kind: "other",
+                message: error.to_string(),
+                file: Some(file.to_owned()),
+                line: None,
+                column: None,
+            }],
+        }
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // Diagnostics within a file are prefixed with where they occurred, like `file:line:column: message`.
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            write!(f, "{}:{}", file.display(), line)?;
+            if let Some(column) = self.column {
+                write!(f, ":{}", column)?;
+            }
+            return write!(f, ": {}", self.message);
+        }
+        f.write_str(&self.message)?;
+        match (self.line, self.column) {
+            (Some(line), Some(column)) => write!(f, " at line {}, column {}", line, column),
+            (Some(line), None) => write!(f, " at line {}", line),
+            _ => Ok(()),
+        }
+    }
+}
+
+// Escape and quote a string so that it is a valid JSON string.
+fn json_string(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len() + 2);
+    ret.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ret.push_str("\\\""),
+            '\\' => ret.push_str("\\\\"),
+            '\n' => ret.push_str("\\n"),
+            '\r' => ret.push_str("\\r"),
+            '\t' => ret.push_str("\\t"),
+            c if c.is_control() => ret.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ret.push(c),
+        }
+    }
+    ret.push('"');
+    ret
+}
+
+fn json_number(n: Option<usize>) -> String {
+    n.map(|n| n.to_string())
+        .unwrap_or_else(|| "null".to_owned())
+}
+
+/// Serialize diagnostics as a JSON array.
+pub fn to_json(diagnostics: &[Diagnostic]) -> String {
+    let objects: Vec<String> = diagnostics
+        .iter()
+        .map(|diagnostic| {
+            format!(
+                r#"{{"severity":{},"type":{},"message":{},"file":{},"line":{},"column":{}}}"#,
+                json_string(diagnostic.severity.as_str()),
+                json_string(diagnostic.kind),
+                json_string(&diagnostic.message),
+                diagnostic
+                    .file
+                    .as_ref()
+                    .map(|file| json_string(&file.display().to_string()))
+                    .unwrap_or_else(|| "null".to_owned()),
+                json_number(diagnostic.line),
+                json_number(diagnostic.column),
+            )
+        })
+        .collect();
+    format!("[{}]", objects.join(","))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display_diagnostic() {
+        let mut diagnostic = Diagnostic {
+            severity: Severity::Error,
+            kind: "other",
+            message: "Message".to_owned(),
+            file: None,
+            line: None,
+            column: None,
+        };
+        assert_eq!(diagnostic.to_string(), "Message");
+        diagnostic.line = Some(2);
+        assert_eq!(diagnostic.to_string(), "Message at line 2");
+        diagnostic.column = Some(3);
+        assert_eq!(diagnostic.to_string(), "Message at line 2, column 3");
+        diagnostic.file = Some(PathBuf::from("config.ambit"));
+        assert_eq!(diagnostic.to_string(), "config.ambit:2:3: Message");
+    }
+
+    #[test]
+    fn include_error_diagnostics() {
+        let content = "a.txt".to_owned();
+        let errors = crate::config::get_statements(content.chars().peekable())
+            .filter_map(Result::err)
+            .collect();
+        let error = AmbitError::Include {
+            path: PathBuf::from("included.ambit"),
+            error: Box::new(AmbitError::Parse { content, errors }),
+        };
+        let diagnostics = Diagnostic::from_error(error, Path::new("config.ambit"));
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].file, Some(PathBuf::from("included.ambit")));
+        assert_eq!(diagnostics[0].line, Some(1));
+    }
+
+    #[test]
+    fn escape_json_string() {
+        assert_eq!(json_string("a \"b\"\n\\c\u{1}"), r#""a \"b\"\n\\c\u0001""#);
+    }
+
+    #[test]
+    fn diagnostics_to_json() {
+        let diagnostics = vec![
+            Diagnostic {
+                severity: Severity::Error,
+                kind: "expected",
+                message: "Expected `;`, found end of file".to_owned(),
+                file: Some(PathBuf::from("config.ambit")),
+                line: Some(1),
+                column: Some(7),
+            },
+            Diagnostic {
+                severity: Severity::Error,
+                kind: "other",
+                message: "Message".to_owned(),
+                file: None,
+                line: None,
+                column: None,
+            },
+        ];
+        assert_eq!(
+            to_json(&diagnostics),
+            concat!(
+                r#"[{"severity":"error","type":"expected","message":"Expected `;`, found end of file","file":"config.ambit","line":1,"column":7},"#,
+                r#"{"severity":"error","type":"other","message":"Message","file":null,"line":null,"column":null}]"#
+            )
+        );
+    }
+
+    #[test]
+    fn empty_diagnostics_to_json() {
+        assert_eq!(to_json(&[]), "[]");
+    }
+}
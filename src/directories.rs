@@ -0,0 +1,348 @@
+use std::{
+    env,
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::ast::Context,
+    error::{AmbitError, AmbitResult},
+};
+
+/// Name of the configuration file.
+pub const CONFIG_NAME: &str = "config.ambit";
+/// Name of the machine-local configuration file, which is kept next to the configuration.
+pub const LOCAL_CONFIG_NAME: &str = "config.local.ambit";
+/// Name of the file in the repository that lists repository files to ignore.
+pub const IGNORE_NAME: &str = ".ambitignore";
+
+/// Whether an `AmbitPath` is a file or a directory.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum AmbitPathKind {
+    File,
+    Directory,
+}
+
+/// A path that ambit manages, together with whether it is a file or a directory.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AmbitPath {
+    pub path: PathBuf,
+    kind: AmbitPathKind,
+}
+
+impl AmbitPath {
+    pub fn new(path: PathBuf, kind: AmbitPathKind) -> Self {
+        Self { path, kind }
+    }
+
+    /// Return whether the path is meant to be a directory, whether or not it exists.
+    pub fn is_dir(&self) -> bool {
+        self.kind == AmbitPathKind::Directory
+    }
+
+    /// Return whether the path exists and is of its kind.
+    pub fn exists(&self) -> bool {
+        match self.kind {
+            AmbitPathKind::File => self.path.is_file(),
+            AmbitPathKind::Directory => self.path.is_dir(),
+        }
+    }
+
+    /// Create the directories that the path is in.
+    pub fn ensure_parent_dirs_exist(&self) -> AmbitResult<()> {
+        if let Some(parent) = &self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        Ok(())
+    }
+
+    /// Return the path as a string slice, failing if it is not valid UTF-8.
+    pub fn to_str(&self) -> AmbitResult<&str> {
+        // Converts path to string slice representation
+        let result = self.path.to_str();
+        match result {
+            Some(e) => Ok(e),
+            None => Err(AmbitError::Other(
+                "Could not yield path as &str slice".to_string(),
+            )),
+        }
+    }
+
+    /// Fetch the content of a path if it is `AmbitPathKind::File`.
+    pub fn as_string(&self) -> AmbitResult<String> {
+        match self.kind {
+            AmbitPathKind::File => {
+                let mut file = match File::open(&self.path) {
+                    Ok(file) => file,
+                    Err(e) => {
+                        return Err(AmbitError::File {
+                            path: self.path.clone(),
+                            error: e,
+                        })
+                    }
+                };
+                let mut content = String::new();
+                file.read_to_string(&mut content)?;
+                Ok(content)
+            }
+            AmbitPathKind::Directory => Err(AmbitError::Other(
+                "Getting content of a directory is not supported".to_owned(),
+            )),
+        }
+    }
+
+    /// Create an empty file or directory at the path.
+    pub fn create(&self) -> AmbitResult<()> {
+        match self.kind {
+            AmbitPathKind::File => {
+                File::create(&self.path)?;
+            }
+            AmbitPathKind::Directory => {
+                fs::create_dir_all(&self.path)?;
+            }
+        };
+        Ok(())
+    }
+
+    /// Remove the file, or the directory and everything in it.
+    pub fn remove(&self) -> AmbitResult<()> {
+        match self.kind {
+            AmbitPathKind::File => fs::remove_file(&self.path)?,
+            AmbitPathKind::Directory => fs::remove_dir_all(&self.path)?,
+        };
+        Ok(())
+    }
+}
+
+/// The paths of one ambit setup, which every command is run against.
+/// They are usually built from the environment with `AmbitPaths::from_env`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct AmbitPaths {
+    /// Directory that host files are relative to.
+    pub home: AmbitPath,
+    pub config: AmbitPath,
+    /// Machine-local configuration that is merged after the main configuration.
+    pub local_config: AmbitPath,
+    /// The dotfile repository.
+    pub repo: AmbitPath,
+    /// Directory within the repository that repository files of entries are relative to.
+    pub root: AmbitPath,
+    pub git: AmbitPath,
+    /// Identity that `.age` repository files are decrypted with.
+    pub age_identity: AmbitPath,
+    /// Record of the host files created by sync.
+    pub manifest: AmbitPath,
+    /// Record of the most recent operations, which can be undone.
+    pub journal: AmbitPath,
+    /// Actions saved by `ambit plan` for `ambit apply`.
+    pub plan: AmbitPath,
+    /// Default directory that `sync --backup` moves conflicting host files to.
+    pub backups: AmbitPath,
+    /// Location that `ambit relocate` moved the repository to, which is used unless the repository path is set.
+    pub repo_location: AmbitPath,
+    /// Selected profile, which `profile(...)` match cases are chosen by.
+    pub profile: Option<String>,
+    /// Answer that prompts are answered with instead of asking, if any.
+    pub assume: Option<bool>,
+}
+
+/// Builds `AmbitPaths`. Paths that are not set are taken from the environment, or their default location.
+#[derive(Default, Debug, Clone)]
+pub struct AmbitPathsBuilder {
+    home: Option<PathBuf>,
+    config: Option<PathBuf>,
+    repo: Option<PathBuf>,
+    profile: Option<String>,
+    assume: Option<bool>,
+}
+
+impl AmbitPathsBuilder {
+    /// Set the home directory, instead of `AMBIT_HOME_PATH` or the home directory of the user.
+    pub fn home(mut self, path: impl Into<PathBuf>) -> Self {
+        self.home = Some(path.into());
+        self
+    }
+
+    /// Set the configuration file, instead of `AMBIT_CONFIG_PATH` or `config.ambit` in the config directory.
+    pub fn config(mut self, path: impl Into<PathBuf>) -> Self {
+        self.config = Some(path.into());
+        self
+    }
+
+    /// Set the dotfile repository, instead of `AMBIT_REPO_PATH` or the location it was relocated to.
+    pub fn repo(mut self, path: impl Into<PathBuf>) -> Self {
+        self.repo = Some(path.into());
+        self
+    }
+
+    /// Select a profile, instead of `AMBIT_PROFILE`.
+    pub fn profile(mut self, profile: impl Into<String>) -> Self {
+        self.profile = Some(profile.into());
+        self
+    }
+
+    /// Answer every prompt with answer, instead of `AMBIT_ASSUME` or asking.
+    pub fn assume(mut self, answer: bool) -> Self {
+        self.assume = Some(answer);
+        self
+    }
+
+    /// Build the paths, failing if no home directory is set or can be found.
+    pub fn build(self) -> AmbitResult<AmbitPaths> {
+        // Source home path from environment variable. This is mainly for integration testing purposes.
+        let home_path = match self
+            .home
+            .or_else(|| AmbitPaths::get_path_from_env("AMBIT_HOME_PATH"))
+            .or_else(dirs::home_dir)
+        {
+            Some(home_path) => home_path,
+            None => return Err(AmbitError::Other("Could not get home directory".to_owned())),
+        };
+
+        // Base directories follow the XDG base directory specification, relative to the home path by default.
+        let configuration_path = AmbitPaths::get_xdg_dir("XDG_CONFIG_HOME", &home_path, ".config");
+        let data_path = AmbitPaths::get_xdg_dir("XDG_DATA_HOME", &home_path, ".local/share");
+        let state_path = AmbitPaths::get_xdg_dir("XDG_STATE_HOME", &home_path, ".local/state");
+
+        let config_path = self
+            .config
+            .or_else(|| AmbitPaths::get_path_from_env("AMBIT_CONFIG_PATH"))
+            .unwrap_or_else(|| configuration_path.join(CONFIG_NAME));
+
+        let local_config_path = config_path.with_file_name(LOCAL_CONFIG_NAME);
+
+        // The journal and plan are kept next to a custom manifest, as they were before being moved to the state directory.
+        let (manifest_path, state_path) = match AmbitPaths::get_path_from_env("AMBIT_MANIFEST_PATH")
+        {
+            Some(manifest_path) => {
+                let state_path = manifest_path.parent().unwrap_or(&home_path).to_path_buf();
+                (manifest_path, state_path)
+            }
+            None => (data_path.join("manifest"), state_path),
+        };
+
+        let repo_location_path = state_path.join("repo_location");
+
+        let repo_path = self
+            .repo
+            .or_else(|| AmbitPaths::get_path_from_env("AMBIT_REPO_PATH"))
+            .or_else(|| AmbitPaths::get_path_from_file(&repo_location_path))
+            .unwrap_or_else(|| configuration_path.join("repo"));
+
+        // The root is relative to the repository, e.g. `dots` if the dotfiles are kept in `dots/`.
+        let root_path = match AmbitPaths::get_path_from_env("AMBIT_REPO_ROOT") {
+            Some(root_path) => repo_path.join(root_path),
+            None => repo_path.clone(),
+        };
+
+        let git_path = repo_path.join(".git");
+
+        let age_identity_path = AmbitPaths::get_path_from_env("AMBIT_AGE_IDENTITY")
+            .unwrap_or_else(|| configuration_path.join("age.key"));
+
+        let journal_path = state_path.join("journal");
+
+        let plan_path = state_path.join("plan");
+
+        let backups_path = manifest_path.with_file_name("backups");
+
+        let profile = self.profile.or_else(|| env::var("AMBIT_PROFILE").ok());
+
+        let assume = self
+            .assume
+            .or_else(|| match env::var("AMBIT_ASSUME").as_deref() {
+                Ok("yes") => Some(true),
+                Ok("no") => Some(false),
+                _ => None,
+            });
+
+        Ok(AmbitPaths {
+            home: AmbitPath::new(home_path, AmbitPathKind::Directory),
+            config: AmbitPath::new(config_path, AmbitPathKind::File),
+            local_config: AmbitPath::new(local_config_path, AmbitPathKind::File),
+            repo: AmbitPath::new(repo_path, AmbitPathKind::Directory),
+            root: AmbitPath::new(root_path, AmbitPathKind::Directory),
+            git: AmbitPath::new(git_path, AmbitPathKind::Directory),
+            age_identity: AmbitPath::new(age_identity_path, AmbitPathKind::File),
+            manifest: AmbitPath::new(manifest_path, AmbitPathKind::File),
+            journal: AmbitPath::new(journal_path, AmbitPathKind::File),
+            plan: AmbitPath::new(plan_path, AmbitPathKind::File),
+            backups: AmbitPath::new(backups_path, AmbitPathKind::Directory),
+            repo_location: AmbitPath::new(repo_location_path, AmbitPathKind::File),
+            profile,
+            assume,
+        })
+    }
+}
+
+impl AmbitPaths {
+    /// Return a builder for paths that differ from the environment.
+    pub fn builder() -> AmbitPathsBuilder {
+        AmbitPathsBuilder::default()
+    }
+
+    /// Build the paths from the environment, e.g. `AMBIT_REPO_PATH` and the XDG base directories.
+    pub fn from_env() -> AmbitResult<Self> {
+        AmbitPaths::builder().build()
+    }
+
+    /// Return the context that the configuration is evaluated in, i.e. the selected profile and the home directory.
+    pub fn context(&self) -> Context {
+        Context::new(self.profile.clone(), self.home.path.clone())
+    }
+
+    // Return the ambit directory within the base directory set by key, or within default relative to home.
+    // Relative base directories are ignored, as required by the specification.
+    fn get_xdg_dir(key: &str, home_path: &Path, default: &str) -> PathBuf {
+        match AmbitPaths::get_path_from_env(key) {
+            Some(path) if path.is_absolute() => path.join("ambit"),
+            _ => home_path.join(default).join("ambit"),
+        }
+    }
+
+    // Read the path stored in the file at path, if there is one.
+    fn get_path_from_file(path: &Path) -> Option<PathBuf> {
+        let content = fs::read_to_string(path).ok()?;
+        let content = content.trim_end_matches('\n');
+        if content.is_empty() {
+            None
+        } else {
+            Some(PathBuf::from(content))
+        }
+    }
+
+    // Attempt to fetch path from env if set
+    fn get_path_from_env(key: &str) -> Option<PathBuf> {
+        match env::var_os(key) {
+            Some(path) => Some(PathBuf::from(path)),
+            None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_paths_of_another_setup() {
+        let paths = AmbitPaths::builder()
+            .home("/home/a")
+            .config("/etc/ambit/config.ambit")
+            .repo("/srv/dotfiles")
+            .profile("minimal")
+            .assume(false)
+            .build()
+            .unwrap();
+        assert_eq!(paths.home.path, Path::new("/home/a"));
+        assert_eq!(paths.config.path, Path::new("/etc/ambit/config.ambit"));
+        assert_eq!(
+            paths.local_config.path,
+            Path::new("/etc/ambit/config.local.ambit")
+        );
+        assert_eq!(paths.git.path, Path::new("/srv/dotfiles/.git"));
+        assert_eq!(paths.profile.as_deref(), Some("minimal"));
+        assert_eq!(paths.assume, Some(false));
+    }
+}
@@ -0,0 +1,148 @@
+use std::{
+    ffi::{OsStr, OsString},
+    io::{self, Write},
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use crate::error::{AmbitError, AmbitResult};
+
+// How a repository file is encrypted, which is decided by its extension.
+// Encrypted files are decrypted when they are copied to the host.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Encryption {
+    // `.age` files, decrypted with the identity in `AmbitPaths::age_identity`.
+    Age,
+    // `.gpg` files, decrypted with the keys of the user's gpg keyring.
+    Gpg,
+}
+
+impl Encryption {
+    // Return the encryption of path, or None if it is not encrypted.
+    pub fn from_path(path: &Path) -> Option<Self> {
+        match path.extension().and_then(OsStr::to_str) {
+            Some("age") => Some(Encryption::Age),
+            Some("gpg") => Some(Encryption::Gpg),
+            _ => None,
+        }
+    }
+
+    // Return the command that decrypts input, to output or otherwise to stdout.
+    // age files are decrypted with age_identity.
+    pub fn decrypt_command(
+        self,
+        input: &Path,
+        output: Option<&Path>,
+        age_identity: &Path,
+    ) -> Vec<OsString> {
+        match self {
+            Encryption::Age => {
+                let mut command: Vec<OsString> = vec![
+                    "age".into(),
+                    "--decrypt".into(),
+                    "--identity".into(),
+                    age_identity.into(),
+                ];
+                if let Some(output) = output {
+                    command.extend(vec!["--output".into(), output.into()]);
+                }
+                command.push(input.into());
+                command
+            }
+            Encryption::Gpg => {
+                let mut command: Vec<OsString> = vec![
+                    "gpg".into(),
+                    "--quiet".into(),
+                    "--batch".into(),
+                    "--yes".into(),
+                ];
+                if let Some(output) = output {
+                    command.extend(vec!["--output".into(), output.into()]);
+                }
+                command.extend(vec!["--decrypt".into(), input.into()]);
+                command
+            }
+        }
+    }
+
+    // Return the decrypted content of path.
+    pub fn decrypt(self, path: &Path, age_identity: &Path) -> AmbitResult<Vec<u8>> {
+        run(&self.decrypt_command(path, None, age_identity), None)
+    }
+
+    // Encrypt content to path.
+    pub fn encrypt(self, content: &[u8], path: &Path, age_identity: &Path) -> AmbitResult<()> {
+        let command: Vec<OsString> = match self {
+            // age encrypts to the recipient of the identity when it is given one.
+            Encryption::Age => vec![
+                "age".into(),
+                "--encrypt".into(),
+                "--identity".into(),
+                age_identity.into(),
+                "--output".into(),
+                path.into(),
+            ],
+            // Files are encrypted to the user's own (default) key.
+            Encryption::Gpg => vec![
+                "gpg".into(),
+                "--quiet".into(),
+                "--batch".into(),
+                "--yes".into(),
+                "--default-recipient-self".into(),
+                "--output".into(),
+                path.into(),
+                "--encrypt".into(),
+            ],
+        };
+        run(&command, Some(content)).map(|_| ())
+    }
+}
+
+// Run command with input as its stdin, returning its stdout.
+fn run(command: &[OsString], input: Option<&[u8]>) -> AmbitResult<Vec<u8>> {
+    let program = command[0].to_string_lossy();
+    let mut child = Command::new(&command[0])
+        .args(&command[1..])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => {
+                AmbitError::Other(format!("Could not find `{}`. Is it installed?", program))
+            }
+            _ => AmbitError::Io(e),
+        })?;
+    if let Some(input) = input {
+        // The child takes stdin, so it is closed once the input is written.
+        child.stdin.take().unwrap().write_all(input)?;
+    }
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(AmbitError::Other(format!(
+            "`{}` failed: {}",
+            program,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(output.stdout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encryption_from_extension() {
+        assert_eq!(
+            Encryption::from_path(Path::new(".ssh/id_ed25519.age")),
+            Some(Encryption::Age)
+        );
+        assert_eq!(
+            Encryption::from_path(Path::new("tokens.gpg")),
+            Some(Encryption::Gpg)
+        );
+        assert_eq!(Encryption::from_path(Path::new(".ssh/id_ed25519")), None);
+        assert_eq!(Encryption::from_path(Path::new(".age")), None);
+    }
+}
@@ -6,28 +6,39 @@ use std::{
     process,
 };
 
-use crate::config;
+use crate::{config, report};
 
+/// The result of every fallible operation of ambit.
 pub type AmbitResult<T> = Result<T, AmbitError>;
 
+/// An error of ambit, which is displayed to the user as is.
 #[derive(Debug)]
 pub enum AmbitError {
     Io(io::Error),
-    // TODO: As of now, a single ParseError is returned from config::get_entries
-    //       Future changes may result in a Vec<ParseError> being returned.
-    //       This should be taken care of.
-    Parse(config::ParseError),
+    /// All errors encountered while parsing a configuration.
+    /// The content of the configuration is kept to display the lines at which the errors occurred.
+    Parse {
+        content: String,
+        errors: Vec<config::ParseError>,
+    },
+    /// Error encountered while parsing a configuration included by another configuration.
+    Include {
+        path: PathBuf,
+        error: Box<AmbitError>,
+    },
     WalkDir(walkdir::Error),
     StripPrefix(path::StripPrefixError),
-    // File error is encountered on failed file open operation
-    // Provides additional path information
+    /// File error is encountered on failed file open operation
+    /// Provides additional path information
     File {
         path: PathBuf,
         error: io::Error,
     },
+    /// The paths of secrets are not shown, as with the output of sync.
     Sync {
         host_file_path: PathBuf,
         repo_file_path: PathBuf,
+        secret: bool,
         error: Box<AmbitError>,
     },
     Other(String),
@@ -38,6 +49,7 @@ impl Error for AmbitError {
         match self {
             AmbitError::File { error, .. } => Some(error),
             AmbitError::Sync { error, .. } => Some(error),
+            AmbitError::Include { error, .. } => Some(error),
             _ => None,
         }
     }
@@ -47,7 +59,17 @@ impl Display for AmbitError {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
         match self {
             AmbitError::Io(ref e) => e.fmt(f),
-            AmbitError::Parse(ref e) => e.fmt(f),
+            AmbitError::Parse { content, errors } => f.write_str(
+                &errors
+                    .iter()
+                    .map(|error| error.render(content))
+                    .collect::<Vec<_>>()
+                    .join("\n\n"),
+            ),
+            AmbitError::Include { path, .. } => f.write_fmt(format_args!(
+                "Failed to parse included configuration `{}`",
+                path.display()
+            )),
             AmbitError::WalkDir(ref e) => e.fmt(f),
             AmbitError::StripPrefix(ref e) => e.fmt(f),
             AmbitError::File { path, .. } => {
@@ -56,11 +78,11 @@ impl Display for AmbitError {
             AmbitError::Sync {
                 repo_file_path,
                 host_file_path,
+                secret,
                 ..
             } => f.write_fmt(format_args!(
-                "Failed to symlink `{}` -> `{}`",
-                host_file_path.display(),
-                repo_file_path.display()
+                "Failed to sync {}",
+                report::display_paths(host_file_path, repo_file_path, *secret)
             )),
             AmbitError::Other(ref s) => f.write_str(s.as_str()),
         }?;
@@ -102,7 +124,7 @@ impl<'a> From<&'a str> for AmbitError {
     }
 }
 
-// Report given error
+/// Report given error
 pub fn default_error_handler(error: &AmbitError) {
     eprintln!("ERROR: {}", error);
     process::exit(1);
@@ -138,11 +160,60 @@ Caused by:
         let err = AmbitError::Sync {
             host_file_path: PathBuf::from("host"),
             repo_file_path: PathBuf::from("repo"),
+            secret: false,
+            error: Box::new(AmbitError::Other("Error message".to_owned())),
+        };
+        assert_eq!(
+            format!("{}", err),
+            r#"Failed to sync host -> repo
+
+Caused by:
+  Error message"#
+        );
+        let err = AmbitError::Sync {
+            host_file_path: PathBuf::from("host"),
+            repo_file_path: PathBuf::from("repo"),
+            secret: true,
+            error: Box::new(AmbitError::Other("Error message".to_owned())),
+        };
+        assert!(format!("{}", err).starts_with("Failed to sync secret file\n"));
+    }
+
+    #[test]
+    fn display_parse() {
+        let content = "[] => a;\na => b";
+        let errors = config::get_statements(content.chars().peekable())
+            .filter_map(Result::err)
+            .collect();
+        let err = AmbitError::Parse {
+            content: content.to_owned(),
+            errors,
+        };
+        assert_eq!(
+            format!("{}", err),
+            r#"Variant expression must have at least one option
+ --> line 1, column 2
+  |
+1 | [] => a;
+  |  ^
+
+Expected `;`, found end of file
+ --> line 2, column 7
+  |
+2 | a => b
+  |       ^"#
+        );
+    }
+
+    #[test]
+    fn display_include() {
+        let err = AmbitError::Include {
+            path: PathBuf::from("laptop.ambit"),
             error: Box::new(AmbitError::Other("Error message".to_owned())),
         };
         assert_eq!(
             format!("{}", err),
-            r#"Failed to symlink `host` -> `repo`
+            r#"Failed to parse included configuration `laptop.ambit`
 
 Caused by:
   Error message"#
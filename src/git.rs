@@ -0,0 +1,529 @@
+// Operations on the dotfile repository.
+// They are run through the git binary, or through libgit2 if the `git2` feature is enabled,
+// which allows ambit to be used where git is not installed.
+// Commands that pass their arguments on to git, e.g. `ambit git` and `ambit push`, always use the binary.
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+use crate::{
+    directories::AmbitPaths,
+    error::{AmbitError, AmbitResult},
+};
+
+pub use backend::{clone, has_uncommitted_changes, init, pull, stash, status, unstash};
+
+// The outcome of fast-forwarding the current branch to its upstream.
+pub enum Pull {
+    // The branch is at its upstream, with a summary to show if git did not print one itself.
+    Done(Option<String>),
+    // The branch has diverged from its upstream, so it could not be fast-forwarded.
+    Diverged,
+}
+
+// Return whether only part of the repository is checked out through sparse checkout.
+pub fn is_sparse(ambit_paths: &AmbitPaths) -> AmbitResult<bool> {
+    // `git config` fails if the option is not set.
+    let output = command(ambit_paths)?
+        .args(["config", "--bool", "core.sparseCheckout"])
+        .output()?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim() == "true")
+}
+
+// Only check out the files of the repository that match patterns, which are in the format of `.gitignore`.
+// Sparse checkout is not supported by libgit2, so this always uses the binary.
+pub fn set_sparse_patterns(ambit_paths: &AmbitPaths, patterns: &[String]) -> AmbitResult<()> {
+    let mut child = command(ambit_paths)?
+        .current_dir(&ambit_paths.repo.path)
+        .args(["sparse-checkout", "set", "--no-cone", "--stdin"])
+        .stdin(Stdio::piped())
+        .spawn()?;
+    // The child takes stdin, so it is closed once the patterns are written.
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(patterns.join("\n").as_bytes())?;
+    match child.wait()?.success() {
+        true => Ok(()),
+        false => Err(AmbitError::Other(
+            "Failed to set sparse checkout patterns".to_owned(),
+        )),
+    }
+}
+
+// Check out every file of the repository again.
+pub fn disable_sparse(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+    let status = command(ambit_paths)?
+        .current_dir(&ambit_paths.repo.path)
+        .args(["sparse-checkout", "disable"])
+        .status()?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(AmbitError::Other(
+            "Failed to disable sparse checkout".to_owned(),
+        )),
+    }
+}
+
+// Check out the submodules of the repository (and their submodules) at the commits that it records.
+pub fn update_submodules(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+    if !ambit_paths.repo.path.join(".gitmodules").exists() {
+        return Ok(());
+    }
+    backend::update_submodules(ambit_paths)
+}
+
+// Return a git command that is run from the dotfile repository.
+pub fn command(ambit_paths: &AmbitPaths) -> AmbitResult<Command> {
+    // The path to repository (git-dir) and the working tree (work-tree) is
+    // passed to ensure that git commands are run from the dotfile repository
+    let mut command = Command::new("git");
+    command.args(&[
+        ["--git-dir=", ambit_paths.git.to_str()?].concat(),
+        ["--work-tree=", ambit_paths.repo.to_str()?].concat(),
+    ]);
+    Ok(command)
+}
+
+// Run git with arguments from the dotfile repository, returning whether it succeeded.
+pub fn run(ambit_paths: &AmbitPaths, arguments: &[&str]) -> AmbitResult<bool> {
+    Ok(command(ambit_paths)?.args(arguments).status()?.success())
+}
+
+// Run git with arguments from the dotfile repository, returning its stdout.
+pub fn output(ambit_paths: &AmbitPaths, arguments: &[&str]) -> AmbitResult<String> {
+    let output = command(ambit_paths)?.args(arguments).output()?;
+    if !output.status.success() {
+        return Err(AmbitError::Other(format!(
+            "`git {}` failed: {}",
+            arguments.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+#[cfg(not(feature = "git2"))]
+mod backend {
+    use std::process::{Command, Stdio};
+
+    use super::{command, output, run, Pull};
+    use crate::{
+        directories::AmbitPaths,
+        error::{AmbitError, AmbitResult},
+    };
+
+    // Initialize an empty repository. git prints its own message, so none is returned.
+    pub fn init(ambit_paths: &AmbitPaths) -> AmbitResult<Option<String>> {
+        match run(ambit_paths, &["init"])? {
+            true => Ok(None),
+            false => Err(AmbitError::Other(
+                "Failed to initialize repository".to_owned(),
+            )),
+        }
+    }
+
+    // Clone the repository with the given `git clone` arguments.
+    pub fn clone(ambit_paths: &AmbitPaths, arguments: &[&str]) -> AmbitResult<()> {
+        // Clone will handle creating the repository directory
+        let status = Command::new("git")
+            .arg("clone")
+            .args(arguments)
+            .args(["--", ambit_paths.repo.to_str()?])
+            .status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(AmbitError::Other("Failed to clone repository".to_owned())),
+        }
+    }
+
+    // Return the status of the repository in the format of `git status --porcelain --branch`.
+    pub fn status(ambit_paths: &AmbitPaths) -> AmbitResult<String> {
+        output(ambit_paths, &["status", "--porcelain", "--branch"])
+    }
+
+    // Return whether tracked files have uncommitted changes.
+    pub fn has_uncommitted_changes(ambit_paths: &AmbitPaths) -> AmbitResult<bool> {
+        let status = output(
+            ambit_paths,
+            &["status", "--porcelain", "--untracked-files=no"],
+        )?;
+        Ok(!status.is_empty())
+    }
+
+    // Stash uncommitted changes to tracked files.
+    pub fn stash(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        match run(ambit_paths, &["stash", "push", "--quiet"])? {
+            true => Ok(()),
+            false => Err(AmbitError::Other(
+                "Failed to stash uncommitted changes".to_owned(),
+            )),
+        }
+    }
+
+    // Restore the most recently stashed changes.
+    pub fn unstash(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        match run(ambit_paths, &["stash", "pop", "--quiet"])? {
+            true => Ok(()),
+            false => Err(AmbitError::Other(
+                "Failed to restore stashed changes. They are kept in `ambit git stash list`."
+                    .to_owned(),
+            )),
+        }
+    }
+
+    // Fast-forward the current branch to its upstream.
+    // Only a branch that has diverged is reported as such, other failures (e.g. of the network) are errors.
+    pub fn pull(ambit_paths: &AmbitPaths) -> AmbitResult<Pull> {
+        output(ambit_paths, &["fetch", "--quiet"])?;
+        if is_ancestor(ambit_paths, "@{upstream}", "HEAD")? {
+            return Ok(Pull::Done(Some("Already up to date.".to_owned())));
+        }
+        if !is_ancestor(ambit_paths, "HEAD", "@{upstream}")? {
+            return Ok(Pull::Diverged);
+        }
+        // git prints a summary of the changes itself.
+        let merge = command(ambit_paths)?
+            .current_dir(&ambit_paths.repo.path)
+            .args(["merge", "--ff-only", "@{upstream}"])
+            .stdout(Stdio::inherit())
+            .output()?;
+        match merge.status.success() {
+            true => Ok(Pull::Done(None)),
+            false => Err(AmbitError::Other(format!(
+                "Failed to fast-forward dotfile repository: {}",
+                String::from_utf8_lossy(&merge.stderr).trim()
+            ))),
+        }
+    }
+
+    // Return whether commit `ancestor` is an ancestor of (or the same as) commit `descendant`.
+    fn is_ancestor(
+        ambit_paths: &AmbitPaths,
+        ancestor: &str,
+        descendant: &str,
+    ) -> AmbitResult<bool> {
+        let output = command(ambit_paths)?
+            .args(["merge-base", "--is-ancestor", ancestor, descendant])
+            .output()?;
+        // git exits with 1 if it is not an ancestor, and with another code if it failed.
+        match output.status.code() {
+            Some(0) => Ok(true),
+            Some(1) => Ok(false),
+            _ => Err(AmbitError::Other(format!(
+                "`git merge-base --is-ancestor {} {}` failed: {}",
+                ancestor,
+                descendant,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))),
+        }
+    }
+
+    pub fn update_submodules(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        // git submodule has to be run from within the working tree.
+        let status = command(ambit_paths)?
+            .current_dir(&ambit_paths.repo.path)
+            .args(["submodule", "update", "--init", "--recursive"])
+            .status()?;
+        match status.success() {
+            true => Ok(()),
+            false => Err(AmbitError::Other("Failed to update submodules".to_owned())),
+        }
+    }
+}
+
+#[cfg(feature = "git2")]
+mod backend {
+    use git2::{
+        build::{CheckoutBuilder, RepoBuilder},
+        BranchType, Cred, CredentialType, ErrorCode, FetchOptions, RemoteCallbacks, Repository,
+        Signature, Status, StatusOptions, SubmoduleUpdateOptions,
+    };
+
+    use std::fs;
+
+    use super::Pull;
+    use crate::{
+        directories::AmbitPaths,
+        error::{AmbitError, AmbitResult},
+    };
+
+    fn to_ambit_error(error: git2::Error) -> AmbitError {
+        AmbitError::Other(error.message().to_owned())
+    }
+
+    fn open(ambit_paths: &AmbitPaths) -> AmbitResult<Repository> {
+        Repository::open(&ambit_paths.repo.path).map_err(to_ambit_error)
+    }
+
+    // Remotes that require authentication are accessed with the keys of the ssh agent over ssh,
+    // and with the credential helpers configured for git otherwise.
+    // libgit2 asks again for as long as credentials are rejected, so each kind is only tried once.
+    fn get_fetch_options<'a>() -> FetchOptions<'a> {
+        let mut tried = CredentialType::empty();
+        let mut callbacks = RemoteCallbacks::new();
+        callbacks.credentials(move |url, username, allowed| {
+            let untried = allowed - tried;
+            if untried.contains(CredentialType::SSH_KEY) {
+                tried |= CredentialType::SSH_KEY;
+                Cred::ssh_key_from_agent(username.unwrap_or("git"))
+            } else if untried.contains(CredentialType::USER_PASS_PLAINTEXT) {
+                tried |= CredentialType::USER_PASS_PLAINTEXT;
+                Cred::credential_helper(&git2::Config::open_default()?, url, username)
+            } else if untried.contains(CredentialType::DEFAULT) {
+                tried |= CredentialType::DEFAULT;
+                Cred::default()
+            } else {
+                Err(git2::Error::from_str(&format!(
+                    "Failed to authenticate to `{}`",
+                    url
+                )))
+            }
+        });
+        let mut options = FetchOptions::new();
+        options.remote_callbacks(callbacks);
+        options
+    }
+
+    // Initialize an empty repository, returning the message that git would print.
+    pub fn init(ambit_paths: &AmbitPaths) -> AmbitResult<Option<String>> {
+        Repository::init(&ambit_paths.repo.path).map_err(to_ambit_error)?;
+        Ok(Some(format!(
+            "Initialized empty Git repository in {}",
+            ambit_paths.git.path.display()
+        )))
+    }
+
+    // Clone the repository from the origin, which is the only argument that is supported.
+    pub fn clone(ambit_paths: &AmbitPaths, arguments: &[&str]) -> AmbitResult<()> {
+        let origin = match arguments {
+            [origin] => origin,
+            _ => {
+                return Err(AmbitError::Other(
+                    "Only the origin can be given to clone with libgit2".to_owned(),
+                ))
+            }
+        };
+        RepoBuilder::new()
+            .fetch_options(get_fetch_options())
+            .clone(origin, &ambit_paths.repo.path)
+            .map_err(to_ambit_error)?;
+        Ok(())
+    }
+
+    // Return the porcelain status code of a file, e.g. ` M` for a modified file that is not staged.
+    fn get_status_code(status: Status) -> String {
+        if status.is_wt_new() && !status.intersects(Status::INDEX_NEW) {
+            return "??".to_owned();
+        }
+        let index = if status.is_index_new() {
+            'A'
+        } else if status.is_index_modified() {
+            'M'
+        } else if status.is_index_deleted() {
+            'D'
+        } else if status.is_index_renamed() {
+            'R'
+        } else if status.is_index_typechange() {
+            'T'
+        } else {
+            ' '
+        };
+        let work_tree = if status.is_wt_modified() {
+            'M'
+        } else if status.is_wt_deleted() {
+            'D'
+        } else if status.is_wt_renamed() {
+            'R'
+        } else if status.is_wt_typechange() {
+            'T'
+        } else {
+            ' '
+        };
+        format!("{}{}", index, work_tree)
+    }
+
+    // Return the header of `git status --porcelain --branch`, e.g. `main...origin/main [ahead 1]`.
+    fn get_branch_header(repo: &Repository) -> AmbitResult<String> {
+        let head = match repo.head() {
+            Ok(head) => head,
+            Err(e) if e.code() == ErrorCode::UnbornBranch => {
+                let head = repo.find_reference("HEAD").map_err(to_ambit_error)?;
+                let branch = head
+                    .symbolic_target()
+                    .unwrap_or("")
+                    .trim_start_matches("refs/heads/");
+                return Ok(format!("No commits yet on {}", branch));
+            }
+            Err(e) => return Err(to_ambit_error(e)),
+        };
+        let name = match (head.is_branch(), head.shorthand()) {
+            (true, Some(name)) => name.to_owned(),
+            _ => return Ok("HEAD (no branch)".to_owned()),
+        };
+        let branch = repo
+            .find_branch(&name, BranchType::Local)
+            .map_err(to_ambit_error)?;
+        let upstream = match branch.upstream() {
+            Ok(upstream) => upstream,
+            Err(_) => return Ok(name),
+        };
+        let upstream_name = upstream
+            .name()
+            .map_err(to_ambit_error)?
+            .unwrap_or("")
+            .to_owned();
+        let mut header = format!("{}...{}", name, upstream_name);
+        if let (Some(local), Some(remote)) = (head.target(), upstream.get().target()) {
+            let (ahead, behind) = repo
+                .graph_ahead_behind(local, remote)
+                .map_err(to_ambit_error)?;
+            let mut tracking = Vec::new();
+            if ahead > 0 {
+                tracking.push(format!("ahead {}", ahead));
+            }
+            if behind > 0 {
+                tracking.push(format!("behind {}", behind));
+            }
+            if !tracking.is_empty() {
+                header.push_str(&format!(" [{}]", tracking.join(", ")));
+            }
+        }
+        Ok(header)
+    }
+
+    // Return the status of the repository in the format of `git status --porcelain --branch`.
+    pub fn status(ambit_paths: &AmbitPaths) -> AmbitResult<String> {
+        let repo = open(ambit_paths)?;
+        let mut status = format!("## {}\n", get_branch_header(&repo)?);
+        let mut options = StatusOptions::new();
+        options
+            .include_untracked(true)
+            .include_ignored(false)
+            .recurse_untracked_dirs(false);
+        for entry in repo
+            .statuses(Some(&mut options))
+            .map_err(to_ambit_error)?
+            .iter()
+        {
+            status.push_str(&format!(
+                "{} {}\n",
+                get_status_code(entry.status()),
+                entry.path().unwrap_or("")
+            ));
+        }
+        Ok(status)
+    }
+
+    // Return whether tracked files have uncommitted changes.
+    pub fn has_uncommitted_changes(ambit_paths: &AmbitPaths) -> AmbitResult<bool> {
+        let mut options = StatusOptions::new();
+        options.include_untracked(false).include_ignored(false);
+        let repo = open(ambit_paths)?;
+        let statuses = repo.statuses(Some(&mut options)).map_err(to_ambit_error)?;
+        Ok(!statuses.is_empty())
+    }
+
+    // Stash uncommitted changes to tracked files.
+    pub fn stash(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        let mut repo = open(ambit_paths)?;
+        // Stashing requires an author, which may not be configured.
+        let signature = repo
+            .signature()
+            .or_else(|_| Signature::now("ambit", "ambit"))
+            .map_err(to_ambit_error)?;
+        repo.stash_save(&signature, "ambit pull", None)
+            .map_err(to_ambit_error)?;
+        Ok(())
+    }
+
+    // Restore the most recently stashed changes.
+    pub fn unstash(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        open(ambit_paths)?.stash_pop(0, None).map_err(|e| {
+            AmbitError::Other(format!(
+                "Failed to restore stashed changes: {}. They are kept in `ambit git stash list`.",
+                e.message()
+            ))
+        })
+    }
+
+    // Fast-forward the current branch to its upstream.
+    pub fn pull(ambit_paths: &AmbitPaths) -> AmbitResult<Pull> {
+        let repo = open(ambit_paths)?;
+        let head = repo.head().map_err(to_ambit_error)?;
+        let head_name = head.name().unwrap_or("").to_owned();
+        let remote_name = repo
+            .branch_upstream_remote(&head_name)
+            .map_err(|_| AmbitError::Other("Current branch has no upstream".to_owned()))?;
+        let mut remote = repo
+            .find_remote(remote_name.as_str().unwrap_or(""))
+            .map_err(to_ambit_error)?;
+        remote
+            .fetch::<&str>(&[], Some(&mut get_fetch_options()), None)
+            .map_err(to_ambit_error)?;
+        let upstream_name = repo
+            .branch_upstream_name(&head_name)
+            .map_err(to_ambit_error)?;
+        let upstream = repo
+            .find_reference(upstream_name.as_str().unwrap_or(""))
+            .and_then(|upstream| upstream.peel_to_commit())
+            .map_err(to_ambit_error)?;
+        let annotated = repo
+            .find_annotated_commit(upstream.id())
+            .map_err(to_ambit_error)?;
+        let (analysis, _) = repo.merge_analysis(&[&annotated]).map_err(to_ambit_error)?;
+        if analysis.is_up_to_date() {
+            return Ok(Pull::Done(Some("Already up to date.".to_owned())));
+        }
+        if !analysis.is_fast_forward() {
+            return Ok(Pull::Diverged);
+        }
+        // The files are checked out before moving the branch, so that they are compared to the old commit.
+        repo.checkout_tree(upstream.as_object(), Some(CheckoutBuilder::new().safe()))
+            .map_err(to_ambit_error)?;
+        repo.find_reference(&head_name)
+            .and_then(|mut reference| {
+                reference.set_target(upstream.id(), "ambit pull: fast-forward")
+            })
+            .map_err(to_ambit_error)?;
+        Ok(Pull::Done(Some(format!(
+            "Fast-forwarded to {}",
+            upstream.id()
+        ))))
+    }
+
+    pub fn update_submodules(ambit_paths: &AmbitPaths) -> AmbitResult<()> {
+        update_submodules_of(&open(ambit_paths)?)
+    }
+
+    // Submodules are updated recursively, as with `git submodule update --init --recursive`.
+    fn update_submodules_of(repo: &Repository) -> AmbitResult<()> {
+        for mut submodule in repo.submodules().map_err(to_ambit_error)? {
+            // libgit2 cannot update a submodule whose working directory was removed,
+            // so its link to the repository in `.git/modules` is restored first, as git does.
+            let module_dir = repo
+                .path()
+                .join("modules")
+                .join(submodule.name().unwrap_or(""));
+            let path = repo.workdir().unwrap_or(repo.path()).join(submodule.path());
+            if module_dir.exists() && !path.join(".git").exists() {
+                fs::create_dir_all(&path)?;
+                fs::write(
+                    path.join(".git"),
+                    format!("gitdir: {}\n", module_dir.display()),
+                )?;
+            }
+            let mut checkout = CheckoutBuilder::new();
+            checkout.safe().recreate_missing(true);
+            let mut options = SubmoduleUpdateOptions::new();
+            options.fetch(get_fetch_options()).checkout(checkout);
+            submodule
+                .update(true, Some(&mut options))
+                .map_err(to_ambit_error)?;
+            update_submodules_of(&submodule.open().map_err(to_ambit_error)?)?;
+        }
+        Ok(())
+    }
+}
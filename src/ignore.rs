@@ -0,0 +1,125 @@
+use std::{fs, io, path::Path};
+
+use globset::{GlobBuilder, GlobMatcher};
+
+use crate::error::{AmbitError, AmbitResult};
+
+// A pattern from an ignore file.
+struct IgnorePattern {
+    glob: GlobMatcher,
+    // Patterns starting with `!` include paths that were ignored by earlier patterns.
+    negated: bool,
+    // Patterns ending with `/` only match directories.
+    dir_only: bool,
+    // Patterns containing a `/` match paths relative to the repository instead of names.
+    anchored: bool,
+}
+
+// Patterns of repository paths to ignore, read from a file with .gitignore syntax.
+#[derive(Default)]
+pub struct Ignore {
+    patterns: Vec<IgnorePattern>,
+}
+
+impl Ignore {
+    // Read the ignore file at path. A missing file ignores nothing.
+    pub fn load(path: &Path) -> AmbitResult<Self> {
+        match fs::read_to_string(path) {
+            Ok(content) => Self::parse(&content),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn parse(content: &str) -> AmbitResult<Self> {
+        let mut patterns = Vec::new();
+        for line in content.lines() {
+            let mut line = line.trim_end();
+            // Blank lines and comments do not match anything.
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let negated = line.starts_with('!');
+            if negated {
+                line = &line[1..];
+            }
+            let dir_only = line.ends_with('/');
+            line = line.trim_end_matches('/');
+            let anchored = line.contains('/');
+            line = line.trim_start_matches('/');
+            let glob = GlobBuilder::new(line)
+                .literal_separator(true)
+                .backslash_escape(true)
+                .build()
+                .map_err(|e| {
+                    AmbitError::Other(format!("Invalid ignore pattern `{}`: {}", line, e.kind()))
+                })?;
+            patterns.push(IgnorePattern {
+                glob: glob.compile_matcher(),
+                negated,
+                dir_only,
+                anchored,
+            });
+        }
+        Ok(Self { patterns })
+    }
+
+    // Return if path, relative to the repository, is ignored.
+    // Paths within an ignored directory are ignored as well.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        if self.patterns.is_empty() {
+            return false;
+        }
+        let components: Vec<_> = path.components().collect();
+        for end in 1..=components.len() {
+            let ancestor: &Path = &components[..end].iter().collect::<std::path::PathBuf>();
+            let ancestor_is_dir = end < components.len() || is_dir;
+            // The last pattern that matches decides whether the path is ignored.
+            let mut ignored = false;
+            for pattern in &self.patterns {
+                if pattern.dir_only && !ancestor_is_dir {
+                    continue;
+                }
+                let is_match = if pattern.anchored {
+                    pattern.glob.is_match(ancestor)
+                } else {
+                    pattern.glob.is_match(components[end - 1].as_os_str())
+                };
+                if is_match {
+                    ignored = !pattern.negated;
+                }
+            }
+            // Like git, paths cannot be included again once a parent directory is ignored.
+            if ignored {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignore_names_and_paths() {
+        let ignore =
+            Ignore::parse("# Comment\n\n*.swp\n/build/\nnotes/*.txt\n!notes/keep.txt\n").unwrap();
+        assert!(ignore.is_ignored(Path::new("nvim/.init.vim.swp"), false));
+        assert!(ignore.is_ignored(Path::new("build/config.ambit"), false));
+        assert!(ignore.is_ignored(Path::new("build"), true));
+        assert!(!ignore.is_ignored(Path::new("build"), false));
+        assert!(!ignore.is_ignored(Path::new("nvim/build/init.vim"), false));
+        assert!(ignore.is_ignored(Path::new("notes/todo.txt"), false));
+        assert!(!ignore.is_ignored(Path::new("notes/keep.txt"), false));
+        assert!(!ignore.is_ignored(Path::new("nvim/init.vim"), false));
+        assert!(!ignore.is_ignored(Path::new("# Comment"), false));
+    }
+
+    #[test]
+    fn missing_ignore_file() {
+        let ignore = Ignore::load(Path::new("does/not/exist/.ambitignore")).unwrap();
+        assert!(!ignore.is_ignored(Path::new("a"), false));
+    }
+}
@@ -0,0 +1,202 @@
+use std::path::{Path, PathBuf};
+
+use crate::{
+    config::ast::LinkMode,
+    error::{AmbitError, AmbitResult},
+    manifest::{format_mode, parse_mode},
+    state::{self, escape_path, split_fields, unescape_path},
+};
+
+// The number of operations that are kept, and so can be undone one after another.
+const MAX_OPERATIONS: usize = 20;
+
+// A change to the file system made by an operation, which can be reversed.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Change {
+    // A host directory that did not exist before.
+    CreatedDir(PathBuf),
+    // A host file that was symlinked to or copied from the repository file.
+    Linked {
+        host_file: PathBuf,
+        repo_file: PathBuf,
+        mode: LinkMode,
+    },
+    // A host file that was removed while it was symlinked to or copied from the repository file.
+    Unlinked {
+        host_file: PathBuf,
+        repo_file: PathBuf,
+        mode: LinkMode,
+    },
+    // A host file that was moved into the repository, from the host path to the repository path.
+    Moved(PathBuf, PathBuf),
+}
+
+// The changes made by a single run of a command, e.g. `sync`.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct Operation {
+    pub name: String,
+    pub changes: Vec<Change>,
+}
+
+// The most recent operations, so that they can be undone with `ambit undo`.
+// An operation starts with an `operation <NAME>` line, followed by one tab-separated line per change,
+// with the paths escaped as described in `state`.
+#[derive(Default, Debug)]
+pub struct Journal {
+    operations: Vec<Operation>,
+}
+
+impl Journal {
+    // Read the journal at path. A missing file records nothing.
+    pub fn load(path: &Path) -> AmbitResult<Self> {
+        match state::load(path)? {
+            Some(content) => Self::parse(&content),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn parse(content: &str) -> AmbitResult<Self> {
+        let mut operations: Vec<Operation> = Vec::new();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let invalid = || AmbitError::Other(format!("Invalid journal line `{}`", line));
+            if let Some(name) = line.strip_prefix("operation ") {
+                operations.push(Operation {
+                    name: name.to_owned(),
+                    changes: Vec::new(),
+                });
+                continue;
+            }
+            let path = |field: &str| unescape_path(field).ok_or_else(invalid);
+            let fields = split_fields(line);
+            let change = match fields.as_slice() {
+                ["dir", dir] => Change::CreatedDir(path(dir)?),
+                ["link", mode, host_file, repo_file] => Change::Linked {
+                    host_file: path(host_file)?,
+                    repo_file: path(repo_file)?,
+                    mode: parse_mode(mode).ok_or_else(invalid)?,
+                },
+                ["unlink", mode, host_file, repo_file] => Change::Unlinked {
+                    host_file: path(host_file)?,
+                    repo_file: path(repo_file)?,
+                    mode: parse_mode(mode).ok_or_else(invalid)?,
+                },
+                ["move", from, to] => Change::Moved(path(from)?, path(to)?),
+                _ => return Err(invalid()),
+            };
+            operations
+                .last_mut()
+                .ok_or_else(invalid)?
+                .changes
+                .push(change);
+        }
+        Ok(Self { operations })
+    }
+
+    fn format(&self) -> String {
+        let mut content = String::new();
+        for operation in &self.operations {
+            content.push_str(&format!("operation {}\n", operation.name));
+            for change in &operation.changes {
+                let line = match change {
+                    Change::CreatedDir(dir) => format!("dir\t{}", escape_path(dir)),
+                    Change::Linked {
+                        host_file,
+                        repo_file,
+                        mode,
+                    } => format!(
+                        "link\t{}\t{}\t{}",
+                        format_mode(*mode),
+                        escape_path(host_file),
+                        escape_path(repo_file)
+                    ),
+                    Change::Unlinked {
+                        host_file,
+                        repo_file,
+                        mode,
+                    } => format!(
+                        "unlink\t{}\t{}\t{}",
+                        format_mode(*mode),
+                        escape_path(host_file),
+                        escape_path(repo_file)
+                    ),
+                    Change::Moved(from, to) => {
+                        format!("move\t{}\t{}", escape_path(from), escape_path(to))
+                    }
+                };
+                content.push_str(&line);
+                content.push('\n');
+            }
+        }
+        content
+    }
+
+    // Write the journal to path, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> AmbitResult<()> {
+        state::save(path, &self.format())
+    }
+
+    // Record an operation, forgetting the oldest ones once there are too many.
+    // Operations that changed nothing are not recorded, so that undo reverses the last one that did.
+    pub fn push(&mut self, operation: Operation) {
+        if operation.changes.is_empty() {
+            return;
+        }
+        self.operations.push(operation);
+        if self.operations.len() > MAX_OPERATIONS {
+            self.operations.remove(0);
+        }
+    }
+
+    // Remove and return the most recent operation.
+    pub fn pop(&mut self) -> Option<Operation> {
+        self.operations.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let content = "operation sync\ndir\t/home/a/.config\nlink\tsymlink\t/home/a/.config/b\t/repo/b\nmove\t/home/a/c\t/repo/c\noperation clean\nunlink\tcopy\t/home/a/d\t/repo/d\n";
+        let mut journal = Journal::parse(content).unwrap();
+        assert_eq!(journal.format(), content);
+        assert_eq!(
+            journal.pop(),
+            Some(Operation {
+                name: "clean".to_owned(),
+                changes: vec![Change::Unlinked {
+                    host_file: PathBuf::from("/home/a/d"),
+                    repo_file: PathBuf::from("/repo/d"),
+                    mode: LinkMode::Copy,
+                }],
+            })
+        );
+        assert_eq!(journal.pop().unwrap().changes.len(), 3);
+        assert_eq!(journal.pop(), None);
+        assert!(Journal::parse("dir\t/a\n").is_err());
+        assert!(Journal::parse("operation sync\nlink\tmove\t/a\t/b\n").is_err());
+    }
+
+    #[test]
+    fn push_operations() {
+        let mut journal = Journal::default();
+        journal.push(Operation {
+            name: "clean".to_owned(),
+            changes: Vec::new(),
+        });
+        assert_eq!(journal.pop(), None);
+        for i in 0..=MAX_OPERATIONS {
+            journal.push(Operation {
+                name: "sync".to_owned(),
+                changes: vec![Change::CreatedDir(PathBuf::from(i.to_string()))],
+            });
+        }
+        assert_eq!(journal.operations.len(), MAX_OPERATIONS);
+        assert_eq!(
+            journal.operations[0].changes,
+            vec![Change::CreatedDir(PathBuf::from("1"))]
+        );
+    }
+}
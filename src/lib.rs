@@ -1,2 +1,29 @@
+//! ambit manages dotfiles by syncing the files of a dotfile repository to the home directory.
+//! Each command in `cmd` is run against the `AmbitPaths` of a setup and reports what it does to a `Reporter`.
+
+/// The commands of the `ambit` binary, which is a thin command line interface around them.
+pub mod cmd;
+/// The configuration language: its lexer, parser and syntax tree.
 pub mod config;
+/// Diagnostics reported by `ambit check`.
+pub mod diagnostic;
+/// The paths that ambit manages, which are built from environment variables or set through `AmbitPathsBuilder`.
+pub mod directories;
+/// The errors of ambit, which the binary prints before exiting.
 pub mod error;
+/// Events of commands, which are reported to a `Reporter` instead of printed directly.
+pub mod report;
+/// Templates, which are rendered with variables and the secrets of a `SecretProvider`.
+pub mod template;
+/// Helpers to run the `ambit` binary against temporary directories in integration tests.
+#[cfg(feature = "testing")]
+pub mod testing;
+
+mod encryption;
+mod git;
+mod ignore;
+mod journal;
+mod manifest;
+mod plan;
+mod prompt;
+mod state;
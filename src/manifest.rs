@@ -0,0 +1,394 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use crate::{
+    config::ast::LinkMode,
+    error::{AmbitError, AmbitResult},
+    state::{self, escape_path, split_fields, unescape_path},
+};
+
+// Return the mode written as `symlink` or `copy`.
+pub fn parse_mode(mode: &str) -> Option<LinkMode> {
+    match mode {
+        "symlink" => Some(LinkMode::Symlink),
+        "copy" => Some(LinkMode::Copy),
+        _ => None,
+    }
+}
+
+pub fn format_mode(mode: LinkMode) -> &'static str {
+    match mode {
+        LinkMode::Symlink => "symlink",
+        LinkMode::Copy => "copy",
+    }
+}
+
+// Return when the file at path was last modified, in nanoseconds since the unix epoch.
+// Symlinks are not followed, so a symlink is only modified when it is replaced.
+fn get_modified(path: &Path) -> Option<u128> {
+    let modified = fs::symlink_metadata(path).ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+// What a host file was synced from, so that `ambit sync --incremental` can skip it while nothing changed.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct SyncState {
+    // Hash of the entry that synced the file, along with the variables of the config.
+    pub entry_hash: u64,
+    pub repo_modified: u128,
+    pub host_modified: u128,
+}
+
+impl SyncState {
+    // Return the current state of host_file and repo_file, if both exist.
+    fn of(host_file: &Path, repo_file: &Path, entry_hash: u64) -> Option<Self> {
+        Some(Self {
+            entry_hash,
+            repo_modified: get_modified(repo_file)?,
+            host_modified: get_modified(host_file)?,
+        })
+    }
+}
+
+// A host file that ambit created, along with the repository file it was synced from.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct ManifestEntry {
+    pub host_file: PathBuf,
+    pub repo_file: PathBuf,
+    pub mode: LinkMode,
+    // Only recorded by `ambit sync`.
+    pub state: Option<SyncState>,
+    // The host file was copied because the symlink could not be created, so it stays a copy.
+    pub fallback: bool,
+}
+
+// The host files created by `ambit sync`, so they can still be removed after their entry leaves the config.
+// Each line of the file holds the mode, host file and repository file of an entry, separated by tabs,
+// with the paths escaped as described in `state`,
+// optionally followed by the entry hash and modification times of its sync state.
+// Copies that fell back from symlinks are written with the `fallback` mode.
+// Entries are keyed by their host file, so that large manifests are looked up without a scan.
+#[derive(Default, Debug)]
+pub struct Manifest {
+    entries: BTreeMap<PathBuf, ManifestEntry>,
+}
+
+impl Manifest {
+    // Read the manifest at path. A missing file records nothing.
+    pub fn load(path: &Path) -> AmbitResult<Self> {
+        match state::load(path)? {
+            Some(content) => Self::parse(&content),
+            None => Ok(Self::default()),
+        }
+    }
+
+    fn parse(content: &str) -> AmbitResult<Self> {
+        let mut entries = BTreeMap::new();
+        for line in content.lines().filter(|line| !line.is_empty()) {
+            let invalid = || AmbitError::Other(format!("Invalid manifest line `{}`", line));
+            let fields = split_fields(line);
+            let (mode, host_file, repo_file, state) = match fields.as_slice() {
+                [mode, host_file, repo_file] => (mode, host_file, repo_file, None),
+                [mode, host_file, repo_file, entry_hash, repo_modified, host_modified] => (
+                    mode,
+                    host_file,
+                    repo_file,
+                    Some(SyncState {
+                        entry_hash: entry_hash.parse().map_err(|_| invalid())?,
+                        repo_modified: repo_modified.parse().map_err(|_| invalid())?,
+                        host_modified: host_modified.parse().map_err(|_| invalid())?,
+                    }),
+                ),
+                _ => return Err(invalid()),
+            };
+            let (mode, fallback) = match *mode {
+                "fallback" => (LinkMode::Copy, true),
+                mode => (parse_mode(mode).ok_or_else(invalid)?, false),
+            };
+            let host_file = unescape_path(host_file).ok_or_else(invalid)?;
+            entries.insert(
+                host_file.clone(),
+                ManifestEntry {
+                    host_file,
+                    repo_file: unescape_path(repo_file).ok_or_else(invalid)?,
+                    mode,
+                    state,
+                    fallback,
+                },
+            );
+        }
+        Ok(Self { entries })
+    }
+
+    fn format(&self) -> String {
+        let mut content = String::new();
+        for entry in self.entries.values() {
+            let mode = match entry.fallback {
+                true => "fallback",
+                false => format_mode(entry.mode),
+            };
+            content.push_str(&format!(
+                "{}\t{}\t{}",
+                mode,
+                escape_path(&entry.host_file),
+                escape_path(&entry.repo_file)
+            ));
+            if let Some(state) = entry.state {
+                content.push_str(&format!(
+                    "\t{}\t{}\t{}",
+                    state.entry_hash, state.repo_modified, state.host_modified
+                ));
+            }
+            content.push('\n');
+        }
+        content
+    }
+
+    // Write the manifest to path, creating its directory if needed.
+    pub fn save(&self, path: &Path) -> AmbitResult<()> {
+        state::save(path, &self.format())
+    }
+
+    // Return the entries, ordered by host file.
+    pub fn entries(&self) -> impl Iterator<Item = &ManifestEntry> {
+        self.entries.values()
+    }
+
+    // Return what was recorded for host_file.
+    pub fn get(&self, host_file: &Path) -> Option<&ManifestEntry> {
+        self.entries.get(host_file)
+    }
+
+    // Record that host_file was synced from repo_file, replacing what was recorded for host_file before.
+    pub fn insert(&mut self, host_file: &Path, repo_file: &Path, mode: LinkMode) {
+        self.entries.insert(
+            host_file.to_path_buf(),
+            ManifestEntry {
+                host_file: host_file.to_path_buf(),
+                repo_file: repo_file.to_path_buf(),
+                mode,
+                state: None,
+                fallback: false,
+            },
+        );
+    }
+
+    // Record that the copy at host_file fell back from a symlink.
+    pub fn set_fallback(&mut self, host_file: &Path) {
+        if let Some(entry) = self.entries.get_mut(host_file) {
+            entry.fallback = true;
+        }
+    }
+
+    // Return the mode that host_file is synced from repo_file with, where mode is the one of its entry.
+    // Copies that fell back from symlinks stay copies.
+    pub fn get_synced_mode(&self, host_file: &Path, repo_file: &Path, mode: LinkMode) -> LinkMode {
+        let is_fallback = self
+            .get(host_file)
+            .is_some_and(|entry| entry.repo_file == repo_file && entry.fallback);
+        match is_fallback {
+            true => LinkMode::Copy,
+            false => mode,
+        }
+    }
+
+    // Record that host_file was synced by the entry with entry_hash, along with the current modification times.
+    pub fn insert_synced(
+        &mut self,
+        host_file: &Path,
+        repo_file: &Path,
+        mode: LinkMode,
+        entry_hash: u64,
+    ) {
+        self.insert(host_file, repo_file, mode);
+        if let Some(entry) = self.entries.get_mut(host_file) {
+            entry.state = SyncState::of(host_file, repo_file, entry_hash);
+        }
+    }
+
+    // Return whether host_file was synced from repo_file by the entry with entry_hash,
+    // and neither file was modified since.
+    pub fn is_unchanged(
+        &self,
+        host_file: &Path,
+        repo_file: &Path,
+        mode: LinkMode,
+        entry_hash: u64,
+    ) -> bool {
+        let state = match SyncState::of(host_file, repo_file, entry_hash) {
+            Some(state) => state,
+            None => return false,
+        };
+        self.get(host_file).is_some_and(|entry| {
+            entry.repo_file == repo_file && entry.mode == mode && entry.state == Some(state)
+        })
+    }
+
+    // Return whether host_file was copied from repo_file and not modified on the host since,
+    // so that it can be updated when the repository file changes.
+    pub fn is_unmodified_copy(&self, host_file: &Path, repo_file: &Path) -> bool {
+        let host_modified = get_modified(host_file);
+        host_modified.is_some()
+            && self.get(host_file).is_some_and(|entry| {
+                entry.repo_file == repo_file
+                    && entry.mode == LinkMode::Copy
+                    && entry.state.map(|state| state.host_modified) == host_modified
+            })
+    }
+
+    pub fn remove(&mut self, host_file: &Path) {
+        self.entries.remove(host_file);
+    }
+
+    // Point the entries synced from within from into to instead, e.g. after the repository was moved.
+    pub fn relocate(&mut self, from: &Path, to: &Path) {
+        for entry in self.entries.values_mut() {
+            if let Ok(relative_path) = entry.repo_file.strip_prefix(from) {
+                entry.repo_file = to.join(relative_path);
+                // The symlink is replaced, so it has to be checked again.
+                entry.state = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let content = "symlink\t/home/a/.bashrc\t/repo/.bashrc\ncopy\t/home/a/.gitconfig\t/repo/.gitconfig.tmpl\n";
+        let manifest = Manifest::parse(content).unwrap();
+        assert_eq!(
+            manifest.get(Path::new("/home/a/.gitconfig")).unwrap(),
+            &ManifestEntry {
+                host_file: PathBuf::from("/home/a/.gitconfig"),
+                repo_file: PathBuf::from("/repo/.gitconfig.tmpl"),
+                mode: LinkMode::Copy,
+                state: None,
+                fallback: false,
+            }
+        );
+        assert_eq!(manifest.format(), content);
+        let content = "symlink\t/home/a/.bashrc\t/repo/.bashrc\t42\t1000\t2000\n";
+        let manifest = Manifest::parse(content).unwrap();
+        assert_eq!(
+            manifest.get(Path::new("/home/a/.bashrc")).unwrap().state,
+            Some(SyncState {
+                entry_hash: 42,
+                repo_modified: 1000,
+                host_modified: 2000,
+            })
+        );
+        assert_eq!(manifest.format(), content);
+        let content = "fallback\t/home/a/.bashrc\t/repo/.bashrc\n";
+        let manifest = Manifest::parse(content).unwrap();
+        let entry = manifest.get(Path::new("/home/a/.bashrc")).unwrap();
+        assert_eq!(entry.mode, LinkMode::Copy);
+        assert!(entry.fallback);
+        assert_eq!(manifest.format(), content);
+        assert!(Manifest::parse("symlink\t/a\t/b\t42\n").is_err());
+        assert!(Manifest::parse("move\t/a\t/b\n").is_err());
+        assert!(Manifest::parse("symlink\t/a\n").is_err());
+        // Paths with tabs and newlines are escaped, so they do not break up the line.
+        let mut manifest = Manifest::default();
+        manifest.insert(
+            Path::new("/home/a/tab\tname"),
+            Path::new("/repo/new\nline"),
+            LinkMode::Symlink,
+        );
+        assert_eq!(
+            manifest.format(),
+            "symlink\t/home/a/tab%09name\t/repo/new%0Aline\n"
+        );
+        assert!(Manifest::parse(&manifest.format())
+            .unwrap()
+            .entries()
+            .eq(manifest.entries()));
+    }
+
+    #[test]
+    fn insert_remove_and_relocate() {
+        let mut manifest = Manifest::default();
+        manifest.insert(Path::new("/a"), Path::new("/repo/a"), LinkMode::Symlink);
+        manifest.insert(Path::new("/b"), Path::new("/repo/b"), LinkMode::Symlink);
+        manifest.insert(Path::new("/a"), Path::new("/repo/c"), LinkMode::Copy);
+        // Entries are written in the order of their host files.
+        assert_eq!(
+            manifest.format(),
+            "copy\t/a\t/repo/c\nsymlink\t/b\t/repo/b\n"
+        );
+        manifest.relocate(Path::new("/repo"), Path::new("/disk/repo"));
+        manifest.remove(Path::new("/b"));
+        assert_eq!(manifest.format(), "copy\t/a\t/disk/repo/c\n");
+    }
+
+    #[test]
+    fn fallback_stays_copy() {
+        let mut manifest = Manifest::default();
+        let (host_file, repo_file) = (Path::new("/a"), Path::new("/repo/a"));
+        manifest.insert(host_file, repo_file, LinkMode::Copy);
+        assert_eq!(
+            manifest.get_synced_mode(host_file, repo_file, LinkMode::Symlink),
+            LinkMode::Symlink
+        );
+        manifest.set_fallback(host_file);
+        assert_eq!(
+            manifest.get_synced_mode(host_file, repo_file, LinkMode::Symlink),
+            LinkMode::Copy
+        );
+        // The fallback only holds for the repository file that was copied.
+        assert_eq!(
+            manifest.get_synced_mode(host_file, Path::new("/repo/b"), LinkMode::Symlink),
+            LinkMode::Symlink
+        );
+        assert_eq!(manifest.format(), "fallback\t/a\t/repo/a\n");
+    }
+
+    #[test]
+    fn insert_synced_and_check_unchanged() {
+        let dir = tempfile::tempdir().unwrap();
+        let (host_file, repo_file) = (dir.path().join("host"), dir.path().join("repo"));
+        fs::write(&repo_file, "a").unwrap();
+        fs::write(&host_file, "a").unwrap();
+        let mut manifest = Manifest::default();
+        manifest.insert_synced(&host_file, &repo_file, LinkMode::Copy, 1);
+        assert!(manifest.is_unchanged(&host_file, &repo_file, LinkMode::Copy, 1));
+        // The entry changed.
+        assert!(!manifest.is_unchanged(&host_file, &repo_file, LinkMode::Copy, 2));
+        assert!(!manifest.is_unchanged(&host_file, &repo_file, LinkMode::Symlink, 1));
+        manifest.insert(&host_file, &repo_file, LinkMode::Copy);
+        assert!(!manifest.is_unchanged(&host_file, &repo_file, LinkMode::Copy, 1));
+        // Files that no longer exist are not unchanged.
+        manifest.insert_synced(&host_file, &repo_file, LinkMode::Copy, 1);
+        fs::remove_file(&host_file).unwrap();
+        assert!(!manifest.is_unchanged(&host_file, &repo_file, LinkMode::Copy, 1));
+    }
+
+    #[test]
+    fn unmodified_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let (host_file, repo_file) = (dir.path().join("host"), dir.path().join("repo"));
+        fs::write(&repo_file, "a").unwrap();
+        fs::write(&host_file, "a").unwrap();
+        let mut manifest = Manifest::default();
+        manifest.insert_synced(&host_file, &repo_file, LinkMode::Copy, 1);
+        fs::write(&repo_file, "b").unwrap();
+        assert!(manifest.is_unmodified_copy(&host_file, &repo_file));
+        // Symlinks are not copies.
+        manifest.insert_synced(&host_file, &repo_file, LinkMode::Symlink, 1);
+        assert!(!manifest.is_unmodified_copy(&host_file, &repo_file));
+        // Neither are host files with a different modification time.
+        manifest.insert_synced(&host_file, &repo_file, LinkMode::Copy, 1);
+        let modified = fs::metadata(&host_file).unwrap().modified().unwrap();
+        let file = fs::OpenOptions::new().write(true).open(&host_file).unwrap();
+        file.set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert!(!manifest.is_unmodified_copy(&host_file, &repo_file));
+    }
+}
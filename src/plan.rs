@@ -0,0 +1,175 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    config::ast::LinkMode,
+    error::{AmbitError, AmbitResult},
+    manifest::{format_mode, parse_mode},
+    state::{self, escape_path, split_fields, unescape_path},
+};
+
+// A pending change that `ambit apply` makes.
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub enum Action {
+    // Symlink or copy the repository file to the host file, which does not exist yet.
+    Link {
+        host_file: PathBuf,
+        repo_file: PathBuf,
+        mode: LinkMode,
+    },
+    // Move the host file into the repository, which does not have the file yet, and link it back.
+    Move {
+        host_file: PathBuf,
+        repo_file: PathBuf,
+        mode: LinkMode,
+    },
+    // Delete a host file that was synced by an entry that is no longer in the config.
+    Delete {
+        host_file: PathBuf,
+        repo_file: PathBuf,
+        mode: LinkMode,
+    },
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Link { .. } => "link",
+            Action::Move { .. } => "move",
+            Action::Delete { .. } => "delete",
+        }
+    }
+
+    pub fn paths(&self) -> (&Path, &Path, LinkMode) {
+        match self {
+            Action::Link {
+                host_file,
+                repo_file,
+                mode,
+            }
+            | Action::Move {
+                host_file,
+                repo_file,
+                mode,
+            }
+            | Action::Delete {
+                host_file,
+                repo_file,
+                mode,
+            } => (host_file, repo_file, *mode),
+        }
+    }
+}
+
+// The actions computed by `ambit plan`, to be reviewed before they are applied.
+// Each line of the file holds the name, mode, host file and repository file of an action, separated by tabs,
+// with the paths escaped as described in `state`.
+#[derive(Default, Debug, PartialEq, Eq)]
+pub struct Plan {
+    pub actions: Vec<Action>,
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> AmbitResult<Self> {
+        let content = state::load(path)?.ok_or_else(|| AmbitError::File {
+            path: path.to_path_buf(),
+            error: io::ErrorKind::NotFound.into(),
+        })?;
+        Self::parse(&content)
+    }
+
+    fn parse(content: &str) -> AmbitResult<Self> {
+        let mut actions = Vec::new();
+        // Comments allow the plan to explain itself to whoever reviews it.
+        for line in content
+            .lines()
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        {
+            let invalid = || AmbitError::Other(format!("Invalid plan line `{}`", line));
+            let fields = split_fields(line);
+            let (name, mode, host_file, repo_file) = match fields.as_slice() {
+                [name, mode, host_file, repo_file] => (
+                    *name,
+                    parse_mode(mode).ok_or_else(invalid)?,
+                    unescape_path(host_file).ok_or_else(invalid)?,
+                    unescape_path(repo_file).ok_or_else(invalid)?,
+                ),
+                _ => return Err(invalid()),
+            };
+            actions.push(match name {
+                "link" => Action::Link {
+                    host_file,
+                    repo_file,
+                    mode,
+                },
+                "move" => Action::Move {
+                    host_file,
+                    repo_file,
+                    mode,
+                },
+                "delete" => Action::Delete {
+                    host_file,
+                    repo_file,
+                    mode,
+                },
+                _ => return Err(invalid()),
+            });
+        }
+        Ok(Self { actions })
+    }
+
+    fn format(&self) -> String {
+        let mut content =
+            "# Pending actions of `ambit plan`, which `ambit apply` executes.\n".to_owned();
+        for action in &self.actions {
+            let (host_file, repo_file, mode) = action.paths();
+            content.push_str(&format!(
+                "{}\t{}\t{}\t{}\n",
+                action.name(),
+                format_mode(mode),
+                escape_path(host_file),
+                escape_path(repo_file)
+            ));
+        }
+        content
+    }
+
+    pub fn save(&self, path: &Path) -> AmbitResult<()> {
+        state::save(path, &self.format())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_format() {
+        let plan = Plan {
+            actions: vec![
+                Action::Link {
+                    host_file: PathBuf::from("/home/a/.bashrc"),
+                    repo_file: PathBuf::from("/repo/.bashrc"),
+                    mode: LinkMode::Symlink,
+                },
+                Action::Move {
+                    host_file: PathBuf::from("/home/a/.vimrc"),
+                    repo_file: PathBuf::from("/repo/.vimrc"),
+                    mode: LinkMode::Symlink,
+                },
+                Action::Delete {
+                    host_file: PathBuf::from("/home/a/.gitconfig"),
+                    repo_file: PathBuf::from("/repo/.gitconfig"),
+                    mode: LinkMode::Copy,
+                },
+            ],
+        };
+        let content = plan.format();
+        assert!(content.ends_with("delete\tcopy\t/home/a/.gitconfig\t/repo/.gitconfig\n"));
+        assert_eq!(Plan::parse(&content).unwrap(), plan);
+        assert!(Plan::parse("unlink\tsymlink\t/a\t/b\n").is_err());
+        assert!(Plan::parse("link\tsymlink\t/a\n").is_err());
+    }
+}
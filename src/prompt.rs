@@ -0,0 +1,119 @@
+use std::{
+    env,
+    fs::File,
+    io::{self, BufRead, BufReader, IsTerminal, Write},
+};
+
+use crate::error::{AmbitError, AmbitResult};
+
+// Ask user to confirm message, where pressing enter picks default.
+// The answer is not asked for if assume is set, e.g. by `--yes` and `--non-interactive`.
+pub fn confirm(assume: Option<bool>, message: &str, default: bool) -> AmbitResult<bool> {
+    if let Some(answer) = assume {
+        println!(
+            "{} {} {}",
+            message,
+            get_options(default),
+            if answer { "y" } else { "n" }
+        );
+        return Ok(answer);
+    }
+    let (mut input, is_terminal): (Box<dyn BufRead>, bool) = match open_terminal() {
+        Some(terminal) => (Box::new(BufReader::new(terminal)), true),
+        None => (Box::new(io::stdin().lock()), io::stdin().is_terminal()),
+    };
+    match ask(message, default, &mut input, &mut io::stdout())? {
+        Some(answer) => Ok(answer),
+        None if is_terminal => Ok(false),
+        // Without a terminal there may be nobody to answer, so running out of input is an error rather than a no.
+        None => Err(AmbitError::Other(format!(
+            "Could not ask `{}`, as stdin is not a terminal.\nPass `--yes` or `--non-interactive` to answer prompts.",
+            message
+        ))),
+    }
+}
+
+fn get_options(default: bool) -> &'static str {
+    match default {
+        true => "[Y/n]",
+        false => "[y/N]",
+    }
+}
+
+// Write message to output and read the answer from input.
+// Returns None if input ran out before an answer was given.
+fn ask(
+    message: &str,
+    default: bool,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> AmbitResult<Option<bool>> {
+    write!(output, "{} {} ", message, get_options(default))?;
+    output.flush()?;
+    let mut answer = String::new();
+    if input.read_line(&mut answer)? == 0 {
+        writeln!(output)?;
+        return Ok(None);
+    }
+    let answer = match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        "y" | "yes" => true,
+        _ => false,
+    };
+    Ok(Some(answer))
+}
+
+// Open the controlling terminal if stdin is not one, so that stdin can be used for other input.
+// Answers are read from stdin regardless if AMBIT_PROMPT_INPUT is `stdin`, e.g. to pipe them in tests.
+fn open_terminal() -> Option<File> {
+    if io::stdin().is_terminal()
+        || env::var_os("AMBIT_PROMPT_INPUT").is_some_and(|input| input == "stdin")
+    {
+        return None;
+    }
+    if cfg!(unix) {
+        File::open("/dev/tty").ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ask;
+    use std::io::Cursor;
+
+    fn ask_with_input(default: bool, input: &str) -> (Option<bool>, String) {
+        let mut output = Vec::new();
+        let answer = ask("Sync?", default, &mut Cursor::new(input), &mut output).unwrap();
+        (answer, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn ask_answers() {
+        assert_eq!(
+            ask_with_input(true, "y\n"),
+            (Some(true), "Sync? [Y/n] ".to_owned())
+        );
+        assert_eq!(ask_with_input(true, "N\n").0, Some(false));
+        assert_eq!(ask_with_input(false, "yes\n").0, Some(true));
+        assert_eq!(ask_with_input(true, "maybe\n").0, Some(false));
+    }
+
+    #[test]
+    fn ask_enter_picks_default() {
+        assert_eq!(ask_with_input(true, "\n").0, Some(true));
+        assert_eq!(
+            ask_with_input(false, "\n"),
+            (Some(false), "Sync? [y/N] ".to_owned())
+        );
+    }
+
+    #[test]
+    fn ask_without_input() {
+        assert_eq!(
+            ask_with_input(true, ""),
+            (None, "Sync? [Y/n] \n".to_owned())
+        );
+    }
+}
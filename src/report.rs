@@ -0,0 +1,237 @@
+use std::{
+    borrow::Cow,
+    fmt::{self, Display, Formatter},
+    path::Path,
+};
+
+// What the paths of secrets are shown as.
+const SECRET_FILE: &str = "secret file";
+// What the entries of secrets are shown as.
+const SECRET_ENTRY: &str = "secret entry";
+
+/// Return path as it is shown to the user. The paths of secrets are hidden.
+pub fn display_path(path: &Path, secret: bool) -> Cow<'_, str> {
+    if secret {
+        Cow::Borrowed(SECRET_FILE)
+    } else {
+        path.to_string_lossy()
+    }
+}
+
+/// Return the text of an entry as it is shown to the user. The entries of secrets are hidden, as they name their paths.
+pub fn display_entry(text: &str, secret: bool) -> &str {
+    if secret {
+        SECRET_ENTRY
+    } else {
+        text
+    }
+}
+
+/// Return how the file_nr-th file of the entry_nr-th entry is listed if it is a secret,
+/// which tells secrets apart without showing their paths.
+pub fn display_secret_file(entry_nr: usize, file_nr: usize) -> String {
+    format!("{} {} of entry {}", SECRET_FILE, file_nr, entry_nr)
+}
+
+/// Return `from -> to` as it is shown to the user, or only `secret file` for secrets.
+pub fn display_paths(from: &Path, to: &Path, secret: bool) -> String {
+    if secret {
+        SECRET_FILE.to_owned()
+    } else {
+        format!("{} -> {}", from.display(), to.display())
+    }
+}
+
+/// The command whose results are summarized.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Command {
+    Sync,
+    Clean,
+}
+
+/// A host file and the repository file it is synced from.
+/// The paths of secrets are not meant to be shown, e.g. to keep them out of shared logs.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub struct Link<'a> {
+    pub host_file: &'a Path,
+    pub repo_file: &'a Path,
+    pub secret: bool,
+}
+
+// Displays the link as `host -> repo`, or as `secret file` for secrets.
+impl Display for Link<'_> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(&display_paths(self.host_file, self.repo_file, self.secret))
+    }
+}
+
+/// Something that happened while running a command, e.g. a file that was synced.
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+pub enum Event<'a> {
+    /// The host file was symlinked to (or copied from) the repository file.
+    Linked(Link<'a>),
+    /// The symlink could not be created, so the host file was copied from the repository file instead.
+    Copied(Link<'a>),
+    /// The host file was not synced yet, but was left alone during a dry run.
+    Skipped(Link<'a>),
+    /// The host file was removed.
+    Removed(Link<'a>),
+    /// The host file was moved into the repository and linked back.
+    Moved(Link<'a>),
+    /// The host file exists, but is not synced from the repository file.
+    Conflict(Link<'a>),
+    /// The conflicting host file was moved to backup before syncing.
+    BackedUp { link: Link<'a>, backup: &'a Path },
+    /// A folded directory was replaced by symlinks per file.
+    Unfolded(&'a Path),
+    /// The counts of a command once it is done, along with the number of files it left alone.
+    Summary {
+        command: Command,
+        total: usize,
+        done: usize,
+    },
+    /// A line of output of a command, e.g. an entry listed by `ambit list`.
+    Message(&'a str),
+    /// Something that went wrong without failing the command by itself, e.g. a change that could not be undone.
+    Warning(&'a str),
+}
+
+/// Receives the events of commands, e.g. to print them.
+pub trait Reporter {
+    fn report(&mut self, event: Event);
+}
+
+/// Prints events to stdout, and warnings to stderr. Individual files are left out if quiet is set.
+#[derive(Default, Debug)]
+pub struct ConsoleReporter {
+    pub quiet: bool,
+}
+
+impl ConsoleReporter {
+    fn format(&self, event: Event) -> Option<String> {
+        let (action, link) = match event {
+            Event::Linked(link) => ("Synced", link),
+            Event::Copied(link) => ("Copied", link),
+            Event::Skipped(link) => ("Ignored", link),
+            Event::Removed(link) => ("Removed", link),
+            Event::Moved(link) => ("Moved", link),
+            // Conflicts are counted as ignored, or cause an error that is reported instead.
+            Event::Conflict(_) => return None,
+            Event::BackedUp { .. } if self.quiet => return None,
+            // Only the link is shown for secrets, which hides their paths.
+            Event::BackedUp { link, .. } if link.secret => {
+                return Some(format!("Backed up {}", link))
+            }
+            Event::BackedUp { link, backup } => {
+                return Some(format!(
+                    "Backed up {} to {}",
+                    link.host_file.display(),
+                    backup.display()
+                ))
+            }
+            Event::Unfolded(_) if self.quiet => return None,
+            Event::Unfolded(host_dir) => return Some(format!("Unfolded {}", host_dir.display())),
+            Event::Summary {
+                command: Command::Sync,
+                total,
+                done,
+            } => {
+                return Some(format!(
+                    "sync result ({} total): {} synced; {} ignored",
+                    total,
+                    done,
+                    total - done
+                ))
+            }
+            Event::Summary {
+                command: Command::Clean,
+                total,
+                done,
+            } => {
+                return Some(format!(
+                    "clean result ({} total): {} deleted: {} ignored",
+                    total,
+                    done,
+                    total - done
+                ))
+            }
+            Event::Message(message) => return Some(message.to_owned()),
+            Event::Warning(warning) => return Some(format!("WARNING: {}", warning)),
+        };
+        if self.quiet {
+            None
+        } else {
+            Some(format!("{} {}", action, link))
+        }
+    }
+}
+
+impl Reporter for ConsoleReporter {
+    fn report(&mut self, event: Event) {
+        match self.format(event) {
+            Some(line) if matches!(event, Event::Warning(_)) => eprintln!("{}", line),
+            Some(line) => println!("{}", line),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_events() {
+        let link = Link {
+            host_file: Path::new("/home/a/.bashrc"),
+            repo_file: Path::new("/repo/.bashrc"),
+            secret: false,
+        };
+        let summary = Event::Summary {
+            command: Command::Clean,
+            total: 3,
+            done: 2,
+        };
+        let reporter = ConsoleReporter::default();
+        assert_eq!(
+            reporter.format(Event::Linked(link)).unwrap(),
+            "Synced /home/a/.bashrc -> /repo/.bashrc"
+        );
+        assert_eq!(
+            reporter
+                .format(Event::Moved(Link {
+                    secret: true,
+                    ..link
+                }))
+                .unwrap(),
+            "Moved secret file"
+        );
+        assert_eq!(
+            reporter.format(Event::Copied(link)).unwrap(),
+            "Copied /home/a/.bashrc -> /repo/.bashrc"
+        );
+        assert_eq!(reporter.format(Event::Conflict(link)), None);
+        assert_eq!(
+            reporter
+                .format(Event::BackedUp {
+                    link,
+                    backup: Path::new("/backups/1/.bashrc"),
+                })
+                .unwrap(),
+            "Backed up /home/a/.bashrc to /backups/1/.bashrc"
+        );
+        assert_eq!(
+            reporter.format(summary).unwrap(),
+            "clean result (3 total): 2 deleted: 1 ignored"
+        );
+        assert_eq!(
+            reporter.format(Event::Warning("Could not undo")).unwrap(),
+            "WARNING: Could not undo"
+        );
+        let reporter = ConsoleReporter { quiet: true };
+        assert_eq!(reporter.format(Event::Removed(link)), None);
+        assert!(reporter.format(summary).is_some());
+        // Messages are the output of commands, so they are not left out.
+        assert_eq!(reporter.format(Event::Message("a.txt")).unwrap(), "a.txt");
+    }
+}
@@ -0,0 +1,139 @@
+// Reading and writing the state files of ambit, i.e. the manifest, journal and plan.
+// Each line of a state file holds tab-separated fields. Paths are written with `%` escapes,
+// so that tabs, newlines and names that are not valid UTF-8 are kept intact.
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+use crate::error::AmbitResult;
+
+// Read the state file at path, or return None if it does not exist.
+pub fn load(path: &Path) -> AmbitResult<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+// Write content to the state file at path, creating its directory if needed.
+// The content is written to a temporary file first and renamed over path,
+// so an interrupted write never leaves a partial file behind.
+pub fn save(path: &Path, content: &str) -> AmbitResult<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_path = path.with_file_name(format!(".{}.{}.tmp", name, process::id()));
+    let result = fs::write(&temp_path, content).and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    Ok(result?)
+}
+
+// Split a line of a state file into its fields.
+pub fn split_fields(line: &str) -> Vec<&str> {
+    line.split('\t').collect()
+}
+
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    use std::{ffi::OsString, os::unix::ffi::OsStringExt};
+    Some(PathBuf::from(OsString::from_vec(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: Vec<u8>) -> Option<PathBuf> {
+    String::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+// Return path as a field, escaping `%`, tabs, newlines and bytes that are not valid UTF-8 as `%XX`.
+pub fn escape_path(path: &Path) -> String {
+    let bytes = path_to_bytes(path);
+    let mut field = String::with_capacity(bytes.len());
+    for chunk in bytes.utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '%' | '\t' | '\n' | '\r' => field.push_str(&format!("%{:02X}", c as u8)),
+                c => field.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            field.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    field
+}
+
+// Return the path that field was escaped from, or None if it is not a valid escaped path.
+pub fn unescape_path(field: &str) -> Option<PathBuf> {
+    let mut bytes = Vec::with_capacity(field.len());
+    let mut rest = field.as_bytes();
+    while let Some((&byte, after)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(after.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &after[2..];
+        } else {
+            bytes.push(byte);
+            rest = after;
+        }
+    }
+    path_from_bytes(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_paths() {
+        for (path, field) in [
+            ("/home/a/.bashrc", "/home/a/.bashrc"),
+            ("/home/a/tab\tname", "/home/a/tab%09name"),
+            ("/home/a/new\nline", "/home/a/new%0Aline"),
+            ("/home/a/100%", "/home/a/100%25"),
+            ("/home/a/ünïcode", "/home/a/ünïcode"),
+        ] {
+            assert_eq!(escape_path(Path::new(path)), field);
+            assert_eq!(unescape_path(field), Some(PathBuf::from(path)));
+        }
+        assert_eq!(unescape_path("/a%2"), None);
+        assert_eq!(unescape_path("/a%zz"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn escape_non_utf8_paths() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+        let path = Path::new(OsStr::from_bytes(b"/home/a/\xff\xfe"));
+        assert_eq!(escape_path(path), "/home/a/%FF%FE");
+        assert_eq!(unescape_path("/home/a/%FF%FE").as_deref(), Some(path));
+    }
+
+    #[test]
+    fn save_replaces_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("state").join("manifest");
+        assert_eq!(load(&path).unwrap(), None);
+        save(&path, "a\n").unwrap();
+        save(&path, "b\n").unwrap();
+        assert_eq!(load(&path).unwrap().as_deref(), Some("b\n"));
+        // Only the state file itself is left behind.
+        assert_eq!(fs::read_dir(path.parent().unwrap()).unwrap().count(), 1);
+    }
+}
@@ -0,0 +1,267 @@
+use std::{cell::RefCell, collections::HashMap, env, ffi::OsStr, path::Path, process::Command};
+
+use crate::{
+    config::ast::{Context, Placeholder},
+    error::{AmbitError, AmbitResult},
+};
+
+/// Return if path is a template, which is rendered when it is copied to the host.
+pub fn is_template(path: &Path) -> bool {
+    path.extension() == Some(OsStr::new("tmpl"))
+}
+
+// Return the value of a variable that is available to every template.
+fn get_builtin_variable(name: &str) -> Option<String> {
+    match name {
+        "host" => Some(Placeholder::Host.value(&Context::default()).to_owned()),
+        "os" => Some(Placeholder::Os.value(&Context::default()).to_owned()),
+        "user" => env::var("USER").or_else(|_| env::var("USERNAME")).ok(),
+        _ => None,
+    }
+}
+
+/// A source of secrets that templates can look up, e.g. `{{ pass("email/work") }}`.
+/// This keeps secrets out of the repository, as they are only pulled in when rendering.
+pub trait SecretProvider {
+    /// The name of the function that templates call the provider with.
+    fn name(&self) -> &str;
+    /// Return the secret stored under key.
+    fn lookup(&self, key: &str) -> AmbitResult<String>;
+}
+
+/// Secrets from the standard unix password manager, `pass`, which ambit renders templates with.
+pub struct Pass;
+
+impl SecretProvider for Pass {
+    fn name(&self) -> &str {
+        "pass"
+    }
+
+    // The secret is the first line of the entry, as with `pass show --clip`.
+    fn lookup(&self, key: &str) -> AmbitResult<String> {
+        let output = Command::new("pass")
+            .args(["show", key])
+            .output()
+            .map_err(|e| AmbitError::Other(format!("Could not run `pass`: {}", e)))?;
+        if !output.status.success() {
+            return Err(AmbitError::Other(format!(
+                "`pass show {}` failed: {}",
+                key,
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        Ok(stdout.lines().next().unwrap_or("").to_owned())
+    }
+}
+
+// A provider that looks up every key only once, returning the same secret for later lookups.
+struct Memoized<P> {
+    provider: P,
+    secrets: RefCell<HashMap<String, String>>,
+}
+
+impl<P> Memoized<P> {
+    fn new(provider: P) -> Self {
+        Self {
+            provider,
+            secrets: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+impl<P: SecretProvider> SecretProvider for Memoized<P> {
+    fn name(&self) -> &str {
+        self.provider.name()
+    }
+
+    fn lookup(&self, key: &str) -> AmbitResult<String> {
+        if let Some(secret) = self.secrets.borrow().get(key) {
+            return Ok(secret.clone());
+        }
+        let secret = self.provider.lookup(key)?;
+        self.secrets
+            .borrow_mut()
+            .insert(key.to_owned(), secret.clone());
+        Ok(secret)
+    }
+}
+
+thread_local! {
+    // Templates are rendered both to check if they are synced and to write them,
+    // so secrets are kept for the rest of the run rather than running `pass` every time.
+    static PASS: Memoized<Pass> = Memoized::new(Pass);
+}
+
+// Split a call such as `pass("email/work")` into the name of the function and its argument.
+fn parse_call(expr: &str) -> Option<(&str, &str)> {
+    let open = expr.find('(')?;
+    let argument = expr[open + 1..].strip_suffix(')')?.trim();
+    let argument = argument
+        .strip_prefix('"')
+        .and_then(|argument| argument.strip_suffix('"'))
+        .unwrap_or(argument);
+    Some((expr[..open].trim(), argument))
+}
+
+// Render content with the secrets of `pass`, as templates are rendered when syncing.
+pub(crate) fn render_with_pass(
+    content: &str,
+    vars: &HashMap<String, String>,
+) -> AmbitResult<String> {
+    PASS.with(|pass| render(content, vars, pass))
+}
+
+/// Render content by replacing every `{{ name }}` with the value of the variable name,
+/// every `{{ function("key") }}` with a secret looked up from provider, whose name is the function,
+/// and every `{{ "text" }}` with text.
+/// Variables from `vars` take precedence over the built-in ones, `host`, `os` and `user`.
+pub fn render(
+    content: &str,
+    vars: &HashMap<String, String>,
+    provider: &dyn SecretProvider,
+) -> AmbitResult<String> {
+    let mut rendered = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| AmbitError::Other("Template has an unclosed `{{`".to_owned()))?;
+        let name = after[..end].trim();
+        // A quoted string is written as is, e.g. `{{ "{{" }}` for a literal `{{`.
+        if let Some(literal) = name
+            .strip_prefix('"')
+            .and_then(|name| name.strip_suffix('"'))
+        {
+            rendered.push_str(literal);
+            rest = &after[end + 2..];
+            continue;
+        }
+        if let Some((function, key)) = parse_call(name) {
+            if provider.name() != function {
+                return Err(AmbitError::Other(format!(
+                    "Template uses unknown function `{}`",
+                    function
+                )));
+            }
+            rendered.push_str(&provider.lookup(key)?);
+            rest = &after[end + 2..];
+            continue;
+        }
+        let value = vars
+            .get(name)
+            .cloned()
+            .or_else(|| get_builtin_variable(name));
+        let value = value.ok_or_else(|| {
+            AmbitError::Other(format!("Template uses unknown variable `{}`", name))
+        })?;
+        rendered.push_str(&value);
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    #[test]
+    fn template_paths() {
+        assert!(is_template(Path::new(".gitconfig.tmpl")));
+        assert!(!is_template(Path::new(".gitconfig")));
+        assert!(!is_template(Path::new(".tmpl")));
+    }
+
+    #[test]
+    fn render_variables() {
+        let vars = HashMap::new();
+        assert_eq!(
+            render("os = {{os}}\nhost = {{ host }}\n", &vars, &FakeProvider).unwrap(),
+            format!(
+                "os = {}\nhost = {}\n",
+                std::env::consts::OS,
+                Placeholder::Host.value(&Context::default())
+            )
+        );
+        assert_eq!(
+            render("no variables }}", &vars, &FakeProvider).unwrap(),
+            "no variables }}"
+        );
+        assert!(render("{{ unknown }}", &vars, &FakeProvider).is_err());
+        assert!(render("{{ os", &vars, &FakeProvider).is_err());
+    }
+
+    #[test]
+    fn render_escapes() {
+        let vars = HashMap::new();
+        assert_eq!(
+            render("{{ \"{{\" }} os }}, {{\"}\"}}", &vars, &FakeProvider).unwrap(),
+            "{{ os }}, }"
+        );
+    }
+
+    struct FakeProvider;
+
+    impl SecretProvider for FakeProvider {
+        fn name(&self) -> &str {
+            "fake"
+        }
+
+        fn lookup(&self, key: &str) -> AmbitResult<String> {
+            Ok(format!("secret of {}", key))
+        }
+    }
+
+    #[test]
+    fn render_secrets() {
+        let vars = HashMap::new();
+        assert_eq!(
+            render(
+                "password = {{ fake(\"email/work\") }}, {{fake(token)}}",
+                &vars,
+                &FakeProvider
+            )
+            .unwrap(),
+            "password = secret of email/work, secret of token"
+        );
+        assert!(render("{{ pass(\"a\") }}", &vars, &FakeProvider).is_err());
+    }
+
+    struct CountingProvider(Cell<usize>);
+
+    impl SecretProvider for CountingProvider {
+        fn name(&self) -> &str {
+            "count"
+        }
+
+        fn lookup(&self, key: &str) -> AmbitResult<String> {
+            self.0.set(self.0.get() + 1);
+            Ok(key.to_owned())
+        }
+    }
+
+    #[test]
+    fn memoized_secrets() {
+        let vars = HashMap::new();
+        let provider = Memoized::new(CountingProvider(Cell::new(0)));
+        let content = "{{ count(a) }} {{ count(a) }} {{ count(b) }}";
+        assert_eq!(render(content, &vars, &provider).unwrap(), "a a b");
+        render(content, &vars, &provider).unwrap();
+        assert_eq!(provider.provider.0.get(), 2);
+    }
+
+    #[test]
+    fn render_config_variables() {
+        let mut vars = HashMap::new();
+        vars.insert("email".to_owned(), "me@work.com".to_owned());
+        vars.insert("os".to_owned(), "custom".to_owned());
+        assert_eq!(
+            render("email = {{ email }}, os = {{ os }}", &vars, &FakeProvider).unwrap(),
+            "email = me@work.com, os = custom"
+        );
+    }
+}
@@ -0,0 +1,198 @@
+// Helpers to test against the behavior of the `ambit` binary, enabled with the `testing` feature.
+use std::{
+    ffi::OsStr,
+    fs::{self, File},
+    path::{Path, PathBuf},
+};
+
+use assert_cmd::{assert::Assert, Command};
+use tempfile::TempDir;
+
+/// Runs ambit within a temporary directory, which serves as the home directory.
+/// The configuration is kept at `config.ambit` and the dotfile repository at `repo` within it.
+#[derive(Debug)]
+pub struct AmbitTester {
+    config_path: PathBuf,
+    repo_path: PathBuf,
+    host_path: PathBuf,
+    executable: Command,
+    // The directory created by `default`, which is deleted once the tester is dropped.
+    _temp_dir: Option<TempDir>,
+}
+// Builder pattern implementation
+impl AmbitTester {
+    /// Allow temp_dir to be passed so it can be owned from outside of the struct.
+    /// The `ambit` binary of the current package is run. Panics if there is none,
+    /// in which case another binary can be run with `with_executable`.
+    pub fn from_temp_dir(temp_dir: &TempDir) -> Self {
+        let executable = Command::cargo_bin("ambit").unwrap_or_else(|e| {
+            panic!(
+                "Could not find the `ambit` binary of the current package: {}",
+                e
+            )
+        });
+        Self::with_executable(temp_dir, executable)
+    }
+
+    /// Run executable instead of looking up the `ambit` binary.
+    pub fn with_executable(temp_dir: &TempDir, mut executable: Command) -> Self {
+        let temp_dir_path = temp_dir.path();
+        let config_path = temp_dir_path.join("config.ambit");
+        let repo_path = temp_dir_path.join("repo");
+        let host_path = temp_dir_path.to_path_buf();
+        // Set environment variables.
+        // AMBIT_HOME_PATH is set as temp_dir. This is important as it will be the prefix path of potential synced files.
+        executable.env("AMBIT_HOME_PATH", host_path.as_os_str());
+        executable.env("AMBIT_CONFIG_PATH", config_path.as_os_str());
+        executable.env("AMBIT_REPO_PATH", repo_path.as_os_str());
+        // The state of ambit is kept within temp_dir, rather than the base directories of the user.
+        for key in ["XDG_CONFIG_HOME", "XDG_DATA_HOME", "XDG_STATE_HOME"] {
+            executable.env_remove(key);
+        }
+        // Answers to prompts are written to stdin, rather than typed into the terminal the tests run in.
+        executable.env("AMBIT_PROMPT_INPUT", "stdin");
+        Self {
+            config_path,
+            repo_path,
+            host_path,
+            executable,
+            _temp_dir: None,
+        }
+    }
+
+    /// The configuration file that ambit is run with.
+    pub fn config_path(&self) -> &Path {
+        &self.config_path
+    }
+
+    /// The dotfile repository that ambit is run with.
+    pub fn repo_path(&self) -> &Path {
+        &self.repo_path
+    }
+
+    /// The home directory that ambit is run with.
+    pub fn host_path(&self) -> &Path {
+        &self.host_path
+    }
+
+    /// Write content to configuration file.
+    pub fn with_config(self, content: &str) -> Self {
+        fs::write(&self.config_path, content).unwrap();
+        self
+    }
+
+    /// Write content to a given path.
+    pub fn with_file_with_content(self, path: &Path, content: &str) -> Self {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+        self
+    }
+
+    /// Create a custom file in repo_path directory. Mimics repo_file.
+    pub fn with_repo_file(self, name: &str) -> Self {
+        let s = self.with_repo_path();
+        File::create(s.repo_path.join(name)).unwrap();
+        s
+    }
+
+    /// Creates a custom file in home_path directory. Mimic host_file.
+    pub fn with_host_file(self, name: &str) -> Self {
+        File::create(self.host_path.join(name)).unwrap();
+        self
+    }
+
+    /// Create the dotfile repository, which ambit recognizes by its `.git` directory.
+    pub fn with_repo_path(self) -> Self {
+        fs::create_dir_all(self.repo_path.join(".git")).unwrap();
+        self
+    }
+
+    /// Set an environment variable for ambit.
+    pub fn env<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, key: K, value: V) -> Self {
+        self.executable.env(key, value);
+        self
+    }
+
+    /// Remove an environment variable for ambit.
+    pub fn env_remove<K: AsRef<OsStr>>(mut self, key: K) -> Self {
+        self.executable.env_remove(key);
+        self
+    }
+
+    /// Run ambit from dir, against which relative paths in arguments are resolved.
+    pub fn current_dir<P: AsRef<Path>>(mut self, dir: P) -> Self {
+        self.executable.current_dir(dir);
+        self
+    }
+
+    /// Add an argument to pass to ambit.
+    pub fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
+        self.executable.arg(arg);
+        self
+    }
+
+    /// Add arguments to pass to ambit.
+    pub fn args<I, S>(mut self, args: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<OsStr>,
+    {
+        self.executable.args(args);
+        self
+    }
+
+    /// Write buffer to the stdin of ambit, e.g. to answer prompts.
+    pub fn write_stdin<S>(mut self, buffer: S) -> Self
+    where
+        S: Into<Vec<u8>>,
+    {
+        self.executable.write_stdin(buffer);
+        self
+    }
+
+    /// Run ambit and return its result to assert on.
+    pub fn assert(mut self) -> Assert {
+        // Consumes self
+        self.executable.assert()
+    }
+}
+impl Default for AmbitTester {
+    // Default should be used when direct access to temporary directory is not needed.
+    // The tester owns the directory, so that it exists for as long as the tester does.
+    fn default() -> Self {
+        let temp_dir = TempDir::new().unwrap();
+        let tester = AmbitTester::from_temp_dir(&temp_dir);
+        Self {
+            _temp_dir: Some(temp_dir),
+            ..tester
+        }
+    }
+}
+
+/// Returns if a is symlinked to b (a -> b).
+pub fn is_symlinked<A: AsRef<Path>, B: AsRef<Path>>(a: A, b: B) -> bool {
+    fs::read_link(a)
+        .map(|link_path| link_path == b.as_ref())
+        .unwrap_or(false)
+}
+
+/// Panics unless a is symlinked to b (a -> b).
+pub fn assert_symlinked<A: AsRef<Path>, B: AsRef<Path>>(a: A, b: B) {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    assert!(
+        is_symlinked(a, b),
+        "{} is not symlinked to {}",
+        a.display(),
+        b.display()
+    );
+}
+
+/// Return what the command wrote to stdout, e.g. to check only part of it.
+pub fn stdout(assert: &Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stdout).into_owned()
+}
+
+/// Return what the command wrote to stderr.
+pub fn stderr(assert: &Assert) -> String {
+    String::from_utf8_lossy(&assert.get_output().stderr).into_owned()
+}
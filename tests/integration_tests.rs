@@ -1,114 +1,10 @@
-use assert_cmd::{assert::Assert, Command};
+use ambit::testing::{is_symlinked, stderr, stdout, AmbitTester};
 use std::{
-    ffi::OsStr,
     fs::{self, File},
     path::{Path, PathBuf},
 };
 use tempfile::TempDir;
 
-#[derive(Debug)]
-pub struct AmbitTester {
-    config_path: PathBuf,
-    repo_path: PathBuf,
-    host_path: PathBuf,
-    executable: Command,
-}
-// Builder pattern implementation
-impl AmbitTester {
-    // Allow temp_dir to be passed so it can be owned from outside of the struct.
-    fn from_temp_dir(temp_dir: &TempDir) -> Self {
-        let temp_dir_path = temp_dir.path();
-        let config_path = temp_dir_path.join("config.ambit");
-        let repo_path = temp_dir_path.join("repo");
-        let host_path = temp_dir_path.to_path_buf();
-        let mut executable = Command::cargo_bin("ambit").unwrap();
-        // Set environment variables.
-        // AMBIT_HOME_PATH is set as temp_dir. This is important as it will be the prefix path of potential synced files.
-        executable.env("AMBIT_HOME_PATH", host_path.as_os_str());
-        executable.env("AMBIT_CONFIG_PATH", config_path.as_os_str());
-        executable.env("AMBIT_REPO_PATH", repo_path.as_os_str());
-        Self {
-            config_path,
-            repo_path,
-            host_path,
-            executable,
-        }
-    }
-
-    // Write content to configuration file.
-    fn with_config(self, content: &str) -> Self {
-        File::create(&self.config_path).unwrap();
-        fs::write(&self.config_path, content).unwrap();
-        self
-    }
-
-    // Write content to a given path.
-    fn with_file_with_content(self, path: &Path, content: &str) -> Self {
-        fs::create_dir_all(path.parent().unwrap()).unwrap();
-        File::create(&path).unwrap();
-        fs::write(&path, content).unwrap();
-        self
-    }
-
-    // Create a custom file in repo_path directory. Mimics repo_file.
-    fn with_repo_file(self, name: &str) -> Self {
-        let s = self.with_repo_path();
-        File::create(s.repo_path.join(name)).unwrap();
-        s
-    }
-
-    // Creates a custom file in home_path directory. Mimic host_file.
-    fn with_host_file(self, name: &str) -> Self {
-        File::create(self.host_path.join(name)).unwrap();
-        self
-    }
-
-    fn with_repo_path(self) -> Self {
-        fs::create_dir_all(&self.repo_path.join(".git")).unwrap();
-        self
-    }
-
-    fn arg<S: AsRef<OsStr>>(mut self, arg: S) -> Self {
-        self.executable.arg(arg);
-        self
-    }
-
-    fn args<I, S>(mut self, args: I) -> Self
-    where
-        I: IntoIterator<Item = S>,
-        S: AsRef<OsStr>,
-    {
-        self.executable.args(args);
-        self
-    }
-
-    fn write_stdin<S>(mut self, buffer: S) -> Self
-    where
-        S: Into<Vec<u8>>,
-    {
-        self.executable.write_stdin(buffer);
-        self
-    }
-
-    fn assert(mut self) -> Assert {
-        // Consumes self
-        self.executable.assert()
-    }
-}
-impl Default for AmbitTester {
-    // Default should be used when direct access to temporary directory is not needed.
-    fn default() -> Self {
-        AmbitTester::from_temp_dir(&TempDir::new().unwrap())
-    }
-}
-
-// Returns if a is symlinked to b (a -> b).
-fn is_symlinked(a: PathBuf, b: PathBuf) -> bool {
-    fs::read_link(a)
-        .map(|link_path| link_path == b)
-        .unwrap_or(false)
-}
-
 #[test]
 fn init_repo_already_exists() {
     // Expect an error when attempting to initialize without force flag.
@@ -120,6 +16,20 @@ fn init_repo_already_exists() {
         .stderr("ERROR: Dotfile repository already exists.\nUse '-f' flag to overwrite.\n");
 }
 
+#[test]
+fn default_tester_keeps_temp_dir() {
+    let tester = AmbitTester::default();
+    assert!(tester.host_path().is_dir());
+    let host_path = tester.host_path().to_path_buf();
+    tester
+        .with_config("a.txt;\n")
+        .arg("check")
+        .assert()
+        .success();
+    // The directory is deleted along with the tester.
+    assert!(!host_path.exists());
+}
+
 #[test]
 fn init_force_overwrites() {
     AmbitTester::default()
@@ -152,6 +62,606 @@ fn clone_repo_already_exists() {
         .stderr("ERROR: Dotfile repository already exists.\nUse '-f' flag to overwrite.\n");
 }
 
+// Run git with args in dir, asserting that it succeeded.
+fn run_git(dir: &Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args([
+            "-c",
+            "user.name=ambit",
+            "-c",
+            "user.email=ambit@example.com",
+        ])
+        .args(args)
+        .current_dir(dir)
+        .status()
+        .unwrap();
+    assert!(status.success());
+}
+
+// Initialize a git repository at path that commits its current files, to clone from.
+fn init_origin(path: &Path) {
+    run_git(path, &["init", "--quiet"]);
+    run_git(path, &["add", "."]);
+    run_git(path, &["commit", "--quiet", "-m", "Add dotfiles"]);
+}
+
+#[test]
+fn pull_reports_divergence_and_failures() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let repo_path = temp_dir.path().join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n")
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("pull")
+        .assert()
+        .success();
+    fs::write(origin_path.join(".vimrc"), "").unwrap();
+    run_git(&origin_path, &["add", "."]);
+    run_git(&origin_path, &["commit", "--quiet", "-m", "Add vimrc"]);
+    fs::write(repo_path.join(".zshrc"), "").unwrap();
+    run_git(&repo_path, &["add", "."]);
+    run_git(&repo_path, &["commit", "--quiet", "-m", "Add zshrc"]);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("pull")
+        .assert()
+        .failure()
+        .stderr("ERROR: Could not fast-forward dotfile repository.\nMerge or rebase the changes with `ambit git pull`, then run `ambit sync`.\n");
+    // Failures other than divergence are reported with what went wrong.
+    run_git(&repo_path, &["branch", "--unset-upstream"]);
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("pull")
+        .assert()
+        .failure();
+    assert!(stderr(&assert).contains("no upstream"));
+}
+
+#[test]
+fn bootstrap_clones_and_syncs() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    fs::create_dir_all(origin_path.join("dotfiles")).unwrap();
+    fs::write(
+        origin_path.join("dotfiles").join("config.ambit"),
+        ".bashrc;\n",
+    )
+    .unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("bootstrap")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        temp_dir.path().join(".bashrc"),
+        temp_dir.path().join("repo").join(".bashrc")
+    ));
+}
+
+#[test]
+fn pull_stashes_and_syncs() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let repo_path = temp_dir.path().join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n.vimrc;\n")
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    fs::write(origin_path.join(".vimrc"), "").unwrap();
+    run_git(&origin_path, &["add", "."]);
+    run_git(&origin_path, &["commit", "--quiet", "-m", "Add vimrc"]);
+    fs::write(repo_path.join(".bashrc"), "uncommitted").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("push")
+        .assert()
+        .failure()
+        .stderr("ERROR: Dotfile repository has uncommitted changes.\nCommit them with `ambit git commit` before pushing.\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("pull")
+        .assert()
+        .failure()
+        .stderr("ERROR: Dotfile repository has uncommitted changes.\nCommit them, or use '--autostash' to stash them while pulling.\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["pull", "--autostash"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(repo_path.join(".bashrc")).unwrap(),
+        "uncommitted"
+    );
+    assert!(is_symlinked(
+        temp_dir.path().join(".vimrc"),
+        repo_path.join(".vimrc")
+    ));
+}
+
+#[test]
+fn commit_all_changes() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let repo_path = temp_dir.path().join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("commit")
+        .assert()
+        .success()
+        .stdout("Nothing to commit\n");
+    fs::write(repo_path.join(".bashrc"), "changed").unwrap();
+    fs::write(repo_path.join(".vimrc"), "").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env("GIT_AUTHOR_NAME", "ambit")
+        .env("GIT_AUTHOR_EMAIL", "ambit@example.com")
+        .env("GIT_COMMITTER_NAME", "ambit")
+        .env("GIT_COMMITTER_EMAIL", "ambit@example.com")
+        .arg("commit")
+        .assert()
+        .success()
+        .stdout("Update .bashrc, .vimrc\n");
+    let log = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%B"])
+        .current_dir(&repo_path)
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(log.stdout).unwrap(),
+        "Update .bashrc, .vimrc\n\n- Update .bashrc\n- Add .vimrc\n\n"
+    );
+}
+
+#[test]
+fn sync_move_commits_moved_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let origin_path = path.join("origin");
+    let repo_path = path.join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    fs::write(repo_path.join(".bashrc"), "uncommitted").unwrap();
+    fs::write(path.join(".vimrc"), "set number").unwrap();
+    let git_log = || {
+        let log = std::process::Command::new("git")
+            .args(["log", "-1", "--format=%B"])
+            .current_dir(&repo_path)
+            .output()
+            .unwrap();
+        String::from_utf8(log.stdout).unwrap()
+    };
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n.vimrc;\n")
+        .env("GIT_AUTHOR_NAME", "ambit")
+        .env("GIT_AUTHOR_EMAIL", "ambit@example.com")
+        .env("GIT_COMMITTER_NAME", "ambit")
+        .env("GIT_COMMITTER_EMAIL", "ambit@example.com")
+        .args(["sync", "--quiet", "--move", "--commit"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\nAdd .vimrc\n");
+    assert_eq!(git_log(), "Add .vimrc\n\n");
+    // Other changes to the repository are left uncommitted.
+    let status = std::process::Command::new("git")
+        .args(["status", "--porcelain"])
+        .current_dir(&repo_path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(status.stdout).unwrap(), " M .bashrc\n");
+    fs::write(path.join(".inputrc"), "set editing-mode vi").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n.vimrc;\n.inputrc;\n")
+        .env("GIT_AUTHOR_NAME", "ambit")
+        .env("GIT_AUTHOR_EMAIL", "ambit@example.com")
+        .env("GIT_COMMITTER_NAME", "ambit")
+        .env("GIT_COMMITTER_EMAIL", "ambit@example.com")
+        .args([
+            "sync",
+            "--quiet",
+            "--move",
+            "--commit",
+            "--message",
+            "Adopt",
+        ])
+        .assert()
+        .success()
+        .stdout("sync result (3 total): 1 synced; 2 ignored\nAdopt\n");
+    assert_eq!(git_log(), "Adopt\n\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--commit"])
+        .assert()
+        .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_move_auto_commit_from_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let path_var = with_fake_encryption(path);
+    let origin_path = path.join("origin");
+    let repo_path = path.join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    fs::write(path.join("token"), "secret").unwrap();
+    fs::write(path.join("key"), "secret").unwrap();
+    let sync = |config: &str| {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_config(config)
+            .env("PATH", &path_var)
+            .env("GIT_AUTHOR_NAME", "ambit")
+            .env("GIT_AUTHOR_EMAIL", "ambit@example.com")
+            .env("GIT_COMMITTER_NAME", "ambit")
+            .env("GIT_COMMITTER_EMAIL", "ambit@example.com")
+            .args(["sync", "--quiet", "--move"])
+            .assert()
+    };
+    // Secrets that would be committed in plain text are not moved into the repository.
+    let assert = sync("autocommit commit;\ntoken [secret=true];\n").failure();
+    assert!(stderr(&assert).contains("Secret files are only committed if they are encrypted."));
+    assert_eq!(fs::read_to_string(path.join("token")).unwrap(), "secret");
+    assert!(!repo_path.join("token").exists());
+    sync("autocommit commit;\nkey.age => key [secret=true];\n")
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\nAdd key.age\n");
+    let log = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%B"])
+        .current_dir(&repo_path)
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(log.stdout).unwrap(), "Add key.age\n\n");
+    // Without auto-commit, they are moved with a warning.
+    sync("token [secret=true];\n")
+        .success()
+        .stderr("WARNING: Moving a secret file into the repository without encrypting it\n");
+    assert!(repo_path.join("token").exists());
+}
+
+#[test]
+fn status_reports_git_state() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    fs::write(temp_dir.path().join("repo").join(".bashrc"), "changed").unwrap();
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .args(["status", "--git"])
+        .assert()
+        .success();
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert!(stdout.contains("git branch: "));
+    assert!(stdout.contains(" -> origin/"));
+    assert!(stdout.ends_with("git changes (1 total)\n    M .bashrc\n"));
+}
+
+#[test]
+fn hook_install() {
+    let temp_dir = TempDir::new().unwrap();
+    let hooks_path = temp_dir.path().join("repo").join(".git").join("hooks");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .args(["hook", "install"])
+        .assert()
+        .success();
+    for name in &["post-merge", "post-checkout"] {
+        let hook = fs::read_to_string(hooks_path.join(name)).unwrap();
+        assert!(hook.starts_with("#!/bin/sh\n"));
+        assert!(hook.ends_with("' sync --quiet\n"));
+        // The hook syncs the repository it was installed into, not the default one.
+        assert!(hook.contains(&format!(
+            "--repo '{}'",
+            temp_dir.path().join("repo").display()
+        )));
+    }
+    // Hooks written by ambit can be reinstalled, but others are kept unless forced.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["hook", "install"])
+        .assert()
+        .success();
+    fs::write(hooks_path.join("post-merge"), "#!/bin/sh\n").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["hook", "install"])
+        .assert()
+        .failure();
+    // A foreign hook that is not valid UTF-8 is kept as well, along with the hooks before it.
+    fs::remove_file(hooks_path.join("post-merge")).unwrap();
+    fs::write(hooks_path.join("post-checkout"), b"#!/bin/sh\n\xff\n").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["hook", "install"])
+        .assert()
+        .failure();
+    assert!(!hooks_path.join("post-merge").exists());
+    assert_eq!(
+        fs::read(hooks_path.join("post-checkout")).unwrap(),
+        b"#!/bin/sh\n\xff\n"
+    );
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["hook", "install", "-f"])
+        .assert()
+        .success();
+}
+
+#[test]
+fn clone_and_update_submodules() {
+    let temp_dir = TempDir::new().unwrap();
+    let plugin_path = temp_dir.path().join("plugin");
+    let origin_path = temp_dir.path().join("origin");
+    fs::create_dir_all(&plugin_path).unwrap();
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(plugin_path.join("plugin.vim"), "").unwrap();
+    init_origin(&plugin_path);
+    run_git(&origin_path, &["init", "--quiet"]);
+    run_git(
+        &origin_path,
+        &[
+            "-c",
+            "protocol.file.allow=always",
+            "submodule",
+            "--quiet",
+            "add",
+            plugin_path.to_str().unwrap(),
+            "vim/plugin",
+        ],
+    );
+    run_git(&origin_path, &["commit", "--quiet", "-m", "Add plugin"]);
+    // Newer versions of git only clone local submodules if allowed to.
+    let allow_file_protocol = |tester: AmbitTester| {
+        tester
+            .env("GIT_CONFIG_COUNT", "1")
+            .env("GIT_CONFIG_KEY_0", "protocol.file.allow")
+            .env("GIT_CONFIG_VALUE_0", "always")
+    };
+    allow_file_protocol(AmbitTester::from_temp_dir(&temp_dir))
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    let repo_plugin = temp_dir
+        .path()
+        .join("repo")
+        .join("vim")
+        .join("plugin")
+        .join("plugin.vim");
+    assert!(repo_plugin.exists());
+    fs::remove_dir_all(repo_plugin.parent().unwrap()).unwrap();
+    allow_file_protocol(AmbitTester::from_temp_dir(&temp_dir))
+        .with_config("vim/plugin/plugin.vim => .vim/plugin.vim;\n")
+        .arg("update")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        temp_dir.path().join(".vim").join("plugin.vim"),
+        repo_plugin
+    ));
+}
+
+#[test]
+fn push_to_every_remote() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let repo_path = temp_dir.path().join("repo");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(&origin_path)
+        .assert()
+        .success();
+    for mirror in &["mirror1", "mirror2"] {
+        let mirror_path = temp_dir.path().join(mirror);
+        run_git(
+            temp_dir.path(),
+            &["init", "--quiet", "--bare", mirror_path.to_str().unwrap()],
+        );
+        run_git(
+            &repo_path,
+            &["remote", "add", mirror, mirror_path.to_str().unwrap()],
+        );
+    }
+    fs::write(repo_path.join(".bashrc"), "changed").unwrap();
+    run_git(&repo_path, &["commit", "--quiet", "-am", "Change bashrc"]);
+    // The origin has the branch checked out, so pushing to it is refused.
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("push")
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.ends_with("ERROR: Failed to push to 1 remote(s)\n"));
+    let stdout = String::from_utf8(assert.get_output().stdout.clone()).unwrap();
+    assert_eq!(
+        stdout,
+        "pushed: mirror1\npushed: mirror2\nfailed: origin\npush result (3 total): 2 pushed; 1 failed\n"
+    );
+}
+
+#[test]
+fn sparse_clone() {
+    let temp_dir = TempDir::new().unwrap();
+    let origin_path = temp_dir.path().join("origin");
+    let repo_path = temp_dir.path().join("repo");
+    fs::create_dir_all(origin_path.join("laptop")).unwrap();
+    fs::create_dir_all(origin_path.join("desktop").join("nvim")).unwrap();
+    fs::write(
+        origin_path.join("config.ambit"),
+        "laptop/.bashrc => .bashrc;\n",
+    )
+    .unwrap();
+    fs::write(origin_path.join("laptop").join(".bashrc"), "").unwrap();
+    fs::write(origin_path.join("desktop").join(".bashrc"), "").unwrap();
+    fs::write(
+        origin_path.join("desktop").join("nvim").join("init.vim"),
+        "",
+    )
+    .unwrap();
+    init_origin(&origin_path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["clone", "--sparse"])
+        .arg(&origin_path)
+        .assert()
+        .success();
+    assert!(repo_path.join("config.ambit").exists());
+    assert!(repo_path.join("laptop").join(".bashrc").exists());
+    assert!(!repo_path.join("desktop").exists());
+    // The patterns follow the configuration when updating.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("desktop/nvim/ => .config/nvim/;\n")
+        .arg("update")
+        .assert()
+        .success();
+    assert!(repo_path
+        .join("desktop")
+        .join("nvim")
+        .join("init.vim")
+        .exists());
+    assert!(!repo_path.join("desktop").join(".bashrc").exists());
+    assert!(!repo_path.join("laptop").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sparse", "--disable"])
+        .assert()
+        .success();
+    assert!(repo_path.join("laptop").join(".bashrc").exists());
+}
+
+#[test]
+fn sync_from_repo_root() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\nvim/* => .vim/*;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join("dots").join(".bashrc"), "")
+        .with_file_with_content(&repo_path.join("dots").join("vim").join("vimrc"), "")
+        .env("AMBIT_REPO_ROOT", "dots")
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        temp_dir.path().join(".bashrc"),
+        repo_path.join("dots").join(".bashrc")
+    ));
+    assert!(is_symlinked(
+        temp_dir.path().join(".vim").join("vimrc"),
+        repo_path.join("dots").join("vim").join("vimrc")
+    ));
+}
+
+#[test]
+fn relocate_repo() {
+    let temp_dir = TempDir::new().unwrap();
+    let new_repo_path = temp_dir.path().join("disk").join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\nnvim/;\n")
+        .with_repo_file(".bashrc")
+        .with_file_with_content(
+            &temp_dir.path().join("repo").join("nvim").join("init.vim"),
+            "",
+        )
+        .arg("sync")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("relocate")
+        .arg(&new_repo_path)
+        .assert()
+        .success();
+    assert!(!temp_dir.path().join("repo").exists());
+    assert!(is_symlinked(
+        temp_dir.path().join(".bashrc"),
+        new_repo_path.join(".bashrc")
+    ));
+    assert!(is_symlinked(
+        temp_dir.path().join("nvim"),
+        new_repo_path.join("nvim")
+    ));
+    // The new location is used from then on, unless AMBIT_REPO_PATH says otherwise.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env_remove("AMBIT_REPO_PATH")
+        .args(["status"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "linked: {0}/.bashrc -> {1}/.bashrc\nlinked: {0}/nvim -> {1}/nvim\nstatus result (2 total): 2 linked; 0 not linked\n",
+            temp_dir.path().display(),
+            new_repo_path.display()
+        ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env("AMBIT_REPO_PATH", &new_repo_path)
+        .arg("relocate")
+        .arg(new_repo_path.join("inner"))
+        .assert()
+        .failure()
+        .stderr("ERROR: Cannot relocate the repository into itself\n");
+}
+
+#[test]
+fn relocate_repo_rolls_back_on_failure() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("repo");
+    let new_repo_path = temp_dir.path().join("disk").join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n")
+        .with_repo_file(".bashrc")
+        .arg("sync")
+        .assert()
+        .success();
+    // A directory in place of the stored location cannot be written, which fails the relocation after the move.
+    fs::create_dir_all(
+        temp_dir
+            .path()
+            .join(".local")
+            .join("state")
+            .join("ambit")
+            .join("repo_location"),
+    )
+    .unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("relocate")
+        .arg(&new_repo_path)
+        .assert()
+        .failure();
+    assert!(repo_path.join(".bashrc").exists());
+    assert!(!new_repo_path.exists());
+    assert!(is_symlinked(
+        temp_dir.path().join(".bashrc"),
+        repo_path.join(".bashrc")
+    ));
+}
+
 #[test]
 fn sync_without_repo() {
     // Error should occur if attempting to sync without initializing.
@@ -161,6 +671,1917 @@ fn sync_without_repo() {
     );
 }
 
+#[test]
+fn check_reports_parse_error() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n[b.txt c.txt];\n")
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(
+            r#"ERROR: Expected `,`, found `c.txt`
+ --> line 2, column 8
+  |
+2 | [b.txt c.txt];
+  |        ^^^^^
+"#,
+        );
+}
+
+#[test]
+fn check_json_format() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.ambit");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\nb.txt")
+        .args(vec!["check", "--format", "json"])
+        .assert()
+        .failure()
+        .stdout(format!(
+            "[{{\"severity\":\"error\",\"type\":\"expected\",\"message\":\"Expected `;`, found end of file\",\"file\":{:?},\"line\":2,\"column\":6}}]\n",
+            config_path.display().to_string()
+        ));
+}
+
+#[test]
+fn check_json_format_with_included_configs() {
+    let temp_dir = TempDir::new().unwrap();
+    let included_path = temp_dir.path().join("included.ambit");
+    fs::write(&included_path, "a.txt;\nb.txt => a.txt;\n").unwrap();
+    // Diagnostics point at the included file they occur in.
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("include \"included.ambit\";\n")
+        .args(vec!["check", "--format", "json"])
+        .assert()
+        .failure();
+    let stdout = stdout(&assert);
+    assert!(stdout.contains("\"type\":\"duplicate-host-path\""));
+    assert!(stdout.ends_with(&format!(
+        "\"file\":{:?},\"line\":2,\"column\":1}}]\n",
+        included_path.display().to_string()
+    )));
+    // Parse errors in included files are reported as JSON too.
+    fs::write(&included_path, "a.txt").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("include \"included.ambit\";\n")
+        .args(vec!["check", "--format", "json"])
+        .assert()
+        .failure()
+        .stdout(format!(
+            "[{{\"severity\":\"error\",\"type\":\"expected\",\"message\":\"Expected `;`, found end of file\",\"file\":{:?},\"line\":1,\"column\":6}}]\n",
+            included_path.display().to_string()
+        ));
+}
+
+#[test]
+fn check_unset_env_vars() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n$AMBIT_TEST_UNSET/b.txt;\n")
+        .env_remove("AMBIT_TEST_UNSET")
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: {}:2:1: Variable `AMBIT_TEST_UNSET` used in `$AMBIT_TEST_UNSET/b.txt` is not defined with `let`, and no environment variable of that name is set\n",
+            temp_dir.path().join("config.ambit").display()
+        ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n$AMBIT_TEST_UNSET/b.txt;\n")
+        .env("AMBIT_TEST_UNSET", "dir")
+        .arg("check")
+        .assert()
+        .success();
+}
+
+#[test]
+fn check_json_format_without_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;")
+        .args(vec!["check", "--format", "json"])
+        .assert()
+        .success()
+        .stdout("[]\n");
+}
+
+#[test]
+fn check_duplicate_host_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.ambit");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt => host.txt;\n[b.txt, c.txt] => [other.txt, host.txt];\n")
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: {1}:2:1: Host file `{}` is produced by more than one entry:\n    {1}:1: a.txt => host.txt;\n    {1}:2: [b.txt, c.txt] => [other.txt, host.txt];\n",
+            temp_dir.path().join("host.txt").display(),
+            config_path.display()
+        ));
+    // Patterns are expanded, and the host files and entries of secrets are not shown.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("b.txt")
+        .with_config("a.txt => s.txt [secret=true];\nb.* => s.txt;\n")
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: {0}:2:1: Host file `secret file` is produced by more than one entry:\n    {0}:1\n    {0}:2\n",
+            config_path.display()
+        ));
+}
+
+#[test]
+fn check_warns_about_unreachable_cases() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n  a{default: b, os(linux): c};\n")
+        .arg("check")
+        .assert()
+        .success()
+        .stderr(format!(
+            "WARNING: {}:2:3: Match case 2 in entry 2 can never be chosen because it follows a `default` case\n",
+            temp_dir.path().join("config.ambit").display()
+        ));
+}
+
+#[cfg(unix)]
+#[test]
+fn check_warns_about_permission_drift() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = TempDir::new().unwrap();
+    let repo_file = temp_dir.path().join("repo").join("a.txt");
+    let config = "a.txt => b.txt [perm=600];\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("check")
+        .assert()
+        .success()
+        .stderr("");
+    fs::set_permissions(&repo_file, fs::Permissions::from_mode(0o644)).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("check")
+        .assert()
+        .success()
+        .stderr(format!(
+            "WARNING: {}:1:1: `{}` has permissions 644 instead of 600\n",
+            temp_dir.path().join("config.ambit").display(),
+            repo_file.display()
+        ));
+}
+
+#[test]
+fn check_strict_missing_repo_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let config = "a.txt;\nb.txt;\n";
+    // Missing repository files are only reported in strict mode.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .arg("check")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(&["check", "--strict"])
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: {}:2:1: Repository file `{}` does not exist\n",
+            temp_dir.path().join("config.ambit").display(),
+            temp_dir.path().join("repo").join("b.txt").display()
+        ));
+}
+
+#[test]
+fn sync_include() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\ninclude \"configs/laptop.ambit\";\n")
+        .with_file_with_content(&path.join("configs").join("laptop.ambit"), "b.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .arg("sync")
+        .assert()
+        .success();
+    let repo_path = path.join("repo");
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(is_symlinked(path.join("b.txt"), repo_path.join("b.txt")));
+}
+
+#[test]
+fn sync_config_fragments() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let fragment_dir = path.join("config.ambit.d");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt => host.txt;\n")
+        .with_file_with_content(&fragment_dir.join("20-c.ambit"), "c.txt;\n")
+        .with_file_with_content(&fragment_dir.join("10-b.ambit"), "b.txt;\n")
+        // Only files with the `.ambit` extension are merged.
+        .with_file_with_content(&fragment_dir.join("notes.txt"), "d.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file("c.txt")
+        .args(&["sync", "--dry-run"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "Ignored {} -> {}\nIgnored {} -> {}\nIgnored {} -> {}\nsync result (3 total): 0 synced; 3 ignored\n",
+            path.join("host.txt").display(),
+            path.join("repo").join("a.txt").display(),
+            path.join("b.txt").display(),
+            path.join("repo").join("b.txt").display(),
+            path.join("c.txt").display(),
+            path.join("repo").join("c.txt").display(),
+        ));
+}
+
+#[test]
+fn sync_local_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n")
+        .with_file_with_content(&path.join("config.local.ambit"), "b.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .arg("sync")
+        .assert()
+        .success();
+    let repo_path = path.join("repo");
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(is_symlinked(path.join("b.txt"), repo_path.join("b.txt")));
+}
+
+#[test]
+fn sync_env_vars_in_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("$AMBIT_TEST_NAME.txt;\n")
+        .with_repo_file("a.txt")
+        .env("AMBIT_TEST_NAME", "a")
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        path.join("a.txt"),
+        path.join("repo").join("a.txt")
+    ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("$AMBIT_TEST_UNSET.txt;\n")
+        .env_remove("AMBIT_TEST_UNSET")
+        .arg("sync")
+        .assert()
+        .failure()
+        .stderr("ERROR: Variable `AMBIT_TEST_UNSET` used in `$AMBIT_TEST_UNSET.txt` is not defined with `let`, and no environment variable of that name is set\n");
+}
+
+#[test]
+fn sync_tilde_in_host_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("[a.txt, b.txt] => [~/a.txt, ~/dir/b.txt];\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .arg("sync")
+        .assert()
+        .success();
+    let repo_path = path.join("repo");
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(is_symlinked(
+        path.join("dir").join("b.txt"),
+        repo_path.join("b.txt")
+    ));
+}
+
+#[test]
+fn sync_outside_home() {
+    let temp_dir = TempDir::new().unwrap();
+    let outside_dir = TempDir::new().unwrap();
+    let host_path = outside_dir.path().join("a.txt");
+    let config = format!("a.txt => {};\n", host_path.display());
+    // Declining the confirmation should leave the host file alone.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(&config)
+        .with_repo_file("a.txt")
+        .arg("sync")
+        .write_stdin("n")
+        .assert()
+        .success();
+    assert!(!host_path.exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(&config)
+        .args(&["sync", "--allow-outside-home"])
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        host_path,
+        temp_dir.path().join("repo").join("a.txt")
+    ));
+}
+
+#[test]
+fn path_options() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let (config_path, repo_path, home_path) = (
+        path.join("setup.ambit"),
+        path.join("dots"),
+        path.join("home"),
+    );
+    fs::create_dir_all(repo_path.join(".git")).unwrap();
+    fs::create_dir_all(&home_path).unwrap();
+    fs::write(&config_path, "a.txt;").unwrap();
+    fs::write(repo_path.join("a.txt"), "").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env_remove("AMBIT_CONFIG_PATH")
+        .env_remove("AMBIT_REPO_PATH")
+        .env_remove("AMBIT_HOME_PATH")
+        .args(["--config".as_ref(), config_path.as_os_str()])
+        .args(["--repo".as_ref(), repo_path.as_os_str()])
+        .arg("sync")
+        .args(["--home".as_ref(), home_path.as_os_str()])
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        home_path.join("a.txt"),
+        repo_path.join("a.txt")
+    ));
+    // Options take precedence over the environment variables.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["--config".as_ref(), config_path.as_os_str()])
+        .args(["--repo".as_ref(), repo_path.as_os_str()])
+        .args(["--home".as_ref(), home_path.as_os_str()])
+        .arg("clean")
+        .assert()
+        .success();
+    assert!(!home_path.join("a.txt").exists());
+}
+
+#[test]
+fn exists_expr_with_home_option() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let home_path = path.join("home");
+    fs::create_dir_all(&home_path).unwrap();
+    fs::write(home_path.join("marker"), "").unwrap();
+    // exists() is resolved within the home directory given by `--home`, not the one in AMBIT_HOME_PATH.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("{exists(marker): a.txt, default: b.txt};")
+        .args(["--home".as_ref(), home_path.as_os_str()])
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        home_path.join("a.txt"),
+        path.join("repo").join("a.txt")
+    ));
+    assert!(!home_path.join("b.txt").exists());
+}
+
+#[test]
+fn xdg_base_directories() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config_dir = path.join("xdg-config").join("ambit");
+    fs::create_dir_all(config_dir.join("repo").join(".git")).unwrap();
+    fs::write(config_dir.join("config.ambit"), "a.txt;").unwrap();
+    fs::write(config_dir.join("repo").join("a.txt"), "").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env_remove("AMBIT_CONFIG_PATH")
+        .env_remove("AMBIT_REPO_PATH")
+        .env("XDG_CONFIG_HOME", path.join("xdg-config"))
+        .env("XDG_DATA_HOME", path.join("xdg-data"))
+        .env("XDG_STATE_HOME", path.join("xdg-state"))
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        path.join("a.txt"),
+        config_dir.join("repo").join("a.txt")
+    ));
+    assert!(path.join("xdg-data/ambit/manifest").exists());
+    assert!(path.join("xdg-state/ambit/journal").exists());
+    assert!(!path.join(".local").exists());
+}
+
+#[test]
+fn prompts_without_terminal() {
+    let temp_dir = TempDir::new().unwrap();
+    let outside_dir = TempDir::new().unwrap();
+    let host_path = outside_dir.path().join("a.txt");
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_config(&format!("a.txt => {};\n", host_path.display()))
+            .with_repo_file("a.txt")
+    };
+    // Nobody can answer the prompt, so it fails instead of waiting.
+    tester().arg("sync").assert().failure().stderr(format!(
+        "ERROR: Could not ask `{} is outside of the home directory. Sync anyway?`, as stdin is not a terminal.\n\
+         Pass `--yes` or `--non-interactive` to answer prompts.\n",
+        host_path.display()
+    ));
+    tester()
+        .args(["sync", "--non-interactive"])
+        .assert()
+        .success();
+    assert!(!host_path.exists());
+    tester().args(["--yes", "sync"]).assert().success();
+    assert!(is_symlinked(
+        &host_path,
+        temp_dir.path().join("repo").join("a.txt")
+    ));
+}
+
+#[test]
+fn add_host_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let host_path = path.join(".config").join("my app").join("settings");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;")
+        .with_repo_path()
+        .with_file_with_content(&host_path, "content")
+        .arg("add")
+        .arg(&host_path)
+        .assert()
+        .success();
+    let repo_path = path
+        .join("repo")
+        .join(".config")
+        .join("my app")
+        .join("settings");
+    assert_eq!(fs::read_to_string(&repo_path).unwrap(), "content");
+    assert!(is_symlinked(host_path.clone(), repo_path));
+    assert_eq!(
+        fs::read_to_string(path.join("config.ambit")).unwrap(),
+        "a.txt;\n\".config/my app/settings\";\n"
+    );
+    // The file is already in the repository.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("add")
+        .arg(&host_path)
+        .assert()
+        .failure();
+}
+
+#[test]
+fn add_outside_home() {
+    let temp_dir = TempDir::new().unwrap();
+    let outside_dir = TempDir::new().unwrap();
+    let host_path = outside_dir.path().join("a.txt");
+    File::create(&host_path).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("")
+        .with_repo_path()
+        .arg("add")
+        .arg(&host_path)
+        .assert()
+        .failure();
+    assert!(host_path.is_file());
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("config.ambit")).unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn add_auto_commit_and_push() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let origin_path = path.join("origin");
+    fs::create_dir_all(&origin_path).unwrap();
+    fs::write(origin_path.join(".bashrc"), "").unwrap();
+    init_origin(&origin_path);
+    // Pushing requires a bare repository, as the branch of origin is checked out.
+    run_git(
+        path,
+        &["clone", "--quiet", "--bare", "origin", "origin.git"],
+    );
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clone")
+        .arg(path.join("origin.git"))
+        .assert()
+        .success();
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .env("GIT_AUTHOR_NAME", "ambit")
+            .env("GIT_AUTHOR_EMAIL", "ambit@example.com")
+            .env("GIT_COMMITTER_NAME", "ambit")
+            .env("GIT_COMMITTER_EMAIL", "ambit@example.com")
+    };
+    fs::write(path.join(".zshrc"), "").unwrap();
+    tester()
+        .env("AMBIT_AUTO_COMMIT", "maybe")
+        .arg("add")
+        .arg(path.join(".zshrc"))
+        .assert()
+        .failure()
+        .stderr("ERROR: AMBIT_AUTO_COMMIT must be `commit` or `push`, not `maybe`\n");
+    tester()
+        .env("AMBIT_AUTO_COMMIT", "push")
+        .arg("add")
+        .arg(path.join(".zshrc"))
+        .assert()
+        .success()
+        .stdout(format!(
+            "Added {} -> {}\nAdd .zshrc\npushed: origin\npush result (1 total): 1 pushed; 0 failed\n",
+            path.join(".zshrc").display(),
+            path.join("repo").join(".zshrc").display()
+        ));
+    let log = std::process::Command::new("git")
+        .args(["log", "-1", "--format=%s"])
+        .current_dir(path.join("origin.git"))
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8(log.stdout).unwrap(), "Add .zshrc\n");
+    // Without the setting, only the flags commit.
+    fs::write(path.join(".inputrc"), "").unwrap();
+    tester()
+        .args(["add", "--commit", "-m", "Adopt inputrc"])
+        .arg(path.join(".inputrc"))
+        .assert()
+        .success()
+        .stdout(format!(
+            "Added {} -> {}\nAdopt inputrc\n",
+            path.join(".inputrc").display(),
+            path.join("repo").join(".inputrc").display()
+        ));
+}
+
+#[test]
+fn rm_entry() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\nb.txt => c.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .arg("sync")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("rm")
+        .arg("--delete")
+        .arg(path.join("c.txt"))
+        .assert()
+        .success();
+    assert!(!path.join("c.txt").exists());
+    assert!(!path.join("repo").join("b.txt").exists());
+    assert!(is_symlinked(
+        path.join("a.txt"),
+        path.join("repo").join("a.txt")
+    ));
+    assert_eq!(
+        fs::read_to_string(path.join("config.ambit")).unwrap(),
+        "a.txt;\n"
+    );
+    // Without `--delete`, the repository file is kept.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("rm")
+        .arg(path.join("a.txt"))
+        .assert()
+        .success();
+    assert!(!path.join("a.txt").exists());
+    assert!(path.join("repo").join("a.txt").exists());
+    assert_eq!(fs::read_to_string(path.join("config.ambit")).unwrap(), "");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("rm")
+        .arg(path.join("a.txt"))
+        .assert()
+        .failure();
+}
+
+#[test]
+#[cfg(unix)]
+fn status_reports_path_states() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\nb.txt;\nc.txt;\nd.txt;\ne.txt;\nf.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file("c.txt")
+        .with_host_file("c.txt")
+        .with_host_file("e.txt");
+    use std::os::unix::fs::symlink;
+    symlink(repo_path.join("a.txt"), path.join("a.txt")).unwrap();
+    // The repository file that f.txt links to does not exist.
+    symlink(repo_path.join("f.txt"), path.join("f.txt")).unwrap();
+    let line = |status: &str, name: &str| {
+        format!(
+            "{}: {} -> {}\n",
+            status,
+            path.join(name).display(),
+            repo_path.join(name).display()
+        )
+    };
+    tester.arg("status").assert().success().stdout(
+        [
+            line("linked", "a.txt"),
+            line("unlinked", "b.txt"),
+            line("conflict", "c.txt"),
+            line("missing repo file", "d.txt"),
+            line("pending move", "e.txt"),
+            line("broken symlink", "f.txt"),
+            "status result (6 total): 1 linked; 5 not linked\n".to_owned(),
+        ]
+        .concat(),
+    );
+}
+
+#[test]
+fn sync_keeps_copy_fallback() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let (host_file, repo_file) = (path.join("a.txt"), path.join("repo").join("a.txt"));
+    // a.txt was copied by `sync --copy-fallback`, as if the home directory could not hold symlinks.
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_repo_path()
+            .with_config("a.txt;")
+    };
+    tester()
+        .with_file_with_content(&repo_file, "a")
+        .with_file_with_content(&host_file, "a")
+        .with_file_with_content(
+            &path.join(".local/share/ambit/manifest"),
+            &format!(
+                "fallback\t{}\t{}\n",
+                host_file.display(),
+                repo_file.display()
+            ),
+        )
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 0 synced; 1 ignored\n");
+    assert!(fs::read_link(&host_file).is_err());
+    tester().arg("status").assert().success().stdout(format!(
+        "linked: {} -> {}\nstatus result (1 total): 1 linked; 0 not linked\n",
+        host_file.display(),
+        repo_file.display()
+    ));
+    tester()
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout("clean result (1 total): 1 deleted: 0 ignored\n");
+    assert!(!host_file.exists());
+}
+
+#[test]
+fn doctor_reports_problems() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let doctor = |assert| stdout(&assert);
+    let output = doctor(
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_config("a.txt;")
+            .arg("doctor")
+            .assert()
+            .failure(),
+    );
+    assert!(output.contains("fail: dotfile repository is initialized"));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;")
+        .with_repo_file("a.txt")
+        .arg("sync")
+        .assert()
+        .success();
+    let output = doctor(
+        AmbitTester::from_temp_dir(&temp_dir)
+            .arg("doctor")
+            .assert()
+            .success(),
+    );
+    assert!(!output.contains("fail"));
+    // Removing the repository file leaves a broken symlink behind.
+    fs::remove_file(path.join("repo").join("a.txt")).unwrap();
+    let output = doctor(
+        AmbitTester::from_temp_dir(&temp_dir)
+            .arg("doctor")
+            .assert()
+            .failure(),
+    );
+    assert!(output.contains("fail: no broken symlinks into the repository"));
+}
+
+#[test]
+#[cfg(unix)]
+fn repair_symlinks() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\nb.txt;\nc.txt;\nd.txt;\n")
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file("c.txt");
+    use std::os::unix::fs::symlink;
+    // a.txt is broken, b.txt links to the wrong repository file and c.txt is fine.
+    symlink(repo_path.join("old.txt"), path.join("a.txt")).unwrap();
+    symlink(repo_path.join("c.txt"), path.join("b.txt")).unwrap();
+    symlink(repo_path.join("c.txt"), path.join("c.txt")).unwrap();
+    // The repository file of d.txt does not exist, so it cannot be repaired.
+    symlink(repo_path.join("d.txt"), path.join("d.txt")).unwrap();
+    let line = |name: &str| {
+        format!(
+            "Repaired {} -> {}\n",
+            path.join(name).display(),
+            repo_path.join(name).display()
+        )
+    };
+    tester.arg("repair").assert().success().stdout(
+        [
+            line("a.txt"),
+            line("b.txt"),
+            "repair result (3 total): 2 repaired; 1 not repaired\n".to_owned(),
+        ]
+        .concat(),
+    );
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(is_symlinked(path.join("b.txt"), repo_path.join("b.txt")));
+    assert!(is_symlinked(path.join("c.txt"), repo_path.join("c.txt")));
+    assert!(is_symlinked(path.join("d.txt"), repo_path.join("d.txt")));
+}
+
+#[test]
+fn prune_unreferenced_repo_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("repo");
+    let config = "a.txt;\nnvim/ => .config/nvim/;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file(".ambitignore")
+        .with_file_with_content(&repo_path.join("nvim").join("init.vim"), "")
+        .with_file_with_content(&repo_path.join("old").join("c.txt"), "")
+        .with_file_with_content(&repo_path.join(".git").join("HEAD"), "")
+        .arg("prune")
+        .assert()
+        .success()
+        .stdout(format!(
+            "unreferenced: {}\nunreferenced: {}\nprune result (2 total): 0 deleted; 2 kept\n",
+            repo_path.join("b.txt").display(),
+            repo_path.join("old").join("c.txt").display()
+        ));
+    // Declining keeps the files.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["prune", "--delete"])
+        .write_stdin("n")
+        .assert()
+        .success();
+    assert!(repo_path.join("b.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["prune", "--delete"])
+        .write_stdin("y")
+        .assert()
+        .success();
+    assert!(!repo_path.join("b.txt").exists());
+    assert!(!repo_path.join("old").exists());
+    assert!(repo_path.join("a.txt").exists());
+    assert!(repo_path.join("nvim").join("init.vim").exists());
+}
+
+#[test]
+fn list_mappings() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let line = |repo: &str, host: &str| {
+        format!(
+            "{} -> {}\n",
+            repo_path.join(repo).display(),
+            path.join(host).display()
+        )
+    };
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("nvim/*.vim => .config/nvim/*.vim;\n.bashrc;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join("nvim").join("init.vim"), "")
+        .with_file_with_content(&repo_path.join("nvim").join("keys.vim"), "")
+        .arg("list")
+        .assert()
+        .success()
+        .stdout(
+            [
+                line("nvim/init.vim", ".config/nvim/init.vim"),
+                line("nvim/keys.vim", ".config/nvim/keys.vim"),
+                line(".bashrc", ".bashrc"),
+            ]
+            .concat(),
+        );
+    // Patterns are matched against both sides.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["list", ".config/nvim/i*"])
+        .assert()
+        .success()
+        .stdout(line("nvim/init.vim", ".config/nvim/init.vim"));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["list", ".bash*"])
+        .assert()
+        .success()
+        .stdout(line(".bashrc", ".bashrc"));
+}
+
+#[test]
+fn sync_and_clean_entries() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_repo_path()
+            .with_repo_file("a.txt")
+            .with_repo_file("b.txt")
+            .with_repo_file("c.txt")
+            .with_config("a.txt;\nb.txt;\nc.txt;\n")
+    };
+    tester()
+        .args(["list", "--numbered", "b.txt"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "2: {} -> {}\n",
+            repo_path.join("b.txt").display(),
+            path.join("b.txt").display()
+        ));
+    tester()
+        .args(["sync", "--entries", "1,3"])
+        .assert()
+        .success();
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(!path.join("b.txt").exists());
+    assert!(is_symlinked(path.join("c.txt"), repo_path.join("c.txt")));
+    tester().args(["clean", "--entry", "3"]).assert().success();
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(!path.join("c.txt").exists());
+    tester()
+        .args(["sync", "--entries", "2-4"])
+        .assert()
+        .failure()
+        .stderr("ERROR: Entry 4 does not exist, as the configuration has 3 entries\n");
+}
+
+#[test]
+fn tree_of_mappings() {
+    let temp_dir = TempDir::new().unwrap();
+    let repo_path = temp_dir.path().join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\nnvim/*.vim => .config/nvim/*.vim;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join("nvim").join("init.vim"), "")
+        .with_file_with_content(&repo_path.join("nvim").join("keys.vim"), "")
+        .arg("tree")
+        .assert()
+        .success()
+        .stdout(
+            "~
+├── .bashrc -> .bashrc
+└── .config
+    └── nvim
+        ├── init.vim -> nvim/init.vim
+        └── keys.vim -> nvim/keys.vim
+",
+        );
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n")
+        .args(["tree", "--dot"])
+        .assert()
+        .success()
+        .stdout(
+            r#"digraph ambit {
+    rankdir=LR;
+    "~" -> "~/.bashrc";
+    "~/.bashrc" [label=".bashrc"];
+    "~/.bashrc" -> "repo:.bashrc" [style=dashed];
+    "repo:.bashrc" [shape=box, label=".bashrc"];
+}
+"#,
+        );
+}
+
+#[test]
+fn which_repo_file() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let config_path = path.join("config.ambit");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\nnvim/*.vim => .config/nvim/*.vim;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join("nvim").join("init.vim"), "")
+        .arg("which")
+        .arg(path.join(".config").join("nvim").join("init.vim"))
+        .assert()
+        .success()
+        .stdout(format!(
+            "{}\n    from {}:2: nvim/*.vim => .config/nvim/*.vim;\n",
+            repo_path.join("nvim").join("init.vim").display(),
+            config_path.display()
+        ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("which")
+        .arg(path.join(".zshrc"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn which_relative_path() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join(".bashrc"), "")
+        .current_dir(&repo_path)
+        .arg("which")
+        .arg(Path::new("..").join(".").join(".bashrc"))
+        .assert()
+        .success();
+}
+
+#[cfg(unix)]
+#[test]
+fn which_symlinked_home() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let link_dir = TempDir::new().unwrap();
+    let home_path = link_dir.path().join("home");
+    std::os::unix::fs::symlink(path, &home_path).unwrap();
+    // Host paths are found within the symlinked home, even though the current directory resolves it.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".bashrc;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join(".bashrc"), "")
+        .env("AMBIT_HOME_PATH", &home_path)
+        .current_dir(&home_path)
+        .arg("which")
+        .arg(".bashrc")
+        .assert()
+        .success();
+}
+
+#[test]
+fn fmt_config() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.ambit");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a=>b;\n")
+        .args(["fmt", "--check"])
+        .assert()
+        .failure()
+        .stdout(format!(
+            "--- {path}\n+++ {path}\n@@ -1 +1 @@\n-a=>b;\n+a => b;\n",
+            path = config_path.display()
+        ));
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), "a=>b;\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("fmt")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&config_path).unwrap(), "a => b;\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["fmt", "--check"])
+        .assert()
+        .success()
+        .stdout("");
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_config() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let editor_path = path.join("editor");
+    // The first edit introduces an error, which the second edit fixes.
+    let editor = format!(
+        r#"#!/bin/sh
+if [ -e "{marker}" ]; then echo 'b.txt;' > "$1"; else touch "{marker}"; echo 'b.txt' > "$1"; fi
+"#,
+        marker = path.join("edited").display()
+    );
+    fs::write(&editor_path, editor).unwrap();
+    fs::set_permissions(&editor_path, fs::Permissions::from_mode(0o755)).unwrap();
+    // Declining to edit again discards the changes.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;")
+        .env_remove("VISUAL")
+        .env("EDITOR", &editor_path)
+        .arg("edit")
+        .write_stdin("n")
+        .assert()
+        .failure();
+    assert_eq!(
+        fs::read_to_string(path.join("config.ambit")).unwrap(),
+        "a.txt;"
+    );
+    fs::remove_file(path.join("edited")).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env_remove("VISUAL")
+        .env("EDITOR", &editor_path)
+        .arg("edit")
+        .write_stdin("y")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join("config.ambit")).unwrap(),
+        "b.txt;\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_config_options_not_passed_to_editor() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config_path = path.join("setup.ambit");
+    fs::write(&config_path, "profile minimal;\na.txt;").unwrap();
+    let editor_path = path.join("editor");
+    // The editor records which of the variables that ambit reads it was run with.
+    let editor = format!(
+        "#!/bin/sh\necho \"${{AMBIT_CONFIG_PATH-}}${{AMBIT_PROFILE-}}${{AMBIT_ASSUME-}}\" > \"{}\"\n",
+        path.join("env").display()
+    );
+    fs::write(&editor_path, editor).unwrap();
+    fs::set_permissions(&editor_path, fs::Permissions::from_mode(0o755)).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env_remove("AMBIT_CONFIG_PATH")
+        .env_remove("VISUAL")
+        .env("EDITOR", &editor_path)
+        .args(["--config".as_ref(), config_path.as_os_str()])
+        .args(["--profile", "minimal", "--yes", "edit"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(path.join("env")).unwrap(), "\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_config_removes_copy() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let tmp_path = path.join("tmp");
+    fs::create_dir(&tmp_path).unwrap();
+    let editor_path = path.join("editor");
+    // The editor checks that the copy is private before writing an invalid config to it.
+    fs::write(
+        &editor_path,
+        "#!/bin/sh\n[ \"$(stat -c %a \"$1\")\" = 600 ] && echo 'b.txt' > \"$1\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&editor_path, fs::Permissions::from_mode(0o755)).unwrap();
+    // Without an answer to the prompt to edit again, the edit fails and the copy is still removed.
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;")
+        .env_remove("VISUAL")
+        .env("EDITOR", &editor_path)
+        .env("TMPDIR", &tmp_path)
+        .arg("edit")
+        .assert()
+        .failure();
+    assert!(stderr(&assert).contains("Edit again?"));
+    assert_eq!(fs::read_dir(&tmp_path).unwrap().count(), 0);
+}
+
+#[test]
+fn expand_spec() {
+    AmbitTester::default()
+        .arg("expand")
+        .arg(".config/[a, b{os(plan9): -plan9, default: -other}]")
+        .assert()
+        .success()
+        .stdout(".config/a\n.config/b-other\nmatch 1: took `default`\n");
+    AmbitTester::default()
+        .arg("expand")
+        .arg("a => b")
+        .assert()
+        .failure();
+    // Environment variables and `~` are expanded as in host paths.
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .env("AMBIT_TEST_DIR", "dir")
+        .args(["expand", "~/$AMBIT_TEST_DIR/x"])
+        .assert()
+        .success()
+        .stdout(format!("{}/dir/x\n", temp_dir.path().display()));
+}
+
+#[test]
+fn diff_host_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\nb.txt;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join("a.txt"), "a\nb\n")
+        .with_file_with_content(&path.join("a.txt"), "a\nc\n")
+        .with_file_with_content(&repo_path.join("b.txt"), "b\n")
+        .with_file_with_content(&path.join("b.txt"), "b\n")
+        .arg("diff")
+        .assert()
+        .success()
+        .stdout(format!(
+            "--- {}\n+++ {}\n@@ -1,2 +1,2 @@\n a\n-b\n+c\n",
+            repo_path.join("a.txt").display(),
+            path.join("a.txt").display()
+        ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("diff")
+        .arg(path.join("b.txt"))
+        .assert()
+        .success()
+        .stdout("");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("diff")
+        .arg(path.join("c.txt"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn sync_copy_mode() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let host_path = path.join("b.txt");
+    let config = "a.txt => b.txt [mode=copy, perm=600];\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(&path.join("repo").join("a.txt"), "content")
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(!fs::symlink_metadata(&host_path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&host_path).unwrap(), "content");
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&host_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // The copy is already up to date.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 0 synced; 1 ignored\n");
+    // Copies that were not modified on the host are updated when the repository file changes.
+    fs::write(path.join("repo").join("a.txt"), "new content").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&host_path).unwrap(), "new content");
+    // Copies that were modified on the host are not.
+    fs::write(&host_path, "host content").unwrap();
+    fs::write(path.join("repo").join("a.txt"), "newer content").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .failure();
+    assert_eq!(fs::read_to_string(&host_path).unwrap(), "host content");
+    fs::write(&host_path, "newer content").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("clean")
+        .assert()
+        .success();
+    assert!(!host_path.exists());
+}
+
+#[test]
+fn sync_secret() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let host_path = path.join(".ssh").join("id_ed25519");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".ssh/id_ed25519 [secret=true];\n")
+        .with_repo_path()
+        .with_file_with_content(&path.join("repo").join(".ssh").join("id_ed25519"), "key")
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout("Synced secret file\nsync result (1 total): 1 synced; 0 ignored\n");
+    assert!(!fs::symlink_metadata(&host_path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(&host_path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // The paths of secrets are hidden from every command and error.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("list")
+        .assert()
+        .success()
+        .stdout("secret file 1 of entry 1\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("which")
+        .arg(&host_path)
+        .assert()
+        .success()
+        .stdout(format!(
+            "secret file\n    from {}:1: secret entry\n",
+            path.join("config.ambit").display()
+        ));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("status")
+        .assert()
+        .success()
+        .stdout("linked: secret file\nstatus result (1 total): 1 linked; 0 not linked\n");
+    fs::write(&host_path, "changed").unwrap();
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("sync")
+        .assert()
+        .failure();
+    let stderr = stderr(&assert);
+    assert!(stderr.starts_with("ERROR: Failed to sync secret file\n"));
+    assert!(!stderr.contains("id_ed25519"));
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("rm")
+        .arg(&host_path)
+        .assert()
+        .success();
+    let stdout = stdout(&assert);
+    assert!(stdout.contains("Removed `secret entry` from"));
+    assert!(!stdout.contains("id_ed25519"));
+}
+
+// Write `age` and `gpg` executables to dir that "encrypt" by prefixing content with `age:` or `gpg:`.
+// Returns a PATH in which they are found first.
+#[cfg(unix)]
+fn with_fake_encryption(dir: &Path) -> std::ffi::OsString {
+    use std::os::unix::fs::PermissionsExt;
+    let bin_path = dir.join("bin");
+    fs::create_dir_all(&bin_path).unwrap();
+    let fakes = [
+        (
+            "age",
+            r#"#!/bin/sh
+case "$1" in
+--decrypt) for input; do :; done; sed 's/^age://' "$input" ;;
+--encrypt) while [ "$1" != --output ]; do shift; done; { printf age:; cat; } > "$2" ;;
+esac
+"#,
+        ),
+        (
+            "gpg",
+            r#"#!/bin/sh
+for last; do :; done
+case "$last" in
+--encrypt) while [ "$1" != --output ]; do shift; done; { printf gpg:; cat; } > "$2" ;;
+*) sed 's/^gpg://' "$last" ;;
+esac
+"#,
+        ),
+    ];
+    for (name, script) in &fakes {
+        fs::write(bin_path.join(name), script).unwrap();
+        fs::set_permissions(bin_path.join(name), fs::Permissions::from_mode(0o755)).unwrap();
+    }
+    let mut path = bin_path.into_os_string();
+    path.push(":");
+    path.push(std::env::var_os("PATH").unwrap_or_default());
+    path
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_decrypts_age_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let path_var = with_fake_encryption(path);
+    let host_path = path.join(".ssh").join("id_ed25519");
+    let config = ".ssh/id_ed25519.age => .ssh/id_ed25519 [secret=true];\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(&path.join("repo/.ssh/id_ed25519.age"), "age:key")
+        .env("PATH", &path_var)
+        .arg("sync")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&host_path).unwrap(), "key");
+    // The decrypted copy is already up to date.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .env("PATH", &path_var)
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 0 synced; 1 ignored\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".ssh/id_ed25519.age => .ssh/id_ed25519;\n")
+        .env("PATH", &path_var)
+        .arg("sync")
+        .assert()
+        .failure();
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_move_encrypts_age_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let path_var = with_fake_encryption(path);
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("token.age => token [mode=copy];\n")
+        .with_repo_path()
+        .with_file_with_content(&path.join("token"), "secret")
+        .env("PATH", &path_var)
+        .args(vec!["sync", "-m"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join("repo").join("token.age")).unwrap(),
+        "age:secret"
+    );
+    assert_eq!(fs::read_to_string(path.join("token")).unwrap(), "secret");
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_and_encrypt_gpg_files() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let path_var = with_fake_encryption(path);
+    let host_path = path.join("tokens");
+    let config = "tokens.gpg => tokens [mode=copy];\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(&path.join("repo").join("tokens.gpg"), "gpg:old")
+        .env("PATH", &path_var)
+        .arg("sync")
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(&host_path).unwrap(), "old");
+    // Changes to the host file are encrypted back into the repository.
+    fs::write(&host_path, "new").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .env("PATH", &path_var)
+        .arg("encrypt")
+        .arg(&host_path)
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join("repo").join("tokens.gpg")).unwrap(),
+        "gpg:new"
+    );
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .env("PATH", &path_var)
+        .arg("encrypt")
+        .arg(path.join("other"))
+        .assert()
+        .failure();
+}
+
+#[test]
+fn sync_renders_templates() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let host_path = path.join(".gitconfig");
+    let config = ".gitconfig.tmpl => .gitconfig;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(
+            &path.join("repo").join(".gitconfig.tmpl"),
+            "os = {{ os }}\n",
+        )
+        .arg("sync")
+        .assert()
+        .success();
+    // Templates are copied even though the entry does not set `mode=copy`.
+    assert!(!fs::symlink_metadata(&host_path)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(
+        fs::read_to_string(&host_path).unwrap(),
+        format!("os = {}\n", std::env::consts::OS)
+    );
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 0 synced; 1 ignored\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("clean")
+        .assert()
+        .success();
+    assert!(!host_path.exists());
+}
+
+#[test]
+fn sync_renders_templates_with_vars() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = r#"
+vars {
+    email = me@home.com;
+    size = {os(plan9): 14, default: 11};
+}
+.gitconfig.tmpl => .config/{email}/.gitconfig;
+"#;
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(
+            &path.join("repo").join(".gitconfig.tmpl"),
+            "email = {{ email }}, size = {{ size }}\n",
+        )
+        .arg("sync")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join(".config/me@home.com/.gitconfig")).unwrap(),
+        "email = me@home.com, size = 11\n"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_renders_templates_with_pass_secrets() {
+    use std::os::unix::fs::PermissionsExt;
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    // A `pass` whose entries hold their own name, followed by metadata.
+    let pass_path = path.join("bin").join("pass");
+    fs::create_dir_all(pass_path.parent().unwrap()).unwrap();
+    fs::write(
+        &pass_path,
+        "#!/bin/sh\necho \"$2-password\"\necho \"user: me\"\n",
+    )
+    .unwrap();
+    fs::set_permissions(&pass_path, fs::Permissions::from_mode(0o755)).unwrap();
+    let mut path_var = pass_path.parent().unwrap().as_os_str().to_owned();
+    path_var.push(":");
+    path_var.push(std::env::var_os("PATH").unwrap_or_default());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(".netrc.tmpl => .netrc;\n")
+        .with_repo_path()
+        .with_file_with_content(
+            &path.join("repo").join(".netrc.tmpl"),
+            "password {{ pass(\"email/work\") }}\n",
+        )
+        .env("PATH", &path_var)
+        .arg("sync")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join(".netrc")).unwrap(),
+        "password email/work-password\n"
+    );
+}
+
+#[test]
+fn sync_and_clean_with_tag() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = "a.txt #work;\nb.txt #gaming #work;\nc.txt;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file("c.txt")
+        .args(vec!["sync", "--quiet", "--tag", "gaming"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(!path.join("a.txt").exists());
+    assert!(path.join("b.txt").exists());
+    assert!(!path.join("c.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(vec!["clean", "--tag", "work"])
+        .assert()
+        .success()
+        .stdout("clean result (2 total): 2 deleted: 0 ignored\n");
+    assert!(!path.join("a.txt").exists());
+    assert!(!path.join("b.txt").exists());
+    assert!(path.join("c.txt").exists());
+}
+
+#[test]
+fn sync_group() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = "group never if host(not-a-real-host) {\n    a.txt;\n}\ngroup work {\n    b.txt;\n    c.txt;\n}\nd.txt;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_repo_file("c.txt")
+        .with_repo_file("d.txt")
+        .args(vec!["sync", "--quiet", "--tag", "work"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (3 total): 1 synced; 2 ignored\n");
+    assert!(!path.join("a.txt").exists());
+    assert!(path.join("b.txt").exists());
+    assert!(path.join("c.txt").exists());
+    assert!(path.join("d.txt").exists());
+}
+
+#[test]
+fn sync_profile() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = "profile minimal;\nprofile full;\n{profile(full): a.txt};\nb.txt;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .args(vec!["sync", "--quiet", "--profile", "minimal"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(!path.join("a.txt").exists());
+    assert!(path.join("b.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(vec!["--profile", "full", "sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 1 synced; 1 ignored\n");
+    assert!(path.join("a.txt").exists());
+    // Profiles must be declared.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(vec!["sync", "--profile", "ful"])
+        .assert()
+        .failure()
+        .stderr("ERROR: Profile `ful` is not declared in the configuration\n");
+}
+
+#[test]
+fn sync_recursive_pattern() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_nvim = path.join("repo").join("nvim");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("nvim/** => .config/nvim/**;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_nvim.join("init.vim"), "")
+        .with_file_with_content(&repo_nvim.join("lua").join("plugins.lua"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    let host_nvim = path.join(".config").join("nvim");
+    assert_eq!(
+        fs::read_link(host_nvim.join("init.vim")).unwrap(),
+        repo_nvim.join("init.vim")
+    );
+    assert_eq!(
+        fs::read_link(host_nvim.join("lua").join("plugins.lua")).unwrap(),
+        repo_nvim.join("lua").join("plugins.lua")
+    );
+}
+
+#[test]
+fn sync_char_class_pattern() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_bash = path.join("repo").join("bash");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("bash/bash_[:ap]* => .config/bash/bash_[:ap]*;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_bash.join("bash_aliases"), "")
+        .with_file_with_content(&repo_bash.join("bash_profile"), "")
+        .with_file_with_content(&repo_bash.join("bash_logout"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    let host_bash = path.join(".config").join("bash");
+    assert!(host_bash.join("bash_aliases").exists());
+    assert!(host_bash.join("bash_profile").exists());
+    assert!(!host_bash.join("bash_logout").exists());
+}
+
+#[test]
+fn sync_escaped_brackets() {
+    let temp_dir = TempDir::new().unwrap();
+    // Escaped brackets are matched literally rather than as a character class.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("x\\[1\\];\n")
+        .with_repo_file("x[1]")
+        .with_repo_file("x1")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(temp_dir.path().join("x[1]").exists());
+    assert!(!temp_dir.path().join("x1").exists());
+}
+
+#[test]
+fn sync_excludes() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_nvim = path.join("repo").join("nvim");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("nvim/* !*.swp => .config/nvim/*;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_nvim.join("init.vim"), "")
+        .with_file_with_content(&repo_nvim.join(".init.vim.swp"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    let host_nvim = path.join(".config").join("nvim");
+    assert!(host_nvim.join("init.vim").exists());
+    assert!(!host_nvim.join(".init.vim.swp").exists());
+}
+
+#[test]
+fn sync_ambitignore() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("nvim/* => .config/nvim/*;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo.join(".ambitignore"), "*.swp\n")
+        .with_file_with_content(&repo.join("nvim").join("init.vim"), "")
+        .with_file_with_content(&repo.join("nvim").join(".init.vim.swp"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    let host_nvim = path.join(".config").join("nvim");
+    assert!(host_nvim.join("init.vim").exists());
+    assert!(!host_nvim.join(".init.vim.swp").exists());
+}
+
+#[test]
+fn sync_and_clean_directory() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_nvim = path.join("repo").join("nvim");
+    let host_nvim = path.join(".config").join("nvim");
+    let config = "nvim/ => .config/nvim/;\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(&repo_nvim.join("init.vim"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(is_symlinked(host_nvim.clone(), repo_nvim.clone()));
+    assert!(host_nvim.join("init.vim").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout("clean result (1 total): 1 deleted: 0 ignored\n");
+    assert!(!host_nvim.exists());
+    assert!(repo_nvim.join("init.vim").exists());
+}
+
+#[test]
+fn sync_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_nvim = path.join("repo").join("nvim");
+    let host_nvim = path.join(".config").join("nvim");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("nvim =>> .config/nvim;\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_nvim.join("init.vim"), "")
+        .with_file_with_content(&repo_nvim.join("lua").join("plugins.lua"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    // Directories are created rather than symlinked.
+    assert!(!is_symlinked(host_nvim.clone(), repo_nvim.clone()));
+    assert!(!is_symlinked(host_nvim.join("lua"), repo_nvim.join("lua")));
+    assert!(is_symlinked(
+        host_nvim.join("init.vim"),
+        repo_nvim.join("init.vim")
+    ));
+    assert!(is_symlinked(
+        host_nvim.join("lua").join("plugins.lua"),
+        repo_nvim.join("lua").join("plugins.lua")
+    ));
+}
+
+#[test]
+fn sync_fold_and_unfold_tree() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo = path.join("repo");
+    let repo_nvim = repo.join("nvim");
+    let host_nvim = path.join(".config").join("nvim");
+    let config = "nvim =>> .config/nvim [fold=true];\n";
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .with_repo_path()
+        .with_file_with_content(&repo_nvim.join("init.vim"), "")
+        .with_file_with_content(&repo_nvim.join("lua").join("plugins.lua"), "")
+        .with_file_with_content(&repo.join("local.vim"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    // Every file comes from the repository, so the directory itself is symlinked.
+    assert!(is_symlinked(host_nvim.clone(), repo_nvim.clone()));
+    // Another entry syncing into the directory unfolds it.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(&format!("{}local.vim => .config/nvim/local.vim;\n", config))
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (3 total): 3 synced; 0 ignored\n");
+    assert!(!is_symlinked(host_nvim.clone(), repo_nvim.clone()));
+    assert!(is_symlinked(
+        host_nvim.join("lua").join("plugins.lua"),
+        repo_nvim.join("lua").join("plugins.lua")
+    ));
+    assert!(is_symlinked(
+        host_nvim.join("local.vim"),
+        repo.join("local.vim")
+    ));
+    assert!(!repo_nvim.join("local.vim").exists());
+    // Once the foreign file is gone, the directory is folded again.
+    fs::remove_file(host_nvim.join("local.vim")).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(is_symlinked(host_nvim.clone(), repo_nvim.clone()));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config(config)
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout("clean result (1 total): 1 deleted: 0 ignored\n");
+    assert!(!host_nvim.exists());
+}
+
+#[test]
+fn sync_one_to_many() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("gitconfig => [.gitconfig, .config/git/config];\n")
+        .with_repo_file("gitconfig")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    let repo_file = path.join("repo").join("gitconfig");
+    assert!(is_symlinked(path.join(".gitconfig"), repo_file.clone()));
+    assert!(is_symlinked(
+        path.join(".config").join("git").join("config"),
+        repo_file
+    ));
+}
+
+#[test]
+fn sync_captures() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_scripts = path.join("repo").join("scripts");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("scripts/*.sh => .local/bin/{1};\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_scripts.join("backup.sh"), "")
+        .with_file_with_content(&repo_scripts.join("notes.txt"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (1 total): 1 synced; 0 ignored\n");
+    assert!(is_symlinked(
+        path.join(".local").join("bin").join("backup"),
+        repo_scripts.join("backup.sh")
+    ));
+}
+
+#[test]
+fn sync_basename() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_configs = path.join("repo").join("configs");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("configs/** => .config/basename();\n")
+        .with_repo_path()
+        .with_file_with_content(&repo_configs.join("a").join("x.conf"), "")
+        .with_file_with_content(&repo_configs.join("y.conf"), "")
+        .args(vec!["sync", "--quiet"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 2 synced; 0 ignored\n");
+    let host_config = path.join(".config");
+    assert!(is_symlinked(
+        host_config.join("x.conf"),
+        repo_configs.join("a").join("x.conf")
+    ));
+    assert!(is_symlinked(
+        host_config.join("y.conf"),
+        repo_configs.join("y.conf")
+    ));
+}
+
+#[test]
+fn check_include_cycle() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("include other.ambit;\n")
+        .with_file_with_content(&path.join("other.ambit"), "include config.ambit;\n")
+        .arg("check")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: Configuration `{}` is included recursively\n",
+            path.join("config.ambit").display()
+        ));
+}
+
+#[test]
+fn sync_reports_all_parse_errors() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_config("[a.txt, b.txt] => c.txt;\nvalid.txt;\nd.txt e.txt;\n")
+        .arg("sync")
+        .assert()
+        .failure()
+        .stderr(
+            r#"ERROR: Left and right sides of mapping must match up
+ --> line 1, column 24
+  |
+1 | [a.txt, b.txt] => c.txt;
+  |                        ^
+
+Expected `;`, found `e.txt`
+ --> line 3, column 7
+  |
+3 | d.txt e.txt;
+  |       ^^^^^
+"#,
+        );
+}
+
 #[test]
 fn sync_host_file_already_exists() {
     // The host file already exists but is not symlinked to repo file.
@@ -185,6 +2606,31 @@ fn sync_repo_file_does_not_exist() {
         .failure();
 }
 
+#[test]
+fn sync_rolls_back_on_failure() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = "a.txt => x/y/a.txt;\nb.txt;\n";
+    // b.txt does not exist in the repository, so the sync fails after a.txt is synced.
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_config(config)
+        .arg("sync")
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.starts_with("WARNING: Sync failed, rolling back 3 change(s)\n"));
+    assert!(!path.join("x").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--keep-partial"])
+        .assert()
+        .failure();
+    assert!(is_symlinked(
+        path.join("x").join("y").join("a.txt"),
+        path.join("repo").join("a.txt")
+    ));
+}
+
 #[test]
 fn sync_normal() {
     let temp_dir = TempDir::new().unwrap();
@@ -220,6 +2666,43 @@ fn sync_move_normal() {
     ));
 }
 
+#[test]
+fn sync_adopt() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_config("repo.txt => host.txt;")
+        .with_file_with_content(&temp_dir.path().join("host.txt"), "host")
+        .args(["sync", "--adopt"])
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(temp_dir.path().join("repo").join("repo.txt")).unwrap(),
+        "host"
+    );
+    assert!(is_symlinked(
+        temp_dir.path().join("host.txt"),
+        temp_dir.path().join("repo").join("repo.txt")
+    ));
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn sync_platform_dir() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_repo_file("init.vim")
+        .with_config("init.vim => configdir()/nvim/init.vim;")
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        temp_dir.path().join(".config/nvim/init.vim"),
+        temp_dir.path().join("repo").join("init.vim")
+    ));
+}
+
 #[test]
 fn sync_dry_run_should_not_symlink() {
     let temp_dir = TempDir::new().unwrap();
@@ -233,6 +2716,288 @@ fn sync_dry_run_should_not_symlink() {
     assert!(!temp_dir.path().join("should-not-exist.txt").exists());
 }
 
+#[test]
+fn sync_replace_identical() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_repo_path()
+            .with_file_with_content(&repo_path.join("a.txt"), "a")
+            .with_file_with_content(&repo_path.join("b.txt"), "b")
+            .with_config("a.txt;\nb.txt;\n")
+    };
+    fs::write(path.join("a.txt"), "a").unwrap();
+    fs::write(path.join("b.txt"), "changed").unwrap();
+    // Host files that differ from the repository file are still conflicts.
+    tester()
+        .args(["sync", "--replace-identical"])
+        .assert()
+        .failure();
+    assert!(!is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    fs::write(path.join("b.txt"), "b").unwrap();
+    tester().arg("sync").assert().failure();
+    let assert = tester()
+        .args(["sync", "--replace-identical"])
+        .assert()
+        .success();
+    assert!(stdout(&assert).ends_with("sync result (2 total): 2 synced; 0 ignored\n"));
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(is_symlinked(path.join("b.txt"), repo_path.join("b.txt")));
+    // Undoing the sync restores the host files.
+    tester().arg("undo").assert().success();
+    assert_eq!(fs::read_to_string(path.join("b.txt")).unwrap(), "b");
+    assert!(fs::symlink_metadata(path.join("b.txt"))
+        .unwrap()
+        .file_type()
+        .is_file());
+}
+
+#[test]
+fn sync_backup() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let backup_dir = path.join("backups");
+    fs::create_dir_all(path.join(".config")).unwrap();
+    fs::write(path.join(".config").join("a.txt"), "host").unwrap();
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_file_with_content(&repo_path.join(".config").join("a.txt"), "repo")
+        .with_config(".config/a.txt;")
+        .args(["sync", &format!("--backup={}", backup_dir.display())])
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        path.join(".config").join("a.txt"),
+        repo_path.join(".config").join("a.txt")
+    ));
+    // The host file is kept within a directory named after the time of the sync.
+    let backups: Vec<PathBuf> = fs::read_dir(&backup_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path().join(".config").join("a.txt"))
+        .collect();
+    assert_eq!(backups.len(), 1);
+    assert_eq!(fs::read_to_string(&backups[0]).unwrap(), "host");
+    assert!(stdout(&assert).starts_with(&format!(
+        "Backed up {} to {}\n",
+        path.join(".config").join("a.txt").display(),
+        backups[0].display()
+    )));
+    // Undoing the sync moves the host file back.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("undo")
+        .assert()
+        .success();
+    assert_eq!(
+        fs::read_to_string(path.join(".config").join("a.txt")).unwrap(),
+        "host"
+    );
+}
+
+#[test]
+fn sync_overwrite() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_repo_path()
+            .with_file_with_content(&repo_path.join("a.txt"), "repo")
+            .with_config("a.txt;")
+    };
+    fs::write(path.join("a.txt"), "host").unwrap();
+    tester().arg("sync").assert().failure();
+    // Dry runs leave the host file alone.
+    tester()
+        .args(["sync", "--overwrite", "--dry-run"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(path.join("a.txt")).unwrap(), "host");
+    let assert = tester().args(["sync", "--overwrite"]).assert().success();
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(stdout(&assert).starts_with(&format!(
+        "Removed {} -> {}\n",
+        path.join("a.txt").display(),
+        repo_path.join("a.txt").display()
+    )));
+    // Backups take precedence, so the host file is kept.
+    fs::remove_file(path.join("a.txt")).unwrap();
+    fs::write(path.join("a.txt"), "host").unwrap();
+    let backup_dir = path.join("backups");
+    tester()
+        .args([
+            "sync",
+            "--overwrite",
+            &format!("--backup={}", backup_dir.display()),
+        ])
+        .assert()
+        .success();
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert_eq!(fs::read_dir(&backup_dir).unwrap().count(), 1);
+}
+
+#[test]
+fn sync_interactive() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_path()
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("a.txt;\nb.txt;\n")
+        .args(["sync", "--interactive"])
+        // Pressing enter accepts, as the default is yes.
+        .write_stdin("\nn\n")
+        .assert()
+        .success();
+    assert!(is_symlinked(path.join("a.txt"), repo_path.join("a.txt")));
+    assert!(!path.join("b.txt").exists());
+    assert!(stdout(&assert).contains(&format!("Sync {}? [Y/n] ", path.join("b.txt").display())));
+    assert!(stdout(&assert).ends_with("sync result (2 total): 1 synced; 1 ignored\n"));
+}
+
+#[test]
+fn sync_and_clean_only() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let tester = || {
+        AmbitTester::from_temp_dir(&temp_dir)
+            .with_repo_path()
+            .with_file_with_content(&repo_path.join(".config/nvim/init.vim"), "")
+            .with_file_with_content(&repo_path.join(".config/nvim/lua/plugins.lua"), "")
+            .with_repo_file(".bashrc")
+            .with_config(".config/nvim/init.vim;\n.config/nvim/lua/plugins.lua;\n.bashrc;\n")
+    };
+    tester()
+        .args(["sync", "--only", ".config/nvim/*"])
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        path.join(".config/nvim/init.vim"),
+        repo_path.join(".config/nvim/init.vim")
+    ));
+    assert!(!path.join(".config/nvim/lua/plugins.lua").exists());
+    assert!(!path.join(".bashrc").exists());
+    tester().arg("sync").assert().success();
+    tester()
+        .args(["clean", "--only", ".config/**"])
+        .assert()
+        .success();
+    assert!(!path.join(".config/nvim/init.vim").exists());
+    assert!(!path.join(".config/nvim/lua/plugins.lua").exists());
+    assert!(is_symlinked(
+        path.join(".bashrc"),
+        repo_path.join(".bashrc")
+    ));
+}
+
+#[test]
+fn sync_duplicate_host_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    let config_path = temp_dir.path().join("config.ambit");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("a.txt => x.txt;\nc.txt;\nb.* => x.txt;\n")
+        .arg("sync")
+        .assert()
+        .failure()
+        .stderr(format!(
+            "ERROR: {1}:3:1: Host file `{}` is produced by more than one entry:\n    {1}:1: a.txt => x.txt;\n    {1}:3: b.* => x.txt;\n",
+            temp_dir.path().join("x.txt").display(),
+            config_path.display()
+        ));
+    // Nothing is synced, as the collision is found before any file is.
+    assert!(!temp_dir.path().join("x.txt").exists());
+}
+
+#[test]
+fn sync_incremental() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_file_with_content(&repo_path.join("a.txt"), "a")
+        .with_repo_file("b.txt")
+        .with_config("a.txt [mode=copy];\nb.txt;\n")
+        .arg("sync")
+        .assert()
+        .success();
+    // The copy is not compared with the repository file again while neither was modified,
+    // so a change that keeps the modification time goes unnoticed.
+    let modified = fs::metadata(path.join("a.txt"))
+        .unwrap()
+        .modified()
+        .unwrap();
+    fs::write(path.join("a.txt"), "changed").unwrap();
+    File::options()
+        .write(true)
+        .open(path.join("a.txt"))
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--incremental", "--check"])
+        .assert()
+        .success()
+        .stdout("sync result (2 total): 0 synced; 2 ignored\n");
+    // As its modification time is unchanged, the copy looks outdated rather than modified.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--check"])
+        .assert()
+        .code(2);
+    // Files that were removed are synced again.
+    fs::remove_file(path.join("b.txt")).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--incremental"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "Synced {} -> {}\nsync result (2 total): 1 synced; 1 ignored\n",
+            path.join("b.txt").display(),
+            repo_path.join("b.txt").display()
+        ));
+    // Changing an entry checks its files again, which finds the outdated copy.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt [mode=copy, perm=644];\nb.txt;\n")
+        .args(["sync", "--incremental"])
+        .assert()
+        .success();
+    assert_eq!(fs::read_to_string(path.join("a.txt")).unwrap(), "a");
+}
+
+#[test]
+fn sync_check_exit_code() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("repo.txt")
+        .with_config("repo.txt => host.txt;")
+        .args(["sync", "--check"])
+        .assert()
+        .code(2);
+    // `--check` implies `--dry-run`.
+    assert!(!temp_dir.path().join("host.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("sync")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--check"])
+        .assert()
+        .success();
+    // Errors are told apart from pending changes.
+    fs::remove_file(temp_dir.path().join("host.txt")).unwrap();
+    fs::write(temp_dir.path().join("host.txt"), "host").unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["sync", "--check"])
+        .assert()
+        .code(1);
+}
+
 #[test]
 fn sync_creates_host_parent_directories() {
     // Parent directories of the host file should be created if they do not exist.
@@ -375,6 +3140,38 @@ fn sync_use_any_repo_config_found_if_required() {
     ));
 }
 
+#[test]
+fn sync_quoted_paths() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("repo file.txt")
+        .with_config("\"repo file.txt\" => \"host=file.txt\";")
+        .arg("sync")
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        temp_dir.path().join("host=file.txt"),
+        temp_dir.path().join("repo").join("repo file.txt"),
+    ));
+}
+
+#[test]
+fn sync_exists_expr() {
+    let temp_dir = TempDir::new().unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("repo.txt")
+        .with_host_file("marker")
+        .with_config("{ exists(~/marker): repo.txt, default: other.txt } => host.txt;")
+        .arg("sync")
+        .assert()
+        .success();
+    // `marker` exists in the home directory so repo.txt should be chosen.
+    assert!(is_symlinked(
+        temp_dir.path().join("host.txt"),
+        temp_dir.path().join("repo").join("repo.txt"),
+    ));
+}
+
 #[test]
 fn clean_after_sync() {
     let temp_dir = TempDir::new().unwrap();
@@ -396,6 +3193,290 @@ fn clean_after_sync() {
     assert!(!host_path.exists());
 }
 
+#[test]
+fn clean_orphaned_symlinks() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("a.txt;\nb.txt => .config/b.txt;\n")
+        .arg("sync")
+        .assert()
+        .success();
+    // b.txt is removed from the config, so its symlink is left behind.
+    let doctor = AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n")
+        .arg("doctor")
+        .assert()
+        .failure();
+    let output = String::from_utf8(doctor.get_output().stdout.clone()).unwrap();
+    assert!(output.contains("fail: no orphaned files from removed entries"));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clean")
+        .arg("--orphans")
+        .assert()
+        .success()
+        .stdout(format!(
+            "Removed {} -> {}\nclean result (1 total): 1 deleted: 0 ignored\n",
+            path.join(".config").join("b.txt").display(),
+            path.join("repo").join("b.txt").display()
+        ));
+    assert!(!path.join(".config").join("b.txt").exists());
+    assert!(is_symlinked(
+        path.join("a.txt"),
+        path.join("repo").join("a.txt")
+    ));
+}
+
+#[test]
+fn clean_orphans_scan_depth() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("a.txt;\nb.txt => .config/b.txt;\n")
+        .arg("sync")
+        .assert()
+        .success();
+    // Without the manifest, the symlink of b.txt is only found by scanning the home directory.
+    fs::remove_file(path.join(".local/share/ambit/manifest")).unwrap();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("a.txt;\n")
+        .args(["clean", "--orphans"])
+        .assert()
+        .success()
+        .stdout("clean result (0 total): 0 deleted: 0 ignored\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["clean", "--orphans", "--scan-depth", "1"])
+        .assert()
+        .success()
+        .stdout("clean result (0 total): 0 deleted: 0 ignored\n");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .args(["clean", "--orphans", "--scan-depth", "2"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "Removed {} -> {}\nclean result (1 total): 1 deleted: 0 ignored\n",
+            path.join(".config").join("b.txt").display(),
+            path.join("repo").join("b.txt").display()
+        ));
+    assert!(fs::symlink_metadata(path.join(".config").join("b.txt")).is_err());
+}
+
+#[test]
+fn clean_files_recorded_in_manifest() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_repo_file("b.txt")
+        .with_config("a.txt [mode=copy];\nb.txt;\n")
+        .arg("sync")
+        .assert()
+        .success();
+    // The sync state recorded after the paths is left out, as it holds modification times.
+    let manifest = fs::read_to_string(path.join(".local/share/ambit/manifest")).unwrap();
+    let recorded: Vec<String> = manifest
+        .lines()
+        .map(|line| line.split('\t').take(3).collect::<Vec<_>>().join("\t"))
+        .collect();
+    assert_eq!(
+        recorded,
+        vec![
+            format!(
+                "copy\t{}\t{}",
+                path.join("a.txt").display(),
+                repo_path.join("a.txt").display()
+            ),
+            format!(
+                "symlink\t{}\t{}",
+                path.join("b.txt").display(),
+                repo_path.join("b.txt").display()
+            ),
+        ]
+    );
+    // The copy of a.txt is only known to be synced through the manifest.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("b.txt;\n")
+        .args(["clean", "--orphans"])
+        .assert()
+        .success()
+        .stdout(format!(
+            "Removed {} -> {}\nclean result (1 total): 1 deleted: 0 ignored\n",
+            path.join("a.txt").display(),
+            repo_path.join("a.txt").display()
+        ));
+    assert!(!path.join("a.txt").exists());
+    // Entries that are gone from the config are still cleaned.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("")
+        .arg("clean")
+        .assert()
+        .success()
+        .stdout("clean result (1 total): 1 deleted: 0 ignored\n");
+    assert!(fs::symlink_metadata(path.join("b.txt")).is_err());
+    assert_eq!(
+        fs::read_to_string(path.join(".local/share/ambit/manifest")).unwrap(),
+        ""
+    );
+}
+
+#[test]
+fn undo_sync_and_clean() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_file_with_content(&repo_path.join("b.txt"), "b")
+        .with_config("a.txt => x/a.txt;\nb.txt [mode=copy, perm=640];\n")
+        .arg("sync")
+        .assert()
+        .success();
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("clean")
+        .assert()
+        .success();
+    assert!(!path.join("b.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout("undo result (`ambit clean`, 2 total): 2 undone; 0 failed\n");
+    assert!(is_symlinked(
+        path.join("x").join("a.txt"),
+        repo_path.join("a.txt")
+    ));
+    assert_eq!(fs::read_to_string(path.join("b.txt")).unwrap(), "b");
+    // The restored copy gets the attributes of its entry.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path.join("b.txt"))
+            .unwrap()
+            .permissions()
+            .mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("undo")
+        .assert()
+        .success()
+        .stdout("undo result (`ambit sync`, 3 total): 3 undone; 0 failed\n");
+    assert!(!path.join("x").exists());
+    assert!(!path.join("b.txt").exists());
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("undo")
+        .assert()
+        .failure()
+        .stderr("ERROR: Nothing to undo\n");
+    // Files moved into the repository are moved back.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_config("c.txt;\n")
+        .with_host_file("c.txt")
+        .args(["sync", "--move"])
+        .assert()
+        .success();
+    assert!(is_symlinked(path.join("c.txt"), repo_path.join("c.txt")));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("undo")
+        .assert()
+        .success();
+    assert!(!repo_path.join("c.txt").exists());
+    assert!(fs::symlink_metadata(path.join("c.txt"))
+        .unwrap()
+        .file_type()
+        .is_file());
+}
+
+#[test]
+fn plan_and_apply_attributes() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let config = "key [mode=copy, perm=640, secret];\n";
+    let plan = AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("key")
+        .with_config(config)
+        .arg("plan")
+        .assert()
+        .success();
+    // Secret paths are kept out of the output of both plan and apply.
+    assert!(!stdout(&plan).contains("key"));
+    let apply = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("apply")
+        .assert()
+        .success();
+    assert!(!stdout(&apply).contains("key"));
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = fs::metadata(path.join("key")).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o640);
+    }
+}
+
+#[test]
+fn plan_and_apply() {
+    let temp_dir = TempDir::new().unwrap();
+    let path = temp_dir.path();
+    let repo_path = path.join("repo");
+    let plan_path = path.join("plan");
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("a.txt")
+        .with_host_file("b.txt")
+        .with_config("a.txt => x/a.txt;\nb.txt;\n")
+        .args(["plan", "--move", "--out"])
+        .arg(&plan_path)
+        .assert()
+        .success();
+    // Nothing is changed until the plan is applied.
+    assert!(!path.join("x").exists());
+    assert!(!repo_path.join("b.txt").exists());
+    let plan = fs::read_to_string(&plan_path).unwrap();
+    assert!(plan.contains(&format!(
+        "link\tsymlink\t{}\t{}\n",
+        path.join("x").join("a.txt").display(),
+        repo_path.join("a.txt").display()
+    )));
+    assert!(plan.contains(&format!(
+        "move\tsymlink\t{}\t{}\n",
+        path.join("b.txt").display(),
+        repo_path.join("b.txt").display()
+    )));
+    AmbitTester::from_temp_dir(&temp_dir)
+        .arg("apply")
+        .arg(&plan_path)
+        .assert()
+        .success();
+    assert!(is_symlinked(
+        path.join("x").join("a.txt"),
+        repo_path.join("a.txt")
+    ));
+    assert!(is_symlinked(path.join("b.txt"), repo_path.join("b.txt")));
+    assert!(!plan_path.exists());
+    // A plan is refused once the file system no longer matches it.
+    AmbitTester::from_temp_dir(&temp_dir)
+        .with_repo_file("c.txt")
+        .with_config("c.txt;\n")
+        .args(["plan", "--out"])
+        .arg(&plan_path)
+        .assert()
+        .success();
+    fs::write(path.join("c.txt"), "c").unwrap();
+    let assert = AmbitTester::from_temp_dir(&temp_dir)
+        .arg("apply")
+        .arg(&plan_path)
+        .assert()
+        .failure();
+    let stderr = String::from_utf8(assert.get_output().stderr.clone()).unwrap();
+    assert!(stderr.contains("Plan is out of date, as the host file already exists"));
+    // A refused plan is kept, so it can still be reviewed.
+    assert!(plan_path.exists());
+}
+
 #[test]
 fn clean_ignores_parent_directories() {
     let temp_dir = TempDir::new().unwrap();